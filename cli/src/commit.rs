@@ -57,6 +57,19 @@ pub fn destroy(context: &Context) -> AtomicResult<()> {
     Ok(())
 }
 
+/// Reverts a Commit - signs and posts a Commit that undoes its changes
+pub fn revert(context: &Context) -> AtomicResult<()> {
+    let commit_url = argument_to_url(context, "commit")?;
+    context.get_write_context();
+    let inverse = atomic_lib::client::post_revert(&commit_url, &context.store)?;
+    println!(
+        "Reverted {} in Commit {}",
+        commit_url,
+        inverse.get_subject()
+    );
+    Ok(())
+}
+
 /// Parses a single argument as string
 fn argument_to_string(context: &Context, argument: &str) -> AtomicResult<String> {
     let command_name = context.matches.subcommand_name().unwrap();
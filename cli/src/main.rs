@@ -1,7 +1,7 @@
 use atomic_lib::{agents::generate_public_key, mapping::Mapping};
 use atomic_lib::{agents::Agent, config::Config};
 use atomic_lib::{errors::AtomicResult, Storelike};
-use clap::{crate_version, Arg, ArgMatches, Command};
+use clap::{crate_version, Arg, ArgAction, ArgMatches, Command};
 use colored::*;
 use dirs::home_dir;
 use std::{cell::RefCell, path::PathBuf, sync::Mutex};
@@ -12,6 +12,7 @@ mod commit;
 mod new;
 mod path;
 mod print;
+mod token;
 
 #[allow(dead_code)]
 /// The Context contains all the data for executing a single CLI command, such as the passed arguments and the in memory store.
@@ -192,6 +193,37 @@ fn main() -> AtomicResult<()> {
                     .required(true)
                 )
         )
+        .subcommand(
+            Command::new("revert")
+                .about("Undoes the changes of a Commit, by signing and posting an inverse Commit. Restores destroyed Resources.")
+                .arg(Arg::new("commit")
+                    .help("URL of the Commit to be reverted")
+                    .required(true)
+                )
+        )
+        .subcommand(
+            Command::new("token")
+                .about("Manage Tokens, which let scripts authenticate as your Agent using an `Authorization: Bearer` header.")
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("create")
+                        .about("Issues a Token on your server and prints its secret. Revoke it by destroying the Token.")
+                        .arg(Arg::new("days")
+                            .long("days")
+                            .value_parser(clap::value_parser!(u32))
+                            .help("Number of days until the Token expires. Defaults to the server's default.")
+                        )
+                        .arg(Arg::new("scope")
+                            .long("scope")
+                            .help("Subject of a resource. The Token can only be used for this resource and its children.")
+                        )
+                        .arg(Arg::new("read-only")
+                            .long("read-only")
+                            .action(ArgAction::SetTrue)
+                            .help("The Token can only be used for reading")
+                        )
+                )
+        )
         .subcommand(Command::new("list").about("List all bookmarks"))
         .subcommand(Command::new("validate").about("Validates the store").hide(true))
         .get_matches();
@@ -261,9 +293,19 @@ fn exec_command(context: &mut Context) -> AtomicResult<()> {
         Some("remove") => {
             commit::remove(context)?;
         }
+        Some("revert") => {
+            commit::revert(context)?;
+        }
         Some("set") => {
             commit::set(context)?;
         }
+        Some("token") => {
+            let matches = context.matches.subcommand_matches("token").unwrap().clone();
+            match matches.subcommand() {
+                Some(("create", create_matches)) => token::create(context, create_matches)?,
+                _ => return Err("Run atomic token --help for available commands".into()),
+            }
+        }
         Some("tpf") => {
             tpf(context)?;
         }
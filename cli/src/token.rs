@@ -0,0 +1,22 @@
+use crate::Context;
+use atomic_lib::{errors::AtomicResult, urls};
+use clap::ArgMatches;
+
+/// Issues a Token on the configured server, which can be used in an `Authorization: Bearer` header.
+pub fn create(context: &Context, matches: &ArgMatches) -> AtomicResult<()> {
+    let server = context.get_write_context().server;
+    let expires_in = matches
+        .get_one::<u32>("days")
+        .map(|days| *days as i64 * 60 * 60 * 24);
+    let scope = matches.get_one::<String>("scope").map(String::as_str);
+    let read_only = matches.get_flag("read-only");
+    let token =
+        atomic_lib::client::create_token(&server, expires_in, scope, read_only, &context.store)?;
+    println!("Created Token {}", token.get_subject());
+    println!(
+        "Revoke it using `atomic-cli destroy {}`",
+        token.get_subject()
+    );
+    println!("Authorization: Bearer {}", token.get(urls::TOKEN_SECRET)?);
+    Ok(())
+}
@@ -4,7 +4,14 @@
 
 use base64::{engine::general_purpose, Engine};
 
-use crate::{errors::AtomicResult, urls, Resource, Storelike, Value};
+use crate::{
+    commit::{Commit, CommitBuilder},
+    errors::AtomicResult,
+    resources::PropVals,
+    urls,
+    values::SubResource,
+    Resource, Storelike, Value,
+};
 
 #[derive(Clone, Debug)]
 pub struct Agent {
@@ -75,6 +82,234 @@ impl Agent {
             created_at: crate::utils::now(),
         })
     }
+
+    /// Generates a new keypair for this Agent, and creates a signed Commit that makes it the Agent's `publicKey`.
+    /// The current key stays valid for Commits created before the rotation.
+    /// Returns the Agent with the new private key, and the Commit, which should be applied or posted before the new Agent is used.
+    pub fn rotate_key(&self, store: &impl Storelike) -> AtomicResult<(Agent, Commit)> {
+        let keypair = generate_keypair()?;
+        let resource = store.get_resource(&self.subject)?;
+        let now = crate::utils::now();
+        let mut keys = get_keys(&resource)?;
+        for key in keys.iter_mut() {
+            if key.public_key == self.public_key {
+                key.valid_until = Some(key.valid_until.map_or(now, |until| until.min(now)));
+            }
+        }
+        keys.push(AgentKey {
+            public_key: keypair.public.clone(),
+            valid_from: now,
+            valid_until: None,
+        });
+
+        let mut commitbuilder = CommitBuilder::new(self.subject.clone());
+        commitbuilder.set(
+            urls::PUBLIC_KEY.into(),
+            Value::String(keypair.public.clone()),
+        );
+        commitbuilder.set(urls::AGENT_KEYS.into(), keys_to_value(&keys));
+        let commit = commitbuilder.sign_at(self, store, &resource, now)?;
+        let new_agent = Agent {
+            private_key: Some(keypair.private),
+            public_key: keypair.public,
+            ..self.clone()
+        };
+        Ok((new_agent, commit))
+    }
+
+    /// Creates a signed Commit that revokes one of the other keys of this Agent.
+    /// Commits and requests signed with that key after `at` are no longer accepted, so use the moment the key leaked.
+    pub fn revoke_key(
+        &self,
+        public_key: &str,
+        at: i64,
+        store: &impl Storelike,
+    ) -> AtomicResult<Commit> {
+        if public_key == self.public_key {
+            return Err(
+                "An Agent can not revoke the key it signs with. Rotate the key first.".into(),
+            );
+        }
+        let resource = store.get_resource(&self.subject)?;
+        let mut keys = get_keys(&resource)?;
+        let key = keys
+            .iter_mut()
+            .find(|key| key.public_key == public_key)
+            .ok_or_else(|| format!("Agent {} has no key {}", self.subject, public_key))?;
+        key.valid_until = Some(key.valid_until.map_or(at, |until| until.min(at)));
+
+        let mut commitbuilder = CommitBuilder::new(self.subject.clone());
+        commitbuilder.set(urls::AGENT_KEYS.into(), keys_to_value(&keys));
+        commitbuilder.sign(self, store, &resource)
+    }
+}
+
+/// A public key of an Agent, and the period in which it can be used for signing.
+/// See [get_keys].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AgentKey {
+    /// Base64 encoded Ed25519 public key
+    pub public_key: String,
+    /// Unix timestamp in milliseconds
+    pub valid_from: i64,
+    /// Unix timestamp in milliseconds. Keys without one stay valid.
+    pub valid_until: Option<i64>,
+}
+
+impl AgentKey {
+    pub fn is_valid_at(&self, timestamp: i64) -> bool {
+        self.valid_from <= timestamp
+            && !matches!(self.valid_until, Some(until) if timestamp > until)
+    }
+
+    fn from_propvals(propvals: &PropVals) -> AtomicResult<AgentKey> {
+        let public_key = propvals
+            .get(urls::PUBLIC_KEY)
+            .ok_or("AgentKey has no publicKey")?
+            .to_string();
+        let valid_from = propvals
+            .get(urls::VALID_FROM)
+            .ok_or("AgentKey has no validFrom")?
+            .to_int()?;
+        let valid_until = match propvals.get(urls::VALID_UNTIL) {
+            Some(until) => Some(until.to_int()?),
+            None => None,
+        };
+        Ok(AgentKey {
+            public_key,
+            valid_from,
+            valid_until,
+        })
+    }
+
+    fn to_propvals(&self) -> PropVals {
+        let mut propvals = PropVals::new();
+        propvals.insert(urls::IS_A.into(), vec![urls::AGENT_KEY.to_string()].into());
+        propvals.insert(
+            urls::PUBLIC_KEY.into(),
+            Value::String(self.public_key.clone()),
+        );
+        propvals.insert(urls::VALID_FROM.into(), Value::Timestamp(self.valid_from));
+        if let Some(until) = self.valid_until {
+            propvals.insert(urls::VALID_UNTIL.into(), Value::Timestamp(until));
+        }
+        propvals
+    }
+}
+
+fn keys_to_value(keys: &[AgentKey]) -> Value {
+    Value::ResourceArray(
+        keys.iter()
+            .map(|key| SubResource::Nested(key.to_propvals()))
+            .collect(),
+    )
+}
+
+/// Returns all keys of an Agent, including rotated and revoked ones.
+/// Agents without [urls::AGENT_KEYS] have one key, their `publicKey`, which is always valid.
+pub fn get_keys(agent: &Resource) -> AtomicResult<Vec<AgentKey>> {
+    match agent.get(urls::AGENT_KEYS) {
+        Ok(Value::ResourceArray(keys)) => keys
+            .iter()
+            .map(|key| match key {
+                SubResource::Nested(propvals) => AgentKey::from_propvals(propvals),
+                SubResource::Resource(resource) => AgentKey::from_propvals(resource.get_propvals()),
+                SubResource::Subject(subject) => Err(format!(
+                    "AgentKey {} of {} should be a nested resource",
+                    subject,
+                    agent.get_subject()
+                )
+                .into()),
+            })
+            .collect(),
+        Ok(other) => Err(format!(
+            "agentKeys of {} should be a ResourceArray, got {}",
+            agent.get_subject(),
+            other
+        )
+        .into()),
+        Err(_) => Ok(vec![AgentKey {
+            public_key: agent.get(urls::PUBLIC_KEY)?.to_string(),
+            valid_from: 0,
+            valid_until: None,
+        }]),
+    }
+}
+
+/// Returns the key of the Agent with this `public_key`, if it was valid at `timestamp`.
+pub fn get_valid_key(
+    store: &impl Storelike,
+    agent: &str,
+    public_key: &str,
+    timestamp: i64,
+) -> AtomicResult<AgentKey> {
+    let key = get_keys(&store.get_resource(agent)?)?
+        .into_iter()
+        .find(|key| key.public_key == public_key)
+        .ok_or_else(|| format!("The public key {} does not belong to {}", public_key, agent))?;
+    if !key.is_valid_at(timestamp) {
+        return Err(format!(
+            "The public key {} of {} is not valid at {}. It may have been rotated or revoked.",
+            public_key, agent, timestamp
+        )
+        .into());
+    }
+    Ok(key)
+}
+
+/// Checks a change to the keys of an Agent, made by a Commit created at `created_at`.
+/// Keys can not be removed, their validity can only be shortened, and new keys can not be valid before the Commit.
+/// This makes sure that existing Commits keep their meaning.
+/// The `publicKey` has to be one of the keys that are valid now.
+pub fn check_key_changes(old: &Resource, new: &Resource, created_at: i64) -> AtomicResult<()> {
+    let old_keys = get_keys(old)?;
+    let new_keys = get_keys(new)?;
+    for old_key in &old_keys {
+        let new_key = new_keys
+            .iter()
+            .find(|key| key.public_key == old_key.public_key)
+            .ok_or_else(|| format!("Key {} can not be removed", old_key.public_key))?;
+        let extended = match (old_key.valid_until, new_key.valid_until) {
+            (Some(old_until), Some(new_until)) => new_until > old_until,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        if new_key.valid_from != old_key.valid_from || extended {
+            return Err(format!(
+                "The validity of key {} can only be shortened",
+                old_key.public_key
+            )
+            .into());
+        }
+    }
+    for new_key in &new_keys {
+        let is_added = !old_keys
+            .iter()
+            .any(|key| key.public_key == new_key.public_key);
+        if is_added {
+            verify_public_key(&new_key.public_key)?;
+            if new_key.valid_from < created_at {
+                return Err(format!(
+                    "Key {} can not be valid before the Commit that adds it",
+                    new_key.public_key
+                )
+                .into());
+            }
+        }
+    }
+    let public_key = new.get(urls::PUBLIC_KEY)?.to_string();
+    let now = crate::utils::now();
+    if !new_keys
+        .iter()
+        .any(|key| key.public_key == public_key && key.is_valid_at(now))
+    {
+        return Err(format!(
+            "The publicKey {} should be one of the agentKeys that is valid now",
+            public_key
+        )
+        .into());
+    }
+    Ok(())
 }
 
 /// keypair, serialized using base64
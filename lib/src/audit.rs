@@ -0,0 +1,104 @@
+//! The audit log records the outcome of rights checks: which Agent read or changed which resource, and which requests were refused.
+//! It is disabled by default. Enable it using [crate::Db::set_audit], which stores the events in an append-only tree.
+//! Only the checks of the resource that a request is about are recorded, using [crate::hierarchy::check_audited] and [record_read].
+//! Resources that are checked while handling a request, such as the members of a Collection or the hits of a search, are not.
+//! Events can be retrieved using the `/audit` endpoint or [crate::Db::audit_events].
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{AtomicErrorType, AtomicResult},
+    hierarchy::Right,
+    Resource, Storelike,
+};
+
+/// The outcome of a single rights check.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuditEvent {
+    /// Milliseconds since UNIX epoch
+    pub timestamp: i64,
+    /// The Agent (or Token) that the check was performed for
+    pub agent: String,
+    pub subject: String,
+    /// URL of the Right, e.g. [crate::urls::READ]
+    pub right: String,
+    pub allowed: bool,
+    /// Why the Right was granted, or the error if it was denied
+    pub explanation: String,
+}
+
+impl AuditEvent {
+    pub fn new(agent: &str, subject: &str, right: &Right, result: &AtomicResult<String>) -> Self {
+        AuditEvent {
+            timestamp: crate::utils::now(),
+            agent: agent.into(),
+            subject: subject.into(),
+            right: right.to_string(),
+            allowed: result.is_ok(),
+            explanation: match result {
+                Ok(msg) => msg.clone(),
+                Err(e) => e.message.clone(),
+            },
+        }
+    }
+}
+
+/// Records the outcome of a request to read `subject`, e.g. using [Storelike::get_resource_extended].
+/// Requests without an Agent, or that failed for another reason than a missing right (such as a missing resource), are not recorded.
+pub fn record_read(
+    store: &impl Storelike,
+    for_agent: Option<&str>,
+    subject: &str,
+    result: &AtomicResult<Resource>,
+) {
+    let agent = match for_agent {
+        Some(agent) => agent,
+        None => return,
+    };
+    let outcome = match result {
+        Ok(_) => Ok("The resource was returned".to_string()),
+        Err(e) if matches!(e.error_type, AtomicErrorType::UnauthorizedError) => Err(e.clone()),
+        Err(_) => return,
+    };
+    store.record_audit_event(agent, subject, &Right::Read, &outcome);
+}
+
+/// Settings for the audit log.
+#[derive(Clone, Debug, Default)]
+pub struct AuditConfig {
+    /// Events older than this amount of milliseconds are removed by [crate::Db::prune_audit_log]. `None` keeps them forever.
+    pub retention: Option<i64>,
+}
+
+/// Selects events from the audit log, see [crate::Db::audit_events].
+#[derive(Clone, Debug, Default)]
+pub struct AuditFilter {
+    pub agent: Option<String>,
+    pub subject: Option<String>,
+    /// URL of the Right
+    pub right: Option<String>,
+    pub allowed: Option<bool>,
+    /// Only events at or after this timestamp
+    pub from: Option<i64>,
+    /// Only events before this timestamp
+    pub until: Option<i64>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, event: &AuditEvent) -> bool {
+        none_or(self.agent.as_ref(), |a| a == &event.agent)
+            && none_or(self.subject.as_ref(), |s| s == &event.subject)
+            && none_or(self.right.as_ref(), |r| r == &event.right)
+            && none_or(self.allowed, |a| a == event.allowed)
+            && none_or(self.from, |from| event.timestamp >= from)
+            && none_or(self.until, |until| event.timestamp < until)
+    }
+}
+
+/// Filters that are not set match every event.
+fn none_or<T>(filter: Option<T>, matches: impl FnOnce(T) -> bool) -> bool {
+    match filter {
+        Some(value) => matches(value),
+        None => true,
+    }
+}
@@ -1,7 +1,10 @@
 //! Check signatures in authentication headers, find the correct agent. Authorization is done in Hierarchies
 
 use crate::{
-    agents::decode_base64, commit::check_timestamp, errors::AtomicResult, urls, Storelike,
+    agents::{decode_base64, get_valid_key},
+    commit::check_timestamp,
+    errors::AtomicResult,
+    Storelike,
 };
 
 /// Set of values extracted from the request.
@@ -24,6 +27,14 @@ pub struct AuthValues {
     pub agent_subject: String,
 }
 
+/// The ways a request can be authenticated.
+pub enum Credentials {
+    /// Signed using the private key of the Agent
+    Signature(AuthValues),
+    /// The secret of a [crate::tokens::Token], from an `Authorization: Bearer` header
+    Bearer(String),
+}
+
 /// Checks if the signature is valid for this timestamp.
 /// Does not check if the agent has rights to access the subject.
 #[tracing::instrument(skip_all)]
@@ -59,21 +70,35 @@ pub fn get_agent_from_auth_values_and_check(
             .map_err(|e| format!("Error checking authentication headers. {}", e))?;
         // check if the timestamp is valid
         check_timestamp(auth_vals.timestamp)?;
-        // check if the public key belongs to the agent
-        let found_public_key = store.get_value(&auth_vals.agent_subject, urls::PUBLIC_KEY)?;
-        if found_public_key.to_string() != auth_vals.public_key {
-            return Err(
-                "The public key in the auth headers does not match the public key in the agent"
-                    .to_string()
-                    .into(),
-            );
-        } else {
-            for_agent = auth_vals.agent_subject;
-        }
+        // check if the public key belongs to the agent, and has not been rotated or revoked
+        get_valid_key(
+            store,
+            &auth_vals.agent_subject,
+            &auth_vals.public_key,
+            crate::utils::now(),
+        )
+        .map_err(|e| format!("Error checking authentication headers. {}", e))?;
+        for_agent = auth_vals.agent_subject;
     };
     Ok(for_agent)
 }
 
+/// Get the subject of the Agent, or of the [crate::tokens::Token], from the [Credentials].
+/// Returns the public agent if there are none.
+#[tracing::instrument(skip_all)]
+pub fn get_agent_from_credentials(
+    credentials: Option<Credentials>,
+    store: &impl Storelike,
+) -> AtomicResult<String> {
+    match credentials {
+        Some(Credentials::Bearer(bearer)) => crate::tokens::check_bearer(store, &bearer),
+        Some(Credentials::Signature(auth_values)) => {
+            get_agent_from_auth_values_and_check(Some(auth_values), store)
+        }
+        None => get_agent_from_auth_values_and_check(None, store),
+    }
+}
+
 // fn get_agent_from_value_index() {
 //     let map = store.get_prop_subject_map(&auth_vals.public_key)?;
 //     let agents = map.get(crate::urls::PUBLIC_KEY).ok_or(format!(
@@ -52,16 +52,17 @@ pub fn fetch_body(url: &str, content_type: &str, for_agent: Option<Agent>) -> At
     if !url.starts_with("http") {
         return Err(format!("Could not fetch url '{}', must start with http.", url).into());
     }
-    if let Some(agent) = for_agent {
-        get_authentication_headers(url, &agent)?;
-    }
-
-    let agent = ureq::builder()
+    let mut req = ureq::builder()
         .timeout(std::time::Duration::from_secs(2))
-        .build();
-    let resp = agent
+        .build()
         .get(url)
-        .set("Accept", content_type)
+        .set("Accept", content_type);
+    if let Some(agent) = for_agent.filter(|a| a.private_key.is_some()) {
+        for (key, value) in get_authentication_headers(url, &agent)? {
+            req = req.set(&key, &value);
+        }
+    }
+    let resp = req
         .call()
         .map_err(|e| format!("Error when server tried fetching {} : {}", url, e))?;
     let status = resp.status();
@@ -144,6 +145,270 @@ pub fn post_commit_custom_endpoint(
     }
 }
 
+/// Posts a [crate::Transaction] to the `/commits` endpoint of the server of its first Commit.
+/// The server applies either all of its Commits, or none of them.
+pub fn post_transaction(
+    transaction: &crate::Transaction,
+    store: &impl Storelike,
+) -> AtomicResult<()> {
+    let first = transaction
+        .commits
+        .first()
+        .ok_or("Transaction does not contain any Commits")?;
+    let server_url = crate::utils::server_url(first.get_subject())?;
+    let endpoint = format!("{}commits", server_url);
+    let json = transaction.into_resource(store)?.to_json_ad()?;
+
+    let agent = ureq::builder()
+        .timeout(std::time::Duration::from_secs(2))
+        .build();
+
+    let resp = agent
+        .post(&endpoint)
+        .set("Content-Type", "application/json")
+        .send_string(&json)
+        .map_err(|e| format!("Error when posting transaction to {} : {}", endpoint, e))?;
+
+    if resp.status() != 200 {
+        Err(format!(
+            "Failed applying transaction to {}. Status: {} Body: {}",
+            endpoint,
+            resp.status(),
+            resp.into_string()?
+        )
+        .into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Reverts a Commit: creates a Commit that undoes its changes, signs it with the default Agent of the store, and posts it to the server of the Commit.
+/// Uses the `/revert` endpoint of that server to get the Resource as it was before the Commit, see [crate::Commit::inverse].
+/// The server refuses the new Commit if the Resource has been changed after the reverted Commit, or if the Agent has no write rights.
+/// Returns the new Commit.
+pub fn post_revert(commit_url: &str, store: &impl Storelike) -> AtomicResult<Resource> {
+    let agent = store.get_default_agent()?;
+    let parse_opts = ParseOpts {
+        save: crate::parse::SaveOpts::DontSave,
+        ..ParseOpts::default()
+    };
+    let body = fetch_body(commit_url, crate::parse::JSON_AD_MIME, Some(agent.clone()))?;
+    let commit = crate::Commit::from_resource(parse_json_ad_resource(&body, store, &parse_opts)?)?;
+
+    let endpoint = format!(
+        "{}revert?commit={}",
+        crate::utils::server_url(commit_url)?,
+        urlencoding::encode(commit_url)
+    );
+    let body = fetch_body(&endpoint, crate::parse::JSON_AD_MIME, Some(agent.clone()))?;
+    let resource_old = parse_json_ad_resource(&body, store, &parse_opts)?;
+    // The Resource has no `lastCommit`, so the `previousCommit` of the inverse is kept.
+    let inverse = commit.inverse(&resource_old)?.sign(
+        &agent,
+        store,
+        &Resource::new(commit.subject.clone()),
+    )?;
+    post_commit(&inverse, store)
+        .map_err(|e| format!("Error when reverting commit {} : {}", commit_url, e))?;
+    inverse.into_resource(store)
+}
+
+/// Asks the server to issue a [crate::tokens::Token] for the default Agent of the store.
+/// Returns the Token, which contains the bearer secret in [crate::urls::TOKEN_SECRET].
+/// `expires_in` is in seconds. Without it, the server picks a default.
+pub fn create_token(
+    server_url: &str,
+    expires_in: Option<i64>,
+    scope: Option<&str>,
+    read_only: bool,
+    store: &impl Storelike,
+) -> AtomicResult<Resource> {
+    let mut endpoint = format!(
+        "{}{}?read_only={}",
+        server_url.trim_end_matches('/'),
+        crate::tokens::TOKENS_PATH,
+        read_only
+    );
+    if let Some(expires_in) = expires_in {
+        endpoint.push_str(&format!("&expires_in={}", expires_in));
+    }
+    if let Some(scope) = scope {
+        endpoint.push_str(&format!("&scope={}", urlencoding::encode(scope)));
+    }
+    let body = send_signed("POST", &endpoint, None, &store.get_default_agent()?)?;
+    let parse_opts = ParseOpts {
+        save: crate::parse::SaveOpts::DontSave,
+        ..ParseOpts::default()
+    };
+    parse_json_ad_resource(&body, store, &parse_opts)
+}
+
+/// Size of the chunks that [upload_file] sends.
+pub const UPLOAD_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+/// How often [resume_upload] retries a chunk before giving up.
+const UPLOAD_RETRIES: usize = 3;
+
+/// Uploads a file as a child of `parent`, using a resumable UploadSession on the server of the `parent`.
+/// Uses the default Agent of the store. Returns the created File.
+/// If the upload fails, the error contains the UploadSession, which can be continued using [resume_upload].
+pub fn upload_file(
+    path: &std::path::Path,
+    parent: &str,
+    chunk_size: usize,
+    store: &impl Storelike,
+) -> AtomicResult<Resource> {
+    let session = create_upload_session(path, parent, store)?;
+    resume_upload(&session, path, chunk_size, store)
+}
+
+/// Creates an UploadSession for a file on the server of the `parent`. Returns its subject.
+pub fn create_upload_session(
+    path: &std::path::Path,
+    parent: &str,
+    store: &impl Storelike,
+) -> AtomicResult<String> {
+    let filename = path
+        .file_name()
+        .ok_or("Path does not contain a filename")?
+        .to_string_lossy();
+    let filesize = std::fs::metadata(path)?.len();
+    let endpoint = format!(
+        "{}upload-sessions?parent={}&filename={}&filesize={}",
+        crate::utils::server_url(parent)?,
+        urlencoding::encode(parent),
+        urlencoding::encode(&filename),
+        filesize
+    );
+    let body = send_signed("POST", &endpoint, None, &store.get_default_agent()?)?;
+    Ok(UploadProgress::parse(&body)?.subject)
+}
+
+/// Sends the chunks of the file that the UploadSession has not received yet, and finishes it.
+/// Returns the created File.
+pub fn resume_upload(
+    session: &str,
+    path: &std::path::Path,
+    chunk_size: usize,
+    store: &impl Storelike,
+) -> AtomicResult<Resource> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    let agent = store.get_default_agent()?;
+    let mut file = std::fs::File::open(path)?;
+    let mut failures = 0;
+    let mut progress = UploadProgress::parse(&fetch_body(
+        session,
+        crate::parse::JSON_AD_MIME,
+        Some(agent.clone()),
+    )?)?;
+    while !progress.finished && progress.offset < progress.filesize {
+        let length = chunk_size.min((progress.filesize - progress.offset) as usize);
+        let mut chunk = vec![0; length];
+        file.seek(SeekFrom::Start(progress.offset))?;
+        file.read_exact(&mut chunk)?;
+        let url = format!(
+            "{}?chunk={}&offset={}",
+            session, progress.chunks, progress.offset
+        );
+        let result = send_signed("PUT", &url, Some(&chunk), &agent)
+            .and_then(|body| UploadProgress::parse(&body));
+        progress = match result {
+            Ok(progress) => {
+                failures = 0;
+                progress
+            }
+            Err(e) => {
+                failures += 1;
+                if failures > UPLOAD_RETRIES {
+                    return Err(format!(
+                        "Uploading {:?} failed. Continue the upload using `resume_upload` with {}. {}",
+                        path, session, e
+                    )
+                    .into());
+                }
+                // The chunk might have arrived, so the server decides where to continue
+                UploadProgress::parse(&fetch_body(
+                    session,
+                    crate::parse::JSON_AD_MIME,
+                    Some(agent.clone()),
+                )?)?
+            }
+        };
+    }
+    let body = send_signed("POST", session, None, &agent)?;
+    let parse_opts = ParseOpts {
+        save: crate::parse::SaveOpts::DontSave,
+        ..ParseOpts::default()
+    };
+    parse_json_ad_resource(&body, store, &parse_opts)
+}
+
+/// The state of an UploadSession on the server
+struct UploadProgress {
+    subject: String,
+    offset: u64,
+    chunks: u64,
+    filesize: u64,
+    finished: bool,
+}
+
+impl UploadProgress {
+    fn parse(json_ad: &str) -> AtomicResult<UploadProgress> {
+        let json: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(json_ad).map_err(|e| format!("Invalid UploadSession. {}", e))?;
+        let number = |property: &str| {
+            json.get(property)
+                .and_then(|v| v.as_u64())
+                .ok_or_else(|| format!("UploadSession is missing {}", property))
+        };
+        Ok(UploadProgress {
+            subject: json
+                .get("@id")
+                .and_then(|v| v.as_str())
+                .ok_or("UploadSession is missing @id")?
+                .to_string(),
+            offset: number(crate::urls::UPLOAD_OFFSET)?,
+            chunks: number(crate::urls::UPLOAD_CHUNKS)?,
+            filesize: number(crate::urls::FILESIZE)?,
+            finished: json.contains_key(crate::urls::UPLOAD_FILE),
+        })
+    }
+}
+
+/// Sends a request that is signed by the Agent, returns the body of the response.
+fn send_signed(
+    method: &str,
+    url: &str,
+    body: Option<&[u8]>,
+    agent: &Agent,
+) -> AtomicResult<String> {
+    let mut req = ureq::builder()
+        .timeout(std::time::Duration::from_secs(60))
+        .build()
+        .request(method, url)
+        .set("Accept", crate::parse::JSON_AD_MIME);
+    for (key, value) in get_authentication_headers(url, agent)? {
+        req = req.set(&key, &value);
+    }
+    let resp = match body {
+        Some(body) => req.send_bytes(body),
+        None => req.call(),
+    };
+    match resp {
+        Ok(resp) => Ok(resp.into_string()?),
+        Err(ureq::Error::Status(status, resp)) => Err(format!(
+            "{} {} failed. Status: {}. Body: {}",
+            method,
+            url,
+            status,
+            resp.into_string().unwrap_or_default()
+        )
+        .into()),
+        Err(e) => Err(format!("{} {} failed. {}", method, url, e).into()),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -2,7 +2,7 @@
 //! They are constructed using a TPF query
 use crate::{
     errors::AtomicResult,
-    storelike::{Query, ResourceCollection},
+    storelike::{Query, QueryExpr, ResourceCollection},
     urls, Resource, Storelike, Value,
 };
 
@@ -39,6 +39,10 @@ pub struct CollectionBuilder {
     pub include_nested: bool,
     /// Whether to include resources from other servers
     pub include_external: bool,
+    /// Composable filter, which is combined with the `property` and `value`
+    pub filter: Option<QueryExpr>,
+    /// Timestamp at which the members are collected, to show the Collection as it was in the past.
+    pub at: Option<i64>,
 }
 
 impl CollectionBuilder {
@@ -54,6 +58,13 @@ impl CollectionBuilder {
         if let Some(val) = &self.value {
             resource.set_propval_string(crate::urls::COLLECTION_VALUE.into(), val, store)?;
         }
+        if let Some(filter) = &self.filter {
+            resource.set_propval_string(
+                crate::urls::COLLECTION_FILTER.into(),
+                &filter.to_json()?,
+                store,
+            )?;
+        }
         if let Some(val) = &self.name {
             resource.set_propval_string(crate::urls::NAME.into(), val, store)?;
         }
@@ -108,6 +119,8 @@ impl CollectionBuilder {
             name: Some(format!("{} collection", path)),
             include_nested: true,
             include_external: false,
+            filter: None,
+            at: None,
         }
     }
 
@@ -154,6 +167,8 @@ pub struct Collection {
     pub include_nested: bool,
     /// Include resources from other servers
     pub include_external: bool,
+    /// Composable filter, which is combined with the `property` and `value`
+    pub filter: Option<QueryExpr>,
 }
 
 /// Sorts a vector or resources by some property.
@@ -218,9 +233,18 @@ impl Collection {
             include_external: collection_builder.include_external,
             include_nested: collection_builder.include_nested,
             for_agent: for_agent.map(|a| a.to_string()),
+            filter: collection_builder.filter.clone(),
         };
 
-        let query_result = store.query(&q)?;
+        let query_result = match collection_builder.at {
+            #[cfg(feature = "db")]
+            Some(timestamp) => crate::plugins::versioning::query_at(store, &q, timestamp)?,
+            #[cfg(not(feature = "db"))]
+            Some(_) => {
+                return Err("Querying Collections at a timestamp requires the `db` feature".into())
+            }
+            None => store.query(&q)?,
+        };
         let members = query_result.subjects;
         let members_nested = Some(query_result.resources);
         let total_items = query_result.count;
@@ -249,6 +273,7 @@ impl Collection {
             name: collection_builder.name,
             include_nested: collection_builder.include_nested,
             include_external: collection_builder.include_external,
+            filter: collection_builder.filter,
         };
         Ok(collection)
     }
@@ -343,6 +368,8 @@ pub fn construct_collection_from_params(
     let mut name = None;
     let mut include_nested = false;
     let mut include_external = false;
+    let mut filter = None;
+    let mut at = None;
 
     if let Ok(val) = resource.get(urls::COLLECTION_PROPERTY) {
         property = Some(val.to_string());
@@ -359,6 +386,9 @@ pub fn construct_collection_from_params(
     if let Ok(val) = resource.get(urls::COLLECTION_INCLUDE_EXTERNAL) {
         include_external = val.to_bool()?;
     }
+    if let Ok(val) = resource.get(urls::COLLECTION_FILTER) {
+        filter = Some(QueryExpr::from_json(&val.to_string())?);
+    }
     for (k, v) in query_params {
         match k.as_ref() {
             "property" => property = Some(v.to_string()),
@@ -369,6 +399,8 @@ pub fn construct_collection_from_params(
             "page_size" => page_size = v.parse::<usize>()?,
             "include_nested" => include_nested = v.parse::<bool>()?,
             "include_external" => include_external = v.parse::<bool>()?,
+            "filter" => filter = Some(QueryExpr::from_json(&v)?),
+            "at" => at = Some(crate::utils::parse_timestamp(&v)?),
             e => {
                 return Err(format!("Invalid query param: {}", e).into());
             }
@@ -385,6 +417,8 @@ pub fn construct_collection_from_params(
         name,
         include_nested,
         include_external,
+        filter,
+        at,
     };
     let collection = Collection::collect_members(store, collection_builder, for_agent)?;
     collection.add_to_resource(resource, store)
@@ -461,6 +495,8 @@ mod test {
             name: Some("Test collection".into()),
             include_nested: false,
             include_external: false,
+            filter: None,
+            at: None,
         };
         let collection = Collection::collect_members(&store, collection_builder, None).unwrap();
         assert!(collection.members.contains(&urls::PROPERTY.into()));
@@ -481,6 +517,8 @@ mod test {
             name: None,
             include_nested: false,
             include_external: false,
+            filter: None,
+            at: None,
         };
         let collection = Collection::collect_members(&store, collection_builder, None).unwrap();
         assert!(collection.members.contains(&urls::PROPERTY.into()));
@@ -507,6 +545,8 @@ mod test {
             // The important bit here
             include_nested: true,
             include_external: false,
+            filter: None,
+            at: None,
         };
         let collection = Collection::collect_members(&store, collection_builder, None).unwrap();
         let first_resource = &collection.members_nested.clone().unwrap()[0];
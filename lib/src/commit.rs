@@ -5,7 +5,7 @@ use std::collections::{HashMap, HashSet};
 use urls::{SET, SIGNER};
 
 use crate::{
-    agents::{decode_base64, encode_base64},
+    agents::{decode_base64, encode_base64, get_keys, AgentKey},
     datatype::DataType,
     errors::AtomicResult,
     hierarchy,
@@ -88,11 +88,43 @@ impl Commit {
     /// Creates, edits or destroys a resource.
     /// Allows for control over which validations should be performed.
     /// Returns the generated Commit, the old Resource and the new Resource.
+    /// The Resource, the Commit and its entry in the commit log are persisted at once, see [Storelike::all_or_nothing].
     #[tracing::instrument(skip(store))]
     pub fn apply_opts(
         &self,
         store: &impl Storelike,
         opts: &CommitOpts,
+    ) -> AtomicResult<CommitResponse> {
+        let commit_response = store.all_or_nothing(|staged| self.apply_staged(staged, opts))?;
+
+        store.handle_commit(&commit_response);
+
+        // AFTER APPLY COMMIT HANDLERS
+        // Commit has been checked and saved.
+        // Here you can add side-effects, such as creating new Commits.
+        #[cfg(feature = "db")]
+        if let Some(resource_new) = &commit_response.resource_new {
+            for class in resource_new.get_classes(store)? {
+                match class.subject.as_str() {
+                    urls::MESSAGE => crate::plugins::chatroom::after_apply_commit_message(
+                        store,
+                        self,
+                        resource_new,
+                    )?,
+                    _other => {}
+                };
+            }
+        }
+
+        Ok(commit_response)
+    }
+
+    /// Checks and applies the Commit, for [Commit::apply_opts].
+    /// The `store` is expected to persist these writes all at once.
+    fn apply_staged(
+        &self,
+        store: &impl Storelike,
+        opts: &CommitOpts,
     ) -> AtomicResult<CommitResponse> {
         let subject_url = url::Url::parse(&self.subject)
             .map_err(|e| format!("Subject '{}' is not a URL. {}", &self.subject, e))?;
@@ -102,27 +134,15 @@ impl Commit {
         }
 
         if opts.validate_signature {
-            let signature = match self.signature.as_ref() {
-                Some(sig) => sig,
-                None => return Err("No signature set".into()),
-            };
-            let pubkey_b64 = store
-                .get_resource(&self.signer)?
-                .get(urls::PUBLIC_KEY)?
-                .to_string();
-            let agent_pubkey = decode_base64(&pubkey_b64)?;
-            let stringified_commit = self.serialize_deterministically_json_ad(store)?;
-            let peer_public_key =
-                ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, agent_pubkey);
-            let signature_bytes = decode_base64(signature)?;
-            peer_public_key
-                .verify(stringified_commit.as_bytes(), &signature_bytes)
-                .map_err(|_e| {
-                    format!(
-                        "Incorrect signature for Commit. This could be due to an error during signing or serialization of the commit. Compare this to the serialized commit in the client: {}",
-                        stringified_commit,
-                    )
-                })?;
+            let key = self.verify_signature(store)?;
+            // Keys that have been rotated or revoked since the Commit was signed can't be used for new Commits
+            if opts.validate_timestamp && !key.is_valid_at(crate::utils::now()) {
+                return Err(format!(
+                    "The key {} that signed this Commit is no longer valid for {}",
+                    key.public_key, self.signer
+                )
+                .into());
+            }
         }
         // Check if the created_at lies in the past
         if opts.validate_timestamp {
@@ -145,13 +165,8 @@ impl Commit {
                 let last_commit = last_commit_val.to_string();
 
                 if let Some(prev_commit) = self.previous_commit.clone() {
-                    // TODO: try auto merge
                     if last_commit != prev_commit {
-                        return Err(format!(
-                            "previousCommit mismatch. Had lastCommit '{}' in Resource {}, but got in Commit '{}'. Perhaps you created the Commit based on an outdated version of the Resource.",
-                            last_commit, subject_url, prev_commit,
-                        )
-                        .into());
+                        self.check_mergeable(store, &prev_commit)?;
                     }
                 } else {
                     return Err(format!("Missing `previousCommit`. Resource {} already exists, and it has a `lastCommit` field, so a `previousCommit` field is required in your Commit.", self.subject).into());
@@ -165,11 +180,19 @@ impl Commit {
         let mut resource_new = self
             .apply_changes(resource_old.clone(), store, false)
             .map_err(|e| format!("Error applying changes to Resource {}. {}", self.subject, e))?;
+        // The rights checks can give `resource_old` a default parent, but without a parent it is not in a Drive
+        let had_parent = resource_old.get(urls::PARENT).is_ok();
 
         if opts.validate_rights {
             let validate_for = opts.validate_for_agent.as_ref().unwrap_or(&self.signer);
             if is_new {
-                hierarchy::check_append(store, &resource_new, validate_for)?;
+                hierarchy::check_audited(
+                    store,
+                    &resource_new,
+                    validate_for,
+                    hierarchy::Right::Append,
+                )?;
+                store.check_drive_quota(None, &resource_new)?;
             } else {
                 // Set a parent only if the rights checks are to be validated.
                 // If there is no explicit parent set on the previous resource, use a default.
@@ -183,7 +206,22 @@ impl Commit {
                     )?;
                 }
                 // This should use the _old_ resource, no the new one, as the new one might maliciously give itself write rights.
-                hierarchy::check_write(store, &resource_old, validate_for)?;
+                hierarchy::check_audited(
+                    store,
+                    &resource_old,
+                    validate_for,
+                    hierarchy::Right::Write,
+                )?;
+                // It may have moved to another Drive, or its file may have grown
+                store.check_drive_quota(had_parent.then_some(&resource_old), &resource_new)?;
+                // Existing Commits of an Agent are verified using its keys, so these can't be rewritten.
+                if resource_old.get(urls::PUBLIC_KEY).is_ok() {
+                    crate::agents::check_key_changes(
+                        &resource_old,
+                        &resource_new,
+                        self.created_at,
+                    )?;
+                }
             }
         };
         // Check if all required props are there
@@ -208,6 +246,7 @@ impl Commit {
                 urls::INVITE => {
                     crate::plugins::invite::before_apply_commit(store, self, &resource_new)?
                 }
+                urls::TOKEN => crate::tokens::before_apply_commit(self)?,
                 _other => {}
             };
         }
@@ -217,8 +256,10 @@ impl Commit {
         if let Some(destroy) = self.destroy {
             if destroy {
                 // Note: the value index is updated before this action, in resource.apply_changes()
+                store.update_drive_usage(had_parent.then_some(&resource_old), None)?;
                 store.remove_resource(&self.subject)?;
                 store.add_resource_opts(&commit_resource, false, opts.update_index, false)?;
+                store.append_to_commit_log(commit_resource.get_subject())?;
                 return Ok(CommitResponse {
                     resource_new: None,
                     resource_old: Some(resource_old),
@@ -228,6 +269,9 @@ impl Commit {
             }
         }
 
+        // Before saving, otherwise the resource is counted twice if the usage of its Drive is not known yet
+        store.update_drive_usage(had_parent.then_some(&resource_old), Some(&resource_new))?;
+
         // We apply the changes again, but this time also update the index
         self.apply_changes(resource_old.clone(), store, opts.update_index)?;
 
@@ -235,32 +279,137 @@ impl Commit {
         store.add_resource_opts(&commit_resource, false, opts.update_index, false)?;
         // Save the resource, but skip updating the index - that has been done in a previous step.
         store.add_resource_opts(&resource_new, false, false, true)?;
+        // Only after the resource is saved, so readers of the commit log see its new version
+        store.append_to_commit_log(commit_resource.get_subject())?;
 
-        let commit_response = CommitResponse {
-            resource_new: Some(resource_new.clone()),
+        Ok(CommitResponse {
+            resource_new: Some(resource_new),
             resource_old: Some(resource_old),
             commit_resource,
             commit_struct: self.clone(),
+        })
+    }
+
+    /// Checks if the signature of this Commit was made by its signer, using a key that was valid when the Commit was created.
+    /// Returns the key that signed it.
+    pub fn verify_signature(&self, store: &impl Storelike) -> AtomicResult<AgentKey> {
+        let signature = match self.signature.as_ref() {
+            Some(sig) => sig,
+            None => return Err("No signature set".into()),
         };
+        let stringified_commit = self.serialize_deterministically_json_ad(store)?;
+        check_signature(store, &self.signer, &stringified_commit, signature, self.created_at).map_err(|e| {
+            format!(
+                "Incorrect signature for Commit: {}. This could be due to an error during signing or serialization of the commit. Compare this to the serialized commit in the client: {}",
+                e, stringified_commit,
+            )
+            .into()
+        })
+    }
 
-        store.handle_commit(&commit_response);
+    /// The properties that are changed by the `set`, `remove` and `push` attributes of this Commit.
+    pub fn changed_properties(&self) -> HashSet<&String> {
+        let mut changed: HashSet<&String> = HashSet::new();
+        changed.extend(self.set.iter().flat_map(|set| set.keys()));
+        changed.extend(self.push.iter().flat_map(|push| push.keys()));
+        changed.extend(self.remove.iter().flatten());
+        changed
+    }
 
-        // AFTER APPLY COMMIT HANDLERS
-        // Commit has been checked and saved.
-        // Here you can add side-effects, such as creating new Commits.
-        #[cfg(feature = "db")]
-        for class in _resource_new_classes {
-            match class.subject.as_str() {
-                urls::MESSAGE => crate::plugins::chatroom::after_apply_commit_message(
-                    store,
-                    self,
-                    &resource_new,
-                )?,
-                _other => {}
-            };
+    /// Creates a [CommitBuilder] that undoes the changes of this Commit.
+    /// `resource_old` is the Resource as it was before this Commit, without properties if the Commit created it (see the `/revert` endpoint).
+    /// Restores destroyed Resources, and destroys Resources that were created by the Commit.
+    /// The `previousCommit` is set to this Commit, so the inverse is refused if the Resource has been changed since.
+    /// Sign it using a Resource without a `lastCommit`, or else the `previousCommit` is overwritten.
+    pub fn inverse(&self, resource_old: &Resource) -> AtomicResult<CommitBuilder> {
+        let mut builder = CommitBuilder::new(self.subject.clone());
+        builder.previous_commit = Some(
+            self.url
+                .clone()
+                .ok_or("The Commit has no URL, so it can not be reverted")?,
+        );
+
+        let old_propvals = resource_old
+            .get_propvals()
+            .iter()
+            .filter(|(prop, _val)| prop.as_str() != urls::LAST_COMMIT);
+        if self.destroy.unwrap_or(false) {
+            for (prop, val) in old_propvals {
+                builder.set(prop.clone(), val.clone());
+            }
+            return Ok(builder);
+        }
+        if old_propvals.count() == 0 {
+            builder.destroy(true);
+            return Ok(builder);
         }
 
-        Ok(commit_response)
+        for prop in self.changed_properties() {
+            match resource_old.get(prop) {
+                Ok(val) => builder.set(prop.clone(), val.clone()),
+                Err(_not_found) => builder.remove(prop.clone()),
+            }
+        }
+        Ok(builder)
+    }
+
+    /// Checks if this Commit can be merged with the Commits that have been applied after its `previousCommit`, which is their common ancestor.
+    /// This is the case if they don't change the same properties.
+    /// Destroying a Resource conflicts with any other change.
+    /// Returns a conflict error that lists the properties that are changed by both.
+    fn check_mergeable(&self, store: &impl Storelike, previous_commit: &str) -> AtomicResult<()> {
+        let mut q = crate::storelike::Query::new_prop_val(urls::SUBJECT, &self.subject);
+        q.sort_by = Some(urls::CREATED_AT.into());
+        let mut history = Vec::new();
+        for commit in store.query(&q)?.resources {
+            history.push((store.get_commit_seq(commit.get_subject())?, commit));
+        }
+        // Sorted in the order in which the Commits were applied, if the Store keeps a commit log.
+        // Commits that are not in the log yet are being applied right now, so they come last, in the order of `createdAt`.
+        history.sort_by_key(|(seq, _commit)| seq.unwrap_or(u64::MAX));
+        let history: Vec<Resource> = history.into_iter().map(|(_seq, commit)| commit).collect();
+        let ancestor_index = history
+            .iter()
+            .position(|commit| commit.get_subject() == previous_commit)
+            .ok_or_else(|| {
+                format!(
+                    "previousCommit '{}' is not part of the history of Resource {}. Perhaps you created the Commit based on a version of the Resource from another server.",
+                    previous_commit, self.subject
+                )
+            })?;
+
+        let mut changed_later: HashSet<String> = HashSet::new();
+        let mut destroyed_later = false;
+        for resource in history.into_iter().skip(ancestor_index + 1) {
+            let commit = Commit::from_resource(resource)?;
+            destroyed_later |= commit.destroy.unwrap_or(false);
+            changed_later.extend(commit.changed_properties().into_iter().cloned());
+        }
+
+        let mut conflicts: Vec<String> = if self.destroy.unwrap_or(false) {
+            changed_later.into_iter().collect()
+        } else if destroyed_later {
+            self.changed_properties().into_iter().cloned().collect()
+        } else {
+            self.changed_properties()
+                .into_iter()
+                .filter(|prop| changed_later.contains(*prop))
+                .cloned()
+                .collect()
+        };
+        if conflicts.is_empty() {
+            return Ok(());
+        }
+        conflicts.sort();
+        Err(crate::AtomicError::conflict(
+            format!(
+                "Commit for {} can not be merged with the Commits applied after its previousCommit '{}', because they change the same properties: {}",
+                self.subject,
+                previous_commit,
+                conflicts.join(", ")
+            ),
+            conflicts,
+        ))
     }
 
     /// Updates the values in the Resource according to the `set`, `remove`, `push`, and `destroy` attributes in the Commit.
@@ -576,17 +725,27 @@ impl CommitBuilder {
     /// Private key is the base64 encoded pkcs8 for the signer.
     /// Sets the `previousCommit` using the `lastCommit`.
     pub fn sign(
+        self,
+        agent: &crate::agents::Agent,
+        store: &impl Storelike,
+        resource: &Resource,
+    ) -> AtomicResult<Commit> {
+        let now = crate::utils::now();
+        self.sign_at(agent, store, resource, now)
+    }
+
+    /// Like [CommitBuilder::sign], but uses `created_at` as the moment of signing.
+    pub fn sign_at(
         mut self,
         agent: &crate::agents::Agent,
         store: &impl Storelike,
         resource: &Resource,
+        created_at: i64,
     ) -> AtomicResult<Commit> {
         if let Ok(last) = resource.get(urls::LAST_COMMIT) {
             self.previous_commit = Some(last.to_string());
         }
-
-        let now = crate::utils::now();
-        sign_at(self, agent, now, store)
+        sign_at(self, agent, created_at, store)
     }
 
     /// Set Property / Value combinations that will either be created or overwritten.
@@ -661,6 +820,35 @@ pub fn sign_message(message: &str, private_key: &str, public_key: &str) -> Atomi
     Ok(encode_base64(signature.as_ref()))
 }
 
+/// Checks if the base64 encoded `signature` of the `message` was created by the `signer` Agent, using a key that was valid at `signed_at`.
+/// Fetches the keys of the signer from the store, and returns the one that matches.
+pub(crate) fn check_signature(
+    store: &impl Storelike,
+    signer: &str,
+    message: &str,
+    signature: &str,
+    signed_at: i64,
+) -> AtomicResult<AgentKey> {
+    let signature_bytes = decode_base64(signature)?;
+    let keys = get_keys(&store.get_resource(signer)?)?;
+    for key in keys.into_iter().filter(|key| key.is_valid_at(signed_at)) {
+        let agent_pubkey = decode_base64(&key.public_key)?;
+        let peer_public_key =
+            ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, agent_pubkey);
+        if peer_public_key
+            .verify(message.as_bytes(), &signature_bytes)
+            .is_ok()
+        {
+            return Ok(key);
+        }
+    }
+    Err(format!(
+        "Signature does not match a key of {} that was valid at {}",
+        signer, signed_at
+    )
+    .into())
+}
+
 /// The amount of milliseconds that a Commit signature is valid for.
 const ACCEPTABLE_TIME_DIFFERENCE: i64 = 10000;
 
@@ -1,15 +1,19 @@
 //! Persistent, ACID compliant, threadsafe to-disk store.
-//! Powered by Sled - an embedded database.
+//! Powered by a [KeyValueBackend], which is Sled - an embedded database - by default.
 
+pub mod backend;
+pub mod memory_backend;
 mod migrations;
 mod prop_val_sub_index;
 mod query_index;
+mod query_planner;
+pub mod sled_backend;
 #[cfg(test)]
 pub mod test;
 mod val_prop_sub_index;
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     sync::{Arc, Mutex},
 };
 
@@ -17,17 +21,23 @@ use tracing::{info, instrument};
 
 use crate::{
     atoms::IndexAtom,
+    audit::{AuditConfig, AuditEvent, AuditFilter},
     commit::CommitResponse,
     db::{query_index::NO_VALUE, val_prop_sub_index::find_in_val_prop_sub_index},
     endpoints::{default_endpoints, Endpoint, HandleGetContext},
     errors::{AtomicError, AtomicResult},
+    quotas::{DriveQuota, DriveUsage},
     resources::PropVals,
     storelike::{Query, QueryResult, Storelike},
+    urls,
     values::SortableValue,
     Atom, Resource,
 };
 
+pub use self::backend::{BackendConfig, KeyValueBackend, KeyValueTree};
+
 use self::{
+    backend::{Batch, KeyValue, KeyValueIterator, Tree},
     migrations::migrate_maybe,
     prop_val_sub_index::{
         add_atom_to_prop_val_sub_index, find_in_prop_val_sub_index,
@@ -37,19 +47,113 @@ use self::{
         check_if_atom_matches_watched_query_filters, query_indexed, update_indexed_member,
         IndexIterator, QueryFilter,
     },
+    query_planner::query_planned,
     val_prop_sub_index::{add_atom_to_reference_index, remove_atom_from_reference_index},
 };
 
 // A function called by the Store when a Commit is accepted
 type HandleCommit = Box<dyn Fn(&CommitResponse) + Send + Sync>;
 
+/// Writes that are collected while applying multiple changes all-or-nothing, see [Db::all_or_nothing].
+#[derive(Default)]
+struct StagedWrites {
+    /// Maps the name of a [KeyValueTree] to its changed keys. A `None` value means that the key is removed.
+    trees: HashMap<String, BTreeMap<Vec<u8>, Option<Vec<u8>>>>,
+    /// Commits that are appended to the `commit_log`. They get their sequence numbers when they are persisted.
+    commit_log: Vec<String>,
+    /// The usage that is removed from and added to every Drive. These are applied to the usage that is persisted at that moment, see [Db::drive_usage].
+    drive_usage: HashMap<String, (DriveUsage, DriveUsage)>,
+}
+
+/// A key of a [KeyValueTree] and its staged value. A `None` value means that the key is removed.
+type StagedChange = (Vec<u8>, Option<Vec<u8>>);
+
+/// Iterates over a [KeyValueTree] and the changes to it that are staged, see [Db::all_or_nothing].
+/// Staged changes replace the persisted values of their keys, and removed keys are skipped.
+struct StagedIter {
+    tree: std::iter::Peekable<KeyValueIterator>,
+    staged: std::iter::Peekable<std::vec::IntoIter<StagedChange>>,
+    reverse: bool,
+}
+
+impl StagedIter {
+    /// `staged` is sorted by key.
+    fn overlay(
+        tree: KeyValueIterator,
+        mut staged: Vec<StagedChange>,
+        reverse: bool,
+    ) -> KeyValueIterator {
+        if staged.is_empty() {
+            return tree;
+        }
+        if reverse {
+            staged.reverse();
+        }
+        Box::new(StagedIter {
+            tree: tree.peekable(),
+            staged: staged.into_iter().peekable(),
+            reverse,
+        })
+    }
+}
+
+impl Iterator for StagedIter {
+    type Item = AtomicResult<KeyValue>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let staged_key = match self.staged.peek() {
+                Some((key, _value)) => key,
+                None => return self.tree.next(),
+            };
+            if let Some(Ok((tree_key, _value))) = self.tree.peek() {
+                let order = if self.reverse {
+                    tree_key.cmp(staged_key)
+                } else {
+                    staged_key.cmp(tree_key)
+                };
+                match order {
+                    std::cmp::Ordering::Greater => return self.tree.next(),
+                    // The staged change replaces the persisted value
+                    std::cmp::Ordering::Equal => {
+                        self.tree.next();
+                    }
+                    std::cmp::Ordering::Less => {}
+                }
+            } else if self.tree.peek().is_some() {
+                return self.tree.next();
+            }
+            if let Some((key, Some(value))) = self.staged.next() {
+                return Some(Ok((key, value)));
+            }
+        }
+    }
+}
+
+/// Data that rights checks need for almost every request.
+/// Entries are removed when their resource changes.
+#[derive(Default)]
+struct RightsCache {
+    /// The members of Groups, and the absence of members for other resources. See [Storelike::get_group_members].
+    group_members: HashMap<String, Vec<String>>,
+    /// The `propertyRead` of Properties. See [Storelike::get_property_readers].
+    property_readers: HashMap<String, Option<Vec<String>>>,
+}
+
+impl RightsCache {
+    fn remove(&mut self, subject: &str) {
+        self.group_members.remove(subject);
+        self.property_readers.remove(subject);
+    }
+}
+
 /// Inside the reference_index, each value is mapped to this type.
 /// The String on the left represents a Property URL, and the second one is the set of subjects.
 pub type PropSubjectMap = HashMap<String, HashSet<String>>;
 
 /// The Db is a persistent on-disk Atomic Data store.
 /// It's an implementation of [Storelike].
-/// It uses [sled::Tree]s as Key Value stores.
+/// It uses [KeyValueTree]s from a [KeyValueBackend] as Key Value stores.
 /// It stores [Resource]s as [PropVals]s by their subject as key.
 /// It builds a value index for performant [Query]s.
 /// It keeps track of Queries and updates their index when [crate::Commit]s are applied.
@@ -60,39 +164,101 @@ pub struct Db {
     /// The Key-Value store that contains all data.
     /// Resources can be found using their Subject.
     /// Try not to use this directly, but use the Trees.
-    db: sled::Db,
+    db: Arc<dyn KeyValueBackend>,
     default_agent: Arc<Mutex<Option<crate::agents::Agent>>>,
     /// Stores all resources. The Key is the Subject as a `string.as_bytes()`, the value a [PropVals]. Propvals must be serialized using [bincode].
-    resources: sled::Tree,
+    resources: Tree,
     /// Index of all Atoms, sorted by {Value}-{Property}-{Subject}.
     /// See [reference_index]
-    reference_index: sled::Tree,
+    reference_index: Tree,
     /// Index sorted by property + value.
     /// Used for TPF queries where the property is known.
-    prop_val_sub_index: sled::Tree,
+    prop_val_sub_index: Tree,
     /// Stores the members of Collections, easily sortable.
-    query_index: sled::Tree,
+    query_index: Tree,
     /// A list of all the Collections currently being used. Is used to update `query_index`.
-    watched_queries: sled::Tree,
+    watched_queries: Tree,
+    /// Versions of Resources, keyed by the Commit after which they were created.
+    /// See [crate::plugins::versioning::SnapshotCache].
+    version_snapshots: Tree,
+    /// For every primary server that this Db replicates, the sequence number of the last replicated entry in its commit log.
+    /// See [crate::replication].
+    replication_cursors: Tree,
+    /// Append-only log of all applied Commits. The key is a sequence number (a big-endian u64), the value the subject of the Commit.
+    /// See [Db::commit_log].
+    commit_log: Tree,
+    /// The sequence number of every Commit in the `commit_log`, by the subject of the Commit. See [Storelike::get_commit_seq].
+    commit_seqs: Tree,
+    /// The sequence number for the next entry in the `commit_log`.
+    /// Locked while appending, so entries are persisted in the order of their sequence numbers, without gaps.
+    next_commit_seq: Arc<Mutex<u64>>,
+    /// For every index outside the Db (e.g. the search index of the server), the sequence number of the last entry in the `commit_log` that it has processed.
+    /// Together with the `commit_log`, this forms a durable queue of changes that still have to be indexed. See [Db::get_index_cursor].
+    index_cursors: Tree,
+    /// Append-only log of rights checks, if enabled in `audit`. The key is a sequence number (a big-endian u64), the value a JSON [AuditEvent].
+    /// See [crate::audit].
+    audit_log: Tree,
+    /// The sequence numbers of the `audit_log` by agent and by subject, so these filters don't need to read the entire log. See [audit_index_key].
+    audit_index: Tree,
+    /// The sequence number for the next entry in the `audit_log`.
+    /// Locked while the timestamp of the entry is taken, so the timestamps increase with the sequence numbers.
+    next_audit_seq: Arc<Mutex<u64>>,
+    /// If `None`, rights checks are not recorded in the `audit_log`.
+    audit: Option<AuditConfig>,
+    /// How much of its quota every Drive uses. The key is the subject of the Drive, the value a JSON [DriveUsage].
+    /// Cleared in [Db::set_drive_quota], and calculated again when a Drive is first used.
+    drive_usage: Tree,
+    /// Locked while the usage of a Drive is read and changed, so concurrent Commits don't lose each other's changes.
+    drive_usage_lock: Arc<Mutex<()>>,
+    /// If `None`, Drives have no storage quota. See [crate::quotas].
+    drive_quota: Option<DriveQuota>,
+    /// Group members and Property read rules that have been used in rights checks.
+    rights_cache: Arc<Mutex<RightsCache>>,
     /// The address where the db will be hosted, e.g. http://localhost/
     server_url: String,
     /// Endpoints are checked whenever a resource is requested. They calculate (some properties of) the resource and return it.
     endpoints: Vec<Endpoint>,
     /// Function called whenever a Commit is applied.
     on_commit: Option<Arc<HandleCommit>>,
+    /// If set, writes to the Trees are collected here instead of being persisted directly.
+    /// See [Db::all_or_nothing].
+    staged_writes: Option<Arc<Mutex<StagedWrites>>>,
 }
 
 impl Db {
-    /// Creates a new store at the specified path, or opens the store if it already exists.
+    /// Creates a new store using the configured backend, e.g. at the specified path, or opens the store if it already exists.
     /// The server_url is the domain where the db will be hosted, e.g. http://localhost/
     /// It is used for distinguishing locally defined items from externally defined ones.
-    pub fn init(path: &std::path::Path, server_url: String) -> AtomicResult<Db> {
-        let db = sled::open(path).map_err(|e|format!("Failed opening DB at this location: {:?} . Is another instance of Atomic Server running? {}", path, e))?;
+    pub fn init(backend: &BackendConfig, server_url: String) -> AtomicResult<Db> {
+        Db::init_with_backend(backend.open()?, server_url)
+    }
+
+    /// Creates a new store using a custom [KeyValueBackend].
+    pub fn init_with_backend(db: Arc<dyn KeyValueBackend>, server_url: String) -> AtomicResult<Db> {
         let resources = db.open_tree("resources_v1").map_err(|e|format!("Failed building resources. Your DB might be corrupt. Go back to a previous version and export your data. {}", e))?;
         let reference_index = db.open_tree("reference_index_v1")?;
         let query_index = db.open_tree("members_index")?;
         let prop_val_sub_index = db.open_tree("prop_val_sub_index")?;
         let watched_queries = db.open_tree("watched_queries")?;
+        let version_snapshots = db.open_tree("version_snapshots")?;
+        let replication_cursors = db.open_tree("replication_cursors")?;
+        let tree_names = db.tree_names()?;
+        let has_commit_log = tree_names.iter().any(|name| name == "commit_log");
+        let has_commit_seqs = tree_names.iter().any(|name| name == "commit_seqs");
+        let commit_log = db.open_tree("commit_log")?;
+        let commit_seqs = db.open_tree("commit_seqs")?;
+        let next_commit_seq = match commit_log.range(&[], &COMMIT_SEQ_END, true).next() {
+            Some(last) => seq_from_key(&last?.0)? + 1,
+            None => 1,
+        };
+        let index_cursors = db.open_tree("index_cursors")?;
+        let audit_log = db.open_tree("audit_log")?;
+        let next_audit_seq = match audit_log.range(&[], &COMMIT_SEQ_END, true).next() {
+            Some(last) => seq_from_key(&last?.0)? + 1,
+            None => 1,
+        };
+        let audit_index = db.open_tree("audit_index")?;
+        let drive_usage = db.open_tree("drive_usage")?;
         let store = Db {
             db,
             default_agent: Arc::new(Mutex::new(None)),
@@ -102,24 +268,41 @@ impl Db {
             prop_val_sub_index,
             server_url,
             watched_queries,
+            version_snapshots,
+            replication_cursors,
+            commit_log,
+            commit_seqs,
+            next_commit_seq: Arc::new(Mutex::new(next_commit_seq)),
+            index_cursors,
+            audit_log,
+            audit_index,
+            next_audit_seq: Arc::new(Mutex::new(next_audit_seq)),
+            audit: None,
+            drive_usage,
+            drive_usage_lock: Arc::new(Mutex::new(())),
+            drive_quota: None,
+            rights_cache: Arc::new(Mutex::new(RightsCache::default())),
             endpoints: default_endpoints(),
             on_commit: None,
+            staged_writes: None,
         };
         migrate_maybe(&store).map(|e| format!("Error during migration of database: {:?}", e))?;
+        if !has_commit_log {
+            migrations::build_commit_log(&store)?;
+        } else if !has_commit_seqs {
+            migrations::build_commit_seqs(&store)?;
+        }
         crate::populate::populate_base_models(&store)
             .map_err(|e| format!("Failed to populate base models. {}", e))?;
         Ok(store)
     }
 
-    /// Create a temporary Db in `.temp/db/{id}`. Useful for testing.
+    /// Create a temporary, in-memory Db. Useful for testing.
+    /// Every call returns a new, separate Db. The `id` is only used for logging.
     /// Populates the database, creates a default agent, and sets the server_url to "http://localhost/".
     pub fn init_temp(id: &str) -> AtomicResult<Db> {
-        let tmp_dir_path = format!(".temp/db/{}", id);
-        let _try_remove_existing = std::fs::remove_dir_all(&tmp_dir_path);
-        let store = Db::init(
-            std::path::Path::new(&tmp_dir_path),
-            "https://localhost".into(),
-        )?;
+        tracing::debug!("Creating temporary Db {}", id);
+        let store = Db::init(&BackendConfig::Memory, "https://localhost".into())?;
         let agent = store.create_agent(None)?;
         store.set_default_agent(agent);
         store.populate()?;
@@ -146,7 +329,419 @@ impl Db {
     #[instrument(skip(self))]
     fn set_propvals(&self, subject: &str, propvals: &PropVals) -> AtomicResult<()> {
         let resource_bin = bincode::serialize(propvals)?;
-        self.resources.insert(subject.as_bytes(), resource_bin)?;
+        self.tree_insert(&self.resources, subject.as_bytes(), &resource_bin)?;
+        self.rights_cache.lock().unwrap().remove(subject);
+        Ok(())
+    }
+
+    /// Inserts a key in one of the Trees.
+    /// If the Db is staging its writes (see [Db::all_or_nothing]), the change is not persisted yet.
+    pub(crate) fn tree_insert(&self, tree: &Tree, key: &[u8], value: &[u8]) -> AtomicResult<()> {
+        match &self.staged_writes {
+            Some(staged) => {
+                staged
+                    .lock()
+                    .unwrap()
+                    .trees
+                    .entry(tree.name().to_string())
+                    .or_default()
+                    .insert(key.to_vec(), Some(value.to_vec()));
+            }
+            None => {
+                tree.insert(key, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes a key from one of the Trees.
+    /// If the Db is staging its writes (see [Db::all_or_nothing]), the change is not persisted yet.
+    pub(crate) fn tree_remove(&self, tree: &Tree, key: &[u8]) -> AtomicResult<()> {
+        match &self.staged_writes {
+            Some(staged) => {
+                staged
+                    .lock()
+                    .unwrap()
+                    .trees
+                    .entry(tree.name().to_string())
+                    .or_default()
+                    .insert(key.to_vec(), None);
+            }
+            None => {
+                tree.remove(key)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a key from one of the Trees, including the changes that are staged.
+    fn tree_get(&self, tree: &Tree, key: &[u8]) -> AtomicResult<Option<Vec<u8>>> {
+        if let Some(staged) = &self.staged_writes {
+            if let Some(found) = staged
+                .lock()
+                .unwrap()
+                .trees
+                .get(tree.name())
+                .and_then(|keys| keys.get(key))
+            {
+                return Ok(found.clone());
+            }
+        }
+        tree.get(key)
+    }
+
+    /// Iterates over the keys of one of the Trees that start with `prefix`, including the changes that are staged.
+    pub(crate) fn tree_scan_prefix(&self, tree: &Tree, prefix: &[u8]) -> KeyValueIterator {
+        let staged = self.staged_changes(tree, prefix, |key| key.starts_with(prefix));
+        StagedIter::overlay(tree.scan_prefix(prefix), staged, false)
+    }
+
+    /// Iterates over the keys of one of the Trees from `start` (inclusive) to `end` (exclusive), including the changes that are staged.
+    pub(crate) fn tree_range(
+        &self,
+        tree: &Tree,
+        start: &[u8],
+        end: &[u8],
+        reverse: bool,
+    ) -> KeyValueIterator {
+        let staged = self.staged_changes(tree, start, |key| key < end);
+        StagedIter::overlay(tree.range(start, end, reverse), staged, reverse)
+    }
+
+    /// The staged changes of one of the Trees, from `start` as long as `in_range` holds, sorted by key.
+    fn staged_changes(
+        &self,
+        tree: &Tree,
+        start: &[u8],
+        in_range: impl Fn(&[u8]) -> bool,
+    ) -> Vec<StagedChange> {
+        let staged = match &self.staged_writes {
+            Some(staged) => staged.lock().unwrap(),
+            None => return Vec::new(),
+        };
+        match staged.trees.get(tree.name()) {
+            Some(keys) => keys
+                .range(start.to_vec()..)
+                .take_while(|(key, _value)| in_range(key))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns the sequence number of the last entry in the commit log of the `primary` server that was replicated.
+    pub fn get_replication_cursor(&self, primary: &str) -> AtomicResult<Option<u64>> {
+        match self.tree_get(&self.replication_cursors, primary.as_bytes())? {
+            Some(bin) => Ok(Some(bincode::deserialize(&bin)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Stores the sequence number of the last entry in the commit log of the `primary` server that was replicated.
+    pub fn set_replication_cursor(&self, primary: &str, seq: u64) -> AtomicResult<()> {
+        self.tree_insert(
+            &self.replication_cursors,
+            primary.as_bytes(),
+            &bincode::serialize(&seq)?,
+        )
+    }
+
+    /// Returns up to `limit` entries of the commit log that come after the `since` sequence number, as `(sequence, commit subject)` pairs.
+    /// Sequence numbers start at 1 and have no gaps. An entry is only visible after all entries before it, so readers can safely continue after the last one they have seen.
+    /// Does not check rights.
+    pub fn commit_log(&self, since: u64, limit: usize) -> AtomicResult<Vec<(u64, String)>> {
+        let start = since.saturating_add(1).to_be_bytes();
+        self.commit_log
+            .range(&start, &COMMIT_SEQ_END, false)
+            .take(limit)
+            .map(|item| {
+                let (key, subject) = item?;
+                let subject = String::from_utf8(subject)
+                    .map_err(|e| format!("Invalid subject in commit log. {}", e))?;
+                Ok((seq_from_key(&key)?, subject))
+            })
+            .collect()
+    }
+
+    /// Returns the sequence number of the last entry in the [Db::commit_log] that the `index` has processed, or `0` if it has not processed any.
+    /// The Commits after it still have to be indexed.
+    pub fn get_index_cursor(&self, index: &str) -> AtomicResult<u64> {
+        match self.tree_get(&self.index_cursors, index.as_bytes())? {
+            Some(bin) => Ok(bincode::deserialize(&bin)?),
+            None => Ok(0),
+        }
+    }
+
+    /// Stores the sequence number of the last entry in the [Db::commit_log] that the `index` has processed.
+    /// Call this after the changes of these Commits are persisted in the index, so they are processed again after a crash.
+    pub fn set_index_cursor(&self, index: &str, seq: u64) -> AtomicResult<()> {
+        self.tree_insert(
+            &self.index_cursors,
+            index.as_bytes(),
+            &bincode::serialize(&seq)?,
+        )
+    }
+
+    /// Returns the sequence number of the last entry in the [Db::commit_log], or `0` if it is empty.
+    pub fn last_commit_seq(&self) -> AtomicResult<u64> {
+        match self.commit_log.range(&[], &COMMIT_SEQ_END, true).next() {
+            Some(last) => seq_from_key(&last?.0),
+            None => Ok(0),
+        }
+    }
+
+    /// Enables or disables the audit log, see [crate::audit].
+    pub fn set_audit(&mut self, config: Option<AuditConfig>) {
+        self.audit = config;
+    }
+
+    /// Returns up to `limit` events from the audit log that come after the `since` sequence number and match the `filter`, as `(sequence, event)` pairs.
+    /// Filtering by `agent` or `subject` only reads the matching events, and `from` and `until` limit the part of the log that is read.
+    /// Does not check rights.
+    pub fn audit_events(
+        &self,
+        since: u64,
+        limit: usize,
+        filter: &AuditFilter,
+    ) -> AtomicResult<Vec<(u64, AuditEvent)>> {
+        let mut start = since.saturating_add(1);
+        if let Some(from) = filter.from {
+            start = start.max(self.audit_seq_at(from)?);
+        }
+        let indexed = match (&filter.agent, &filter.subject) {
+            (Some(agent), _) => Some(audit_index_key(AUDIT_BY_AGENT, agent, None)),
+            (None, Some(subject)) => Some(audit_index_key(AUDIT_BY_SUBJECT, subject, None)),
+            (None, None) => None,
+        };
+        let entries: Box<dyn Iterator<Item = AtomicResult<(u64, Vec<u8>)>>> =
+            match indexed {
+                Some(prefix) => {
+                    let range_start = [prefix.as_slice(), &start.to_be_bytes()].concat();
+                    let range_end = [prefix.as_slice(), &COMMIT_SEQ_END].concat();
+                    Box::new(self.audit_index.range(&range_start, &range_end, false).map(
+                        move |item| {
+                            let (key, _) = item?;
+                            let seq = seq_from_key(&key[prefix.len()..])?;
+                            let value = self
+                                .audit_log
+                                .get(&seq.to_be_bytes())?
+                                .ok_or("Indexed event is missing from the audit log")?;
+                            Ok((seq, value))
+                        },
+                    ))
+                }
+                None => Box::new(
+                    self.audit_log
+                        .range(&start.to_be_bytes(), &COMMIT_SEQ_END, false)
+                        .map(|item| {
+                            let (key, value) = item?;
+                            Ok((seq_from_key(&key)?, value))
+                        }),
+                ),
+            };
+        let mut events = Vec::new();
+        for entry in entries {
+            if events.len() >= limit {
+                break;
+            }
+            let (seq, value) = entry?;
+            let event = parse_audit_event(&value)?;
+            // Timestamps increase with the sequence numbers, so no later event matches
+            if matches!(filter.until, Some(until) if event.timestamp >= until) {
+                break;
+            }
+            if filter.matches(&event) {
+                events.push((seq, event));
+            }
+        }
+        Ok(events)
+    }
+
+    /// The sequence number of the first event in the audit log that was recorded at or after `timestamp`.
+    /// Timestamps increase with the sequence numbers, so this is a binary search.
+    fn audit_seq_at(&self, timestamp: i64) -> AtomicResult<u64> {
+        let mut low = 0;
+        let mut high = *self.next_audit_seq.lock().unwrap();
+        while low < high {
+            let mid = low + (high - low) / 2;
+            // There are gaps in the log, where events were pruned or failed to be written
+            match self
+                .audit_log
+                .range(&mid.to_be_bytes(), &COMMIT_SEQ_END, false)
+                .next()
+            {
+                Some(item) => {
+                    let (key, value) = item?;
+                    if parse_audit_event(&value)?.timestamp < timestamp {
+                        low = seq_from_key(&key)? + 1;
+                    } else {
+                        high = mid;
+                    }
+                }
+                None => high = mid,
+            }
+        }
+        Ok(low)
+    }
+
+    /// Removes the events that are older than the `retention` of the audit log. Returns the amount of removed events.
+    pub fn prune_audit_log(&self) -> AtomicResult<usize> {
+        let retention = match self.audit.as_ref().and_then(|a| a.retention) {
+            Some(retention) => retention,
+            None => return Ok(0),
+        };
+        let cutoff = crate::utils::now() - retention;
+        let mut removed = 0;
+        // Events are appended in order, so the old ones are at the start
+        for item in self.audit_log.iter() {
+            let (key, value) = item?;
+            let event = parse_audit_event(&value)?;
+            if event.timestamp >= cutoff {
+                break;
+            }
+            let seq = seq_from_key(&key)?;
+            let mut log_batch = Batch::default();
+            log_batch.remove(&key);
+            let mut index_batch = Batch::default();
+            index_batch.remove(&audit_index_key(AUDIT_BY_AGENT, &event.agent, Some(seq)));
+            index_batch.remove(&audit_index_key(
+                AUDIT_BY_SUBJECT,
+                &event.subject,
+                Some(seq),
+            ));
+            self.db.apply_transaction(&[
+                (self.audit_log.name().to_string(), log_batch),
+                (self.audit_index.name().to_string(), index_batch),
+            ])?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    /// Writes the events of the audit log that match the `filter` as JSON Lines, oldest first. Returns the amount of written events.
+    pub fn export_audit_jsonl(
+        &self,
+        writer: &mut dyn std::io::Write,
+        filter: &AuditFilter,
+    ) -> AtomicResult<usize> {
+        let mut count = 0;
+        for item in self.audit_log.iter() {
+            let (_key, value) = item?;
+            let event = parse_audit_event(&value)?;
+            if !filter.matches(&event) {
+                continue;
+            }
+            writer
+                .write_all(&value)
+                .and_then(|_| writer.write_all(b"\n"))
+                .map_err(|e| format!("Failed to write audit log. {}", e))?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Enables or disables the storage quotas of Drives, see [crate::quotas].
+    /// Forgets the stored usage of all Drives, so it is calculated again with the current resources.
+    pub fn set_drive_quota(&mut self, quota: Option<DriveQuota>) -> AtomicResult<()> {
+        self.drive_usage.clear()?;
+        self.drive_quota = quota;
+        Ok(())
+    }
+
+    /// Returns how much of its quota the Drive uses, including the changes that are staged.
+    /// If this is not known yet, counts the resources of all Drives, which is slow for large Dbs.
+    pub fn drive_usage(&self, drive: &str) -> AtomicResult<DriveUsage> {
+        let usage = {
+            let _lock = self.drive_usage_lock.lock().unwrap();
+            self.persisted_drive_usage(drive)?
+        };
+        let staged = self
+            .staged_writes
+            .as_ref()
+            .and_then(|staged| staged.lock().unwrap().drive_usage.get(drive).copied());
+        Ok(match staged {
+            Some((removed, added)) => usage.subtract(&removed).add(&added),
+            None => usage,
+        })
+    }
+
+    /// Returns the usage of the Drive that is persisted, without the changes that are staged.
+    /// Lock the `drive_usage_lock` while calling this.
+    fn persisted_drive_usage(&self, drive: &str) -> AtomicResult<DriveUsage> {
+        if self.staged_writes.is_some() {
+            // The staged resources are counted when their changes are persisted
+            let persisted = Db {
+                staged_writes: None,
+                ..self.clone()
+            };
+            return persisted.persisted_drive_usage(drive);
+        }
+        if let Some(found) = self.tree_get(&self.drive_usage, drive.as_bytes())? {
+            return serde_json::from_slice(&found)
+                .map_err(|e| format!("Invalid usage of Drive {}. {}", drive, e).into());
+        }
+        let mut usages: HashMap<String, DriveUsage> = HashMap::new();
+        usages.insert(drive.into(), DriveUsage::default());
+        // The nearest Drive of every parent, as many resources share a parent
+        let mut drives_of_parents: HashMap<String, Option<String>> = HashMap::new();
+        for resource in self.all_resources(true) {
+            let parent = match resource.get(urls::PARENT) {
+                Ok(parent) => parent.to_string(),
+                Err(_) => continue,
+            };
+            let found = drives_of_parents
+                .entry(parent.clone())
+                .or_insert_with(|| crate::quotas::nearest_drive(self, &parent));
+            if let Some(found) = found {
+                let usage = usages.entry(found.clone()).or_default();
+                *usage = usage.add(&DriveUsage::of_resource(&resource));
+            }
+        }
+        for (subject, usage) in &usages {
+            self.set_drive_usage(subject, usage)?;
+        }
+        Ok(usages[drive])
+    }
+
+    /// Throws a [crate::AtomicErrorType::LimitExceeded] if adding `added` to the Drive of `parent` exceeds its quota.
+    /// Does nothing if quotas are disabled, or if `parent` is not in a Drive.
+    pub fn check_drive_quota_for(&self, parent: &str, added: &DriveUsage) -> AtomicResult<()> {
+        let quota = match &self.drive_quota {
+            Some(quota) => quota,
+            None => return Ok(()),
+        };
+        match crate::quotas::nearest_drive(self, parent) {
+            Some(drive) => quota.check(&drive, &self.drive_usage(&drive)?, added),
+            None => Ok(()),
+        }
+    }
+
+    fn set_drive_usage(&self, drive: &str, usage: &DriveUsage) -> AtomicResult<()> {
+        let json = serde_json::to_vec(usage).map_err(|e| AtomicError::from(e.to_string()))?;
+        self.tree_insert(&self.drive_usage, drive.as_bytes(), &json)
+    }
+
+    /// Subtracts `removed` from the usage of the Drive, and adds `added`.
+    /// If the Db is staging its writes (see [Db::all_or_nothing]), the change is applied when it is persisted.
+    fn change_drive_usage(
+        &self,
+        drive: &str,
+        removed: &DriveUsage,
+        added: &DriveUsage,
+    ) -> AtomicResult<()> {
+        match &self.staged_writes {
+            Some(staged) => {
+                let mut staged = staged.lock().unwrap();
+                let change = staged.drive_usage.entry(drive.into()).or_default();
+                change.0 = change.0.add(removed);
+                change.1 = change.1.add(added);
+            }
+            None => {
+                let _lock = self.drive_usage_lock.lock().unwrap();
+                let usage = self.persisted_drive_usage(drive)?;
+                self.set_drive_usage(drive, &usage.subtract(removed).add(added))?;
+            }
+        }
         Ok(())
     }
 
@@ -157,12 +752,11 @@ impl Db {
     }
 
     /// Finds resource by Subject, return PropVals HashMap
-    /// Deals with the binary API of Sled
+    /// Deals with the binary API of the [KeyValueBackend]
     #[instrument(skip(self))]
     fn get_propvals(&self, subject: &str) -> AtomicResult<PropVals> {
         let propval_maybe = self
-            .resources
-            .get(subject.as_bytes())
+            .tree_get(&self.resources, subject.as_bytes())
             .map_err(|e| format!("Can't open {} from store: {}", subject, e))?;
         match propval_maybe.as_ref() {
             Some(binpropval) => {
@@ -191,8 +785,8 @@ impl Db {
         Ok(())
     }
 
-    fn map_sled_item_to_resource(
-        item: Result<(sled::IVec, sled::IVec), sled::Error>,
+    fn map_item_to_resource(
+        item: AtomicResult<KeyValue>,
         self_url: String,
         include_external: bool,
     ) -> Option<Resource> {
@@ -311,6 +905,35 @@ impl Storelike for Db {
         &self.server_url
     }
 
+    fn get_group_members(&self, subject: &str) -> AtomicResult<Vec<String>> {
+        // The lock is held while reading, so a change of the resource can't be overwritten by an outdated entry
+        let mut cache = self.rights_cache.lock().unwrap();
+        if let Some(members) = cache.group_members.get(subject) {
+            return Ok(members.clone());
+        }
+        let members = match self.get_propvals(subject) {
+            Ok(propvals) => crate::hierarchy::group_members(&propvals)?,
+            Err(_) => Vec::new(),
+        };
+        cache.group_members.insert(subject.into(), members.clone());
+        Ok(members)
+    }
+
+    fn get_property_readers(&self, property: &str) -> AtomicResult<Option<Vec<String>>> {
+        let mut cache = self.rights_cache.lock().unwrap();
+        if let Some(readers) = cache.property_readers.get(property) {
+            return Ok(readers.clone());
+        }
+        let readers = match self.get_propvals(property) {
+            Ok(propvals) => crate::hierarchy::property_readers(&propvals)?,
+            Err(_) => None,
+        };
+        cache
+            .property_readers
+            .insert(property.into(), readers.clone());
+        Ok(readers)
+    }
+
     // Since the DB is often also the server, this should make sense.
     // Some edge cases might appear later on (e.g. a slave DB that only stores copies?)
     fn get_self_url(&self) -> Option<String> {
@@ -326,17 +949,20 @@ impl Storelike for Db {
 
     #[instrument(skip(self))]
     fn get_resource(&self, subject: &str) -> AtomicResult<Resource> {
-        let propvals = self.get_propvals(subject);
-
-        match propvals {
-            Ok(propvals) => {
-                let resource = crate::resources::Resource::from_propvals(propvals, subject.into());
-                Ok(resource)
-            }
+        match self.get_resource_local(subject) {
+            Ok(resource) => Ok(resource),
             Err(e) => self.handle_not_found(subject, e),
         }
     }
 
+    fn get_resource_local(&self, subject: &str) -> AtomicResult<Resource> {
+        let propvals = self.get_propvals(subject)?;
+        Ok(crate::resources::Resource::from_propvals(
+            propvals,
+            subject.into(),
+        ))
+    }
+
     #[instrument(skip(self))]
     fn get_resource_extended(
         &self,
@@ -347,6 +973,12 @@ impl Storelike for Db {
         let url_span = tracing::span!(tracing::Level::TRACE, "URL parse").entered();
         // This might add a trailing slash
         let url = url::Url::parse(subject)?;
+        // Point in time for which a version of the resource is requested
+        let at = url
+            .query_pairs()
+            .find(|(k, _v)| k == "at")
+            .map(|(_k, v)| crate::utils::parse_timestamp(&v))
+            .transpose()?;
 
         let mut removed_query_params = {
             let mut url_altered = url.clone();
@@ -374,24 +1006,53 @@ impl Storelike for Db {
                         store: self,
                         for_agent,
                     };
-                    (handle)(context).map_err(|e| {
-                        format!("Error handling {} Endpoint: {}", endpoint.shortname, e)
+                    (handle)(context).map_err(|e| AtomicError {
+                        message: format!(
+                            "Error handling {} Endpoint: {}",
+                            endpoint.shortname, e.message
+                        ),
+                        ..e
                     })?
                 } else {
                     endpoint.to_resource(self)?
                 };
                 // Extended resources must always return the requested subject as their own subject
                 resource.set_subject(subject.into());
+                if let Some(agent) = for_agent {
+                    crate::hierarchy::remove_hidden_properties(self, &mut resource, agent)?;
+                }
                 return Ok(resource.to_owned());
             }
         }
         endpoint_span.exit();
 
         let dynamic_span = tracing::span!(tracing::Level::TRACE, "Dynamic").entered();
-        let mut resource = self.get_resource(&removed_query_params)?;
+        let current = self.get_resource(&removed_query_params);
+        let is_collection = current
+            .as_ref()
+            .ok()
+            .and_then(|r| r.get(crate::urls::IS_A).ok())
+            .map(|classes| {
+                classes.contains_value(&crate::Value::AtomicUrl(crate::urls::COLLECTION.into()))
+            })
+            .unwrap_or(false);
+        // Collections handle the `at` parameter themselves, for their members.
+        let mut resource = match at {
+            Some(timestamp) if !is_collection => {
+                self.get_resource_at(&removed_query_params, timestamp)?
+            }
+            _ => current?,
+        };
+        // Older versions don't have dynamic properties.
+        let skip_dynamic = skip_dynamic || (at.is_some() && !is_collection);
 
         if let Some(agent) = for_agent {
-            let _explanation = crate::hierarchy::check_read(self, &resource, agent)?;
+            if at.is_some() && !is_collection {
+                // The version carries its old rights, so check the current ones
+                crate::plugins::versioning::check_read_version(self, &resource, agent)?;
+            } else {
+                crate::hierarchy::check_read(self, &resource, agent)?;
+            }
         }
 
         // Whether the resource has dynamic properties
@@ -446,6 +1107,10 @@ impl Storelike for Db {
         // make sure the actual subject matches the one requested - It should not be changed in the logic above
         resource.set_subject(subject.into());
 
+        if let Some(agent) = for_agent {
+            crate::hierarchy::remove_hidden_properties(self, &mut resource, agent)?;
+        }
+
         // This lets clients know that the resource may have dynamic properties that are currently not included
         if has_dynamic && skip_dynamic {
             resource.set_propval(
@@ -457,6 +1122,233 @@ impl Storelike for Db {
         Ok(resource)
     }
 
+    fn get_resource_at(&self, subject: &str, timestamp: i64) -> AtomicResult<Resource> {
+        crate::plugins::versioning::construct_version_at(subject, timestamp, self, Some(self))
+    }
+
+    /// Runs `apply` on a copy of this Db that keeps all its writes in memory.
+    /// If `apply` succeeds, the writes to the resources and all indexes are persisted in a single transaction of the [KeyValueBackend].
+    /// Commits that are applied get their sequence numbers in the `commit_log` in that same transaction.
+    /// If it fails, nothing is persisted.
+    /// The `on_commit` handler is not called for changes made in `apply`, so call [Storelike::handle_commit] afterwards.
+    #[instrument(skip_all)]
+    fn all_or_nothing<T>(&self, apply: impl FnOnce(&Db) -> AtomicResult<T>) -> AtomicResult<T> {
+        if self.staged_writes.is_some() {
+            // We're already staging writes, these will be persisted by the outer call.
+            return apply(self);
+        }
+        let staged_db = Db {
+            on_commit: None,
+            staged_writes: Some(Arc::new(Mutex::new(StagedWrites::default()))),
+            // Staged changes should not end up in the cache if they are not persisted
+            rights_cache: Arc::new(Mutex::new(RightsCache::default())),
+            ..self.clone()
+        };
+        let result = apply(&staged_db)?;
+        let staged = staged_db
+            .staged_writes
+            .as_ref()
+            .map(|s| std::mem::take(&mut *s.lock().unwrap()))
+            .unwrap_or_default();
+
+        // These may be cached Groups or Properties
+        let changed_subjects: Vec<String> = staged
+            .trees
+            .get(self.resources.name())
+            .map(|keys| {
+                keys.keys()
+                    .map(|key| String::from_utf8_lossy(key).to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut batches: Vec<(String, Batch)> = staged
+            .trees
+            .into_iter()
+            .map(|(tree_name, keys)| {
+                let mut batch = Batch::default();
+                for (key, value) in keys {
+                    match value {
+                        Some(v) => batch.insert(&key, &v),
+                        None => batch.remove(&key),
+                    }
+                }
+                (tree_name, batch)
+            })
+            .collect();
+        // Locked until the transaction is persisted, so concurrent transactions don't lose each other's changes to the usage
+        let _drive_usage_lock = self.drive_usage_lock.lock().unwrap();
+        if !staged.drive_usage.is_empty() {
+            let mut usage_batch = Batch::default();
+            for (drive, (removed, added)) in &staged.drive_usage {
+                let usage = self.persisted_drive_usage(drive)?;
+                // Other transactions may have added to the Drive after the quota was checked
+                if let Some(quota) = &self.drive_quota {
+                    quota.check(drive, &usage, &added.subtract(removed))?;
+                }
+                let json = serde_json::to_vec(&usage.subtract(removed).add(added))
+                    .map_err(|e| AtomicError::from(e.to_string()))?;
+                usage_batch.insert(drive.as_bytes(), &json);
+            }
+            batches.push((self.drive_usage.name().to_string(), usage_batch));
+        }
+        let mut next_commit_seq = self.next_commit_seq.lock().unwrap();
+        let mut next = *next_commit_seq;
+        if !staged.commit_log.is_empty() {
+            let mut log_batch = Batch::default();
+            let mut seqs_batch = Batch::default();
+            for commit_subject in staged.commit_log {
+                log_batch.insert(&next.to_be_bytes(), commit_subject.as_bytes());
+                seqs_batch.insert(commit_subject.as_bytes(), &next.to_be_bytes());
+                next += 1;
+            }
+            batches.push((self.commit_log.name().to_string(), log_batch));
+            batches.push((self.commit_seqs.name().to_string(), seqs_batch));
+        }
+        self.db.apply_transaction(&batches)?;
+        *next_commit_seq = next;
+        drop(next_commit_seq);
+        let mut rights_cache = self.rights_cache.lock().unwrap();
+        for subject in changed_subjects {
+            rights_cache.remove(&subject);
+        }
+        Ok(result)
+    }
+
+    fn append_to_commit_log(&self, commit_subject: &str) -> AtomicResult<()> {
+        if let Some(staged) = &self.staged_writes {
+            staged
+                .lock()
+                .unwrap()
+                .commit_log
+                .push(commit_subject.to_string());
+            return Ok(());
+        }
+        // The sequence number is only used if the entry is persisted, while no other entries can be appended
+        let mut next_commit_seq = self.next_commit_seq.lock().unwrap();
+        let seq = next_commit_seq.to_be_bytes();
+        let mut log_batch = Batch::default();
+        log_batch.insert(&seq, commit_subject.as_bytes());
+        let mut seqs_batch = Batch::default();
+        seqs_batch.insert(commit_subject.as_bytes(), &seq);
+        self.db.apply_transaction(&[
+            (self.commit_log.name().to_string(), log_batch),
+            (self.commit_seqs.name().to_string(), seqs_batch),
+        ])?;
+        *next_commit_seq += 1;
+        Ok(())
+    }
+
+    fn get_commit_seq(&self, commit_subject: &str) -> AtomicResult<Option<u64>> {
+        if let Some(staged) = &self.staged_writes {
+            // Staged Commits get the sequence numbers after the persisted ones, in the order in which they were applied
+            if let Some(index) = staged
+                .lock()
+                .unwrap()
+                .commit_log
+                .iter()
+                .position(|subject| subject == commit_subject)
+            {
+                return Ok(Some(*self.next_commit_seq.lock().unwrap() + index as u64));
+            }
+        }
+        match self.commit_seqs.get(commit_subject.as_bytes())? {
+            Some(seq) => Ok(Some(seq_from_key(&seq)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn record_audit_event(
+        &self,
+        for_agent: &str,
+        subject: &str,
+        right: &crate::hierarchy::Right,
+        result: &AtomicResult<String>,
+    ) {
+        if self.audit.is_none() {
+            return;
+        }
+        let (seq, event) = {
+            let mut next_audit_seq = self.next_audit_seq.lock().unwrap();
+            let seq = *next_audit_seq;
+            *next_audit_seq += 1;
+            (seq, AuditEvent::new(for_agent, subject, right, result))
+        };
+        // Written directly, so refused requests are recorded even if their Transaction fails
+        let written = serde_json::to_vec(&event)
+            .map_err(|e| AtomicError::from(e.to_string()))
+            .and_then(|json| {
+                let mut log_batch = Batch::default();
+                log_batch.insert(&seq.to_be_bytes(), &json);
+                let mut index_batch = Batch::default();
+                index_batch.insert(&audit_index_key(AUDIT_BY_AGENT, for_agent, Some(seq)), &[]);
+                index_batch.insert(&audit_index_key(AUDIT_BY_SUBJECT, subject, Some(seq)), &[]);
+                self.db.apply_transaction(&[
+                    (self.audit_log.name().to_string(), log_batch),
+                    (self.audit_index.name().to_string(), index_batch),
+                ])
+            });
+        if let Err(e) = written {
+            tracing::error!("Failed to write to audit log: {}", e);
+        }
+    }
+
+    fn check_drive_quota(
+        &self,
+        resource_old: Option<&Resource>,
+        resource_new: &Resource,
+    ) -> AtomicResult<()> {
+        let quota = match &self.drive_quota {
+            Some(quota) => quota,
+            None => return Ok(()),
+        };
+        let drive = match crate::quotas::drive_of(self, resource_new) {
+            Some(drive) => drive,
+            None => return Ok(()),
+        };
+        let new_usage = DriveUsage::of_resource(resource_new);
+        let added = match resource_old {
+            Some(old) if crate::quotas::drive_of(self, old).as_ref() == Some(&drive) => {
+                new_usage.subtract(&DriveUsage::of_resource(old))
+            }
+            // Moved from another Drive, together with its descendants
+            Some(old) => new_usage.add(&crate::quotas::usage_of_descendants(self, old)?),
+            None => new_usage,
+        };
+        quota.check(&drive, &self.drive_usage(&drive)?, &added)
+    }
+
+    fn update_drive_usage(
+        &self,
+        resource_old: Option<&Resource>,
+        resource_new: Option<&Resource>,
+    ) -> AtomicResult<()> {
+        if self.drive_quota.is_none() {
+            return Ok(());
+        }
+        let old_drive = resource_old.and_then(|r| crate::quotas::drive_of(self, r));
+        let new_drive = resource_new.and_then(|r| crate::quotas::drive_of(self, r));
+        if old_drive == new_drive
+            && resource_old.map(DriveUsage::of_resource)
+                == resource_new.map(DriveUsage::of_resource)
+        {
+            return Ok(());
+        }
+        // Descendants only move if the resource moves to another Drive
+        let descendants = match resource_old {
+            Some(old) if old_drive != new_drive => crate::quotas::usage_of_descendants(self, old)?,
+            _ => DriveUsage::default(),
+        };
+        if let (Some(drive), Some(old)) = (&old_drive, resource_old) {
+            let removed = DriveUsage::of_resource(old).add(&descendants);
+            self.change_drive_usage(drive, &removed, &DriveUsage::default())?;
+        }
+        if let (Some(drive), Some(new)) = (&new_drive, resource_new) {
+            let added = DriveUsage::of_resource(new).add(&descendants);
+            self.change_drive_usage(drive, &DriveUsage::default(), &added)?;
+        }
+        Ok(())
+    }
+
     fn handle_commit(&self, commit_response: &CommitResponse) {
         if let Some(fun) = &self.on_commit {
             fun(commit_response);
@@ -468,6 +1360,26 @@ impl Storelike for Db {
     /// Tries `query_cache`, which you should implement yourself.
     #[instrument(skip(self))]
     fn query(&self, q: &Query) -> AtomicResult<QueryResult> {
+        // Sorting or filtering by hidden values would reveal them
+        if let Some(agent) = &q.for_agent {
+            let expr = q.combined_expr();
+            let properties = q
+                .sort_by
+                .iter()
+                .map(|p| p.as_str())
+                .chain(expr.iter().flat_map(|e| e.properties()));
+            for property in properties {
+                if !crate::hierarchy::can_read_property(self, property, agent)? {
+                    return Err(AtomicError::unauthorized(format!(
+                        "Can't sort or filter by {}, because its values are hidden",
+                        property
+                    )));
+                }
+            }
+        }
+        if let Some(expr) = q.to_expr() {
+            return query_planned(self, q, &expr);
+        }
         let q_filter: QueryFilter = q.into();
         if let Ok(res) = query_indexed(self, q) {
             if res.count > 0 || q_filter.is_watched(self) {
@@ -523,9 +1435,11 @@ impl Storelike for Db {
             .get_self_url()
             .expect("No self URL set, is required in DB");
 
-        let result = self.resources.into_iter().filter_map(move |item| {
-            Db::map_sled_item_to_resource(item, self_url.clone(), include_external)
-        });
+        let result = self
+            .tree_scan_prefix(&self.resources, &[])
+            .filter_map(move |item| {
+                Db::map_item_to_resource(item, self_url.clone(), include_external)
+            });
 
         Box::new(result)
     }
@@ -604,7 +1518,8 @@ impl Storelike for Db {
                 let remove_atom = crate::Atom::new(subject.into(), prop.clone(), val.clone());
                 self.remove_atom_from_index(&remove_atom, &resource)?;
             }
-            let _found = self.resources.remove(subject.as_bytes())?;
+            self.tree_remove(&self.resources, subject.as_bytes())?;
+            self.rights_cache.lock().unwrap().remove(subject);
         } else {
             return Err(format!(
                 "Resource {} could not be deleted, because it was not found in the store.",
@@ -620,6 +1535,53 @@ impl Storelike for Db {
     }
 }
 
+impl crate::plugins::versioning::SnapshotCache for Db {
+    fn get_snapshot(&self, commit_url: &str) -> Option<PropVals> {
+        let bin = self
+            .tree_get(&self.version_snapshots, commit_url.as_bytes())
+            .ok()??;
+        bincode::deserialize(&bin).ok()
+    }
+
+    fn set_snapshot(&self, commit_url: &str, propvals: &PropVals) -> AtomicResult<()> {
+        self.tree_insert(
+            &self.version_snapshots,
+            commit_url.as_bytes(),
+            &bincode::serialize(propvals)?,
+        )
+    }
+}
+
+/// Sorts after every key in the `commit_log` and `audit_log`, which are all 8 bytes long.
+const COMMIT_SEQ_END: [u8; 9] = [u8::MAX; 9];
+
+fn seq_from_key(key: &[u8]) -> AtomicResult<u64> {
+    let bytes: [u8; 8] = key
+        .try_into()
+        .map_err(|_| "Invalid sequence number in commit log")?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+const AUDIT_BY_AGENT: u8 = b'a';
+const AUDIT_BY_SUBJECT: u8 = b's';
+
+/// Key in the `audit_index`: the kind of index, the agent or subject, a zero byte and the sequence number.
+/// Without a sequence number, it is the prefix of all entries of the agent or subject.
+fn audit_index_key(kind: u8, value: &str, seq: Option<u64>) -> Vec<u8> {
+    let mut key = Vec::with_capacity(value.len() + 10);
+    key.push(kind);
+    key.extend_from_slice(value.as_bytes());
+    key.push(0);
+    if let Some(seq) = seq {
+        key.extend_from_slice(&seq.to_be_bytes());
+    }
+    key
+}
+
+fn parse_audit_event(value: &[u8]) -> AtomicResult<AuditEvent> {
+    serde_json::from_slice(value).map_err(|e| format!("Invalid event in audit log. {}", e).into())
+}
+
 fn corrupt_db_message(subject: &str) -> String {
     format!("Could not deserialize item {} from database. DB is possibly corrupt, could be due to an update or a lack of migrations. Restore to a previous version, export your data and import your data again.", subject)
 }
@@ -0,0 +1,117 @@
+//! The [KeyValueBackend] is the storage layer of the [crate::Db].
+//! It consists of named [KeyValueTree]s, which are sorted maps of bytes to bytes.
+//! Implementations are available for [sled](super::sled_backend) and for a purely in-memory store (see [super::memory_backend]).
+
+use std::sync::Arc;
+
+use crate::errors::AtomicResult;
+
+use super::{memory_backend::MemoryBackend, sled_backend::SledBackend};
+
+/// A key-value pair, as stored in a [KeyValueTree].
+pub type KeyValue = (Vec<u8>, Vec<u8>);
+
+/// Returned by functions that iterate over a [KeyValueTree], sorted by key.
+pub type KeyValueIterator = Box<dyn Iterator<Item = AtomicResult<KeyValue>>>;
+
+/// A handle to a [KeyValueTree] that can be shared between threads.
+pub type Tree = Arc<dyn KeyValueTree>;
+
+/// Selects which [KeyValueBackend] the [crate::Db] uses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackendConfig {
+    /// Persists data in a [sled] database in this directory.
+    Sled(std::path::PathBuf),
+    /// Keeps all data in memory. Everything is lost when the [crate::Db] is dropped.
+    Memory,
+}
+
+impl BackendConfig {
+    /// Opens (or creates) the configured backend.
+    pub fn open(&self) -> AtomicResult<Arc<dyn KeyValueBackend>> {
+        Ok(match self {
+            BackendConfig::Sled(path) => Arc::new(SledBackend::open(path)?),
+            BackendConfig::Memory => Arc::new(MemoryBackend::default()),
+        })
+    }
+}
+
+/// A set of changes to a single [KeyValueTree], which are applied at once.
+#[derive(Debug, Default, Clone)]
+pub struct Batch {
+    /// Keys with a `None` value are removed.
+    pub(crate) changes: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl Batch {
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) {
+        self.changes.push((key.to_vec(), Some(value.to_vec())));
+    }
+
+    pub fn remove(&mut self, key: &[u8]) {
+        self.changes.push((key.to_vec(), None));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// A key-value store with multiple named [KeyValueTree]s.
+pub trait KeyValueBackend: Send + Sync {
+    /// Opens a Tree, creates it if it does not exist yet.
+    fn open_tree(&self, name: &str) -> AtomicResult<Tree>;
+
+    /// Names of all existing Trees.
+    fn tree_names(&self) -> AtomicResult<Vec<String>>;
+
+    /// Removes a Tree and all its contents. Returns `false` if the Tree did not exist.
+    fn drop_tree(&self, name: &str) -> AtomicResult<bool>;
+
+    /// Makes sure that all changes are persisted.
+    fn flush(&self) -> AtomicResult<()>;
+
+    /// Applies a [Batch] to each of the named Trees in a single transaction.
+    /// Either all changes are applied, or none of them.
+    fn apply_transaction(&self, batches: &[(String, Batch)]) -> AtomicResult<()>;
+}
+
+/// A sorted map of bytes to bytes, stored in a [KeyValueBackend].
+pub trait KeyValueTree: Send + Sync {
+    /// The name of the Tree, which was used to open it.
+    fn name(&self) -> &str;
+
+    fn get(&self, key: &[u8]) -> AtomicResult<Option<Vec<u8>>>;
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> AtomicResult<()>;
+
+    fn remove(&self, key: &[u8]) -> AtomicResult<()>;
+
+    fn contains_key(&self, key: &[u8]) -> AtomicResult<bool> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    /// Iterates over all keys that start with `prefix`.
+    fn scan_prefix(&self, prefix: &[u8]) -> KeyValueIterator;
+
+    /// Iterates over the keys from `start` (inclusive) to `end` (exclusive).
+    fn range(&self, start: &[u8], end: &[u8], reverse: bool) -> KeyValueIterator;
+
+    /// Iterates over all keys.
+    fn iter(&self) -> KeyValueIterator {
+        self.scan_prefix(&[])
+    }
+
+    /// The amount of keys in the Tree.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all keys.
+    fn clear(&self) -> AtomicResult<()>;
+
+    /// Applies all changes in the [Batch] atomically.
+    fn apply_batch(&self, batch: &Batch) -> AtomicResult<()>;
+}
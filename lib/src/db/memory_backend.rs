@@ -0,0 +1,193 @@
+//! [KeyValueBackend] implementation that keeps everything in memory, using [BTreeMap]s.
+//! Useful for tests and short-lived stores.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Bound,
+    sync::{Arc, RwLock},
+};
+
+use crate::errors::AtomicResult;
+
+use super::backend::{Batch, KeyValue, KeyValueBackend, KeyValueIterator, KeyValueTree, Tree};
+
+type Trees = HashMap<String, BTreeMap<Vec<u8>, Vec<u8>>>;
+
+/// All Trees share one lock, which makes transactions over multiple Trees atomic.
+#[derive(Default)]
+pub struct MemoryBackend {
+    trees: Arc<RwLock<Trees>>,
+}
+
+impl KeyValueBackend for MemoryBackend {
+    fn open_tree(&self, name: &str) -> AtomicResult<Tree> {
+        self.trees
+            .write()
+            .unwrap()
+            .entry(name.to_string())
+            .or_default();
+        Ok(Arc::new(MemoryTree {
+            trees: self.trees.clone(),
+            name: name.to_string(),
+        }))
+    }
+
+    fn tree_names(&self) -> AtomicResult<Vec<String>> {
+        Ok(self.trees.read().unwrap().keys().cloned().collect())
+    }
+
+    fn drop_tree(&self, name: &str) -> AtomicResult<bool> {
+        Ok(self.trees.write().unwrap().remove(name).is_some())
+    }
+
+    fn flush(&self) -> AtomicResult<()> {
+        Ok(())
+    }
+
+    fn apply_transaction(&self, batches: &[(String, Batch)]) -> AtomicResult<()> {
+        let mut trees = self.trees.write().unwrap();
+        for (name, batch) in batches {
+            apply_changes(trees.entry(name.clone()).or_default(), batch);
+        }
+        Ok(())
+    }
+}
+
+struct MemoryTree {
+    trees: Arc<RwLock<Trees>>,
+    name: String,
+}
+
+impl MemoryTree {
+    /// Collects the items in the range that start with `prefix`, so the lock is not held while iterating.
+    fn collect_range(
+        &self,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+        prefix: &[u8],
+    ) -> Vec<KeyValue> {
+        self.trees
+            .read()
+            .unwrap()
+            .get(&self.name)
+            .map(|tree| {
+                tree.range::<[u8], _>((start, end))
+                    .take_while(|(k, _v)| k.starts_with(prefix))
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl KeyValueTree for MemoryTree {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn get(&self, key: &[u8]) -> AtomicResult<Option<Vec<u8>>> {
+        Ok(self
+            .trees
+            .read()
+            .unwrap()
+            .get(&self.name)
+            .and_then(|tree| tree.get(key).cloned()))
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> AtomicResult<()> {
+        self.trees
+            .write()
+            .unwrap()
+            .entry(self.name.clone())
+            .or_default()
+            .insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> AtomicResult<()> {
+        if let Some(tree) = self.trees.write().unwrap().get_mut(&self.name) {
+            tree.remove(key);
+        }
+        Ok(())
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> KeyValueIterator {
+        let items = self.collect_range(Bound::Included(prefix), Bound::Unbounded, prefix);
+        Box::new(items.into_iter().map(Ok))
+    }
+
+    fn range(&self, start: &[u8], end: &[u8], reverse: bool) -> KeyValueIterator {
+        if start > end {
+            return Box::new(std::iter::empty());
+        }
+        let items = self.collect_range(Bound::Included(start), Bound::Excluded(end), &[]);
+        if reverse {
+            Box::new(items.into_iter().rev().map(Ok))
+        } else {
+            Box::new(items.into_iter().map(Ok))
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.trees
+            .read()
+            .unwrap()
+            .get(&self.name)
+            .map(|tree| tree.len())
+            .unwrap_or(0)
+    }
+
+    fn clear(&self) -> AtomicResult<()> {
+        if let Some(tree) = self.trees.write().unwrap().get_mut(&self.name) {
+            tree.clear();
+        }
+        Ok(())
+    }
+
+    fn apply_batch(&self, batch: &Batch) -> AtomicResult<()> {
+        let mut trees = self.trees.write().unwrap();
+        apply_changes(trees.entry(self.name.clone()).or_default(), batch);
+        Ok(())
+    }
+}
+
+fn apply_changes(tree: &mut BTreeMap<Vec<u8>, Vec<u8>>, batch: &Batch) {
+    for (key, value) in &batch.changes {
+        match value {
+            Some(v) => tree.insert(key.clone(), v.clone()),
+            None => tree.remove(key),
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scans_and_transactions() {
+        let backend = MemoryBackend::default();
+        let tree = backend.open_tree("test").unwrap();
+        for key in ["a/1", "a/2", "b/1", "c/1"] {
+            tree.insert(key.as_bytes(), b"").unwrap();
+        }
+        let prefixed: Vec<Vec<u8>> = tree.scan_prefix(b"a/").map(|kv| kv.unwrap().0).collect();
+        assert_eq!(prefixed, vec![b"a/1".to_vec(), b"a/2".to_vec()]);
+        let reversed: Vec<Vec<u8>> = tree
+            .range(b"a/2", b"c", true)
+            .map(|kv| kv.unwrap().0)
+            .collect();
+        assert_eq!(reversed, vec![b"b/1".to_vec(), b"a/2".to_vec()]);
+
+        let mut batch = Batch::default();
+        batch.remove(b"a/1");
+        batch.insert(b"d/1", b"value");
+        backend
+            .apply_transaction(&[("test".into(), batch), ("other".into(), Batch::default())])
+            .unwrap();
+        assert!(!tree.contains_key(b"a/1").unwrap());
+        assert_eq!(tree.get(b"d/1").unwrap(), Some(b"value".to_vec()));
+        assert_eq!(tree.len(), 4);
+        assert!(backend.tree_names().unwrap().contains(&"other".to_string()));
+    }
+}
@@ -16,8 +16,8 @@ use crate::{errors::AtomicResult, Db, Storelike};
 
 /// Checks the current version(s) of the internal Store, and performs migrations if needed.
 pub fn migrate_maybe(store: &Db) -> AtomicResult<()> {
-    for tree in store.db.tree_names() {
-        match String::from_utf8_lossy(&tree).as_ref() {
+    for tree in store.db.tree_names()? {
+        match tree.as_ref() {
             // Add migrations for outdated Trees to this list
             "resources" => v0_to_v1(store)?,
             "reference_index" => ref_v0_to_v1(store)?,
@@ -35,11 +35,11 @@ fn v0_to_v1(store: &Db) -> AtomicResult<()> {
     let old = store.db.open_tree(old_key)?;
     let mut count = 0;
 
-    for item in old.into_iter() {
+    for item in old.iter() {
         let (subject, resource_bin) = item.expect("Unable to convert into iterable");
         let subject: String =
             bincode::deserialize(&subject).expect("Unable to deserialize subject");
-        new.insert(subject.as_bytes(), resource_bin)?;
+        new.insert(subject.as_bytes(), &resource_bin)?;
         count += 1;
     }
 
@@ -81,3 +81,47 @@ fn ref_v0_to_v1(store: &Db) -> AtomicResult<()> {
     tracing::warn!("Rebuilding index finished!");
     Ok(())
 }
+
+/// Fills the `commit_log` with the existing Commits, sorted by their `createdAt`.
+/// Runs when a Db is opened that was created before the `commit_log` existed.
+pub(crate) fn build_commit_log(store: &Db) -> AtomicResult<()> {
+    let is_commit = crate::storelike::QueryExpr::IsA(crate::urls::COMMIT.into());
+    let mut commits: Vec<(i64, String)> = store
+        .all_resources(true)
+        .filter(|resource| is_commit.matches(resource))
+        .map(|resource| {
+            let created_at = resource
+                .get(crate::urls::CREATED_AT)
+                .and_then(|v| v.to_int())
+                .unwrap_or(0);
+            (created_at, resource.get_subject().clone())
+        })
+        .collect();
+    if commits.is_empty() {
+        return Ok(());
+    }
+    tracing::warn!("Building commit log for {} Commits...", commits.len());
+    commits.sort();
+    for (_created_at, subject) in commits {
+        store.append_to_commit_log(&subject)?;
+    }
+    tracing::warn!("Building commit log finished!");
+    Ok(())
+}
+
+/// Fills the `commit_seqs` with the sequence numbers of the Commits in the `commit_log`.
+/// Runs when a Db is opened that was created before the `commit_seqs` existed.
+pub(crate) fn build_commit_seqs(store: &Db) -> AtomicResult<()> {
+    let log = store.commit_log(0, usize::MAX)?;
+    if log.is_empty() {
+        return Ok(());
+    }
+    tracing::warn!("Indexing sequence numbers of {} Commits...", log.len());
+    for (seq, subject) in log {
+        store
+            .commit_seqs
+            .insert(subject.as_bytes(), &seq.to_be_bytes())?;
+    }
+    tracing::warn!("Indexing sequence numbers finished!");
+    Ok(())
+}
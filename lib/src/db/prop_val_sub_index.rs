@@ -15,9 +15,7 @@ pub fn find_in_prop_val_sub_index(store: &Db, prop: &str, val: Option<&Value>) -
     }
     Box::new(
         store
-            .prop_val_sub_index
-            .scan_prefix(prefix)
-            .into_iter()
+            .tree_scan_prefix(&store.prop_val_sub_index, &prefix)
             .map(|kv| {
                 let (key, _value) = kv?;
                 key_to_index_atom(&key)
@@ -27,16 +25,12 @@ pub fn find_in_prop_val_sub_index(store: &Db, prop: &str, val: Option<&Value>) -
 
 #[instrument(skip(store))]
 pub fn add_atom_to_prop_val_sub_index(index_atom: &IndexAtom, store: &Db) -> AtomicResult<()> {
-    let _existing = store
-        .prop_val_sub_index
-        .insert(key_from_atom(index_atom), b"");
-    Ok(())
+    store.tree_insert(&store.prop_val_sub_index, &key_from_atom(index_atom), b"")
 }
 
 #[instrument(skip(store))]
 pub fn remove_atom_from_prop_val_sub_index(index_atom: &IndexAtom, store: &Db) -> AtomicResult<()> {
-    let _existing = store.prop_val_sub_index.remove(key_from_atom(index_atom));
-    Ok(())
+    store.tree_remove(&store.prop_val_sub_index, &key_from_atom(index_atom))
 }
 
 /// Constructs the Key for the prop_val_sub_index.
@@ -1,5 +1,5 @@
 //! The QueryIndex is used to speed up queries by persisting filtered, sorted collections.
-//! It relies on lexicographic ordering of keys, which Sled utilizes using `scan_prefix` queries.
+//! It relies on lexicographic ordering of keys, which the [super::KeyValueBackend] utilizes using `scan_prefix` and `range` queries.
 
 use crate::{
     atoms::IndexAtom,
@@ -36,17 +36,14 @@ impl QueryFilter {
         if self.property.is_none() && self.value.is_none() {
             return Err("Cannot watch a query without a property or value. These types of queries are not implemented. See https://github.com/atomicdata-dev/atomic-data-rust/issues/548 ".into());
         };
-        store
-            .watched_queries
-            .insert(bincode::serialize(self)?, b"")?;
-        Ok(())
+        store.tree_insert(&store.watched_queries, &bincode::serialize(self)?, b"")
     }
 
     /// Check if this [QueryFilter] is being indexed
     pub fn is_watched(&self, store: &Db) -> bool {
         store
             .watched_queries
-            .contains_key(bincode::serialize(self).unwrap())
+            .contains_key(&bincode::serialize(self).unwrap())
             .unwrap_or(false)
     }
 }
@@ -88,12 +85,7 @@ pub fn query_indexed(store: &Db, q: &Query) -> AtomicResult<QueryResult> {
     let start_key = create_query_index_key(&q.into(), Some(&start.to_sortable_string()), None)?;
     let end_key = create_query_index_key(&q.into(), Some(&end.to_sortable_string()), None)?;
 
-    let iter: Box<dyn Iterator<Item = std::result::Result<(sled::IVec, sled::IVec), sled::Error>>> =
-        if q.sort_desc {
-            Box::new(store.query_index.range(start_key..end_key).rev())
-        } else {
-            Box::new(store.query_index.range(start_key..end_key))
-        };
+    let iter = store.tree_range(&store.query_index, &start_key, &end_key, q.sort_desc);
 
     let mut subjects: Vec<String> = vec![];
     let mut resources = Vec::new();
@@ -122,31 +114,7 @@ pub fn query_indexed(store: &Db, q: &Query) -> AtomicResult<QueryResult> {
                 continue;
             }
 
-            // When an agent is defined, we must perform authorization checks
-            // WARNING: EXPENSIVE!
-            // TODO: Make async
-            if q.include_nested || q.for_agent.is_some() {
-                match store.get_resource_extended(subject, true, q.for_agent.as_deref()) {
-                    Ok(resource) => {
-                        resources.push(resource);
-                        subjects.push(subject.into())
-                    }
-                    Err(e) => match &e.error_type {
-                        crate::AtomicErrorType::NotFoundError => {}
-                        crate::AtomicErrorType::UnauthorizedError => {}
-                        _other => {
-                            return Err(format!(
-                                "Error when getting resource in collection: {}",
-                                &e
-                            )
-                            .into());
-                        }
-                    },
-                }
-            } else {
-                // If there is no need for nested resources, and no auth checks, we can skip the expensive part!
-                subjects.push(subject.into())
-            }
+            add_member(store, q, subject, &mut subjects, &mut resources)?;
         }
         // We iterate over every single resource, even if we don't perform any computation on the items.
         // This helps with pagination, but it comes at a serious performance cost. We might need to change how this works later on.
@@ -162,6 +130,38 @@ pub fn query_indexed(store: &Db, q: &Query) -> AtomicResult<QueryResult> {
     })
 }
 
+/// Adds a hit of a Query to the results.
+/// When an agent is defined, authorization checks are performed, and unauthorized hits are skipped.
+pub fn add_member(
+    store: &Db,
+    q: &Query,
+    subject: &str,
+    subjects: &mut Vec<String>,
+    resources: &mut Vec<Resource>,
+) -> AtomicResult<()> {
+    // WARNING: EXPENSIVE!
+    // TODO: Make async
+    if q.include_nested || q.for_agent.is_some() {
+        match store.get_resource_extended(subject, true, q.for_agent.as_deref()) {
+            Ok(resource) => {
+                resources.push(resource);
+                subjects.push(subject.into())
+            }
+            Err(e) => match &e.error_type {
+                crate::AtomicErrorType::NotFoundError => {}
+                crate::AtomicErrorType::UnauthorizedError => {}
+                _other => {
+                    return Err(format!("Error when getting resource in collection: {}", &e).into());
+                }
+            },
+        }
+    } else {
+        // If there is no need for nested resources, and no auth checks, we can skip the expensive part!
+        subjects.push(subject.into())
+    }
+    Ok(())
+}
+
 /// Checks if the resource will match with a QueryFilter.
 /// Does any value or property or sort value match?
 /// Returns the matching property, if found.
@@ -282,7 +282,7 @@ pub fn check_if_atom_matches_watched_query_filters(
     delete: bool,
     resource: &Resource,
 ) -> AtomicResult<()> {
-    for query in store.watched_queries.iter() {
+    for query in store.tree_scan_prefix(&store.watched_queries, &[]) {
         // The keys store all the data
         if let Ok((k, _v)) = query {
             let q_filter = bincode::deserialize::<QueryFilter>(&k)
@@ -318,11 +318,10 @@ pub fn update_indexed_member(
         Some(subject),
     )?;
     if delete {
-        store.query_index.remove(key)?;
+        store.tree_remove(&store.query_index, &key)
     } else {
-        store.query_index.insert(key, b"")?;
+        store.tree_insert(&store.query_index, &key, b"")
     }
-    Ok(())
 }
 
 /// Maximum string length for values in the query_index. Should be long enough to contain pretty long URLs, but not very long documents.
@@ -0,0 +1,174 @@
+//! Plans and runs Queries that have a [QueryExpr] filter.
+//! For every clause, the planner counts the matching keys in the `prop_val_sub_index` or the `reference_index`.
+//! The most selective clause of a conjunction provides the candidate subjects,
+//! which are then intersected with the rest of the filter by checking the stored Resources.
+
+use std::collections::HashSet;
+
+use tracing::instrument;
+
+use crate::{
+    errors::AtomicResult,
+    storelike::{Query, QueryExpr, QueryResult},
+    urls,
+    values::SortableValue,
+    Db, Storelike, Value,
+};
+
+use super::{
+    prop_val_sub_index::find_in_prop_val_sub_index,
+    query_index::{add_member, IndexIterator, NO_VALUE},
+    val_prop_sub_index::find_in_val_prop_sub_index,
+};
+
+/// Performs a [Query] with a filter.
+/// Unlike [super::query_index::query_indexed], the results are not persisted in the query index.
+#[instrument(skip(store))]
+pub fn query_planned(store: &Db, q: &Query, expr: &QueryExpr) -> AtomicResult<QueryResult> {
+    let self_url = store
+        .get_self_url()
+        .ok_or("No self_url set, required for Queries")?;
+
+    let start = q.start_val.as_ref().map(|v| v.to_sortable_string());
+    let end = q.end_val.as_ref().map(|v| v.to_sortable_string());
+
+    let mut hits: Vec<(SortableValue, String)> = Vec::new();
+    for subject in find_candidates(store, expr)? {
+        if !q.include_external && !subject.starts_with(&self_url) {
+            continue;
+        }
+        let resource = match store.get_resource(&subject) {
+            Ok(resource) => resource,
+            // The index can contain subjects that are no longer stored.
+            Err(_) => continue,
+        };
+        if !expr.matches(&resource) {
+            continue;
+        }
+        let sort_val = match &q.sort_by {
+            Some(sort) => resource
+                .get(sort)
+                .map(|v| v.to_sortable_string())
+                .unwrap_or_else(|_| NO_VALUE.to_string()),
+            None => subject.clone(),
+        };
+        if start.as_ref().map(|s| &sort_val < s).unwrap_or(false)
+            || end.as_ref().map(|e| &sort_val > e).unwrap_or(false)
+        {
+            continue;
+        }
+        hits.push((sort_val, subject));
+    }
+    hits.sort();
+    if q.sort_desc {
+        hits.reverse();
+    }
+
+    let count = hits.len();
+    let limit = q.limit.unwrap_or(usize::MAX);
+    let mut subjects = Vec::new();
+    let mut resources = Vec::new();
+    for (_sort_val, subject) in hits.iter().skip(q.offset) {
+        if subjects.len() >= limit {
+            break;
+        }
+        add_member(store, q, subject, &mut subjects, &mut resources)?;
+    }
+
+    Ok(QueryResult {
+        subjects,
+        resources,
+        count,
+    })
+}
+
+/// Returns the subjects that possibly match the filter.
+/// These still have to be checked using [QueryExpr::matches].
+fn find_candidates(store: &Db, expr: &QueryExpr) -> AtomicResult<HashSet<String>> {
+    match expr {
+        QueryExpr::And(clauses) => {
+            // Use the clause with the fewest hits, the other ones are checked later.
+            let mut most_selective = None;
+            let mut lowest = usize::MAX;
+            for clause in clauses {
+                let estimate = estimate_hits(store, clause, lowest)?;
+                if most_selective.is_none() || estimate < lowest {
+                    lowest = estimate;
+                    most_selective = Some(clause);
+                }
+            }
+            match most_selective {
+                Some(clause) => find_candidates(store, clause),
+                None => Err("An `and` filter needs at least one clause".into()),
+            }
+        }
+        QueryExpr::Or(clauses) => {
+            let mut subjects = HashSet::new();
+            for clause in clauses {
+                subjects.extend(find_candidates(store, clause)?);
+            }
+            Ok(subjects)
+        }
+        QueryExpr::Range { min, max, .. } => {
+            let mut subjects = HashSet::new();
+            for atom in index_atoms_for_clause(store, expr) {
+                let atom = atom?;
+                // Numbers are not lexicographically sortable, so we parse every value in the index.
+                if let Ok(number) = atom.ref_value.parse::<f64>() {
+                    if QueryExpr::in_range(number, *min, *max) {
+                        subjects.insert(atom.subject);
+                    }
+                }
+            }
+            Ok(subjects)
+        }
+        leaf => index_atoms_for_clause(store, leaf)
+            .map(|atom| atom.map(|a| a.subject))
+            .collect(),
+    }
+}
+
+/// Counts the amount of index entries for a clause, but stops counting at `max`.
+/// Used for finding the most selective clause.
+fn estimate_hits(store: &Db, expr: &QueryExpr, max: usize) -> AtomicResult<usize> {
+    match expr {
+        QueryExpr::And(clauses) => {
+            let mut lowest = max;
+            for clause in clauses {
+                lowest = lowest.min(estimate_hits(store, clause, lowest)?);
+            }
+            Ok(lowest)
+        }
+        QueryExpr::Or(clauses) => {
+            let mut total = 0;
+            for clause in clauses {
+                if total >= max {
+                    break;
+                }
+                total += estimate_hits(store, clause, max - total)?;
+            }
+            Ok(total)
+        }
+        leaf => Ok(index_atoms_for_clause(store, leaf).take(max).count()),
+    }
+}
+
+/// Picks the index for a single clause.
+/// Clauses with a known Property use the `prop_val_sub_index`, a Value without a Property uses the `reference_index`.
+fn index_atoms_for_clause(store: &Db, expr: &QueryExpr) -> IndexIterator {
+    match expr {
+        QueryExpr::Equals { property, value } => {
+            find_in_prop_val_sub_index(store, property, Some(&Value::String(value.clone())))
+        }
+        QueryExpr::Exists(property) | QueryExpr::Range { property, .. } => {
+            find_in_prop_val_sub_index(store, property, None)
+        }
+        QueryExpr::IsA(class) => {
+            find_in_prop_val_sub_index(store, urls::IS_A, Some(&Value::AtomicUrl(class.clone())))
+        }
+        QueryExpr::References(value) => {
+            find_in_val_prop_sub_index(store, &Value::String(value.clone()), None)
+        }
+        QueryExpr::And(_) | QueryExpr::Or(_) => Box::new(std::iter::empty()),
+    }
+}
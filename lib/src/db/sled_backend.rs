@@ -0,0 +1,144 @@
+//! [KeyValueBackend] implementation for [sled], an embedded database that persists to disk.
+
+use std::sync::Arc;
+
+use sled::Transactional;
+
+use crate::errors::AtomicResult;
+
+use super::backend::{Batch, KeyValueBackend, KeyValueIterator, KeyValueTree, Tree};
+
+pub struct SledBackend {
+    db: sled::Db,
+}
+
+impl SledBackend {
+    pub fn open(path: &std::path::Path) -> AtomicResult<SledBackend> {
+        let db = sled::open(path).map_err(|e|format!("Failed opening DB at this location: {:?} . Is another instance of Atomic Server running? {}", path, e))?;
+        Ok(SledBackend { db })
+    }
+}
+
+impl KeyValueBackend for SledBackend {
+    fn open_tree(&self, name: &str) -> AtomicResult<Tree> {
+        let tree = self.db.open_tree(name)?;
+        Ok(Arc::new(SledTree {
+            tree,
+            name: name.to_string(),
+        }))
+    }
+
+    fn tree_names(&self) -> AtomicResult<Vec<String>> {
+        Ok(self
+            .db
+            .tree_names()
+            .iter()
+            .map(|name| String::from_utf8_lossy(name).to_string())
+            .collect())
+    }
+
+    fn drop_tree(&self, name: &str) -> AtomicResult<bool> {
+        Ok(self.db.drop_tree(name)?)
+    }
+
+    fn flush(&self) -> AtomicResult<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn apply_transaction(&self, batches: &[(String, Batch)]) -> AtomicResult<()> {
+        let mut trees = Vec::new();
+        let mut sled_batches = Vec::new();
+        for (name, batch) in batches {
+            trees.push(self.db.open_tree(name)?);
+            sled_batches.push(to_sled_batch(batch));
+        }
+        trees[..]
+            .transaction(|tx_trees| {
+                for (tree, batch) in tx_trees.iter().zip(sled_batches.iter()) {
+                    tree.apply_batch(batch)?;
+                }
+                Ok(())
+            })
+            .map_err(|e: sled::transaction::TransactionError<()>| {
+                format!("Failed to persist changes in a single transaction: {:?}", e)
+            })?;
+        Ok(())
+    }
+}
+
+struct SledTree {
+    tree: sled::Tree,
+    name: String,
+}
+
+impl KeyValueTree for SledTree {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn get(&self, key: &[u8]) -> AtomicResult<Option<Vec<u8>>> {
+        Ok(self.tree.get(key)?.map(|v| v.to_vec()))
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> AtomicResult<()> {
+        self.tree.insert(key, value)?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> AtomicResult<()> {
+        self.tree.remove(key)?;
+        Ok(())
+    }
+
+    fn contains_key(&self, key: &[u8]) -> AtomicResult<bool> {
+        Ok(self.tree.contains_key(key)?)
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> KeyValueIterator {
+        map_sled_iter(self.tree.scan_prefix(prefix))
+    }
+
+    fn range(&self, start: &[u8], end: &[u8], reverse: bool) -> KeyValueIterator {
+        let iter = self.tree.range(start..end);
+        if reverse {
+            map_sled_iter(iter.rev())
+        } else {
+            map_sled_iter(iter)
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    fn clear(&self) -> AtomicResult<()> {
+        self.tree.clear()?;
+        Ok(())
+    }
+
+    fn apply_batch(&self, batch: &Batch) -> AtomicResult<()> {
+        self.tree.apply_batch(to_sled_batch(batch))?;
+        Ok(())
+    }
+}
+
+fn map_sled_iter(
+    iter: impl Iterator<Item = sled::Result<(sled::IVec, sled::IVec)>> + 'static,
+) -> KeyValueIterator {
+    Box::new(iter.map(|item| {
+        let (k, v) = item?;
+        Ok((k.to_vec(), v.to_vec()))
+    }))
+}
+
+fn to_sled_batch(batch: &Batch) -> sled::Batch {
+    let mut sled_batch = sled::Batch::default();
+    for (key, value) in &batch.changes {
+        match value {
+            Some(v) => sled_batch.insert(key.as_slice(), v.as_slice()),
+            None => sled_batch.remove(key.as_slice()),
+        }
+    }
+    sled_batch
+}
@@ -185,9 +185,22 @@ fn destroy_resource_and_check_collection_and_commits() {
 fn get_extended_resource_pagination() {
     let store = Db::init_temp("get_extended_resource_pagination").unwrap();
     let subject = format!("{}/commits?current_page=2", store.get_server_url());
-    // Should throw, because page 2 is out of bounds for default page size
+    // Should throw, because the page after the last one is out of bounds for default page size.
+    // Every default Collection is created by a Commit, so the amount of pages depends on the default Classes.
+    let total_pages = store
+        .get_resource_extended(&format!("{}/commits", store.get_server_url()), false, None)
+        .unwrap()
+        .get(urls::COLLECTION_TOTAL_PAGES)
+        .unwrap()
+        .to_int()
+        .unwrap();
+    let out_of_bounds = format!(
+        "{}/commits?current_page={}",
+        store.get_server_url(),
+        total_pages + 1
+    );
     let _wrong_resource = store
-        .get_resource_extended(&subject, false, None)
+        .get_resource_extended(&out_of_bounds, false, None)
         .unwrap_err();
     // let subject = "https://atomicdata.dev/classes?current_page=2&page_size=1";
     let subject_with_page_size = format!("{}&page_size=1", subject);
@@ -263,6 +276,7 @@ fn queries() {
         include_external: true,
         include_nested: false,
         for_agent: None,
+        filter: None,
     };
     let res = store.query(&q).unwrap();
     assert_eq!(
@@ -389,6 +403,7 @@ fn query_include_external() {
         include_external: true,
         include_nested: false,
         for_agent: None,
+        filter: None,
     };
     let res_include = store.query(&q).unwrap();
     q.include_external = false;
@@ -501,6 +516,7 @@ fn test_collection_update_value(store: &Db, property_url: &str, old_val: Value,
         include_external: true,
         include_nested: true,
         for_agent: None,
+        filter: None,
     };
     let mut res = store.query(&q).unwrap();
     assert_eq!(
@@ -557,3 +573,1511 @@ fn test_collection_update_value(store: &Db, property_url: &str, old_val: Value,
         "Modifying the filtered value did not remove the item from the results"
     );
 }
+
+#[test]
+/// A Transaction either persists all of its Commits, or none of them.
+fn transaction_all_or_nothing() {
+    let store = Db::init_temp("transaction_all_or_nothing").unwrap();
+    let agent = store.get_default_agent().unwrap();
+    let opts = crate::commit::CommitOpts {
+        validate_schema: true,
+        validate_signature: true,
+        validate_timestamp: true,
+        validate_rights: true,
+        validate_previous_commit: true,
+        validate_for_agent: None,
+        update_index: true,
+    };
+    let parent_subject = format!("{}/transaction-parent", store.get_server_url());
+    let child_subject = format!("{}/transaction-child", store.get_server_url());
+
+    let sign_commit = |subject: &str, parent: &str| {
+        let mut builder = crate::commit::CommitBuilder::new(subject.into());
+        builder.set(urls::PARENT.into(), Value::AtomicUrl(parent.into()));
+        builder.set(urls::NAME.into(), Value::String("transaction test".into()));
+        builder
+            .sign(&agent, &store, &Resource::new(subject.into()))
+            .unwrap()
+    };
+
+    // The second Commit uses a Value that does not match the datatype of the Property
+    let mut invalid_child = crate::commit::CommitBuilder::new(child_subject.clone());
+    invalid_child.set(
+        urls::PARENT.into(),
+        Value::AtomicUrl(parent_subject.clone()),
+    );
+    invalid_child.set(urls::CREATED_AT.into(), Value::String("not a date".into()));
+    let invalid = crate::Transaction::sign(
+        vec![
+            sign_commit(&parent_subject, store.get_server_url()),
+            invalid_child
+                .sign(&agent, &store, &Resource::new(child_subject.clone()))
+                .unwrap(),
+        ],
+        &agent,
+        &store,
+    )
+    .unwrap();
+    invalid.apply_opts(&store, &opts).unwrap_err();
+    store.get_resource(&parent_subject).unwrap_err();
+    let query = Query::new_prop_val(urls::PARENT, store.get_server_url());
+    let children_before = store.query(&query).unwrap().count;
+
+    // The child is appended to a parent that is created in the same Transaction
+    let valid = crate::Transaction::sign(
+        vec![
+            sign_commit(&parent_subject, store.get_server_url()),
+            sign_commit(&child_subject, &parent_subject),
+        ],
+        &agent,
+        &store,
+    )
+    .unwrap();
+    let responses = valid.apply_opts(&store, &opts).unwrap();
+    assert_eq!(responses.len(), 2);
+    store.get_resource(&parent_subject).unwrap();
+    let child = store.get_resource(&child_subject).unwrap();
+    assert_eq!(child.get(urls::PARENT).unwrap().to_string(), parent_subject);
+    assert_eq!(store.query(&query).unwrap().count, children_before + 1);
+    let children = store
+        .query(&Query::new_prop_val(urls::PARENT, &parent_subject))
+        .unwrap();
+    assert_eq!(children.subjects, vec![child_subject]);
+    // Applying the same Transaction twice should fail
+    valid.apply_opts(&store, &opts).unwrap_err();
+}
+
+#[test]
+/// Queries using a composable filter, combining multiple indexes.
+fn query_expressions() {
+    use crate::storelike::QueryExpr;
+    const DOCUMENT: &str = "https://atomicdata.dev/classes/Document";
+    let store = &Db::init_temp("query_expressions").unwrap();
+    let parent_a = format!("{}/query-parent-a", store.get_server_url());
+    let parent_b = format!("{}/query-parent-b", store.get_server_url());
+    let mut subjects = Vec::new();
+    for i in 0..6 {
+        let mut resource = Resource::new_generate_subject(store);
+        let parent = if i % 2 == 0 { &parent_a } else { &parent_b };
+        resource
+            .set_propval(urls::PARENT.into(), Value::AtomicUrl(parent.into()), store)
+            .unwrap();
+        resource
+            .set_propval(urls::IS_A.into(), vec![DOCUMENT.to_string()].into(), store)
+            .unwrap();
+        resource
+            .set_propval(urls::CREATED_AT.into(), Value::Timestamp(i * 1000), store)
+            .unwrap();
+        if i == 0 {
+            resource
+                .set_propval(
+                    urls::DESCRIPTION.into(),
+                    Value::Markdown("first".into()),
+                    store,
+                )
+                .unwrap();
+        }
+        resource.save_locally(store).unwrap();
+        subjects.push(resource.get_subject().clone());
+    }
+    let in_parent_a = QueryExpr::Equals {
+        property: urls::PARENT.into(),
+        value: parent_a.clone(),
+    };
+
+    let mut q = Query::new_expr(QueryExpr::And(vec![
+        QueryExpr::IsA(DOCUMENT.into()),
+        in_parent_a.clone(),
+    ]));
+    q.sort_by = Some(urls::CREATED_AT.into());
+    q.sort_desc = true;
+    let res = store.query(&q).unwrap();
+    assert_eq!(res.count, 3);
+    assert_eq!(res.subjects[0], subjects[4], "sort by desc");
+
+    let range = QueryExpr::Range {
+        property: urls::CREATED_AT.into(),
+        min: Some(1500.0),
+        max: Some(4000.0),
+    };
+    let q = Query::new_expr(QueryExpr::And(vec![in_parent_a.clone(), range]));
+    assert_eq!(store.query(&q).unwrap().count, 2, "range filter");
+
+    let q = Query::new_expr(QueryExpr::And(vec![
+        QueryExpr::Or(vec![
+            in_parent_a.clone(),
+            QueryExpr::Equals {
+                property: urls::PARENT.into(),
+                value: parent_b.clone(),
+            },
+        ]),
+        QueryExpr::Range {
+            property: urls::CREATED_AT.into(),
+            min: None,
+            max: Some(1000.0),
+        },
+    ]));
+    let mut found = store.query(&q).unwrap().subjects;
+    found.sort();
+    let mut expected = vec![subjects[0].clone(), subjects[1].clone()];
+    expected.sort();
+    assert_eq!(found, expected, "or filter");
+
+    let q = Query::new_expr(QueryExpr::And(vec![
+        QueryExpr::Exists(urls::DESCRIPTION.into()),
+        in_parent_a,
+    ]));
+    assert_eq!(store.query(&q).unwrap().subjects, vec![subjects[0].clone()]);
+
+    let q = Query::new_expr(QueryExpr::References(parent_b.clone()));
+    assert_eq!(store.query(&q).unwrap().count, 3, "references filter");
+
+    // The property and value of the Query are combined with the filter
+    let mut q = Query::new_expr(QueryExpr::IsA(DOCUMENT.into()));
+    q.property = Some(urls::PARENT.into());
+    q.value = Some(Value::AtomicUrl(parent_b.clone()));
+    assert_eq!(store.query(&q).unwrap().count, 3);
+
+    // Filters can be passed to Collections as a query parameter
+    let filter = format!(
+        r#"{{"and":[{{"isA":"{}"}},{{"equals":{{"property":"{}","value":"{}"}}}}]}}"#,
+        DOCUMENT,
+        urls::PARENT,
+        parent_b
+    );
+    let url = url::Url::parse_with_params(
+        &format!("{}/query", store.get_server_url()),
+        &[("filter", filter)],
+    )
+    .unwrap();
+    let collection = store
+        .get_resource_extended(url.as_str(), false, None)
+        .unwrap();
+    assert_eq!(
+        collection
+            .get(urls::COLLECTION_MEMBER_COUNT)
+            .unwrap()
+            .to_int()
+            .unwrap(),
+        3
+    );
+}
+
+#[test]
+/// Resources, indexes and all-or-nothing changes work on the sled backend, which stores data on disk.
+fn sled_backend() {
+    let path = std::env::temp_dir().join(format!(
+        "atomic-sled-backend-{}",
+        crate::utils::random_string(10)
+    ));
+    let store = Db::init(
+        &BackendConfig::Sled(path.clone()),
+        "https://localhost".into(),
+    )
+    .unwrap();
+    let resource_with_description = |subject: &str| {
+        let mut resource = Resource::new(subject.into());
+        resource
+            .set_propval(
+                urls::DESCRIPTION.into(),
+                Value::Markdown("kept".into()),
+                &store,
+            )
+            .unwrap();
+        resource
+    };
+    let subject = "https://localhost/persisted";
+    store
+        .add_resource(&resource_with_description(subject))
+        .unwrap();
+    let failed: AtomicResult<()> = store.all_or_nothing(|staged| {
+        staged.add_resource(&resource_with_description("https://localhost/discarded"))?;
+        Err("Something went wrong".into())
+    });
+    failed.unwrap_err();
+    store
+        .get_resource("https://localhost/discarded")
+        .unwrap_err();
+
+    // Queries and iterators inside `all_or_nothing` include the staged changes
+    let staged_subject = "https://localhost/staged";
+    let rolled_back: AtomicResult<()> = store.all_or_nothing(|staged| {
+        staged.add_resource(&resource_with_description(staged_subject))?;
+        staged.remove_resource(subject)?;
+        let q = Query::new_prop_val(urls::DESCRIPTION, "kept");
+        assert_eq!(staged.query(&q)?.subjects, vec![staged_subject.to_string()]);
+        let all: Vec<String> = staged
+            .all_resources(true)
+            .map(|r| r.get_subject().to_string())
+            .collect();
+        assert!(all.contains(&staged_subject.to_string()));
+        assert!(!all.contains(&subject.to_string()));
+        Err("Rolled back".into())
+    });
+    rolled_back.unwrap_err();
+
+    let found = store.get_resource(subject).unwrap();
+    assert_eq!(found.get(urls::DESCRIPTION).unwrap().to_string(), "kept");
+    let q = Query::new_prop_val(urls::DESCRIPTION, "kept");
+    assert_eq!(store.query(&q).unwrap().subjects, vec![subject.to_string()]);
+    drop(store);
+    std::fs::remove_dir_all(&path).unwrap();
+}
+
+#[test]
+/// Resources and Collections can be constructed as they were at some point in time.
+fn resource_at_timestamp() {
+    let store = &Db::init_temp("resource_at_timestamp").unwrap();
+    let wait = || std::thread::sleep(std::time::Duration::from_millis(5));
+    let before = crate::utils::now();
+    wait();
+    let mut resource = Resource::new_generate_subject(store);
+    let subject = resource.get_subject().clone();
+    resource
+        .set_propval(
+            urls::PARENT.into(),
+            Value::AtomicUrl(store.get_server_url().into()),
+            store,
+        )
+        .unwrap();
+    resource
+        .set_propval(
+            urls::DESCRIPTION.into(),
+            Value::Markdown("first".into()),
+            store,
+        )
+        .unwrap();
+    resource.save(store).unwrap();
+    wait();
+    let first_time = crate::utils::now();
+    wait();
+    resource
+        .set_propval(
+            urls::DESCRIPTION.into(),
+            Value::Markdown("second".into()),
+            store,
+        )
+        .unwrap();
+    resource.save(store).unwrap();
+    wait();
+    let second_time = crate::utils::now();
+    wait();
+    resource.destroy(store).unwrap();
+
+    store.get_resource_at(&subject, before).unwrap_err();
+    let description_at = |timestamp: i64| {
+        store
+            .get_resource_at(&subject, timestamp)
+            .unwrap()
+            .get(urls::DESCRIPTION)
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(description_at(first_time), "first");
+    assert_eq!(description_at(second_time), "second");
+    // The second call uses the snapshot
+    assert_eq!(description_at(second_time), "second");
+    store
+        .get_resource_at(&subject, crate::utils::now())
+        .unwrap_err();
+
+    let extended = store
+        .get_resource_extended(&format!("{}?at={}", subject, first_time), false, None)
+        .unwrap();
+    assert_eq!(
+        extended.get(urls::DESCRIPTION).unwrap().to_string(),
+        "first"
+    );
+
+    let members_at = |at: Option<i64>| {
+        crate::collections::CollectionBuilder {
+            subject: format!("{}/described-first", store.get_server_url()),
+            property: Some(urls::DESCRIPTION.into()),
+            value: Some("first".into()),
+            sort_by: None,
+            sort_desc: false,
+            current_page: 0,
+            page_size: 10,
+            name: None,
+            include_nested: false,
+            include_external: false,
+            filter: None,
+            at,
+        }
+        .into_collection(store, None)
+        .unwrap()
+        .members
+    };
+    assert_eq!(members_at(Some(first_time)), vec![subject.clone()]);
+    assert!(members_at(Some(second_time)).is_empty());
+    assert!(members_at(None).is_empty());
+}
+
+#[test]
+/// Older versions are only returned if the Agent can read the current Resource.
+fn resource_at_checks_current_rights() {
+    let store = &Db::init_temp("resource_at_checks_current_rights").unwrap();
+    let wait = || std::thread::sleep(std::time::Duration::from_millis(5));
+    let reader = store.create_agent(Some("reader")).unwrap().subject;
+    let server_agent = store.get_default_agent().unwrap().subject;
+    // Make the Drive private
+    let mut drive = store.get_resource(store.get_server_url()).unwrap();
+    drive
+        .set_propval(urls::READ.into(), vec![server_agent.clone()].into(), store)
+        .unwrap();
+    drive.save(store).unwrap();
+    let mut resource = Resource::new_generate_subject(store);
+    let subject = resource.get_subject().clone();
+    resource
+        .set_propval(
+            urls::PARENT.into(),
+            Value::AtomicUrl(store.get_server_url().into()),
+            store,
+        )
+        .unwrap();
+    resource
+        .set_propval(urls::READ.into(), vec![reader.clone()].into(), store)
+        .unwrap();
+    resource.save(store).unwrap();
+    wait();
+    let readable_time = crate::utils::now();
+    wait();
+    let at_url = format!("{}?at={}", subject, readable_time);
+    store
+        .get_resource_extended(&at_url, false, Some(&reader))
+        .unwrap();
+
+    resource.remove_propval(urls::READ);
+    resource.save(store).unwrap();
+    let err = store
+        .get_resource_extended(&at_url, false, Some(&reader))
+        .unwrap_err();
+    assert!(matches!(
+        err.error_type,
+        crate::errors::AtomicErrorType::UnauthorizedError
+    ));
+
+    resource.destroy(store).unwrap();
+    store
+        .get_resource_extended(&at_url, false, Some(&reader))
+        .unwrap_err();
+    store
+        .get_resource_extended(&at_url, false, Some(&server_agent))
+        .unwrap();
+}
+
+#[test]
+/// Commits can be reverted by signing an inverse Commit, using the Resource from the `/revert` endpoint.
+fn revert_commits() {
+    let store = &Db::init_temp("revert_commits").unwrap();
+    let agent = store.get_default_agent().unwrap();
+    let revert = |commit: &str, agent: &crate::agents::Agent| -> AtomicResult<Resource> {
+        let url = format!(
+            "{}/revert?commit={}",
+            store.get_server_url(),
+            urlencoding::encode(commit)
+        );
+        let resource_old = store.get_resource_extended(&url, false, Some(&agent.subject))?;
+        let commit = crate::Commit::from_resource(store.get_resource(commit)?)?;
+        let inverse = commit.inverse(&resource_old)?.sign(
+            agent,
+            store,
+            &Resource::new(commit.subject.clone()),
+        )?;
+        let opts = crate::commit::CommitOpts {
+            validate_schema: true,
+            validate_signature: true,
+            validate_timestamp: true,
+            validate_rights: true,
+            validate_previous_commit: true,
+            validate_for_agent: None,
+            update_index: true,
+        };
+        Ok(inverse.apply_opts(store, &opts)?.commit_resource)
+    };
+    let description = |subject: &str| {
+        store
+            .get_resource(subject)
+            .unwrap()
+            .get(urls::DESCRIPTION)
+            .map(|v| v.to_string())
+    };
+
+    let mut resource = Resource::new_generate_subject(store);
+    let subject = resource.get_subject().clone();
+    resource
+        .set_propval(
+            urls::PARENT.into(),
+            Value::AtomicUrl(store.get_server_url().into()),
+            store,
+        )
+        .unwrap();
+    resource
+        .set_propval(
+            urls::DESCRIPTION.into(),
+            Value::Markdown("first".into()),
+            store,
+        )
+        .unwrap();
+    let created = resource.save(store).unwrap().commit_resource;
+    resource
+        .set_propval(
+            urls::DESCRIPTION.into(),
+            Value::Markdown("second".into()),
+            store,
+        )
+        .unwrap();
+    resource
+        .set_propval(urls::NAME.into(), Value::String("name".into()), store)
+        .unwrap();
+    let edited = resource.save(store).unwrap().commit_resource;
+
+    let stranger = store.create_agent(Some("stranger")).unwrap();
+    revert(edited.get_subject(), &stranger)
+        .expect_err("Agents without write rights should not be able to revert");
+
+    let inverse = revert(edited.get_subject(), &agent).unwrap();
+    assert_eq!(inverse.get(urls::SUBJECT).unwrap().to_string(), subject);
+    assert_eq!(description(&subject).unwrap(), "first");
+    assert!(store
+        .get_resource(&subject)
+        .unwrap()
+        .get(urls::NAME)
+        .is_err());
+
+    // The Resource has changed since the first Commit, so that one can not be reverted anymore.
+    revert(created.get_subject(), &agent).unwrap_err();
+
+    let mut resource = store.get_resource(&subject).unwrap();
+    let destroyed = resource.destroy(store).unwrap().commit_resource;
+    store.get_resource(&subject).unwrap_err();
+    revert(destroyed.get_subject(), &agent).unwrap();
+    assert_eq!(description(&subject).unwrap(), "first");
+}
+
+#[test]
+/// A follower applies the Commits of a primary in order, and resumes where it left off.
+fn replicate_commits() {
+    use crate::replication::{commits_since, pull_with};
+
+    let primary = &Db::init_temp("replicate_commits").unwrap();
+    let follower = &Db::init(&BackendConfig::Memory, "https://follower.localhost".into()).unwrap();
+    let follower_agent = follower.create_agent(None).unwrap();
+    follower.set_default_agent(follower_agent);
+    follower.populate().unwrap();
+    let primary_url = primary.get_server_url();
+    let agent = primary.get_default_agent().unwrap();
+    // The follower needs the public key of the signer
+    follower
+        .add_resource(&primary.get_resource(&agent.subject).unwrap())
+        .unwrap();
+    let fetch = |since: u64| commits_since(primary, since, None);
+
+    let mut resource = Resource::new_generate_subject(primary);
+    let subject = resource.get_subject().clone();
+    resource
+        .set_propval(
+            urls::PARENT.into(),
+            Value::AtomicUrl(primary_url.into()),
+            primary,
+        )
+        .unwrap();
+    resource
+        .set_propval(
+            urls::DESCRIPTION.into(),
+            Value::Markdown("first".into()),
+            primary,
+        )
+        .unwrap();
+    resource.save(primary).unwrap();
+    let mut destroyed = Resource::new_generate_subject(primary);
+    destroyed
+        .set_propval(
+            urls::PARENT.into(),
+            Value::AtomicUrl(primary_url.into()),
+            primary,
+        )
+        .unwrap();
+    destroyed.save(primary).unwrap();
+    destroyed.destroy(primary).unwrap();
+
+    let applied = pull_with(follower, primary_url, fetch).unwrap();
+    assert!(applied >= 3);
+    let replicated = follower.get_resource(&subject).unwrap();
+    assert_eq!(
+        replicated.get(urls::DESCRIPTION).unwrap().to_string(),
+        "first"
+    );
+    assert!(follower.get_propvals(destroyed.get_subject()).is_err());
+    // The cursor is the last entry in the commit log of the primary
+    let last_seq = primary
+        .commit_log(0, usize::MAX)
+        .unwrap()
+        .last()
+        .map(|(seq, _subject)| *seq);
+    assert_eq!(
+        follower.get_replication_cursor(primary_url).unwrap(),
+        last_seq
+    );
+
+    // Nothing new, so nothing is applied
+    assert_eq!(pull_with(follower, primary_url, fetch).unwrap(), 0);
+
+    resource
+        .set_propval(
+            urls::DESCRIPTION.into(),
+            Value::Markdown("second".into()),
+            primary,
+        )
+        .unwrap();
+    resource.save(primary).unwrap();
+    assert_eq!(pull_with(follower, primary_url, fetch).unwrap(), 1);
+    let replicated = follower.get_resource(&subject).unwrap();
+    assert_eq!(
+        replicated.get(urls::DESCRIPTION).unwrap().to_string(),
+        "second"
+    );
+}
+
+#[test]
+/// Applied Commits are appended to the commit log, which can be read using the `/changes` endpoint.
+fn commit_log() {
+    let backend: Arc<dyn KeyValueBackend> = Arc::new(memory_backend::MemoryBackend::default());
+    let store = &Db::init_with_backend(backend.clone(), "https://localhost".into()).unwrap();
+    let agent = store.create_agent(None).unwrap();
+    store.set_default_agent(agent.clone());
+    store.populate().unwrap();
+    // Make the Drive private
+    let mut drive = store.get_resource(store.get_server_url()).unwrap();
+    drive
+        .set_propval(urls::READ.into(), vec![agent.subject.clone()].into(), store)
+        .unwrap();
+    drive.save(store).unwrap();
+    let since = store
+        .commit_log(0, usize::MAX)
+        .unwrap()
+        .last()
+        .map(|(seq, _subject)| *seq)
+        .unwrap_or(0);
+
+    let mut resource = Resource::new_generate_subject(store);
+    resource
+        .set_propval(
+            urls::PARENT.into(),
+            Value::AtomicUrl(store.get_server_url().into()),
+            store,
+        )
+        .unwrap();
+    let first = resource.save(store).unwrap().commit_resource;
+    resource
+        .set_propval(
+            urls::DESCRIPTION.into(),
+            Value::Markdown("changed".into()),
+            store,
+        )
+        .unwrap();
+    let second = resource.save(store).unwrap().commit_resource;
+
+    let log = store.commit_log(since, 10).unwrap();
+    assert_eq!(log.len(), 2);
+    assert!(log[0].0 > since && log[1].0 > log[0].0);
+    assert_eq!(&log[0].1, first.get_subject());
+    assert_eq!(&log[1].1, second.get_subject());
+
+    let changes = |query: &str, for_agent: &str| {
+        store
+            .get_resource_extended(
+                &format!("{}/changes?{}", store.get_server_url(), query),
+                false,
+                Some(for_agent),
+            )
+            .unwrap()
+    };
+    let page = changes(&format!("since={}&page_size=1", since), &agent.subject);
+    let results = page
+        .get(urls::ENDPOINT_RESULTS)
+        .unwrap()
+        .to_subjects(None)
+        .unwrap();
+    assert_eq!(results, vec![first.get_subject().clone()]);
+    let next = page.get(urls::CHANGES_NEXT).unwrap().to_int().unwrap();
+    assert_eq!(next as u64, log[0].0);
+    let page = changes(&format!("since={}", next), &agent.subject);
+    let results = page
+        .get(urls::ENDPOINT_RESULTS)
+        .unwrap()
+        .to_subjects(None)
+        .unwrap();
+    assert_eq!(results, vec![second.get_subject().clone()]);
+
+    // Commits that the reader can't read are skipped, but the cursor moves on
+    let stranger = store.create_agent(Some("stranger")).unwrap();
+    let page = changes(&format!("since={}", since), &stranger.subject);
+    assert!(page
+        .get(urls::ENDPOINT_RESULTS)
+        .unwrap()
+        .to_subjects(None)
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        page.get(urls::CHANGES_NEXT).unwrap().to_int().unwrap() as u64,
+        log[1].0
+    );
+
+    // The log is built from the existing Commits for Dbs that don't have one yet
+    backend.drop_tree("commit_log").unwrap();
+    let reopened = Db::init_with_backend(backend, "https://localhost".into()).unwrap();
+    let rebuilt = reopened.commit_log(0, usize::MAX).unwrap();
+    assert!(rebuilt
+        .iter()
+        .any(|(_seq, subject)| subject == second.get_subject()));
+}
+
+#[test]
+/// Sequence numbers in the commit log have no gaps, also with failed Transactions and concurrent Commits.
+fn commit_log_without_gaps() {
+    let store = &Db::init_temp("commit_log_without_gaps").unwrap();
+    let new_resource = |store: &Db| {
+        let mut resource = Resource::new_generate_subject(store);
+        resource
+            .set_propval(
+                urls::PARENT.into(),
+                Value::AtomicUrl(store.get_server_url().into()),
+                store,
+            )
+            .unwrap();
+        resource
+    };
+    let start = store.last_commit_seq().unwrap();
+
+    let failed: AtomicResult<()> = store.all_or_nothing(|staged| {
+        new_resource(staged).save(staged)?;
+        Err("Something went wrong".into())
+    });
+    failed.unwrap_err();
+    assert_eq!(store.last_commit_seq().unwrap(), start);
+
+    store
+        .all_or_nothing(|staged| {
+            new_resource(staged).save(staged)?;
+            new_resource(staged).save(staged)?;
+            Ok(())
+        })
+        .unwrap();
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let store = store.clone();
+            std::thread::spawn(move || {
+                for _ in 0..10 {
+                    new_resource(&store).save(&store).unwrap();
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let seqs: Vec<u64> = store
+        .commit_log(start, usize::MAX)
+        .unwrap()
+        .into_iter()
+        .map(|(seq, _subject)| seq)
+        .collect();
+    assert_eq!(seqs, (start + 1..=start + 42).collect::<Vec<u64>>());
+}
+
+#[test]
+/// Index cursors point into the commit log, and survive a restart.
+fn index_cursors() {
+    let backend: Arc<dyn KeyValueBackend> = Arc::new(memory_backend::MemoryBackend::default());
+    let store = &Db::init_with_backend(backend.clone(), "https://localhost".into()).unwrap();
+    let agent = store.create_agent(None).unwrap();
+    store.set_default_agent(agent);
+    store.populate().unwrap();
+    assert_eq!(store.get_index_cursor("search").unwrap(), 0);
+
+    let mut resource = Resource::new_generate_subject(store);
+    resource
+        .set_propval(
+            urls::PARENT.into(),
+            Value::AtomicUrl(store.get_server_url().into()),
+            store,
+        )
+        .unwrap();
+    let commit = resource.save(store).unwrap().commit_resource;
+    let last = store.last_commit_seq().unwrap();
+    let pending = store
+        .commit_log(store.get_index_cursor("search").unwrap(), usize::MAX)
+        .unwrap();
+    assert_eq!(
+        pending.last().unwrap(),
+        &(last, commit.get_subject().clone())
+    );
+
+    store.set_index_cursor("search", last).unwrap();
+    let reopened = Db::init_with_backend(backend, "https://localhost".into()).unwrap();
+    assert_eq!(reopened.get_index_cursor("search").unwrap(), last);
+    assert!(reopened.commit_log(last, usize::MAX).unwrap().is_empty());
+    assert_eq!(reopened.get_index_cursor("other").unwrap(), 0);
+}
+
+#[test]
+#[timeout(30000)]
+fn export_import_ndjson() {
+    let store = &Db::init_temp("export_import_ndjson").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    let since = crate::utils::now();
+    let mut resource = Resource::new_generate_subject(store);
+    resource
+        .set_propval(
+            urls::DESCRIPTION.into(),
+            Value::Markdown("exported".into()),
+            store,
+        )
+        .unwrap();
+    let commit = resource.save_locally(store).unwrap().commit_resource;
+
+    let mut full = Vec::new();
+    let full_count = store.export_ndjson(&mut full, true, None).unwrap();
+    let mut incremental = Vec::new();
+    let count = store
+        .export_ndjson(&mut incremental, true, Some(since))
+        .unwrap();
+    assert_eq!(full_count, String::from_utf8(full).unwrap().lines().count());
+    assert_eq!(
+        count, 2,
+        "Only the new Resource and its Commit are exported"
+    );
+    let incremental = String::from_utf8(incremental).unwrap();
+    assert!(incremental.contains(resource.get_subject()));
+    assert!(incremental.contains(commit.get_subject()));
+
+    let target = &Db::init_temp("export_import_ndjson_target").unwrap();
+    let parse_opts = crate::parse::ParseOpts {
+        save: crate::parse::SaveOpts::Save,
+        ..Default::default()
+    };
+    let imported = target
+        .import_ndjson(&mut format!("\n{}", incremental).as_bytes(), &parse_opts)
+        .unwrap();
+    assert_eq!(imported, 2);
+    let found = target.get_resource(resource.get_subject()).unwrap();
+    assert_eq!(
+        found.get(urls::DESCRIPTION).unwrap().to_string(),
+        "exported"
+    );
+
+    // Destroyed Resources are removed by importing the Commits that destroyed them
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    let destroyed_since = crate::utils::now();
+    resource.destroy(store).unwrap();
+    let mut deletions = Vec::new();
+    store
+        .export_ndjson(&mut deletions, true, Some(destroyed_since))
+        .unwrap();
+    target
+        .import_ndjson(&mut deletions.as_slice(), &parse_opts)
+        .unwrap();
+    target
+        .get_resource_local(resource.get_subject())
+        .unwrap_err();
+}
+
+#[test]
+/// Rotated keys keep verifying the Commits they signed, but can't sign new ones.
+fn rotate_and_revoke_agent_keys() {
+    let store = Db::init_temp("rotate_and_revoke_agent_keys").unwrap();
+    let agent = crate::agents::Agent::new(Some("rotating"), &store).unwrap();
+    store.add_resource(&agent.to_resource().unwrap()).unwrap();
+    let opts = crate::commit::CommitOpts {
+        validate_schema: true,
+        validate_signature: true,
+        validate_timestamp: true,
+        validate_rights: true,
+        validate_previous_commit: true,
+        validate_for_agent: None,
+        update_index: true,
+    };
+    let subject = format!("{}/rotated-keys", store.get_server_url());
+    let sign_commit = |agent: &crate::agents::Agent, description: &str| {
+        let mut builder = crate::commit::CommitBuilder::new(subject.clone());
+        builder.set(urls::PARENT.into(), Value::AtomicUrl(agent.subject.clone()));
+        builder.set(
+            urls::DESCRIPTION.into(),
+            Value::Markdown(description.into()),
+        );
+        let resource = store
+            .get_resource(&subject)
+            .unwrap_or_else(|_| Resource::new(subject.clone()));
+        builder.sign(agent, &store, &resource).unwrap()
+    };
+    let old_commit = sign_commit(&agent, "signed with the first key");
+    old_commit.apply_opts(&store, &opts).unwrap();
+
+    std::thread::sleep(std::time::Duration::from_millis(2));
+    let (rotated, rotate_commit) = agent.rotate_key(&store).unwrap();
+    assert_eq!(rotated.subject, agent.subject);
+    assert_ne!(rotated.public_key, agent.public_key);
+    rotate_commit.apply_opts(&store, &opts).unwrap();
+    let keys = crate::agents::get_keys(&store.get_resource(&agent.subject).unwrap()).unwrap();
+    assert_eq!(keys.len(), 2);
+
+    // Existing Commits are verified with the key that was valid when they were created
+    old_commit.verify_signature(&store).unwrap();
+    rotate_commit.verify_signature(&store).unwrap();
+    // The old key can no longer be used
+    sign_commit(&agent, "signed with a rotated key")
+        .apply_opts(&store, &opts)
+        .unwrap_err();
+    let now = crate::utils::now();
+    crate::agents::get_valid_key(&store, &agent.subject, &agent.public_key, now).unwrap_err();
+    crate::agents::get_valid_key(&store, &agent.subject, &rotated.public_key, now).unwrap();
+    sign_commit(&rotated, "signed with the new key")
+        .apply_opts(&store, &opts)
+        .unwrap();
+
+    // The history of keys can't be rewritten
+    let mut restore = crate::commit::CommitBuilder::new(agent.subject.clone());
+    restore.set(
+        urls::PUBLIC_KEY.into(),
+        Value::String(agent.public_key.clone()),
+    );
+    let agent_resource = store.get_resource(&agent.subject).unwrap();
+    restore
+        .sign(&rotated, &store, &agent_resource)
+        .unwrap()
+        .apply_opts(&store, &opts)
+        .unwrap_err();
+    rotated
+        .revoke_key(&rotated.public_key, now, &store)
+        .unwrap_err();
+
+    // Revoking the old key from before it signed anything invalidates its Commits
+    rotated
+        .revoke_key(&agent.public_key, old_commit.created_at - 1, &store)
+        .unwrap()
+        .apply_opts(&store, &opts)
+        .unwrap();
+    old_commit.verify_signature(&store).unwrap_err();
+}
+
+#[test]
+/// Rights that are given to a Group apply to the members of its (nested) Groups.
+fn group_rights() {
+    let store = &Db::init_temp("group_rights").unwrap();
+    let agent = store.create_agent(Some("member")).unwrap().subject;
+    let new_group = |name: &str| {
+        let mut group = Resource::new_instance(urls::GROUP, store).unwrap();
+        group
+            .set_propval(urls::NAME.into(), Value::String(name.into()), store)
+            .unwrap();
+        group
+            .set_propval(
+                urls::PARENT.into(),
+                Value::AtomicUrl(store.get_server_url().into()),
+                store,
+            )
+            .unwrap();
+        group.save_locally(store).unwrap();
+        group
+    };
+    let mut team = new_group("team");
+    let mut department = new_group("department");
+    // The Groups contain each other
+    team.push_propval(
+        urls::MEMBERS,
+        department.get_subject().as_str().into(),
+        true,
+    )
+    .unwrap();
+    team.save_locally(store).unwrap();
+    department
+        .push_propval(urls::MEMBERS, team.get_subject().as_str().into(), true)
+        .unwrap();
+    department.save_locally(store).unwrap();
+
+    // Without a parent, so it only has its own rights
+    let mut document = Resource::new_generate_subject(store);
+    document
+        .push_propval(urls::READ, team.get_subject().as_str().into(), true)
+        .unwrap();
+    document.save_locally(store).unwrap();
+    crate::hierarchy::check_read(store, &document, &agent).unwrap_err();
+
+    // Invites to a Group add the Agent as a member
+    crate::plugins::invite::add_rights(&agent, department.get_subject(), false, store).unwrap();
+    let department = store.get_resource(department.get_subject()).unwrap();
+    assert!(department
+        .get(urls::MEMBERS)
+        .unwrap()
+        .to_subjects(None)
+        .unwrap()
+        .contains(&agent));
+    crate::hierarchy::check_read(store, &document, &agent).unwrap();
+    crate::hierarchy::check_write(store, &document, &agent).unwrap_err();
+
+    // Removing the Agent from the Group takes away its rights
+    let mut department = department;
+    department
+        .set_propval(
+            urls::MEMBERS.into(),
+            vec![team.get_subject().to_string()].into(),
+            store,
+        )
+        .unwrap();
+    department.save_locally(store).unwrap();
+    crate::hierarchy::check_read(store, &document, &agent).unwrap_err();
+}
+
+#[test]
+/// Denials override grants from parents, and hidden properties are removed from everything an Agent reads.
+fn deny_rules_and_hidden_properties() {
+    let store = &Db::init_temp("deny_rules_and_hidden_properties").unwrap();
+    let agent = store.create_agent(Some("employee")).unwrap().subject;
+    let hr = store.create_agent(Some("hr")).unwrap().subject;
+    let new_child = |parent: Option<&str>| {
+        let mut resource = Resource::new_generate_subject(store);
+        if let Some(parent) = parent {
+            resource
+                .set_propval(urls::PARENT.into(), Value::AtomicUrl(parent.into()), store)
+                .unwrap();
+        }
+        resource
+    };
+    let mut root = new_child(None);
+    root.push_propval(urls::READ, agent.as_str().into(), true)
+        .unwrap();
+    root.push_propval(urls::WRITE, agent.as_str().into(), true)
+        .unwrap();
+    root.save_locally(store).unwrap();
+    let mut blocked = new_child(Some(root.get_subject()));
+    blocked
+        .push_propval(urls::DENY_READ, agent.as_str().into(), true)
+        .unwrap();
+    blocked.save_locally(store).unwrap();
+    let mut nested = new_child(Some(blocked.get_subject()));
+    // Grants inside the blocked subtree do not override the denial
+    nested
+        .push_propval(urls::READ, agent.as_str().into(), true)
+        .unwrap();
+    nested.save_locally(store).unwrap();
+    crate::hierarchy::check_read(store, &root, &agent).unwrap();
+    crate::hierarchy::check_read(store, &blocked, &agent).unwrap_err();
+    crate::hierarchy::check_read(store, &nested, &agent).unwrap_err();
+    crate::hierarchy::check_write(store, &nested, &agent).unwrap();
+    store
+        .get_resource_extended(nested.get_subject(), false, Some(&agent))
+        .unwrap_err();
+
+    let mut salary = Resource::new_instance(urls::PROPERTY, store).unwrap();
+    salary.set_subject(format!("{}/salary", store.get_server_url()));
+    salary
+        .set_propval_string(urls::SHORTNAME.into(), "salary", store)
+        .unwrap();
+    salary
+        .set_propval_string(urls::DESCRIPTION.into(), "Yearly salary", store)
+        .unwrap();
+    salary
+        .set_propval(
+            urls::DATATYPE_PROP.into(),
+            Value::AtomicUrl(urls::INTEGER.into()),
+            store,
+        )
+        .unwrap();
+    salary
+        .push_propval(urls::PROPERTY_READ, hr.as_str().into(), true)
+        .unwrap();
+    salary.save_locally(store).unwrap();
+
+    let mut person = new_child(Some(root.get_subject()));
+    person
+        .set_propval(urls::NAME.into(), Value::String("Alice".into()), store)
+        .unwrap();
+    person
+        .set_propval(salary.get_subject().into(), Value::Integer(50000), store)
+        .unwrap();
+    person
+        .push_propval(urls::READ, hr.as_str().into(), true)
+        .unwrap();
+    let commit = person.save_locally(store).unwrap().commit_resource;
+
+    let seen = store
+        .get_resource_extended(person.get_subject(), false, Some(&agent))
+        .unwrap();
+    assert_eq!(seen.get(urls::NAME).unwrap().to_string(), "Alice");
+    seen.get(salary.get_subject()).unwrap_err();
+    let seen_by_hr = store
+        .get_resource_extended(person.get_subject(), false, Some(&hr))
+        .unwrap();
+    assert_eq!(
+        seen_by_hr
+            .get(salary.get_subject())
+            .unwrap()
+            .to_int()
+            .unwrap(),
+        50000
+    );
+
+    // The value is also removed from the Commit that set it
+    let seen_commit = store
+        .get_resource_extended(commit.get_subject(), false, Some(&agent))
+        .unwrap();
+    let set = seen_commit.get(urls::SET).unwrap().to_nested().unwrap();
+    assert!(set.contains_key(urls::NAME));
+    assert!(!set.contains_key(salary.get_subject()));
+
+    // Query results don't include hidden values, and can't be sorted by them
+    let mut query = Query::new_prop_val(urls::PARENT, root.get_subject());
+    query.for_agent = Some(agent.clone());
+    let results = store.query(&query).unwrap();
+    let found = results
+        .resources
+        .iter()
+        .find(|r| r.get_subject() == person.get_subject())
+        .unwrap();
+    found.get(salary.get_subject()).unwrap_err();
+    query.sort_by = Some(salary.get_subject().into());
+    assert!(store.query(&query).is_err());
+}
+
+#[test]
+#[timeout(30000)]
+fn bearer_tokens() {
+    let store = &Db::init_temp("bearer_tokens").unwrap();
+    let agent = crate::agents::Agent::new(Some("ci"), store).unwrap();
+    store.add_resource(&agent.to_resource().unwrap()).unwrap();
+    let new_resource = |parent: Option<&str>| {
+        let mut resource = Resource::new_generate_subject(store);
+        match parent {
+            Some(parent) => resource
+                .set_propval(urls::PARENT.into(), Value::AtomicUrl(parent.into()), store)
+                .unwrap(),
+            None => {
+                resource
+                    .push_propval(urls::READ, agent.subject.as_str().into(), true)
+                    .unwrap();
+                resource
+                    .push_propval(urls::WRITE, agent.subject.as_str().into(), true)
+                    .unwrap();
+            }
+        }
+        resource.save_locally(store).unwrap();
+        resource
+    };
+    let root = new_resource(None);
+    let child = new_resource(Some(root.get_subject()));
+    let outside = new_resource(None);
+    let expires_at = crate::utils::now() + 60_000;
+
+    let (scoped, secret) = crate::tokens::create_token(
+        store,
+        &agent.subject,
+        expires_at,
+        Some(root.get_subject()),
+        true,
+    )
+    .unwrap();
+    let token = crate::tokens::check_bearer(store, &secret).unwrap();
+    assert_eq!(&token, scoped.get_subject());
+    crate::tokens::check_bearer(store, &format!("{}x", secret)).unwrap_err();
+    // Scoped and read-only
+    crate::hierarchy::check_read(store, &child, &token).unwrap();
+    crate::hierarchy::check_write(store, &child, &token).unwrap_err();
+    crate::hierarchy::check_read(store, &outside, &token).unwrap_err();
+    crate::hierarchy::check_write(store, &outside, &agent.subject).unwrap();
+    // Tokens can't issue Tokens, and can't be edited
+    crate::tokens::create_token(store, &token, expires_at, None, false).unwrap_err();
+    let mut edited = store.get_resource(&token).unwrap();
+    edited
+        .set_propval(urls::TOKEN_READ_ONLY.into(), Value::Boolean(false), store)
+        .unwrap();
+    edited.save_locally(store).unwrap_err();
+
+    let (_, secret) =
+        crate::tokens::create_token(store, &agent.subject, expires_at, None, false).unwrap();
+    let token = crate::tokens::check_bearer(store, &secret).unwrap();
+    crate::hierarchy::check_write(store, &outside, &token).unwrap();
+
+    // Revoked by the Agent, by destroying the Token
+    let opts = crate::commit::CommitOpts {
+        validate_schema: true,
+        validate_signature: true,
+        validate_timestamp: true,
+        validate_rights: true,
+        validate_previous_commit: false,
+        validate_for_agent: None,
+        update_index: true,
+    };
+    let mut revoke = crate::commit::CommitBuilder::new(token.clone());
+    revoke.destroy(true);
+    revoke
+        .sign(&agent, store, &store.get_resource(&token).unwrap())
+        .unwrap()
+        .apply_opts(store, &opts)
+        .unwrap();
+    crate::tokens::check_bearer(store, &secret).unwrap_err();
+    crate::hierarchy::check_read(store, &outside, &token).unwrap_err();
+
+    crate::tokens::create_token(store, &agent.subject, crate::utils::now() - 1, None, false)
+        .unwrap_err();
+}
+
+#[test]
+#[timeout(30000)]
+fn audit_log() {
+    let mut store = Db::init_temp("audit_log").unwrap();
+    store.set_audit(Some(crate::audit::AuditConfig { retention: None }));
+    let agent = store.create_agent(Some("auditee")).unwrap().subject;
+    let mut resource = Resource::new_generate_subject(&store);
+    resource
+        .push_propval(urls::READ, agent.as_str().into(), true)
+        .unwrap();
+    resource.save_locally(&store).unwrap();
+    crate::hierarchy::check_audited(&store, &resource, &agent, crate::hierarchy::Right::Read)
+        .unwrap();
+    crate::hierarchy::check_audited(&store, &resource, &agent, crate::hierarchy::Right::Write)
+        .unwrap_err();
+    // Checks that are not about the resource of a request are not recorded
+    crate::hierarchy::check_read(&store, &resource, &agent).unwrap();
+
+    let filter = crate::audit::AuditFilter {
+        agent: Some(agent.clone()),
+        ..Default::default()
+    };
+    let events = store.audit_events(0, 10, &filter).unwrap();
+    assert_eq!(events.len(), 2);
+    assert!(events[0].0 < events[1].0);
+    assert_eq!(events[0].1.right, urls::READ);
+    assert!(events[0].1.allowed);
+    assert_eq!(events[1].1.right, urls::WRITE);
+    assert!(!events[1].1.allowed);
+    let denied = crate::audit::AuditFilter {
+        allowed: Some(false),
+        ..filter.clone()
+    };
+    assert_eq!(store.audit_events(0, 10, &denied).unwrap().len(), 1);
+    assert!(store
+        .audit_events(events[1].0, 10, &filter)
+        .unwrap()
+        .is_empty());
+    let by_subject = crate::audit::AuditFilter {
+        subject: Some(resource.get_subject().into()),
+        right: Some(urls::WRITE.into()),
+        ..Default::default()
+    };
+    let written = store.audit_events(0, 10, &by_subject).unwrap();
+    assert_eq!(written.len(), 1);
+    assert_eq!(written[0].0, events[1].0);
+    let in_time = |from, until| crate::audit::AuditFilter {
+        from: Some(from),
+        until: Some(until),
+        ..filter.clone()
+    };
+    let (first, second) = (events[0].1.timestamp, events[1].1.timestamp);
+    assert_eq!(
+        store
+            .audit_events(0, 10, &in_time(first, second + 1))
+            .unwrap()
+            .len(),
+        2
+    );
+    assert!(store
+        .audit_events(0, 10, &in_time(second + 1, second + 2))
+        .unwrap()
+        .is_empty());
+    assert!(store
+        .audit_events(0, 10, &in_time(first - 1, first))
+        .unwrap()
+        .is_empty());
+
+    // Only the server's Agent can read the audit log
+    let endpoint = format!(
+        "{}/audit?agent={}",
+        store.get_server_url(),
+        urlencoding::encode(&agent)
+    );
+    store
+        .get_resource_extended(&endpoint, false, Some(&agent))
+        .unwrap_err();
+    let server_agent = store.get_default_agent().unwrap().subject;
+    let page = store
+        .get_resource_extended(&endpoint, false, Some(&server_agent))
+        .unwrap();
+    match page.get(urls::ENDPOINT_RESULTS).unwrap() {
+        Value::ResourceArray(results) => assert_eq!(results.len(), 2),
+        _ => panic!("Expected a list of events"),
+    }
+
+    let mut export = Vec::new();
+    let count = store.export_audit_jsonl(&mut export, &filter).unwrap();
+    assert_eq!(count, 2);
+    assert_eq!(String::from_utf8(export).unwrap().lines().count(), 2);
+
+    // Old events are removed
+    std::thread::sleep(std::time::Duration::from_millis(2));
+    store.set_audit(Some(crate::audit::AuditConfig { retention: Some(1) }));
+    assert!(store.prune_audit_log().unwrap() >= 2);
+    assert!(store.audit_events(0, 10, &filter).unwrap().is_empty());
+}
+
+#[test]
+#[timeout(30000)]
+fn drive_quotas() {
+    let store = &mut Db::init_temp("drive_quotas").unwrap();
+    let agent = store.create_agent(Some("quota_user")).unwrap();
+    let mut drive = Resource::new_instance(urls::DRIVE, store).unwrap();
+    drive
+        .set_propval_string(urls::NAME.into(), "Quota drive", store)
+        .unwrap();
+    drive
+        .push_propval(urls::WRITE, agent.subject.as_str().into(), true)
+        .unwrap();
+    drive.save_locally(store).unwrap();
+    let drive = drive.get_subject().clone();
+    let mut existing = Resource::new_generate_subject(store);
+    existing
+        .set_propval_string(urls::PARENT.into(), &drive, store)
+        .unwrap();
+    existing.save_locally(store).unwrap();
+
+    store
+        .set_drive_quota(Some(crate::quotas::DriveQuota {
+            max_resources: Some(2),
+            max_upload_bytes: Some(100),
+        }))
+        .unwrap();
+    assert_eq!(store.drive_usage(&drive).unwrap().resources, 1);
+
+    let opts = crate::commit::CommitOpts {
+        validate_schema: true,
+        validate_signature: true,
+        validate_timestamp: true,
+        validate_rights: true,
+        validate_previous_commit: false,
+        validate_for_agent: None,
+        update_index: true,
+    };
+    let create_child = |store: &Db| {
+        let mut child = Resource::new_generate_subject(store);
+        child
+            .set_propval_string(urls::PARENT.into(), &drive, store)
+            .unwrap();
+        child
+            .get_commit_builder()
+            .clone()
+            .sign(&agent, store, &child)
+            .unwrap()
+            .apply_opts(store, &opts)
+    };
+    let child = create_child(store).unwrap().resource_new.unwrap();
+    assert_eq!(store.drive_usage(&drive).unwrap().resources, 2);
+    let err = create_child(store).unwrap_err();
+    assert!(matches!(
+        err.error_type,
+        crate::AtomicErrorType::LimitExceeded
+    ));
+
+    // Destroying a resource frees up space
+    let mut destroy = crate::commit::CommitBuilder::new(child.get_subject().clone());
+    destroy.destroy(true);
+    destroy
+        .sign(&agent, store, &child)
+        .unwrap()
+        .apply_opts(store, &opts)
+        .unwrap();
+    assert_eq!(store.drive_usage(&drive).unwrap().resources, 1);
+    create_child(store).unwrap();
+
+    // Uploads are checked by their size
+    let upload = |bytes| crate::quotas::DriveUsage {
+        resources: 0,
+        upload_bytes: bytes,
+    };
+    store.check_drive_quota_for(&drive, &upload(100)).unwrap();
+    store
+        .check_drive_quota_for(&drive, &upload(101))
+        .unwrap_err();
+
+    // Changing the filesize changes the usage
+    let edit = |store: &Db, subject: &str, prop: &str, value: Value| {
+        let resource = store.get_resource(subject).unwrap();
+        let mut builder = crate::commit::CommitBuilder::new(subject.into());
+        builder.set(prop.into(), value);
+        builder
+            .sign(&agent, store, &resource)
+            .unwrap()
+            .apply_opts(store, &opts)
+    };
+    let existing = existing.get_subject();
+    edit(store, existing, urls::FILESIZE, Value::Integer(50)).unwrap();
+    assert_eq!(store.drive_usage(&drive).unwrap().upload_bytes, 50);
+    let err = edit(store, existing, urls::FILESIZE, Value::Integer(101)).unwrap_err();
+    assert!(matches!(
+        err.error_type,
+        crate::AtomicErrorType::LimitExceeded
+    ));
+    edit(store, existing, urls::FILESIZE, Value::Integer(20)).unwrap();
+    assert_eq!(store.drive_usage(&drive).unwrap().upload_bytes, 20);
+
+    // Moving a resource to another Drive moves its descendants as well
+    let mut other = Resource::new_instance(urls::DRIVE, store).unwrap();
+    other
+        .push_propval(urls::WRITE, agent.subject.as_str().into(), true)
+        .unwrap();
+    other.save_locally(store).unwrap();
+    let other = other.get_subject().clone();
+    let mut folder = Resource::new_generate_subject(store);
+    folder
+        .set_propval_string(urls::PARENT.into(), &other, store)
+        .unwrap();
+    folder.save_locally(store).unwrap();
+    let folder = folder.get_subject().clone();
+    let mut nested = Resource::new_generate_subject(store);
+    nested
+        .set_propval_string(urls::PARENT.into(), &folder, store)
+        .unwrap();
+    nested
+        .set_propval(urls::FILESIZE.into(), Value::Integer(30), store)
+        .unwrap();
+    nested.save_locally(store).unwrap();
+    assert_eq!(store.drive_usage(&other).unwrap().resources, 2);
+    let move_folder = |store: &Db| {
+        edit(
+            store,
+            &folder,
+            urls::PARENT,
+            Value::AtomicUrl(drive.clone()),
+        )
+    };
+    let err = move_folder(store).unwrap_err();
+    assert!(matches!(
+        err.error_type,
+        crate::AtomicErrorType::LimitExceeded
+    ));
+
+    store
+        .set_drive_quota(Some(crate::quotas::DriveQuota {
+            max_resources: Some(4),
+            max_upload_bytes: Some(100),
+        }))
+        .unwrap();
+    move_folder(store).unwrap();
+    let usage = |store: &Db, drive: &str| store.drive_usage(drive).unwrap();
+    assert_eq!(
+        usage(store, &drive),
+        crate::quotas::DriveUsage {
+            resources: 4,
+            upload_bytes: 50
+        }
+    );
+    assert_eq!(usage(store, &other), crate::quotas::DriveUsage::default());
+    // The same as counting all resources again
+    let counted = (usage(store, &drive), usage(store, &other));
+    store.set_drive_quota(store.drive_quota.clone()).unwrap();
+    assert_eq!((usage(store, &drive), usage(store, &other)), counted);
+
+    // Concurrent Commits don't lose each other's changes to the usage
+    store
+        .set_drive_quota(Some(crate::quotas::DriveQuota {
+            max_resources: Some(1000),
+            max_upload_bytes: None,
+        }))
+        .unwrap();
+    let before = usage(store, &drive).resources;
+    let mut child = Resource::new_generate_subject(store);
+    child
+        .set_propval_string(urls::PARENT.into(), &drive, store)
+        .unwrap();
+    std::thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                for _ in 0..50 {
+                    store.update_drive_usage(None, Some(&child)).unwrap();
+                }
+            });
+        }
+    });
+    assert_eq!(usage(store, &drive).resources, before + 400);
+
+    // Commits that are applied at the same time can't exceed the quota together
+    store.set_drive_quota(None).unwrap();
+    let before = usage(store, &drive).resources;
+    store
+        .set_drive_quota(Some(crate::quotas::DriveQuota {
+            max_resources: Some(before + 3),
+            max_upload_bytes: None,
+        }))
+        .unwrap();
+    let created = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..8)
+            .map(|_| scope.spawn(|| create_child(store).is_ok()))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .filter(|ok| *ok)
+            .count() as u64
+    });
+    assert_eq!(created, 3);
+    assert_eq!(usage(store, &drive).resources, before + 3);
+}
+
+#[test]
+/// Commits based on an older version are merged, unless they change the same properties.
+/// The history is ordered by the commit log, so Commits that share a `createdAt` are merged in the order in which they were applied.
+fn merges_concurrent_commits() {
+    let store = &Db::init_temp("merges_concurrent_commits").unwrap();
+    let agent = store.get_default_agent().unwrap();
+    let subject = format!("{}/concurrent", store.get_server_url());
+    let opts = crate::commit::CommitOpts {
+        validate_schema: true,
+        validate_signature: true,
+        validate_timestamp: true,
+        validate_previous_commit: true,
+        validate_rights: false,
+        validate_for_agent: None,
+        update_index: true,
+    };
+    let created_at = crate::utils::now();
+    let edit = |prop: &str, val: Value, base: &Resource| {
+        let mut builder = crate::commit::CommitBuilder::new(subject.clone());
+        builder.set(prop.into(), val);
+        builder
+            .sign_at(&agent, store, base, created_at)
+            .unwrap()
+            .apply_opts(store, &opts)
+    };
+
+    let empty = Resource::new(subject.clone());
+    edit(urls::DESCRIPTION, Value::Markdown("base".into()), &empty).unwrap();
+    let base = store.get_resource(&subject).unwrap();
+    edit(urls::DESCRIPTION, Value::Markdown("first".into()), &base).unwrap();
+    // Changes a different property, based on the same version
+    edit(urls::NAME, Value::String("second".into()), &base).unwrap();
+    let merged = store.get_resource(&subject).unwrap();
+    assert_eq!(merged.get(urls::DESCRIPTION).unwrap().to_string(), "first");
+    assert_eq!(merged.get(urls::NAME).unwrap().to_string(), "second");
+
+    // Changes a property that has been changed after the base version
+    let err = edit(urls::DESCRIPTION, Value::Markdown("third".into()), &base).unwrap_err();
+    match err.error_type {
+        crate::AtomicErrorType::ConflictError(properties) => {
+            assert_eq!(properties, vec![urls::DESCRIPTION.to_string()])
+        }
+        other => panic!("Expected a conflict, got {:?}", other),
+    }
+    let err_resource = edit(urls::DESCRIPTION, Value::Markdown("third".into()), &base)
+        .unwrap_err()
+        .into_resource("https://localhost/error".into());
+    assert_eq!(
+        err_resource
+            .get(urls::CONFLICTING_PROPERTIES)
+            .unwrap()
+            .to_subjects(None)
+            .unwrap(),
+        vec![urls::DESCRIPTION.to_string()]
+    );
+    // Based on the latest version, it is applied without merging
+    edit(urls::DESCRIPTION, Value::Markdown("third".into()), &merged).unwrap();
+}
@@ -6,16 +6,12 @@ use super::query_index::{IndexIterator, SEPARATION_BIT};
 
 #[instrument(skip(store))]
 pub fn add_atom_to_reference_index(index_atom: &IndexAtom, store: &Db) -> AtomicResult<()> {
-    let _existing = store
-        .reference_index
-        .insert(key_from_atom(index_atom), b"")?;
-    Ok(())
+    store.tree_insert(&store.reference_index, &key_from_atom(index_atom), b"")
 }
 
 #[instrument(skip(store))]
 pub fn remove_atom_from_reference_index(index_atom: &IndexAtom, store: &Db) -> AtomicResult<()> {
-    store.reference_index.remove(key_from_atom(index_atom))?;
-    Ok(())
+    store.tree_remove(&store.reference_index, &key_from_atom(index_atom))
 }
 
 /// Constructs the Key for the prop_val_sub_index.
@@ -51,9 +47,7 @@ pub fn find_in_val_prop_sub_index(store: &Db, val: &Value, prop: Option<&str>) -
     }
     Box::new(
         store
-            .reference_index
-            .scan_prefix(prefix)
-            .into_iter()
+            .tree_scan_prefix(&store.reference_index, &prefix)
             .map(|kv| {
                 let (key, _value) = kv?;
                 key_to_index_atom(&key)
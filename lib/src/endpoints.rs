@@ -76,11 +76,16 @@ pub fn default_endpoints() -> Vec<Endpoint> {
     vec![
         plugins::versioning::version_endpoint(),
         plugins::versioning::all_versions_endpoint(),
+        plugins::versioning::revert_endpoint(),
         plugins::path::path_endpoint(),
+        plugins::query::query_endpoint(),
         plugins::search::search_endpoint(),
+        plugins::search::similar_endpoint(),
         plugins::files::upload_endpoint(),
         #[cfg(feature = "html")]
         plugins::bookmark::bookmark_endpoint(),
         plugins::importer::import_endpoint(),
+        plugins::changes::changes_endpoint(),
+        plugins::audit::audit_endpoint(),
     ]
 }
@@ -31,6 +31,10 @@ pub enum AtomicErrorType {
     ParseError,
     OtherError,
     MethodNotAllowed,
+    /// Two changes can not be merged, because they both touch these properties.
+    ConflictError(Vec<String>),
+    /// A rate limit or storage quota has been reached.
+    LimitExceeded,
 }
 
 impl std::error::Error for AtomicError {
@@ -76,6 +80,25 @@ impl AtomicError {
         }
     }
 
+    /// A server will probably return this error as a 409.
+    /// Lists the properties that cause the conflict.
+    pub fn conflict(message: String, properties: Vec<String>) -> AtomicError {
+        AtomicError {
+            message: format!("Conflict. {}", message),
+            error_type: AtomicErrorType::ConflictError(properties),
+            subject: None,
+        }
+    }
+
+    /// A server will probably return this error as a 429.
+    pub fn limit_exceeded(message: String) -> AtomicError {
+        AtomicError {
+            message: format!("Limit exceeded. {}", message),
+            error_type: AtomicErrorType::LimitExceeded,
+            subject: None,
+        }
+    }
+
     pub fn parse_error(
         message: &str,
         subject: Option<&str>,
@@ -106,6 +129,9 @@ impl AtomicError {
         let mut r = Resource::new(subject);
         r.set_class(urls::ERROR);
         r.set_propval_unsafe(urls::DESCRIPTION.into(), Value::String(self.message));
+        if let AtomicErrorType::ConflictError(properties) = self.error_type {
+            r.set_propval_unsafe(urls::CONFLICTING_PROPERTIES.into(), properties.into());
+        }
         r
     }
 
@@ -3,10 +3,14 @@
 //! See
 
 use core::fmt;
+use std::collections::HashSet;
 
-use crate::{errors::AtomicResult, storelike::Query, urls, Resource, Storelike};
+use crate::{
+    errors::AtomicResult, resources::PropVals, storelike::Query, urls, values::SubResource,
+    Resource, Storelike, Value,
+};
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum Right {
     /// Full read access to the resource and its children.
     /// https://atomicdata.dev/properties/read
@@ -19,6 +23,17 @@ pub enum Right {
     Append,
 }
 
+impl Right {
+    /// The Property that lists the Agents and Groups that are denied this right.
+    pub fn deny_property(&self) -> &'static str {
+        match self {
+            Right::Read => urls::DENY_READ,
+            Right::Write => urls::DENY_WRITE,
+            Right::Append => urls::DENY_APPEND,
+        }
+    }
+}
+
 impl fmt::Display for Right {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         let str = match self {
@@ -39,6 +54,156 @@ pub fn add_children(store: &impl Storelike, resource: &mut Resource) -> AtomicRe
     Ok(resource.to_owned())
 }
 
+/// Returns the `members` of a Group. Returns nothing for other resources, such as Agents.
+pub fn group_members(propvals: &PropVals) -> AtomicResult<Vec<String>> {
+    let is_group = match propvals.get(urls::IS_A) {
+        Some(classes) => classes.to_subjects(None)?.iter().any(|c| c == urls::GROUP),
+        None => false,
+    };
+    match propvals.get(urls::MEMBERS) {
+        Some(members) if is_group => members.to_subjects(None),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Returns the `propertyRead` of a Property, or `None` if its values can be read by everyone.
+pub fn property_readers(propvals: &PropVals) -> AtomicResult<Option<Vec<String>>> {
+    match propvals.get(urls::PROPERTY_READ) {
+        Some(readers) => Ok(Some(readers.to_subjects(None)?)),
+        None => Ok(None),
+    }
+}
+
+/// Checks if the Agent can read the values of the Property. See [urls::PROPERTY_READ].
+pub fn can_read_property(
+    store: &impl Storelike,
+    property: &str,
+    for_agent: &str,
+) -> AtomicResult<bool> {
+    let readers = match store.get_property_readers(property)? {
+        Some(readers) => readers,
+        None => return Ok(true),
+    };
+    if let Ok(server_agent) = store.get_default_agent() {
+        if server_agent.subject == for_agent {
+            return Ok(true);
+        }
+    }
+    let token_agent;
+    let for_agent = if crate::tokens::is_token(store, for_agent) {
+        token_agent = crate::tokens::get_token(store, for_agent)?.agent;
+        &token_agent
+    } else {
+        for_agent
+    };
+    for reader in readers {
+        if reader == urls::PUBLIC_AGENT
+            || reader == for_agent
+            || is_member(store, &reader, for_agent)?
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Removes the values of Properties that the Agent can not read, also from Nested Resources such as the `set` of Commits.
+/// Use this for every Resource that is shown to an Agent.
+pub fn remove_hidden_properties(
+    store: &impl Storelike,
+    resource: &mut Resource,
+    for_agent: &str,
+) -> AtomicResult<()> {
+    let mut hidden = Vec::new();
+    let mut changed = Vec::new();
+    for (property, value) in resource.get_propvals() {
+        if !can_read_property(store, property, for_agent)? {
+            hidden.push(property.clone());
+        } else if let Some(stripped) = remove_hidden_nested(store, value, for_agent)? {
+            changed.push((property.clone(), stripped));
+        }
+    }
+    for property in hidden {
+        resource.remove_propval(&property);
+    }
+    for (property, value) in changed {
+        resource.set_propval_unsafe(property, value);
+    }
+    Ok(())
+}
+
+/// Returns the Value without hidden properties, if it contains Nested Resources.
+fn remove_hidden_nested(
+    store: &impl Storelike,
+    value: &Value,
+    for_agent: &str,
+) -> AtomicResult<Option<Value>> {
+    let remove_from = |sub: &SubResource| -> AtomicResult<SubResource> {
+        Ok(match sub {
+            SubResource::Nested(propvals) => {
+                SubResource::Nested(remove_hidden_propvals(store, propvals, for_agent)?)
+            }
+            SubResource::Resource(resource) => {
+                let mut resource = resource.as_ref().clone();
+                remove_hidden_properties(store, &mut resource, for_agent)?;
+                SubResource::Resource(Box::new(resource))
+            }
+            SubResource::Subject(subject) => SubResource::Subject(subject.clone()),
+        })
+    };
+    match value {
+        Value::NestedResource(sub) => Ok(Some(Value::NestedResource(remove_from(sub)?))),
+        Value::ResourceArray(subs)
+            if subs
+                .iter()
+                .any(|sub| !matches!(sub, SubResource::Subject(_))) =>
+        {
+            let subs = subs
+                .iter()
+                .map(remove_from)
+                .collect::<AtomicResult<Vec<SubResource>>>()?;
+            Ok(Some(Value::ResourceArray(subs)))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn remove_hidden_propvals(
+    store: &impl Storelike,
+    propvals: &PropVals,
+    for_agent: &str,
+) -> AtomicResult<PropVals> {
+    let mut visible = PropVals::new();
+    for (property, value) in propvals {
+        if can_read_property(store, property, for_agent)? {
+            let value =
+                remove_hidden_nested(store, value, for_agent)?.unwrap_or_else(|| value.clone());
+            visible.insert(property.clone(), value);
+        }
+    }
+    Ok(visible)
+}
+
+/// Checks if the Agent is a member of the Group, or of one of the Groups in it.
+/// Groups can contain each other, so every Group is only visited once.
+/// Uses [Storelike::get_group_members], which is cached by the Db.
+pub fn is_member(store: &impl Storelike, group: &str, agent: &str) -> AtomicResult<bool> {
+    let mut visited: HashSet<String> = HashSet::new();
+    let mut queue = vec![group.to_string()];
+    while let Some(current) = queue.pop() {
+        if !visited.insert(current.clone()) {
+            continue;
+        }
+        for member in store.get_group_members(&current)? {
+            if member == agent {
+                return Ok(true);
+            }
+            queue.push(member);
+        }
+    }
+    Ok(false)
+}
+
 /// Throws if not allowed.
 /// Returns string with explanation if allowed.
 pub fn check_write(
@@ -94,7 +259,25 @@ pub fn check_append(
     }
 }
 
+/// Checks the right like [check_append] (for [Right::Append]) or [check_rights], and records the outcome in the audit log, see [crate::audit].
+/// Use this for the resource that a request is about, not for the resources that are checked while handling it (such as the members of a Collection).
+pub fn check_audited(
+    store: &impl Storelike,
+    resource: &Resource,
+    for_agent: &str,
+    right: Right,
+) -> AtomicResult<String> {
+    let result = match right {
+        Right::Append => check_append(store, resource, for_agent),
+        _ => check_rights(store, resource, for_agent, right),
+    };
+    store.record_audit_event(for_agent, resource.get_subject(), &right, &result);
+    result
+}
+
 /// Recursively checks a Resource and its Parents for rights.
+/// A denial (e.g. [urls::DENY_READ]) in the resource or any of its parents overrides the rights that are granted.
+/// If `for_agent` is a [crate::tokens::Token], its scope and read-only flag are checked, and then the rights of its Agent.
 /// Throws if not allowed.
 /// Returns string with explanation if allowed.
 #[tracing::instrument(skip(store, resource))]
@@ -104,14 +287,62 @@ pub fn check_rights(
     for_agent: &str,
     right: Right,
 ) -> AtomicResult<String> {
-    if resource.get_subject() == for_agent {
-        return Ok("Agents can always edit themselves or their children.".into());
-    }
     if let Ok(server_agent) = store.get_default_agent() {
         if server_agent.subject == for_agent {
             return Ok("Server agent has root access, and can edit anything.".into());
         }
     }
+    if crate::tokens::is_token(store, for_agent) {
+        let token = crate::tokens::get_token(store, for_agent)?;
+        token.check_access(store, resource, &right)?;
+        return check_rights(store, resource, &token.agent, right);
+    }
+    // Commits are checked using their target
+    if resource.get(urls::SUBJECT).is_err() {
+        check_denied(store, resource, for_agent, &right)?;
+    }
+    check_granted(store, resource, for_agent, right)
+}
+
+/// Throws if the Agent, or a Group it is a member of, is denied the right in the resource or one of its parents.
+/// The PublicAgent in a deny list denies everyone.
+fn check_denied(
+    store: &impl Storelike,
+    resource: &Resource,
+    for_agent: &str,
+    right: &Right,
+) -> AtomicResult<()> {
+    let mut current = resource.clone();
+    loop {
+        if let Ok(denied) = current.get(right.deny_property()) {
+            for s in denied.to_subjects(None)? {
+                if s == urls::PUBLIC_AGENT || s == for_agent || is_member(store, &s, for_agent)? {
+                    return Err(crate::errors::AtomicError::unauthorized(format!(
+                        "{} has been denied the {} right in {}",
+                        for_agent,
+                        right,
+                        current.get_subject()
+                    )));
+                }
+            }
+        }
+        match current.get_parent(store) {
+            Ok(parent) => current = parent,
+            Err(_) => return Ok(()),
+        }
+    }
+}
+
+/// Looks for a grant of the right in the resource and its parents.
+fn check_granted(
+    store: &impl Storelike,
+    resource: &Resource,
+    for_agent: &str,
+    right: Right,
+) -> AtomicResult<String> {
+    if resource.get_subject() == for_agent {
+        return Ok("Agents can always edit themselves or their children.".into());
+    }
 
     // Handle Commits.
     if let Ok(commit_subject) = resource.get(urls::SUBJECT) {
@@ -143,6 +374,13 @@ pub fn check_rights(
                             resource.get_subject()
                         ));
                     }
+                    if is_member(store, agent, for_agent)? {
+                        return Ok(format!(
+                            "Right has been set for Group {} in {}",
+                            agent,
+                            resource.get_subject()
+                        ));
+                    }
                 }
             };
         }
@@ -150,7 +388,7 @@ pub fn check_rights(
 
     // Try the parents recursively
     if let Ok(parent) = resource.get_parent(store) {
-        check_rights(store, &parent, for_agent, right)
+        check_granted(store, &parent, for_agent, right)
     } else {
         if for_agent == urls::PUBLIC_AGENT {
             // resource has no parent and agent is not in rights array - check fails
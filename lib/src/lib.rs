@@ -9,10 +9,12 @@ See the [Atomic Data Docs](https://docs.atomicdata.dev) for more information.
   - **In-memory** [Store] for getting / setting data. Useful for client applications.
   - **On disk** [Db], powered by Sled. Useful for applications that persist Atomic Data, such as [`atomic-server`](https://crates.io/crates/atomic-server).
 - [serialize] and [parse] tools for [JSON-AD](https://docs.atomicdata.dev/core/json-ad.html), plain JSON, RDF, Turtle, N-Triples and JSON-LD.
+- [rdf] imports Turtle, N-Triples and JSON-LD into a store.
 - [Resource] with getters, setters and a `.save` function that creates Commits.
 - [Value] converts Atomic Data to Rust native types
 - Validate [Atomic Schema](https://docs.atomicdata.dev/schema/intro.html)
 - [Commit]s (transactions / delta's / changes / updates / versioning / history).
+- [Transaction]s for applying multiple Commits all-or-nothing.
 - [plugins] system (although not very mature)
 - [collections] (pagination, sorting, filtering)
 - Querying (using triple pattern fragments) (see [storelike::Query])
@@ -60,6 +62,7 @@ assert!(fetched_new_resource.get_shortname("description", &store).unwrap().to_st
 
 pub mod agents;
 pub mod atoms;
+pub mod audit;
 pub mod authentication;
 pub mod client;
 pub mod collections;
@@ -78,6 +81,11 @@ pub mod parse;
 #[cfg(feature = "db")]
 pub mod plugins;
 pub mod populate;
+pub mod quotas;
+#[cfg(feature = "rdf")]
+pub mod rdf;
+#[cfg(feature = "db")]
+pub mod replication;
 pub mod resources;
 pub mod schema;
 pub mod serialize;
@@ -85,6 +93,8 @@ pub mod store;
 pub mod storelike;
 #[cfg(test)]
 mod test_utils;
+pub mod tokens;
+pub mod transaction;
 pub mod urls;
 pub mod utils;
 pub mod validate;
@@ -99,4 +109,5 @@ pub use errors::AtomicErrorType;
 pub use resources::Resource;
 pub use store::Store;
 pub use storelike::Storelike;
+pub use transaction::Transaction;
 pub use values::Value;
@@ -129,6 +129,73 @@ pub fn parse_json_ad_string(
     Ok(vec)
 }
 
+/// Parses NDJSON-AD (newline-delimited JSON-AD), where every line contains a single JSON-AD object.
+/// Reads and processes one line at a time, so memory use does not depend on the size of the input.
+/// Empty lines are skipped. Returns the amount of parsed Resources.
+#[tracing::instrument(skip(reader, store))]
+pub fn parse_ndjson_ad(
+    reader: &mut dyn std::io::BufRead,
+    store: &impl Storelike,
+    parse_opts: &ParseOpts,
+) -> AtomicResult<usize> {
+    let mut count = 0;
+    let mut line = String::new();
+    let mut line_number = 0;
+    loop {
+        line.clear();
+        if reader
+            .read_line(&mut line)
+            .map_err(|e| format!("Unable to read line {}. {}", line_number + 1, e))?
+            == 0
+        {
+            return Ok(count);
+        }
+        line_number += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let obj: Map<String, serde_json::Value> = serde_json::from_str(&line).map_err(|e| {
+            AtomicError::parse_error(
+                &format!("Invalid JSON on line {}: {}", line_number, e),
+                None,
+                None,
+            )
+        })?;
+        let resource = json_ad_object_to_resource(obj, store, parse_opts)
+            .map_err(|e| format!("Unable to process resource on line {}. {}", line_number, e))?;
+        if let SaveOpts::Save = parse_opts.save {
+            remove_destroyed(store, &resource)?;
+        }
+        count += 1;
+    }
+}
+
+/// If `commit` is a Commit that destroyed a Resource, removes that Resource, unless it was changed again after the Commit.
+/// Incremental exports contain the Commits that destroyed Resources (see [crate::Storelike::export_ndjson]), so importing these after a full export removes these Resources as well.
+fn remove_destroyed(store: &impl crate::Storelike, commit: &Resource) -> AtomicResult<()> {
+    if !matches!(commit.get(urls::DESTROY), Ok(Value::Boolean(true))) {
+        return Ok(());
+    }
+    let destroyed_at = match commit.get(urls::CREATED_AT) {
+        Ok(Value::Timestamp(created_at)) => *created_at,
+        _ => return Ok(()),
+    };
+    let target = commit.get(urls::SUBJECT)?.to_string();
+    if let Ok(resource) = store.get_resource_local(&target) {
+        // A `lastCommit` that is not imported yet belongs to a newer version
+        let changed_before = match resource.get(urls::LAST_COMMIT) {
+            Ok(_) => {
+                matches!(store.last_changed_at(&resource), Some(changed) if changed <= destroyed_at)
+            }
+            Err(_) => true,
+        };
+        if changed_before {
+            store.remove_resource(&target)?;
+        }
+    }
+    Ok(())
+}
+
 /// Parse a single Json AD string that represents an incoming Commit.
 /// WARNING: Does not match all props to datatypes (in Nested Resources), so it could result in invalid data,
 /// if the input data does not match the required datatypes.
@@ -144,7 +211,12 @@ pub fn parse_json_ad_commit_resource(
         .to_string();
     let subject = format!("{}/commits/{}", store.get_server_url(), signature);
     let mut resource = Resource::new(subject);
-    let propvals = match parse_json_ad_map_to_resource(json, store, &ParseOpts::default())? {
+    // The Commit is saved when it is applied, not when it is parsed
+    let parse_opts = ParseOpts {
+        save: SaveOpts::DontSave,
+        ..Default::default()
+    };
+    let propvals = match parse_json_ad_map_to_resource(json, store, &parse_opts)? {
         SubResource::Resource(r) => r.into_propvals(),
         SubResource::Nested(pv) => pv,
         SubResource::Subject(_) => {
@@ -157,6 +229,28 @@ pub fn parse_json_ad_commit_resource(
     Ok(resource)
 }
 
+/// Parse a single JSON-AD string that represents an incoming [crate::Transaction].
+/// The Transaction and its Commits are not saved.
+#[tracing::instrument(skip(store))]
+pub fn parse_json_ad_transaction(
+    string: &str,
+    store: &impl crate::Storelike,
+) -> AtomicResult<crate::Transaction> {
+    let json: Map<String, serde_json::Value> = serde_json::from_str(string)?;
+    let parse_opts = ParseOpts {
+        save: SaveOpts::DontSave,
+        ..Default::default()
+    };
+    let resource = match parse_json_ad_map_to_resource(json, store, &parse_opts)? {
+        SubResource::Resource(r) => *r,
+        SubResource::Nested(pv) => Resource::from_propvals(pv, "".into()),
+        SubResource::Subject(_) => {
+            return Err("Transaction resource is a string, should be a resource.".into())
+        }
+    };
+    crate::Transaction::from_resource(resource)
+}
+
 /// Parse a single Json AD string, convert to Atoms
 /// Does not match all props to datatypes, so it could result in invalid data.
 /// Adds to the store if `add` is true.
@@ -294,66 +388,76 @@ fn parse_json_ad_map_to_resource(
         }
     }
     if let Some(subj) = { subject } {
-        let r = match &parse_opts.save {
-            SaveOpts::DontSave => {
-                let mut r = Resource::new(subj);
-                r.set_propvals_unsafe(propvals);
-                r
-            }
-            SaveOpts::Save => {
-                let mut r = Resource::new(subj);
-                r.set_propvals_unsafe(propvals);
-                store.add_resource(&r)?;
-                r
-            }
-            SaveOpts::Commit => {
-                let mut r = if let Ok(orig) = store.get_resource(&subj) {
-                    // If the resource already exists, and overwrites outside are not permitted, and it does not have the importer as parent...
-                    // Then we throw!
-                    // Because this would enable malicious users to overwrite resources that they shouldn't.
-                    if !parse_opts.overwrite_outside {
-                        let importer = parse_opts.importer.as_deref().unwrap();
-                        if !orig.has_parent(store, importer) {
-                            Err(
-                                format!("Cannot overwrite {subj} outside of importer! Enable `overwrite_outside`"),
-                            )?
-                        }
-                    };
-                    orig
-                } else {
-                    Resource::new(subj)
-                };
-                for (prop, val) in propvals {
-                    r.set_propval(prop, val, store)?;
-                }
-                let signer = parse_opts
-                    .signer
-                    .clone()
-                    .ok_or("No agent to sign Commit with. Either pass a `for_agent` or ")?;
-                let commit = r.get_commit_builder().clone().sign(&signer, store, &r)?;
-                let opts = CommitOpts {
-                    validate_schema: true,
-                    validate_signature: true,
-                    validate_timestamp: false,
-                    validate_rights: parse_opts.for_agent.is_some(),
-                    validate_previous_commit: false,
-                    validate_for_agent: parse_opts.for_agent.clone(),
-                    update_index: true,
-                };
-
-                commit
-                    .apply_opts(store, &opts)
-                    .map_err(|e| format!("Failed to save {}: {}", r.get_subject(), e))?
-                    .resource_new
-                    .unwrap()
-            }
-        };
-        Ok(r.into())
+        Ok(save_propvals(subj, propvals, store, parse_opts)?.into())
     } else {
         Ok(SubResource::Nested(propvals))
     }
 }
 
+/// Creates a Resource from parsed PropVals, and saves it depending on the [SaveOpts].
+pub(crate) fn save_propvals(
+    subj: String,
+    propvals: PropVals,
+    store: &impl crate::Storelike,
+    parse_opts: &ParseOpts,
+) -> AtomicResult<Resource> {
+    let r = match &parse_opts.save {
+        SaveOpts::DontSave => {
+            let mut r = Resource::new(subj);
+            r.set_propvals_unsafe(propvals);
+            r
+        }
+        SaveOpts::Save => {
+            let mut r = Resource::new(subj);
+            r.set_propvals_unsafe(propvals);
+            store.add_resource(&r)?;
+            r
+        }
+        SaveOpts::Commit => {
+            let mut r = if let Ok(orig) = store.get_resource(&subj) {
+                // If the resource already exists, and overwrites outside are not permitted, and it does not have the importer as parent...
+                // Then we throw!
+                // Because this would enable malicious users to overwrite resources that they shouldn't.
+                if !parse_opts.overwrite_outside {
+                    let importer = parse_opts.importer.as_deref().unwrap();
+                    if !orig.has_parent(store, importer) {
+                        Err(
+                            format!("Cannot overwrite {subj} outside of importer! Enable `overwrite_outside`"),
+                        )?
+                    }
+                };
+                orig
+            } else {
+                Resource::new(subj)
+            };
+            for (prop, val) in propvals {
+                r.set_propval(prop, val, store)?;
+            }
+            let signer = parse_opts
+                .signer
+                .clone()
+                .ok_or("No agent to sign Commit with. Either pass a `for_agent` or ")?;
+            let commit = r.get_commit_builder().clone().sign(&signer, store, &r)?;
+            let opts = CommitOpts {
+                validate_schema: true,
+                validate_signature: true,
+                validate_timestamp: false,
+                validate_rights: parse_opts.for_agent.is_some(),
+                validate_previous_commit: false,
+                validate_for_agent: parse_opts.for_agent.clone(),
+                update_index: true,
+            };
+
+            commit
+                .apply_opts(store, &opts)
+                .map_err(|e| format!("Failed to save {}: {}", r.get_subject(), e))?
+                .resource_new
+                .unwrap()
+        }
+    };
+    Ok(r)
+}
+
 fn generate_id_from_local_id(importer_subject: &str, local_id: &str) -> String {
     format!("{}/{}", importer_subject, local_id)
 }
@@ -0,0 +1,105 @@
+//! Pages through the audit log of the Db, which records the outcome of rights checks.
+//! Only the server's default Agent can read it. See [crate::audit].
+
+use crate::{
+    audit::{AuditEvent, AuditFilter},
+    endpoints::{Endpoint, HandleGetContext},
+    errors::AtomicResult,
+    resources::PropVals,
+    urls,
+    values::SubResource,
+    AtomicError, Resource, Storelike, Value,
+};
+
+/// The amount of events returned if no `page_size` is passed.
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
+pub fn audit_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/audit".to_string(),
+        params: [
+            urls::AUDIT_SINCE.to_string(),
+            urls::AUDIT_AGENT.to_string(),
+            urls::AUDIT_SUBJECT.to_string(),
+            urls::AUDIT_RIGHT.to_string(),
+            urls::AUDIT_ALLOWED.to_string(),
+            urls::AUDIT_FROM.to_string(),
+            urls::AUDIT_UNTIL.to_string(),
+            urls::COLLECTION_PAGE_SIZE.to_string(),
+        ]
+        .into(),
+        description: "Lists the events of the audit log that were recorded after the `since` sequence number: who read or changed which resource, and which requests were refused. Filter using `agent`, `subject`, `right` (`read`, `write` or `append`), `allowed`, and the `from` and `until` timestamps. Pass the returned `next` as `since` to get the next page. Only available to the server's Agent, and only if the audit log is enabled.".to_string(),
+        shortname: "audit".to_string(),
+        handle: Some(handle_audit_request),
+        handle_post: None,
+    }
+}
+
+#[tracing::instrument]
+fn handle_audit_request(context: HandleGetContext) -> AtomicResult<Resource> {
+    let HandleGetContext {
+        store,
+        for_agent,
+        subject,
+    } = context;
+    let server_agent = store.get_default_agent()?.subject;
+    if for_agent != Some(server_agent.as_str()) {
+        return Err(AtomicError::unauthorized(
+            "Only the server's Agent can read the audit log".into(),
+        ));
+    }
+    let mut since = 0;
+    let mut page_size = DEFAULT_PAGE_SIZE;
+    let mut filter = AuditFilter::default();
+    for (k, v) in subject.query_pairs() {
+        match k.as_ref() {
+            "since" => since = v.parse::<u64>()?,
+            "page_size" => page_size = v.parse::<usize>()?.min(MAX_PAGE_SIZE),
+            "agent" => filter.agent = Some(v.to_string()),
+            "subject" => filter.subject = Some(v.to_string()),
+            "right" => {
+                filter.right = Some(match v.as_ref() {
+                    "read" => urls::READ.into(),
+                    "write" => urls::WRITE.into(),
+                    "append" => urls::APPEND.into(),
+                    other => other.into(),
+                })
+            }
+            "allowed" => filter.allowed = Some(v.parse::<bool>()?),
+            "from" => filter.from = Some(crate::utils::parse_timestamp(&v)?),
+            "until" => filter.until = Some(crate::utils::parse_timestamp(&v)?),
+            _ => {}
+        }
+    }
+
+    let mut next = since;
+    let mut events = Vec::new();
+    for (seq, event) in store.audit_events(since, page_size, &filter)? {
+        next = seq;
+        events.push(SubResource::Nested(event_to_propvals(event)));
+    }
+
+    let mut resource = Resource::new(subject.to_string());
+    resource.set_propval_unsafe(urls::ENDPOINT_RESULTS.into(), Value::ResourceArray(events));
+    resource.set_propval_unsafe(urls::AUDIT_NEXT.into(), Value::Integer(next as i64));
+    Ok(resource)
+}
+
+fn event_to_propvals(event: AuditEvent) -> PropVals {
+    let mut propvals = PropVals::new();
+    propvals.insert(
+        urls::IS_A.into(),
+        vec![urls::AUDIT_EVENT.to_string()].into(),
+    );
+    propvals.insert(urls::AUDIT_AGENT.into(), Value::AtomicUrl(event.agent));
+    propvals.insert(urls::AUDIT_SUBJECT.into(), Value::AtomicUrl(event.subject));
+    propvals.insert(urls::AUDIT_RIGHT.into(), Value::AtomicUrl(event.right));
+    propvals.insert(urls::AUDIT_ALLOWED.into(), Value::Boolean(event.allowed));
+    propvals.insert(
+        urls::AUDIT_EXPLANATION.into(),
+        Value::String(event.explanation),
+    );
+    propvals.insert(urls::CREATED_AT.into(), Value::Timestamp(event.timestamp));
+    propvals
+}
@@ -0,0 +1,81 @@
+//! Pages through the commit log of the Db, which lists all applied Commits in order.
+//! Useful for incremental exports and for clients that need to stay in sync.
+
+use crate::{
+    endpoints::{Endpoint, HandleGetContext},
+    errors::AtomicResult,
+    urls, Db, Resource, Storelike, Value,
+};
+
+/// The amount of Commits returned if no `page_size` is passed.
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
+pub fn changes_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/changes".to_string(),
+        params: [
+            urls::CHANGES_SINCE.to_string(),
+            urls::COLLECTION_PAGE_SIZE.to_string(),
+        ]
+        .into(),
+        description: "Lists the Commits that were applied after the `since` sequence number, in the order in which they were applied. Pass the returned `next` as `since` to get the next page. Only shows the Commits that you can read.".to_string(),
+        shortname: "changes".to_string(),
+        handle: Some(handle_changes_request),
+        handle_post: None,
+    }
+}
+
+#[tracing::instrument]
+fn handle_changes_request(context: HandleGetContext) -> AtomicResult<Resource> {
+    let HandleGetContext {
+        store,
+        for_agent,
+        subject,
+    } = context;
+    let mut since = None;
+    let mut page_size = DEFAULT_PAGE_SIZE;
+    for (k, v) in subject.query_pairs() {
+        match k.as_ref() {
+            "since" => since = Some(v.parse::<u64>()?),
+            "page_size" => page_size = v.parse::<usize>()?.min(MAX_PAGE_SIZE),
+            _ => {}
+        }
+    }
+    let since = match since {
+        Some(since) => since,
+        None => return changes_endpoint().to_resource(store),
+    };
+
+    let (commits, next) = changes_since(store, since, page_size, for_agent)?;
+    let mut resource = Resource::new(subject.to_string());
+    resource.set_propval_unsafe(urls::ENDPOINT_RESULTS.into(), commits.into());
+    resource.set_propval_unsafe(urls::CHANGES_NEXT.into(), Value::Integer(next as i64));
+    Ok(resource)
+}
+
+/// Returns the Commits in up to `page_size` entries of the commit log after `since` that the Agent can read, and the sequence number to continue from.
+/// Pass `None` as `for_agent` to skip the rights checks.
+pub fn changes_since(
+    store: &Db,
+    since: u64,
+    page_size: usize,
+    for_agent: Option<&str>,
+) -> AtomicResult<(Vec<Resource>, u64)> {
+    let mut next = since;
+    let mut commits = Vec::new();
+    for (seq, commit_subject) in store.commit_log(since, page_size)? {
+        next = seq;
+        let commit = match store.get_resource(&commit_subject) {
+            Ok(commit) => commit,
+            Err(_removed) => continue,
+        };
+        if let Some(agent) = for_agent {
+            if crate::hierarchy::check_read(store, &commit, agent).is_err() {
+                continue;
+            }
+        }
+        commits.push(commit);
+    }
+    Ok((commits, next))
+}
@@ -44,6 +44,7 @@ pub fn construct_chatroom(
         include_external: false,
         include_nested: true,
         for_agent: for_agent.map(|s| s.to_string()),
+        filter: None,
     };
 
     let mut messages_unfiltered = store.query(&query_children)?.resources;
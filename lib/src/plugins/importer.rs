@@ -1,5 +1,6 @@
 /*!
 Importers allow users to (periodically) import JSON-AD files from a remote source.
+With the `rdf` feature, Turtle, N-Triples and JSON-LD can be imported too, by passing a `format` query param.
 */
 
 use crate::{
@@ -15,8 +16,9 @@ pub fn import_endpoint() -> Endpoint {
             urls::IMPORTER_OVERWRITE_OUTSIDE.to_string(),
             urls::IMPORTER_PARENT.to_string(),
             urls::IMPORTER_URL.to_string(),
+            urls::IMPORTER_FORMAT.to_string(),
         ].into(),
-        description: "Imports one or more Resources to some parent. POST your JSON-AD and add a `parent` query param to the URL. See https://docs.atomicdata.dev/create-json-ad.html".to_string(),
+        description: "Imports one or more Resources to some parent. POST your JSON-AD and add a `parent` query param to the URL. See https://docs.atomicdata.dev/create-json-ad.html . To import RDF, add a `format` query param (`turtle`, `ntriples` or `jsonld`).".to_string(),
         shortname: "path".to_string(),
         // Not sure if we need this, or if we should derive it from `None` here.
         handle: Some(handle_get),
@@ -41,6 +43,7 @@ pub fn handle_post(context: HandlePostContext) -> AtomicResult<Resource> {
     let mut json = None;
     let mut parent_maybe = None;
     let mut overwrite_outside = false;
+    let mut format = None;
     for (k, v) in subject.query_pairs() {
         match k.as_ref() {
             "json" | urls::IMPORTER_URL => return Err("JSON must be POSTed in the body".into()),
@@ -49,6 +52,7 @@ pub fn handle_post(context: HandlePostContext) -> AtomicResult<Resource> {
             "overwrite-outside" | urls::IMPORTER_OVERWRITE_OUTSIDE => {
                 overwrite_outside = v == "true"
             }
+            "format" | urls::IMPORTER_FORMAT if v != "json-ad" => format = Some(v.to_string()),
             _ => {}
         }
     }
@@ -62,9 +66,22 @@ pub fn handle_post(context: HandlePostContext) -> AtomicResult<Resource> {
             })?);
     }
 
+    #[cfg(feature = "rdf")]
+    let rdf_format = format
+        .map(|f| f.parse::<crate::rdf::RdfFormat>())
+        .transpose()?;
+    #[cfg(not(feature = "rdf"))]
+    if let Some(f) = format {
+        return Err(format!("Importing {} requires the `rdf` feature", f).into());
+    }
+
     if let Some(fetch_url) = url {
+        #[cfg(feature = "rdf")]
+        let mime = rdf_format.map_or(crate::parse::JSON_AD_MIME, |f| f.to_mime());
+        #[cfg(not(feature = "rdf"))]
+        let mime = crate::parse::JSON_AD_MIME;
         json = Some(
-            crate::client::fetch_body(&fetch_url, crate::parse::JSON_AD_MIME, None)
+            crate::client::fetch_body(&fetch_url, mime, None)
                 .map_err(|e| format!("Error while fetching {}: {}", fetch_url, e))?,
         );
     }
@@ -83,6 +100,11 @@ pub fn handle_post(context: HandlePostContext) -> AtomicResult<Resource> {
         if for_agent.is_none() {
             return Err("No agent specified for importer".to_string().into());
         }
+        #[cfg(feature = "rdf")]
+        if let Some(rdf_format) = rdf_format {
+            crate::rdf::import_rdf(store, &json_string, rdf_format, &parse_opts)?;
+            return import_endpoint().to_resource(context.store);
+        }
         store.import(&json_string, &parse_opts)?;
     } else {
         return Err(
@@ -116,6 +116,7 @@ pub fn construct_invite_redirect(
 }
 
 /// Adds the requested rights to the target resource.
+/// If the target is a Group, the Agent becomes one of its `members` instead of getting read rights, so it gets the rights of the Group.
 /// Overwrites the target resource to include the new rights.
 /// Checks if the Agent has a valid URL.
 /// Will not throw an error if the Agent already has the rights.
@@ -129,7 +130,18 @@ pub fn add_rights(
     check_valid_url(agent)?;
     // Get the Resource that the user is being invited to
     let mut target = store.get_resource(target)?;
-    let right = if write { urls::WRITE } else { urls::READ };
+    let is_group = target
+        .get(urls::IS_A)
+        .and_then(|classes| classes.to_subjects(None))
+        .map(|classes| classes.iter().any(|c| c == urls::GROUP))
+        .unwrap_or(false);
+    let right = if write {
+        urls::WRITE
+    } else if is_group {
+        urls::MEMBERS
+    } else {
+        urls::READ
+    };
 
     target.push_propval(right, agent.into(), true)?;
     target
@@ -39,9 +39,12 @@ pub mod importer;
 pub mod invite;
 
 // Endpoints
+pub mod audit;
 #[cfg(feature = "html")]
 pub mod bookmark;
+pub mod changes;
 pub mod files;
 pub mod path;
+pub mod query;
 pub mod search;
 pub mod versioning;
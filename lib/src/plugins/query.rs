@@ -0,0 +1,40 @@
+//! Queries the Store using a composable filter, see [crate::storelike::QueryExpr].
+
+use crate::{
+    collections::construct_collection_from_params,
+    endpoints::{Endpoint, HandleGetContext},
+    errors::AtomicResult,
+    urls, Resource,
+};
+
+pub fn query_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/query".to_string(),
+        params: [
+            urls::COLLECTION_FILTER.to_string(),
+            urls::COLLECTION_SORT_BY.to_string(),
+            urls::COLLECTION_SORT_DESC.to_string(),
+            urls::COLLECTION_PAGE_SIZE.to_string(),
+            urls::COLLECTION_CURRENT_PAGE.to_string(),
+        ]
+        .into(),
+        description: "Returns a Collection of the Resources that match the `filter`. The filter is JSON, and can combine clauses using `and` and `or`, e.g. `{\"and\":[{\"isA\":\"https://atomicdata.dev/classes/Article\"},{\"equals\":{\"property\":\"https://atomicdata.dev/properties/parent\",\"value\":\"https://example.com/blog\"}}]}`. Other clauses are `exists` (a property URL), `references` (a value in any property) and `range` (with a `property`, `min` and `max`) for Integer, Float and Timestamp values.".to_string(),
+        shortname: "query".to_string(),
+        handle: Some(handle_query_request),
+        handle_post: None,
+    }
+}
+
+#[tracing::instrument]
+fn handle_query_request(context: HandleGetContext) -> AtomicResult<Resource> {
+    let HandleGetContext {
+        store,
+        for_agent,
+        subject,
+    } = context;
+    if !subject.query_pairs().any(|(k, _v)| k == "filter") {
+        return query_endpoint().to_resource(store);
+    }
+    let mut resource = Resource::new(subject.to_string());
+    construct_collection_from_params(store, subject.query_pairs(), &mut resource, for_agent)
+}
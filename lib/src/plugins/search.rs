@@ -8,10 +8,30 @@ pub fn search_endpoint() -> Endpoint {
         urls::SEARCH_QUERY.into(),
         urls::SEARCH_LIMIT.into(),
         urls::SEARCH_PROPERTY.into(),
+        urls::SEARCH_SORT_BY.into(),
+        urls::SEARCH_SORT_DESC.into(),
+        urls::SEARCH_RANGE.into(),
+        urls::SEARCH_CLASS.into(),
+        urls::SEARCH_FACETS.into(),
+        urls::SEARCH_CONSISTENT.into(),
+        urls::SEARCH_LANG.into(),
+        urls::SEARCH_MODE.into(),
     ],
-      description: "Full text-search endpoint. You can use the keyword `AND` and `OR`, or use `\"` for advanced searches. ".to_string(),
+      description: "Full text-search endpoint. You can use the keyword `AND` and `OR`, or use `\"` for advanced searches. Sort by a numeric, date or boolean Property using `sort_by` and `sort_desc`, filter on their values using `range`, filter by Class using `class`, and count the values of Properties using `facets`. The `hits` explain every result, with its relevance `score` and `snippets` in which the words of the query are highlighted. Changes are indexed within a few seconds, pass `consistent=true` to wait until all applied Commits are indexed. Pass one or more comma-separated language codes in `lang` (e.g. `nl,de`) to also match other forms of the words, in resources that have that `language`. Use `mode=hybrid` to also find resources with similar words, if the server runs with `--similarity`.".to_string(),
       shortname: "search".to_string(),
       handle: None,
       handle_post: None,
   }
 }
+
+// Like the search endpoint, this is handled by `atomic-server`, which keeps the vectors in memory.
+pub fn similar_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/similar".to_string(),
+        params: vec![urls::SUBJECT.into(), urls::SEARCH_LIMIT.into()],
+        description: "Finds the resources that are most similar to the `subject`, by comparing embedding vectors of their text. The `hits` contain the similarity `score` of every result. Only available if the server runs with `--similarity`.".to_string(),
+        shortname: "similar".to_string(),
+        handle: None,
+        handle_post: None,
+    }
+}
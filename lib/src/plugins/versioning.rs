@@ -1,13 +1,27 @@
 use tracing::warn;
 
+use std::collections::BTreeSet;
+
 use crate::{
     collections::CollectionBuilder,
+    commit::CommitResponse,
     endpoints::{Endpoint, HandleGetContext},
     errors::AtomicResult,
-    storelike::Query,
+    resources::PropVals,
+    storelike::{Query, QueryExpr, QueryResult},
     urls, AtomicError, Commit, Resource, Storelike,
 };
 
+/// While replaying Commits, a snapshot is stored after every this many Commits.
+const SNAPSHOT_INTERVAL: usize = 10;
+
+/// Stores versions of Resources, so that [construct_version_at] does not have to replay all Commits every time.
+/// A snapshot is the state of a Resource right after a specific Commit was applied, so it never changes.
+pub trait SnapshotCache {
+    fn get_snapshot(&self, commit_url: &str) -> Option<PropVals>;
+    fn set_snapshot(&self, commit_url: &str, propvals: &PropVals) -> AtomicResult<()>;
+}
+
 pub fn version_endpoint() -> Endpoint {
     Endpoint {
         path: "/version".to_string(),
@@ -31,6 +45,17 @@ pub fn all_versions_endpoint() -> Endpoint {
     }
 }
 
+pub fn revert_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/revert".to_string(),
+        params: [urls::REVERT_COMMIT.to_string()].into(),
+        description: "Shows a Resource as it was right before a Commit, using a `commit` query param. Clients use this to create, sign and post a Commit that undoes its changes. That inverse Commit is refused if the Resource has been changed after the reverted Commit.".to_string(),
+        shortname: "revert".to_string(),
+        handle: Some(handle_revert_request),
+        handle_post: None,
+    }
+}
+
 #[tracing::instrument]
 fn handle_version_request(context: HandleGetContext) -> AtomicResult<Resource> {
     let params = context.subject.query_pairs();
@@ -77,6 +102,8 @@ fn handle_all_versions_request(context: HandleGetContext) -> AtomicResult<Resour
         name: Some(format!("Versions of {}", target)),
         include_nested: false,
         include_external: false,
+        filter: None,
+        at: None,
     };
     let mut collection = collection_builder.into_collection(store, for_agent)?;
     let new_members = collection
@@ -88,6 +115,58 @@ fn handle_all_versions_request(context: HandleGetContext) -> AtomicResult<Resour
     collection.to_resource(store)
 }
 
+/// Returns the Resource as it was right before the Commit in the `commit` query param, see [Commit::inverse].
+/// It has no properties if the Commit created the Resource.
+#[tracing::instrument]
+fn handle_revert_request(context: HandleGetContext) -> AtomicResult<Resource> {
+    let HandleGetContext {
+        store,
+        for_agent,
+        subject,
+    } = context;
+    let mut commit_url = None;
+    for (k, v) in subject.query_pairs() {
+        if let "commit" = k.as_ref() {
+            commit_url = Some(v.to_string())
+        };
+    }
+    let commit_url = match commit_url {
+        Some(url) => url,
+        None => return revert_endpoint().to_resource(store),
+    };
+    let response = construct_commit_response(&commit_url, store)?;
+    let mut resource_old = response
+        .resource_old
+        .ok_or("The old Resource is missing, so the Commit can not be reverted")?;
+    if let Some(agent) = for_agent {
+        check_read_version(store, &resource_old, agent)?;
+    }
+    resource_old.set_subject(subject.to_string());
+    Ok(resource_old)
+}
+
+/// Checks whether the Agent may read an older `version` of a Resource.
+/// A version carries its old rights and parent, so the rights are checked using the current Resource and its current parents instead.
+/// If the Resource no longer exists, the Agent needs write rights on the current parent of the version.
+pub fn check_read_version(
+    store: &impl Storelike,
+    version: &Resource,
+    for_agent: &str,
+) -> AtomicResult<String> {
+    match store.get_resource(version.get_subject()) {
+        Ok(current) => crate::hierarchy::check_read(store, &current, for_agent),
+        Err(_destroyed) => {
+            let parent = version.get_parent(store).map_err(|_| {
+                AtomicError::unauthorized(format!(
+                    "{} no longer exists, and neither does its parent",
+                    version.get_subject()
+                ))
+            })?;
+            crate::hierarchy::check_write(store, &parent, for_agent)
+        }
+    }
+}
+
 /// Searches the local store for all commits with this subject, returns sorted from old to new.
 #[tracing::instrument(skip(store))]
 fn get_commits_for_resource(subject: &str, store: &impl Storelike) -> AtomicResult<Vec<Commit>> {
@@ -147,6 +226,43 @@ pub fn construct_version(
     Ok(version)
 }
 
+/// Reconstructs the [CommitResponse] of a Commit that has been applied before, by replaying the Commits of its subject.
+/// Does not check any rights.
+#[tracing::instrument(skip(store))]
+pub fn construct_commit_response(
+    commit_url: &str,
+    store: &impl Storelike,
+) -> AtomicResult<CommitResponse> {
+    let commit_resource = store.get_resource(commit_url)?;
+    let target = Commit::from_resource(commit_resource.clone())?;
+    let mut version = Resource::new(target.subject.clone());
+    for commit in get_commits_for_resource(&target.subject, store)? {
+        if commit.url.as_deref() == Some(commit_url) {
+            let resource_new = if commit.destroy.unwrap_or(false) {
+                None
+            } else {
+                Some(commit.apply_changes(version.clone(), store, false)?)
+            };
+            return Ok(CommitResponse {
+                commit_resource,
+                resource_new,
+                resource_old: Some(version),
+                commit_struct: commit,
+            });
+        }
+        if commit.destroy.unwrap_or(false) {
+            version = Resource::new(target.subject.clone());
+        } else {
+            version = commit.apply_changes(version, store, false)?;
+        }
+    }
+    Err(format!(
+        "Commit {} was not found in the history of {}",
+        commit_url, target.subject
+    )
+    .into())
+}
+
 /// Creates the versioning URL for some specific Commit
 fn construct_version_endpoint_url(store: &impl Storelike, commit_url: &str) -> String {
     format!(
@@ -175,6 +291,127 @@ pub fn get_version(
     }
 }
 
+/// Constructs the Resource as it was at some point in time, by replaying its Commits up to and including `timestamp`.
+/// Starts at the most recent snapshot in the `cache`, if there is one, and adds snapshots while replaying.
+/// Returns a not found error if the Resource did not exist yet, or was destroyed at that time.
+/// Does not check any rights.
+#[tracing::instrument(skip(store, cache))]
+pub fn construct_version_at(
+    subject: &str,
+    timestamp: i64,
+    store: &impl Storelike,
+    cache: Option<&dyn SnapshotCache>,
+) -> AtomicResult<Resource> {
+    let commits: Vec<Commit> = get_commits_for_resource(subject, store)?
+        .into_iter()
+        .filter(|commit| commit.created_at <= timestamp && commit.url.is_some())
+        .collect();
+    let last_commit = commits.last().ok_or_else(|| {
+        AtomicError::not_found(format!(
+            "Resource {} did not exist at {}",
+            subject, timestamp
+        ))
+    })?;
+    if last_commit.destroy.unwrap_or(false) {
+        return Err(AtomicError::not_found(format!(
+            "Resource {} was destroyed at {}",
+            subject, timestamp
+        )));
+    }
+
+    let mut version = Resource::new(subject.into());
+    let mut start = 0;
+    if let Some(cache) = cache {
+        for (i, commit) in commits.iter().enumerate().rev() {
+            if let Some(propvals) = cache.get_snapshot(commit.url.as_ref().unwrap()) {
+                version = Resource::from_propvals(propvals, subject.into());
+                start = i + 1;
+                break;
+            }
+        }
+    }
+    for (i, commit) in commits.iter().enumerate().skip(start) {
+        if commit.destroy.unwrap_or(false) {
+            version = Resource::new(subject.into());
+        }
+        version = commit.apply_changes(version, store, false)?;
+        if let Some(cache) = cache {
+            if (i + 1) % SNAPSHOT_INTERVAL == 0 || i + 1 == commits.len() {
+                cache.set_snapshot(commit.url.as_ref().unwrap(), version.get_propvals())?;
+            }
+        }
+    }
+    Ok(version)
+}
+
+/// Performs a [Query] on the Resources as they were at some point in time.
+/// Only Resources that were created using Commits are included.
+/// The `property`, `value` and `filter` of the Query are checked against the versions of the Resources.
+/// The rights of `for_agent` are checked using [check_read_version].
+/// Note that this replays the history of every Resource that was changed before `timestamp`, which is expensive.
+#[tracing::instrument(skip(store))]
+pub fn query_at(store: &impl Storelike, q: &Query, timestamp: i64) -> AtomicResult<QueryResult> {
+    let mut commits_query = Query::new_expr(QueryExpr::And(vec![
+        QueryExpr::IsA(urls::COMMIT.into()),
+        QueryExpr::Range {
+            property: urls::CREATED_AT.into(),
+            min: None,
+            max: Some(timestamp as f64),
+        },
+    ]));
+    commits_query.include_external = q.include_external;
+    let changed_subjects: BTreeSet<String> = store
+        .query(&commits_query)?
+        .resources
+        .iter()
+        .filter_map(|commit| commit.get(urls::SUBJECT).ok().map(|s| s.to_string()))
+        .collect();
+
+    let expr = q.combined_expr();
+    let mut hits = Vec::new();
+    for subject in changed_subjects {
+        let version = match store.get_resource_at(&subject, timestamp) {
+            Ok(version) => version,
+            Err(_not_found) => continue,
+        };
+        if let Some(expr) = &expr {
+            if !expr.matches(&version) {
+                continue;
+            }
+        }
+        if let Some(agent) = &q.for_agent {
+            if check_read_version(store, &version, agent).is_err() {
+                continue;
+            }
+        }
+        let sort_val = match &q.sort_by {
+            Some(sort) => version
+                .get(sort)
+                .map(|v| v.to_sortable_string())
+                .unwrap_or_default(),
+            None => subject.clone(),
+        };
+        hits.push((sort_val, version));
+    }
+    hits.sort_by(|a, b| a.0.cmp(&b.0));
+    if q.sort_desc {
+        hits.reverse();
+    }
+
+    let count = hits.len();
+    let page: Vec<Resource> = hits
+        .into_iter()
+        .skip(q.offset)
+        .take(q.limit.unwrap_or(usize::MAX))
+        .map(|(_sort_val, version)| version)
+        .collect();
+    Ok(QueryResult {
+        count,
+        subjects: page.iter().map(|r| r.get_subject().clone()).collect(),
+        resources: if q.include_nested { page } else { Vec::new() },
+    })
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
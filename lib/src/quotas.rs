@@ -0,0 +1,135 @@
+//! Storage quotas limit the amount of resources and the total size of the uploaded files in a Drive.
+//! Every resource counts towards the nearest Drive among its ancestors.
+//! They are disabled by default. Enable them using [crate::Db::set_drive_quota].
+//! Changes are checked in [crate::Commit::apply_opts] if the rights of the Commit are validated, and uploads are checked by the server before they are saved.
+//! A resource that gets another parent moves to the Drive of that parent together with its descendants.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{errors::AtomicResult, urls, AtomicError, Resource, Storelike};
+
+/// Prevents endless loops when a hierarchy contains a cycle.
+const MAX_DEPTH: usize = 100;
+
+/// The limits that apply to every Drive. `None` means unlimited.
+#[derive(Clone, Debug, Default)]
+pub struct DriveQuota {
+    pub max_resources: Option<u64>,
+    /// The total size of the Files in the Drive, in bytes
+    pub max_upload_bytes: Option<u64>,
+}
+
+/// How much of its quota a Drive uses, or how much a resource adds to it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DriveUsage {
+    pub resources: u64,
+    pub upload_bytes: u64,
+}
+
+impl DriveUsage {
+    /// The usage of a single resource: one resource, plus its `filesize` if it is a File.
+    pub fn of_resource(resource: &Resource) -> DriveUsage {
+        DriveUsage {
+            resources: 1,
+            upload_bytes: resource
+                .get(urls::FILESIZE)
+                .and_then(|size| size.to_int())
+                .map(|size| size.max(0) as u64)
+                .unwrap_or(0),
+        }
+    }
+
+    pub fn add(&self, other: &DriveUsage) -> DriveUsage {
+        DriveUsage {
+            resources: self.resources.saturating_add(other.resources),
+            upload_bytes: self.upload_bytes.saturating_add(other.upload_bytes),
+        }
+    }
+
+    pub fn subtract(&self, other: &DriveUsage) -> DriveUsage {
+        DriveUsage {
+            resources: self.resources.saturating_sub(other.resources),
+            upload_bytes: self.upload_bytes.saturating_sub(other.upload_bytes),
+        }
+    }
+}
+
+impl DriveQuota {
+    /// Throws a [crate::AtomicErrorType::LimitExceeded] if adding `added` to the `usage` of the `drive` exceeds the quota.
+    pub fn check(&self, drive: &str, usage: &DriveUsage, added: &DriveUsage) -> AtomicResult<()> {
+        let total = usage.add(added);
+        if let Some(max) = self.max_resources {
+            if added.resources > 0 && total.resources > max {
+                return Err(AtomicError::limit_exceeded(format!(
+                    "Drive {} can not contain more than {} resources",
+                    drive, max
+                )));
+            }
+        }
+        if let Some(max) = self.max_upload_bytes {
+            if added.upload_bytes > 0 && total.upload_bytes > max {
+                return Err(AtomicError::limit_exceeded(format!(
+                    "Drive {} can not contain more than {} bytes of files, {} bytes are in use",
+                    drive, max, usage.upload_bytes
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_drive(resource: &Resource) -> bool {
+    resource
+        .get(urls::IS_A)
+        .and_then(|classes| classes.to_subjects(None))
+        .map(|classes| classes.iter().any(|c| c == urls::DRIVE))
+        .unwrap_or(false)
+}
+
+/// Returns the subject of the nearest Drive, starting at `subject` itself and walking up its parents.
+pub fn nearest_drive(store: &impl Storelike, subject: &str) -> Option<String> {
+    let mut current = store.get_resource(subject).ok()?;
+    for _ in 0..MAX_DEPTH {
+        if is_drive(&current) {
+            return Some(current.get_subject().into());
+        }
+        current = current.get_parent(store).ok()?;
+    }
+    None
+}
+
+/// Returns the subject of the Drive that the resource counts towards: the nearest Drive among its parents.
+pub fn drive_of(store: &impl Storelike, resource: &Resource) -> Option<String> {
+    let parent = resource.get(urls::PARENT).ok()?.to_string();
+    nearest_drive(store, &parent)
+}
+
+/// The usage of all descendants of the resource, which move to another Drive together with it.
+/// Descendants of a Drive count towards that Drive, so this is empty for Drives.
+pub fn usage_of_descendants(
+    store: &impl Storelike,
+    resource: &Resource,
+) -> AtomicResult<DriveUsage> {
+    let mut usage = DriveUsage::default();
+    if is_drive(resource) {
+        return Ok(usage);
+    }
+    let mut parents = vec![resource.get_subject().clone()];
+    for _ in 0..MAX_DEPTH {
+        let mut next = Vec::new();
+        for parent in parents {
+            let q = crate::storelike::Query::new_prop_val(urls::PARENT, &parent);
+            for child in store.query(&q)?.resources {
+                usage = usage.add(&DriveUsage::of_resource(&child));
+                if !is_drive(&child) {
+                    next.push(child.get_subject().clone());
+                }
+            }
+        }
+        if next.is_empty() {
+            break;
+        }
+        parents = next;
+    }
+    Ok(usage)
+}
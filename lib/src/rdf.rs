@@ -0,0 +1,998 @@
+/*!
+Parses RDF (Turtle, N-Triples and JSON-LD) to [Atom]s, and imports it into a Store.
+
+- Literals with an XSD datatype are converted to the matching [Value], e.g. `xsd:integer` becomes [Value::Integer]. Atomic Data datatypes are supported too, so the output of [crate::serialize::atoms_to_turtle] can be parsed again.
+- Blank nodes become Nested Resources of the Resource that refers to them.
+- When importing, predicates that are not known as Properties get a generated Property, so existing RDF vocabularies can be used.
+
+The JSON-LD parser supports inline `@context` objects (terms, prefixes, `@vocab`, `@base` and type coercion), `@graph`, `@list` and `@set`.
+Remote contexts are not supported.
+*/
+
+use std::collections::{HashMap, HashSet};
+
+use rio_api::parser::TriplesParser;
+use serde_json::Map;
+
+use crate::{
+    datatype::DataType, errors::AtomicResult, parse::ParseOpts, resources::PropVals, urls,
+    values::SubResource, Atom, AtomicError, Storelike, Value,
+};
+
+const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+const RDF_TYPE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+
+/// The RDF serialization formats that can be parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RdfFormat {
+    /// https://www.w3.org/TR/turtle/
+    Turtle,
+    /// https://www.w3.org/TR/n-triples/
+    NTriples,
+    /// https://www.w3.org/TR/json-ld/
+    JsonLd,
+}
+
+impl RdfFormat {
+    pub fn to_mime(&self) -> &'static str {
+        match self {
+            RdfFormat::Turtle => "text/turtle",
+            RdfFormat::NTriples => "application/n-triples",
+            RdfFormat::JsonLd => "application/ld+json",
+        }
+    }
+
+    /// Guesses the format from a file extension, e.g. `ttl`.
+    pub fn from_extension(extension: &str) -> Option<RdfFormat> {
+        match extension {
+            "ttl" => Some(RdfFormat::Turtle),
+            "nt" => Some(RdfFormat::NTriples),
+            "jsonld" => Some(RdfFormat::JsonLd),
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for RdfFormat {
+    type Err = AtomicError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "turtle" | "ttl" => Ok(RdfFormat::Turtle),
+            "ntriples" | "n-triples" | "nt" => Ok(RdfFormat::NTriples),
+            "jsonld" | "json-ld" => Ok(RdfFormat::JsonLd),
+            other => Err(format!(
+                "Unknown RDF format '{}'. Use turtle, ntriples or jsonld.",
+                other
+            )
+            .into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Node {
+    Iri(String),
+    Blank(String),
+}
+
+#[derive(Debug, Clone)]
+enum Object {
+    Node(Node),
+    Literal {
+        value: String,
+        datatype: Option<String>,
+    },
+}
+
+#[derive(Debug)]
+struct Triple {
+    subject: Node,
+    predicate: String,
+    object: Object,
+}
+
+/// Parses an RDF document to Atoms.
+/// Blank nodes that are used as an object become Nested Resources.
+/// Other blank nodes get a subject by appending their ID to the `base` URL, which is also used to resolve relative IRIs.
+pub fn parse_rdf(input: &str, format: RdfFormat, base: Option<&str>) -> AtomicResult<Vec<Atom>> {
+    let triples = match format {
+        RdfFormat::Turtle => {
+            let base_iri = match base {
+                Some(base) => Some(
+                    oxiri::Iri::parse(base.to_string())
+                        .map_err(|e| format!("Invalid base IRI {}: {}", base, e))?,
+                ),
+                None => None,
+            };
+            parse_rio(rio_turtle::TurtleParser::new(input.as_bytes(), base_iri))?
+        }
+        RdfFormat::NTriples => parse_rio(rio_turtle::NTriplesParser::new(input.as_bytes()))?,
+        RdfFormat::JsonLd => parse_json_ld(input, base)?,
+    };
+    triples_to_atoms(triples, base)
+}
+
+fn parse_rio<P>(mut parser: P) -> AtomicResult<Vec<Triple>>
+where
+    P: TriplesParser,
+    P::Error: std::fmt::Display,
+{
+    use rio_api::model::{Literal, Subject, Term};
+
+    let mut triples = Vec::new();
+    parser
+        .parse_all(&mut |t| -> Result<(), P::Error> {
+            let subject = match t.subject {
+                Subject::NamedNode(n) => Node::Iri(n.iri.into()),
+                Subject::BlankNode(n) => Node::Blank(n.id.into()),
+                Subject::Triple(_) => {
+                    tracing::warn!("Skipping RDF-star triple {}", t);
+                    return Ok(());
+                }
+            };
+            let object = match t.object {
+                Term::NamedNode(n) => Object::Node(Node::Iri(n.iri.into())),
+                Term::BlankNode(n) => Object::Node(Node::Blank(n.id.into())),
+                Term::Literal(Literal::Simple { value })
+                | Term::Literal(Literal::LanguageTaggedString { value, .. }) => Object::Literal {
+                    value: value.into(),
+                    datatype: None,
+                },
+                Term::Literal(Literal::Typed { value, datatype }) => Object::Literal {
+                    value: value.into(),
+                    datatype: Some(datatype.iri.into()),
+                },
+                Term::Triple(_) => {
+                    tracing::warn!("Skipping RDF-star triple {}", t);
+                    return Ok(());
+                }
+            };
+            triples.push(Triple {
+                subject,
+                predicate: t.predicate.iri.into(),
+                object,
+            });
+            Ok(())
+        })
+        .map_err(|e| AtomicError::parse_error(&format!("Invalid RDF: {}", e), None, None))?;
+    Ok(triples)
+}
+
+/// Converts an RDF literal to a [Value], using its datatype.
+fn literal_to_value(value: &str, datatype: Option<&str>) -> AtomicResult<Value> {
+    let datatype = match datatype {
+        Some(datatype) => datatype,
+        None => return Ok(Value::String(value.into())),
+    };
+    let xsd_type = match datatype.strip_prefix(XSD) {
+        Some(xsd_type) => xsd_type,
+        // Atomic Data datatypes, e.g. from `serialize::atoms_to_turtle`
+        None => {
+            return match datatype.parse::<DataType>() {
+                Ok(DataType::Unsupported(_)) | Err(_) => Ok(Value::String(value.into())),
+                Ok(atomic_type) => Value::new(value, &atomic_type),
+            }
+        }
+    };
+    let converted = match xsd_type {
+        "boolean" => Value::new(
+            if value == "1" { "true" } else { value },
+            &DataType::Boolean,
+        ),
+        "integer" | "int" | "long" | "short" | "byte" | "nonNegativeInteger"
+        | "nonPositiveInteger" | "positiveInteger" | "negativeInteger" | "unsignedLong"
+        | "unsignedInt" | "unsignedShort" | "unsignedByte" => Value::new(value, &DataType::Integer),
+        "decimal" | "double" | "float" => Value::new(value, &DataType::Float),
+        "dateTime" | "dateTimeStamp" => crate::utils::parse_timestamp(value).map(Value::Timestamp),
+        "date" => Value::new(value, &DataType::Date),
+        _ => Ok(Value::String(value.into())),
+    };
+    converted.map_err(|e| {
+        AtomicError::parse_error(
+            &format!("Invalid {} literal '{}': {}", datatype, value, e),
+            None,
+            None,
+        )
+    })
+}
+
+/// Groups the triples by subject, and nests the blank nodes.
+fn triples_to_atoms(triples: Vec<Triple>, base: Option<&str>) -> AtomicResult<Vec<Atom>> {
+    let mut subjects: Vec<Node> = Vec::new();
+    let mut by_subject: HashMap<Node, Vec<(String, Object)>> = HashMap::new();
+    let mut nested: HashSet<String> = HashSet::new();
+    for triple in triples {
+        if let Object::Node(Node::Blank(id)) = &triple.object {
+            nested.insert(id.clone());
+        }
+        by_subject
+            .entry(triple.subject.clone())
+            .or_insert_with(|| {
+                subjects.push(triple.subject.clone());
+                Vec::new()
+            })
+            .push((triple.predicate, triple.object));
+    }
+
+    let mut atoms = Vec::new();
+    for node in subjects {
+        let subject = match &node {
+            Node::Iri(iri) => iri.clone(),
+            Node::Blank(id) if nested.contains(id) => continue,
+            Node::Blank(id) => match base {
+                Some(base) => format!("{}/{}", base.trim_end_matches('/'), id),
+                None => {
+                    return Err(format!(
+                        "Blank node _:{} is not nested in another resource. Pass a base URL to give it a subject.",
+                        id
+                    )
+                    .into())
+                }
+            },
+        };
+        let propvals = node_to_propvals(&node, &by_subject, &mut Vec::new())?;
+        for (property, value) in propvals {
+            atoms.push(Atom::new(subject.clone(), property, value));
+        }
+    }
+    Ok(atoms)
+}
+
+/// Converts the statements about a single subject to PropVals.
+/// `path` contains the blank nodes that are currently being nested, which is used to detect cycles.
+fn node_to_propvals(
+    node: &Node,
+    by_subject: &HashMap<Node, Vec<(String, Object)>>,
+    path: &mut Vec<String>,
+) -> AtomicResult<PropVals> {
+    // Keep all objects per predicate, because multiple objects are combined in a ResourceArray
+    let mut grouped: Vec<(&String, Vec<Value>)> = Vec::new();
+    for (predicate, object) in by_subject.get(node).into_iter().flatten() {
+        let value = match object {
+            Object::Node(Node::Iri(iri)) => Value::AtomicUrl(iri.clone()),
+            Object::Node(blank @ Node::Blank(id)) => {
+                if path.contains(id) {
+                    return Err(format!(
+                        "Blank node _:{} refers to itself, which can't be converted to a Nested Resource",
+                        id
+                    )
+                    .into());
+                }
+                path.push(id.clone());
+                let propvals = node_to_propvals(blank, by_subject, path)?;
+                path.pop();
+                Value::NestedResource(SubResource::Nested(propvals))
+            }
+            Object::Literal { value, datatype } => literal_to_value(value, datatype.as_deref())
+                .map_err(|e| format!("{} (property {})", e, predicate))?,
+        };
+        match grouped.iter_mut().find(|(p, _)| *p == predicate) {
+            Some((_, values)) => values.push(value),
+            None => grouped.push((predicate, vec![value])),
+        }
+    }
+
+    let mut propvals = PropVals::new();
+    for (predicate, mut values) in grouped {
+        let value = if values.len() == 1 {
+            values.remove(0)
+        } else {
+            let resources: Vec<SubResource> = values
+                .iter()
+                .filter_map(|v| match v {
+                    Value::AtomicUrl(url) => Some(SubResource::Subject(url.clone())),
+                    Value::NestedResource(nested) => Some(nested.clone()),
+                    _ => None,
+                })
+                .collect();
+            if resources.is_empty() {
+                // Atomic Data has one value per property, e.g. a label in multiple languages.
+                tracing::warn!(
+                    "Found {} literals for {}, only using the first one",
+                    values.len(),
+                    predicate
+                );
+                values.remove(0)
+            } else {
+                if resources.len() < values.len() {
+                    tracing::warn!(
+                        "Found {} literals and {} resources for {}, only using the resources",
+                        values.len() - resources.len(),
+                        resources.len(),
+                        predicate
+                    );
+                }
+                Value::ResourceArray(resources)
+            }
+        };
+        propvals.insert(predicate.clone(), value);
+    }
+    Ok(propvals)
+}
+
+#[derive(Debug, Clone)]
+struct TermDefinition {
+    iri: String,
+    /// Type coercion, e.g. `@id` or an XSD datatype.
+    coerce: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct JsonLdContext {
+    vocab: Option<String>,
+    base: Option<String>,
+    terms: HashMap<String, TermDefinition>,
+}
+
+impl JsonLdContext {
+    /// Returns a new context, with the definitions of `context` added.
+    fn extend(&self, context: &serde_json::Value) -> AtomicResult<JsonLdContext> {
+        let mut ctx = self.clone();
+        match context {
+            serde_json::Value::Null => {
+                ctx = JsonLdContext {
+                    base: self.base.clone(),
+                    ..JsonLdContext::default()
+                }
+            }
+            serde_json::Value::Array(contexts) => {
+                for context in contexts {
+                    ctx = ctx.extend(context)?;
+                }
+            }
+            serde_json::Value::Object(map) => {
+                if let Some(serde_json::Value::String(base)) = map.get("@base") {
+                    ctx.base = Some(base.clone());
+                }
+                if let Some(serde_json::Value::String(vocab)) = map.get("@vocab") {
+                    ctx.vocab = Some(ctx.expand_iri(vocab, true).unwrap_or_else(|| vocab.clone()));
+                }
+                // Terms first, so prefixes defined in this context can be used in the other definitions.
+                let mut raw = Vec::new();
+                for (term, definition) in map.iter().filter(|(k, _)| !k.starts_with('@')) {
+                    match definition {
+                        serde_json::Value::String(iri) => raw.push((term, Some(iri.clone()), None)),
+                        serde_json::Value::Object(def) => {
+                            let iri = match def.get("@id") {
+                                Some(serde_json::Value::String(iri)) => Some(iri.clone()),
+                                _ => None,
+                            };
+                            let coerce = match def.get("@type") {
+                                Some(serde_json::Value::String(t)) => Some(t.clone()),
+                                _ => None,
+                            };
+                            raw.push((term, iri, coerce))
+                        }
+                        serde_json::Value::Null => {
+                            ctx.terms.remove(term);
+                        }
+                        other => {
+                            return Err(format!(
+                                "Invalid JSON-LD term definition for {}: {}",
+                                term, other
+                            )
+                            .into())
+                        }
+                    }
+                }
+                for (term, iri, _) in &raw {
+                    if let Some(iri) = iri {
+                        ctx.terms.insert(
+                            term.to_string(),
+                            TermDefinition {
+                                iri: iri.clone(),
+                                coerce: None,
+                            },
+                        );
+                    }
+                }
+                for (term, iri, coerce) in raw {
+                    // Definitions without an `@id` use the `@vocab`
+                    let iri = match (iri, &ctx.vocab) {
+                        (Some(iri), _) => ctx.expand_prefix(&iri),
+                        (None, Some(vocab)) => format!("{}{}", vocab, term),
+                        (None, None) => continue,
+                    };
+                    let definition = TermDefinition {
+                        iri,
+                        coerce: coerce.map(|c| {
+                            if c.starts_with('@') {
+                                c
+                            } else {
+                                ctx.expand_iri(&c, true).unwrap_or(c)
+                            }
+                        }),
+                    };
+                    ctx.terms.insert(term.clone(), definition);
+                }
+            }
+            serde_json::Value::String(url) => {
+                return Err(format!("Remote JSON-LD contexts are not supported: {}", url).into())
+            }
+            other => return Err(format!("Invalid JSON-LD @context: {}", other).into()),
+        }
+        Ok(ctx)
+    }
+
+    /// Expands compact IRIs like `schema:name`.
+    fn expand_prefix(&self, value: &str) -> String {
+        if let Some((prefix, suffix)) = value.split_once(':') {
+            if !suffix.starts_with("//") {
+                if let Some(definition) = self.terms.get(prefix) {
+                    return format!("{}{}", definition.iri, suffix);
+                }
+            }
+        }
+        value.into()
+    }
+
+    /// Expands a term, compact IRI or relative IRI to a full IRI.
+    /// Keys and types are `vocab` relative, `@id`s are relative to the base.
+    /// Returns `None` if the value can't be expanded, in which case it should be ignored.
+    fn expand_iri(&self, value: &str, vocab: bool) -> Option<String> {
+        if vocab {
+            if let Some(definition) = self.terms.get(value) {
+                return Some(definition.iri.clone());
+            }
+        }
+        if value.contains(':') {
+            return Some(self.expand_prefix(value));
+        }
+        if vocab {
+            self.vocab.as_ref().map(|v| format!("{}{}", v, value))
+        } else {
+            let base = url::Url::parse(self.base.as_ref()?).ok()?;
+            base.join(value).ok().map(|u| u.to_string())
+        }
+    }
+}
+
+#[derive(Default)]
+struct JsonLdParser {
+    triples: Vec<Triple>,
+    blank_nodes: usize,
+}
+
+fn parse_json_ld(input: &str, base: Option<&str>) -> AtomicResult<Vec<Triple>> {
+    let json: serde_json::Value = serde_json::from_str(input)
+        .map_err(|e| AtomicError::parse_error(&format!("Invalid JSON: {}", e), None, None))?;
+    let context = JsonLdContext {
+        base: base.map(|b| b.to_string()),
+        ..JsonLdContext::default()
+    };
+    let mut parser = JsonLdParser::default();
+    for item in flatten(&json) {
+        match item {
+            serde_json::Value::Object(map) => {
+                parser.node(map, &context)?;
+            }
+            other => return Err(format!("Expected a JSON-LD node object, got {}", other).into()),
+        }
+    }
+    Ok(parser.triples)
+}
+
+/// Lists the items of arrays, `@list`s and `@set`s. Ordering of lists is not preserved.
+fn flatten(value: &serde_json::Value) -> Vec<&serde_json::Value> {
+    match value {
+        serde_json::Value::Array(items) => items.iter().flat_map(flatten).collect(),
+        serde_json::Value::Object(map) if map.contains_key("@list") => flatten(&map["@list"]),
+        serde_json::Value::Object(map) if map.contains_key("@set") => flatten(&map["@set"]),
+        other => vec![other],
+    }
+}
+
+impl JsonLdParser {
+    /// Adds the triples of a node object, and returns its subject.
+    fn node(
+        &mut self,
+        map: &Map<String, serde_json::Value>,
+        context: &JsonLdContext,
+    ) -> AtomicResult<Node> {
+        let context = match map.get("@context") {
+            Some(c) => context.extend(c)?,
+            None => context.clone(),
+        };
+        let subject = match map.get("@id") {
+            Some(serde_json::Value::String(id)) => self.node_ref(id, &context)?,
+            Some(other) => return Err(format!("@id must be a string, got {}", other).into()),
+            None => self.new_blank_node(),
+        };
+        for (key, value) in map {
+            match key.as_str() {
+                "@type" => {
+                    for class in flatten(value) {
+                        let class = class
+                            .as_str()
+                            .and_then(|c| context.expand_iri(c, true))
+                            .ok_or_else(|| format!("Invalid @type: {}", class))?;
+                        self.triples.push(Triple {
+                            subject: subject.clone(),
+                            predicate: RDF_TYPE.into(),
+                            object: Object::Node(Node::Iri(class)),
+                        });
+                    }
+                }
+                "@graph" => {
+                    for item in flatten(value) {
+                        if let serde_json::Value::Object(item) = item {
+                            self.node(item, &context)?;
+                        }
+                    }
+                }
+                // Other keywords, such as `@reverse` and `@index`, are not supported.
+                k if k.starts_with('@') => {}
+                k => {
+                    // Like in JSON-LD expansion, keys that can't be expanded are dropped.
+                    let predicate = match context.expand_iri(k, true) {
+                        Some(p) => p,
+                        None => continue,
+                    };
+                    let coerce = context.terms.get(k).and_then(|d| d.coerce.clone());
+                    for item in flatten(value) {
+                        if let Some(object) = self.object(item, coerce.as_deref(), &context)? {
+                            self.triples.push(Triple {
+                                subject: subject.clone(),
+                                predicate: predicate.clone(),
+                                object,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        Ok(subject)
+    }
+
+    fn object(
+        &mut self,
+        value: &serde_json::Value,
+        coerce: Option<&str>,
+        context: &JsonLdContext,
+    ) -> AtomicResult<Option<Object>> {
+        let literal = |value: String, datatype: Option<String>| Object::Literal { value, datatype };
+        Ok(Some(match value {
+            serde_json::Value::Null => return Ok(None),
+            serde_json::Value::Bool(b) => literal(b.to_string(), Some(format!("{}boolean", XSD))),
+            serde_json::Value::Number(n) => literal(n.to_string(), Some(number_type(n))),
+            serde_json::Value::String(s) => match coerce {
+                Some("@id") => Object::Node(self.node_ref(s, context)?),
+                Some("@vocab") => match context.expand_iri(s, true) {
+                    Some(iri) => Object::Node(Node::Iri(iri)),
+                    None => return Ok(None),
+                },
+                datatype => literal(s.clone(), datatype.map(|d| d.to_string())),
+            },
+            serde_json::Value::Object(map) => {
+                if let Some(v) = map.get("@value") {
+                    let datatype = match map.get("@type") {
+                        Some(serde_json::Value::String(t)) => context.expand_iri(t, true),
+                        _ => None,
+                    };
+                    match v {
+                        serde_json::Value::Null => return Ok(None),
+                        serde_json::Value::String(s) => literal(s.clone(), datatype),
+                        serde_json::Value::Number(n) => {
+                            literal(n.to_string(), datatype.or_else(|| Some(number_type(n))))
+                        }
+                        serde_json::Value::Bool(b) => literal(
+                            b.to_string(),
+                            datatype.or_else(|| Some(format!("{}boolean", XSD))),
+                        ),
+                        other => return Err(format!("Invalid @value: {}", other).into()),
+                    }
+                } else if let (1, Some(serde_json::Value::String(id))) = (map.len(), map.get("@id"))
+                {
+                    Object::Node(self.node_ref(id, context)?)
+                } else {
+                    Object::Node(self.node(map, context)?)
+                }
+            }
+            serde_json::Value::Array(_) => {
+                return Err("Nested arrays are not supported in JSON-LD".into())
+            }
+        }))
+    }
+
+    fn node_ref(&self, id: &str, context: &JsonLdContext) -> AtomicResult<Node> {
+        if let Some(blank) = id.strip_prefix("_:") {
+            return Ok(Node::Blank(blank.into()));
+        }
+        context
+            .expand_iri(id, false)
+            .map(Node::Iri)
+            .ok_or_else(|| format!("Unable to expand @id '{}', pass a base URL", id).into())
+    }
+
+    fn new_blank_node(&mut self) -> Node {
+        self.blank_nodes += 1;
+        Node::Blank(format!("jsonld-b{}", self.blank_nodes))
+    }
+}
+
+fn number_type(n: &serde_json::Number) -> String {
+    if n.is_f64() {
+        format!("{}double", XSD)
+    } else {
+        format!("{}integer", XSD)
+    }
+}
+
+/// How the values of a predicate are stored.
+enum PropertyKind {
+    /// The Property exists, values are converted to its DataType.
+    Existing(DataType),
+    /// A Property is generated, with a DataType that fits all values.
+    Generated(DataType),
+}
+
+/// Parses RDF and saves the Resources to the `store`, using the same [ParseOpts] as JSON-AD imports.
+/// Blank nodes that are not nested get a subject in the `importer`.
+/// Creates Properties for predicates that are not known yet.
+/// Returns the amount of imported Resources, including the generated Properties.
+pub fn import_rdf(
+    store: &impl Storelike,
+    input: &str,
+    format: RdfFormat,
+    parse_opts: &ParseOpts,
+) -> AtomicResult<usize> {
+    let atoms = parse_rdf(input, format, parse_opts.importer.as_deref())?;
+
+    let mut subjects: Vec<String> = Vec::new();
+    let mut resources: HashMap<String, PropVals> = HashMap::new();
+    let mut kinds: HashMap<String, PropertyKind> = HashMap::new();
+    let mut generated: Vec<String> = Vec::new();
+    for atom in atoms {
+        register_properties(
+            store,
+            &atom.property,
+            &atom.value,
+            &mut kinds,
+            &mut generated,
+        );
+        resources
+            .entry(atom.subject.clone())
+            .or_insert_with(|| {
+                subjects.push(atom.subject.clone());
+                PropVals::new()
+            })
+            .insert(atom.property, atom.value);
+    }
+
+    // Properties are saved first, because the other Resources need them.
+    let mut ordered: Vec<(String, PropVals)> = Vec::new();
+    for property in &generated {
+        let datatype = match &kinds[property] {
+            PropertyKind::Generated(datatype) => datatype.to_string(),
+            PropertyKind::Existing(_) => continue,
+        };
+        let mut propvals = resources.remove(property).unwrap_or_default();
+        let defaults = [
+            (
+                urls::IS_A,
+                Value::ResourceArray(vec![SubResource::Subject(urls::PROPERTY.into())]),
+            ),
+            (urls::SHORTNAME, Value::Slug(shortname_from_iri(property))),
+            (urls::DATATYPE_PROP, Value::AtomicUrl(datatype)),
+            (
+                urls::DESCRIPTION,
+                Value::Markdown(format!("Imported from RDF: `{}`", property)),
+            ),
+        ];
+        for (prop, value) in defaults {
+            propvals.entry(prop.into()).or_insert(value);
+        }
+        ordered.push((property.clone(), propvals));
+    }
+    for subject in subjects {
+        if let Some(propvals) = resources.remove(&subject) {
+            ordered.push((subject, propvals));
+        }
+    }
+
+    let count = ordered.len();
+    for (subject, propvals) in ordered {
+        let mut propvals = coerce_propvals(propvals, &kinds)
+            .map_err(|e| format!("Unable to import {}. {}", subject, e))?;
+        if let Some(importer) = &parse_opts.importer {
+            propvals
+                .entry(urls::PARENT.into())
+                .or_insert_with(|| Value::AtomicUrl(importer.into()));
+        }
+        crate::parse::save_propvals(subject, propvals, store, parse_opts)?;
+    }
+    Ok(count)
+}
+
+/// Checks whether the predicate is a known Property, including the predicates of Nested Resources.
+/// Only Properties in the `store` are used, unknown predicates are not fetched from the web.
+/// Widens the DataType of generated Properties, so all values fit.
+fn register_properties(
+    store: &impl Storelike,
+    property: &str,
+    value: &Value,
+    kinds: &mut HashMap<String, PropertyKind>,
+    generated: &mut Vec<String>,
+) {
+    let kind = kinds.entry(property.into()).or_insert_with(|| {
+        match store
+            .get_resource_local(property)
+            .and_then(crate::schema::Property::from_resource)
+        {
+            Ok(p) => PropertyKind::Existing(p.data_type),
+            Err(_) => {
+                generated.push(property.into());
+                PropertyKind::Generated(value.datatype())
+            }
+        }
+    });
+    if let PropertyKind::Generated(datatype) = kind {
+        *datatype = widen_datatype(datatype, &value.datatype());
+    }
+    let nested: Vec<&PropVals> = match value {
+        Value::NestedResource(SubResource::Nested(propvals)) => vec![propvals],
+        Value::ResourceArray(items) => items
+            .iter()
+            .filter_map(|item| match item {
+                SubResource::Nested(propvals) => Some(propvals),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    for propvals in nested {
+        for (prop, val) in propvals {
+            register_properties(store, prop, val, kinds, generated);
+        }
+    }
+}
+
+/// Returns a DataType that can hold values of both DataTypes.
+fn widen_datatype(current: &DataType, new: &DataType) -> DataType {
+    match (current, new) {
+        (a, b) if a == b => a.clone(),
+        (DataType::Integer, DataType::Float) | (DataType::Float, DataType::Integer) => {
+            DataType::Float
+        }
+        (DataType::AtomicUrl, DataType::ResourceArray)
+        | (DataType::ResourceArray, DataType::AtomicUrl) => DataType::ResourceArray,
+        _ => DataType::String,
+    }
+}
+
+/// Converts the Values to the DataTypes of their Properties.
+fn coerce_propvals(
+    propvals: PropVals,
+    kinds: &HashMap<String, PropertyKind>,
+) -> AtomicResult<PropVals> {
+    let mut coerced = PropVals::new();
+    for (prop, value) in propvals {
+        let datatype = match kinds.get(&prop) {
+            Some(PropertyKind::Existing(datatype)) | Some(PropertyKind::Generated(datatype)) => {
+                datatype
+            }
+            None => {
+                coerced.insert(prop, value);
+                continue;
+            }
+        };
+        let value = match value {
+            Value::NestedResource(SubResource::Nested(nested)) => {
+                Value::NestedResource(SubResource::Nested(coerce_propvals(nested, kinds)?))
+            }
+            Value::ResourceArray(items) => Value::ResourceArray(
+                items
+                    .into_iter()
+                    .map(|item| match item {
+                        SubResource::Nested(nested) => {
+                            Ok(SubResource::Nested(coerce_propvals(nested, kinds)?))
+                        }
+                        other => Ok(other),
+                    })
+                    .collect::<AtomicResult<_>>()?,
+            ),
+            other => other,
+        };
+        let value = match (value, datatype) {
+            (value, datatype) if &value.datatype() == datatype => value,
+            (Value::AtomicUrl(url), DataType::ResourceArray) => {
+                Value::ResourceArray(vec![SubResource::Subject(url)])
+            }
+            (Value::NestedResource(nested), DataType::ResourceArray) => {
+                Value::ResourceArray(vec![nested])
+            }
+            (value, datatype) => Value::new(&value.to_string(), datatype).map_err(|e| {
+                format!(
+                    "Value '{}' does not fit the datatype {} of {}: {}",
+                    value, datatype, prop, e
+                )
+            })?,
+        };
+        coerced.insert(prop, value);
+    }
+    Ok(coerced)
+}
+
+/// Creates a valid shortname from the last part of an IRI, e.g. `http://schema.org/birthDate` becomes `birth-date`.
+fn shortname_from_iri(iri: &str) -> String {
+    let local = iri
+        .trim_end_matches(['/', '#'])
+        .rsplit(['/', '#', ':'])
+        .next()
+        .unwrap_or_default();
+    let mut shortname = String::new();
+    let mut previous_lowercase = false;
+    for c in local.chars() {
+        if c.is_ascii_alphanumeric() {
+            if c.is_ascii_uppercase() && previous_lowercase {
+                shortname.push('-');
+            }
+            shortname.push(c.to_ascii_lowercase());
+            previous_lowercase = c.is_ascii_lowercase() || c.is_ascii_digit();
+        } else {
+            if !shortname.ends_with('-') {
+                shortname.push('-');
+            }
+            previous_lowercase = false;
+        }
+    }
+    let shortname = shortname.trim_matches('-').to_string();
+    if shortname.is_empty() {
+        "property".into()
+    } else {
+        shortname
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TURTLE: &str = r#"
+        @prefix schema: <http://schema.org/> .
+        @prefix xsd: <http://www.w3.org/2001/XMLSchema#> .
+
+        <https://example.com/alice> a schema:Person ;
+            schema:name "Alice"@en ;
+            schema:age "42"^^xsd:integer ;
+            schema:height "1.72"^^xsd:decimal ;
+            schema:active true ;
+            schema:birthDate "1981-03-01"^^xsd:date ;
+            schema:dateModified "2023-03-01T12:00:00Z"^^xsd:dateTime ;
+            schema:knows <https://example.com/bob>, <https://example.com/carol> ;
+            schema:address [ schema:streetAddress "Main Street 1" ] .
+    "#;
+
+    fn find<'a>(atoms: &'a [Atom], property: &str) -> &'a Value {
+        &atoms
+            .iter()
+            .find(|a| a.property == format!("http://schema.org/{}", property))
+            .unwrap_or_else(|| panic!("no atom for {}", property))
+            .value
+    }
+
+    fn assert_value(atoms: &[Atom], property: &str, expected: &str, datatype: DataType) {
+        let value = find(atoms, property);
+        assert_eq!(value.to_string(), expected, "{}", property);
+        assert_eq!(value.datatype(), datatype, "{}", property);
+    }
+
+    #[test]
+    fn parse_turtle() {
+        let atoms = parse_rdf(TURTLE, RdfFormat::Turtle, None).unwrap();
+        assert!(atoms
+            .iter()
+            .all(|a| a.subject == "https://example.com/alice"));
+        assert_value(&atoms, "name", "Alice", DataType::String);
+        assert_value(&atoms, "age", "42", DataType::Integer);
+        assert_value(&atoms, "height", "1.72", DataType::Float);
+        assert_value(&atoms, "active", "true", DataType::Boolean);
+        assert_value(&atoms, "birthDate", "1981-03-01", DataType::Date);
+        assert_value(&atoms, "dateModified", "1677672000000", DataType::Timestamp);
+        assert!(matches!(
+            find(&atoms, "knows"),
+            Value::ResourceArray(items) if items.len() == 2
+        ));
+        match find(&atoms, "address") {
+            Value::NestedResource(SubResource::Nested(propvals)) => assert_eq!(
+                propvals
+                    .get("http://schema.org/streetAddress")
+                    .unwrap()
+                    .to_string(),
+                "Main Street 1"
+            ),
+            other => panic!("Expected a nested resource, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_ntriples_and_json_ld() {
+        let ntriples = r#"<https://example.com/alice> <http://schema.org/age> "42"^^<http://www.w3.org/2001/XMLSchema#integer> .
+_:b1 <http://schema.org/name> "Unnamed" .
+"#;
+        let atoms = parse_rdf(
+            ntriples,
+            RdfFormat::NTriples,
+            Some("https://example.com/import"),
+        )
+        .unwrap();
+        assert_eq!(atoms.len(), 2);
+        assert_eq!(atoms[1].subject, "https://example.com/import/b1");
+        assert!(parse_rdf(ntriples, RdfFormat::NTriples, None).is_err());
+
+        let json_ld = r#"{
+            "@context": {
+                "@vocab": "http://schema.org/",
+                "xsd": "http://www.w3.org/2001/XMLSchema#",
+                "knows": { "@type": "@id" },
+                "birthDate": { "@type": "xsd:date" }
+            },
+            "@id": "https://example.com/alice",
+            "@type": "Person",
+            "name": "Alice",
+            "age": 42,
+            "birthDate": "1981-03-01",
+            "knows": ["https://example.com/bob", "https://example.com/carol"],
+            "address": { "streetAddress": "Main Street 1" }
+        }"#;
+        let atoms = parse_rdf(json_ld, RdfFormat::JsonLd, None).unwrap();
+        assert_value(&atoms, "name", "Alice", DataType::String);
+        assert_value(&atoms, "age", "42", DataType::Integer);
+        assert_value(&atoms, "birthDate", "1981-03-01", DataType::Date);
+        assert!(matches!(
+            find(&atoms, "knows"),
+            Value::ResourceArray(items) if items.len() == 2
+        ));
+        assert!(matches!(
+            find(&atoms, "address"),
+            Value::NestedResource(SubResource::Nested(_))
+        ));
+        let class = atoms.iter().find(|a| a.property == RDF_TYPE).unwrap();
+        assert_eq!(class.value.to_string(), "http://schema.org/Person");
+    }
+
+    #[test]
+    fn shortnames() {
+        assert_eq!(
+            shortname_from_iri("http://schema.org/birthDate"),
+            "birth-date"
+        );
+        assert_eq!(
+            shortname_from_iri("http://www.w3.org/2000/01/rdf-schema#label"),
+            "label"
+        );
+        assert_eq!(
+            shortname_from_iri("http://example.com/some_prop/"),
+            "some-prop"
+        );
+    }
+
+    #[cfg(feature = "db")]
+    #[test]
+    fn import_turtle() {
+        let store = &crate::Db::init_temp("import_turtle").unwrap();
+        let importer = format!("{}/import", store.get_server_url());
+        let parse_opts = ParseOpts {
+            importer: Some(importer.clone()),
+            signer: Some(store.get_default_agent().unwrap()),
+            save: crate::parse::SaveOpts::Commit,
+            ..ParseOpts::default()
+        };
+        let count = import_rdf(store, TURTLE, RdfFormat::Turtle, &parse_opts).unwrap();
+        // One resource and a generated Property for every predicate
+        assert_eq!(count, 11);
+
+        let alice = store.get_resource("https://example.com/alice").unwrap();
+        assert_eq!(alice.get(urls::PARENT).unwrap().to_string(), importer);
+        assert_eq!(
+            alice.get("http://schema.org/age").unwrap().to_string(),
+            "42"
+        );
+        let age = store.get_property("http://schema.org/age").unwrap();
+        assert_eq!(age.data_type, DataType::Integer);
+        assert_eq!(age.shortname, "age");
+        let knows = store.get_property("http://schema.org/knows").unwrap();
+        assert_eq!(knows.data_type, DataType::ResourceArray);
+    }
+}
@@ -0,0 +1,150 @@
+/*!
+Replication lets a [Db] follow a primary Atomic Server, which makes it a read replica or an offline backup.
+
+Every Commit is signed and self-contained, so the follower can fetch the Commit log of the primary and apply the Commits in the same order, while verifying their original signatures.
+Commits are fetched using the `/changes` endpoint of the primary, which pages through its commit log by sequence number (see [Db::commit_log]).
+The follower keeps a cursor (the sequence number of the last entry that it has seen), so it can resume where it stopped after a restart.
+The follower only receives the Commits that its default Agent is allowed to read.
+Note that Commits of destroyed Resources are only readable by the server Agent of the primary.
+*/
+
+use crate::{
+    agents::Agent,
+    commit::CommitOpts,
+    errors::AtomicResult,
+    parse::{parse_json_ad_resource, ParseOpts, SaveOpts},
+    urls,
+    values::SubResource,
+    Commit, Db, Storelike, Value,
+};
+
+/// Amount of commit log entries that are fetched in a single request.
+pub const PAGE_SIZE: usize = 100;
+
+/// Commits from the commit log of a primary, and the sequence number to continue from.
+#[derive(Debug)]
+pub struct CommitsPage {
+    /// The readable Commits, in the order in which the primary applied them.
+    pub commits: Vec<Commit>,
+    /// The sequence number of the last entry in this page, including Commits that were skipped.
+    /// Equal to the requested `since` if there are no new entries.
+    pub next: u64,
+}
+
+/// Returns the Commits in the commit log of the `store` after the `since` sequence number.
+/// This is what a primary serves to its followers.
+pub fn commits_since(store: &Db, since: u64, for_agent: Option<&str>) -> AtomicResult<CommitsPage> {
+    let (resources, next) =
+        crate::plugins::changes::changes_since(store, since, PAGE_SIZE, for_agent)?;
+    let commits = resources
+        .into_iter()
+        .map(Commit::from_resource)
+        .collect::<AtomicResult<_>>()?;
+    Ok(CommitsPage { commits, next })
+}
+
+/// Fetches the Commits after the `since` sequence number from the `/changes` endpoint of the `primary` server.
+/// Signs the request using the `agent`, if it is passed.
+pub fn fetch_commits_since(
+    primary: &str,
+    since: u64,
+    agent: Option<Agent>,
+    store: &impl Storelike,
+) -> AtomicResult<CommitsPage> {
+    let url = format!(
+        "{}/changes?since={}&page_size={}",
+        primary.trim_end_matches('/'),
+        since,
+        PAGE_SIZE
+    );
+    let body = crate::client::fetch_body(&url, crate::parse::JSON_AD_MIME, agent)?;
+    let parse_opts = ParseOpts {
+        save: SaveOpts::DontSave,
+        ..ParseOpts::default()
+    };
+    let page = parse_json_ad_resource(&body, store, &parse_opts)
+        .map_err(|e| format!("Error parsing Commits from {}. {}", primary, e))?;
+    let next = page
+        .get(urls::CHANGES_NEXT)
+        .and_then(|next| next.to_int())
+        .map_err(|e| format!("Missing the next sequence number from {}. {}", primary, e))?;
+    let members = match page.get(urls::ENDPOINT_RESULTS) {
+        Ok(Value::ResourceArray(members)) => members.clone(),
+        Ok(other) => return Err(format!("Expected Commits from {}, got {}", primary, other).into()),
+        // Pages without readable Commits have no results
+        Err(_) => Vec::new(),
+    };
+    let commits = members
+        .into_iter()
+        .map(|member| match member {
+            SubResource::Resource(resource) => Commit::from_resource(*resource),
+            other => {
+                Err(format!("Expected a nested Commit from {}, got {}", primary, other).into())
+            }
+        })
+        .collect::<AtomicResult<_>>()?;
+    Ok(CommitsPage {
+        commits,
+        next: next as u64,
+    })
+}
+
+/// Applies the Commits of a page from the `primary` in order, using their original signatures.
+/// Skips Commits that have been applied before, and then moves the replication cursor to the end of the page.
+/// Returns the amount of Commits that were applied.
+pub fn apply_commits(store: &Db, primary: &str, page: &CommitsPage) -> AtomicResult<usize> {
+    // The primary has checked the rights and the schema, and it decides the order of the Commits.
+    let opts = CommitOpts {
+        validate_schema: false,
+        validate_signature: true,
+        validate_timestamp: false,
+        validate_rights: false,
+        validate_previous_commit: false,
+        update_index: true,
+        validate_for_agent: None,
+    };
+    let mut applied = 0;
+    for commit in &page.commits {
+        let commit_subject = commit.into_resource(store)?.get_subject().to_string();
+        if store.get_resource(&commit_subject).is_err() {
+            commit.apply_opts(store, &opts).map_err(|e| {
+                format!(
+                    "Failed to replicate Commit for {} from {}. {}",
+                    commit.subject, primary, e
+                )
+            })?;
+            applied += 1;
+        }
+    }
+    store.set_replication_cursor(primary, page.next)?;
+    Ok(applied)
+}
+
+/// Fetches all new Commits using `fetch_page` (which gets the `since` sequence number), and applies them.
+/// Starts at the stored replication cursor of the `primary`.
+/// Returns the amount of Commits that were applied.
+pub fn pull_with(
+    store: &Db,
+    primary: &str,
+    fetch_page: impl Fn(u64) -> AtomicResult<CommitsPage>,
+) -> AtomicResult<usize> {
+    let mut applied = 0;
+    loop {
+        let since = store.get_replication_cursor(primary)?.unwrap_or(0);
+        let page = fetch_page(since)?;
+        applied += apply_commits(store, primary, &page)?;
+        if page.next <= since {
+            return Ok(applied);
+        }
+    }
+}
+
+/// Fetches all new Commits from the `primary` server over HTTP, and applies them.
+/// Requests are signed by the default Agent of the `store`, which needs read rights on the primary.
+/// Returns the amount of Commits that were applied.
+pub fn pull(store: &Db, primary: &str) -> AtomicResult<usize> {
+    let agent = store.get_default_agent().ok();
+    pull_with(store, primary, |since| {
+        fetch_commits_since(primary, since, agent.clone(), store)
+    })
+}
@@ -128,11 +128,23 @@ impl Resource {
     }
 
     /// Walks the parent tree upwards until there is no parent, then returns them as a vector.
+    /// Throws if the parents form a cycle.
     pub fn get_parent_tree(&self, store: &impl Storelike) -> AtomicResult<Vec<Resource>> {
         let mut parents: Vec<Resource> = Vec::new();
         let mut current = self.clone();
 
         while let Ok(parent) = current.get_parent(store) {
+            if parent.get_subject() == self.get_subject()
+                || parents
+                    .iter()
+                    .any(|p| p.get_subject() == parent.get_subject())
+            {
+                return Err(format!(
+                    "There is a circular relationship in the parents of {}.",
+                    self.get_subject()
+                )
+                .into());
+            }
             parents.push(parent.clone());
             current = parent;
         }
@@ -573,6 +585,24 @@ mod test {
             .unwrap_err();
     }
 
+    #[test]
+    fn parent_tree_cycle() {
+        let store = init_store();
+        let mut a = Resource::new("https://localhost/a".into());
+        let mut b = Resource::new("https://localhost/b".into());
+        a.set_propval_unsafe(
+            urls::PARENT.into(),
+            Value::AtomicUrl(b.get_subject().into()),
+        );
+        b.set_propval_unsafe(
+            urls::PARENT.into(),
+            Value::AtomicUrl(a.get_subject().into()),
+        );
+        store.add_resource_opts(&a, false, false, true).unwrap();
+        store.add_resource_opts(&b, false, false, true).unwrap();
+        a.get_parent_tree(&store).unwrap_err();
+    }
+
     #[test]
     fn check_required_props() {
         let store = init_store();
@@ -21,6 +21,15 @@ pub fn resources_to_json_ad(resources: &[Resource]) -> AtomicResult<String> {
     serde_json::to_string_pretty(&serde_array).map_err(|_| "Could not serialize to JSON-AD".into())
 }
 
+/// Serializes a Resource to a single line of NDJSON-AD (newline-delimited JSON-AD), without the trailing newline.
+pub fn resource_to_ndjson_ad_line(resource: &Resource) -> AtomicResult<String> {
+    let obj = propvals_to_json_ad_map(
+        resource.get_propvals(),
+        Some(resource.get_subject().clone()),
+    )?;
+    serde_json::to_string(&obj).map_err(|e| format!("Could not serialize to JSON-AD. {}", e).into())
+}
+
 /// Converts an Atomic Value to a Serde Value.
 // TODO: Accept JSON-LD / JSON as options
 // https://github.com/atomicdata-dev/atomic-data-rust/issues/315
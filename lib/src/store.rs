@@ -191,10 +191,19 @@ impl Storelike for Store {
     }
 
     fn get_resource(&self, subject: &str) -> AtomicResult<Resource> {
-        if let Some(resource) = self.hashmap.lock().unwrap().get(subject) {
-            return Ok(resource.clone());
+        match self.get_resource_local(subject) {
+            Ok(resource) => Ok(resource),
+            Err(e) => self.handle_not_found(subject, e),
+        }
+    }
+
+    fn get_resource_local(&self, subject: &str) -> AtomicResult<Resource> {
+        match self.hashmap.lock().unwrap().get(subject) {
+            Some(resource) => Ok(resource.clone()),
+            None => Err(crate::AtomicError::not_found(
+                "Not found in HashMap.".into(),
+            )),
         }
-        self.handle_not_found(subject, "Not found in HashMap.".into())
     }
 
     fn remove_resource(&self, subject: &str) -> AtomicResult<()> {
@@ -214,20 +223,32 @@ impl Storelike for Store {
     }
 
     fn query(&self, q: &crate::storelike::Query) -> AtomicResult<crate::storelike::QueryResult> {
-        let atoms = self.tpf(
-            None,
-            q.property.as_deref(),
-            q.value.as_ref(),
-            q.include_external,
-        )?;
-
-        // Remove duplicate subjects
-        let mut subjects_deduplicated: Vec<String> = atoms
-            .iter()
-            .map(|atom| atom.subject.clone())
-            .collect::<std::collections::HashSet<String>>()
-            .into_iter()
-            .collect();
+        let (mut subjects_deduplicated, count) = if let Some(expr) = q.to_expr() {
+            // The in-memory store has no indexes, so we check the filter for every resource.
+            let subjects: Vec<String> = self
+                .all_resources(q.include_external)
+                .filter(|resource| expr.matches(resource))
+                .map(|resource| resource.get_subject().clone())
+                .collect();
+            let count = subjects.len();
+            (subjects, count)
+        } else {
+            let atoms = self.tpf(
+                None,
+                q.property.as_deref(),
+                q.value.as_ref(),
+                q.include_external,
+            )?;
+
+            // Remove duplicate subjects
+            let subjects: Vec<String> = atoms
+                .iter()
+                .map(|atom| atom.subject.clone())
+                .collect::<std::collections::HashSet<String>>()
+                .into_iter()
+                .collect();
+            (subjects, atoms.len())
+        };
 
         // Sort by subject, better than no sorting
         subjects_deduplicated.sort();
@@ -263,7 +284,7 @@ impl Storelike for Store {
         }
 
         Ok(QueryResult {
-            count: atoms.len(),
+            count,
             subjects,
             resources,
         })
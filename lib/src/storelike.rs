@@ -128,6 +128,64 @@ pub trait Storelike: Sized {
         crate::serialize::resources_to_json_ad(&properties)
     }
 
+    /// Writes the store as NDJSON-AD (newline-delimited JSON-AD) to the `writer`, one Resource per line.
+    /// Unlike [Storelike::export], this never holds the whole export in memory.
+    /// Properties are written first, just like in [Storelike::export].
+    /// If `since` (a UNIX timestamp in milliseconds) is passed, only Resources that were changed at or after that moment are exported, including their Commits.
+    /// Resources without a Commit history can't be dated, so these are left out of incremental exports.
+    /// Resources that were destroyed are only present as the Commits that destroyed them. Importing these with [crate::parse::SaveOpts::Save] removes the Resources.
+    /// Returns the amount of exported Resources.
+    fn export_ndjson(
+        &self,
+        writer: &mut dyn std::io::Write,
+        include_external: bool,
+        since: Option<i64>,
+    ) -> AtomicResult<usize> {
+        let is_property = |r: &Resource| {
+            r.get_main_class()
+                .map(|class| class == urls::PROPERTY)
+                .unwrap_or(false)
+        };
+        let mut count = 0;
+        for properties_pass in [true, false] {
+            for r in self.all_resources(include_external) {
+                if is_property(&r) != properties_pass {
+                    continue;
+                }
+                if let Some(since) = since {
+                    match self.last_changed_at(&r) {
+                        Some(changed) if changed >= since => {}
+                        _ => continue,
+                    }
+                }
+                let line = crate::serialize::resource_to_ndjson_ad_line(&r)?;
+                writeln!(writer, "{}", line)
+                    .map_err(|e| format!("Failed to write export. {}", e))?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Returns the moment (UNIX timestamp in milliseconds) at which the Resource was last changed.
+    /// For Commits, this is their `createdAt`, for other Resources the `createdAt` of their `lastCommit`.
+    fn last_changed_at(&self, resource: &Resource) -> Option<i64> {
+        if let Ok(Value::Timestamp(created_at)) = resource.get(urls::CREATED_AT) {
+            if resource.get_main_class().ok().as_deref() == Some(urls::COMMIT) {
+                return Some(*created_at);
+            }
+        }
+        let last_commit = resource.get(urls::LAST_COMMIT).ok()?.to_string();
+        match self
+            .get_resource_local(&last_commit)
+            .ok()?
+            .get(urls::CREATED_AT)
+        {
+            Ok(Value::Timestamp(created_at)) => Some(*created_at),
+            _ => None,
+        }
+    }
+
     /// Fetches a resource, makes sure its subject matches.
     /// Uses the default agent to sign the request.
     /// Save to the store.
@@ -143,6 +201,9 @@ pub trait Storelike: Sized {
     /// If you're not sure what to use, use `get_resource_extended`.
     fn get_resource(&self, subject: &str) -> AtomicResult<Resource>;
 
+    /// Returns the Resource if it is in this store. Unlike [Storelike::get_resource], missing Resources are not fetched.
+    fn get_resource_local(&self, subject: &str) -> AtomicResult<Resource>;
+
     /// Returns an existing resource, or creates a new one with the given Subject
     fn get_resource_new(&self, subject: &str) -> Resource {
         match self.get_resource(subject) {
@@ -166,6 +227,27 @@ pub trait Storelike: Sized {
         Ok(classes)
     }
 
+    /// Returns the `members` of a Group, or nothing if the subject is not a local Group.
+    /// Used for resolving rights, see [hierarchy::is_member].
+    fn get_group_members(&self, subject: &str) -> AtomicResult<Vec<String>> {
+        if !subject.starts_with(self.get_server_url()) {
+            return Ok(Vec::new());
+        }
+        match self.get_resource(subject) {
+            Ok(resource) => hierarchy::group_members(resource.get_propvals()),
+            Err(_) => Ok(Vec::new()),
+        }
+    }
+
+    /// Returns the Agents and Groups that can read the values of a Property, or `None` if everyone can.
+    /// Used for hiding values, see [hierarchy::remove_hidden_properties].
+    fn get_property_readers(&self, property: &str) -> AtomicResult<Option<Vec<String>>> {
+        match self.get_resource(property) {
+            Ok(resource) => hierarchy::property_readers(resource.get_propvals()),
+            Err(_) => Ok(None),
+        }
+    }
+
     /// Fetches a property by URL, returns a Property instance
     #[tracing::instrument(skip(self))]
     fn get_property(&self, subject: &str) -> AtomicResult<Property> {
@@ -187,14 +269,67 @@ pub trait Storelike: Sized {
         for_agent: Option<&str>,
     ) -> AtomicResult<Resource> {
         let _ignore = skip_dynamic;
-        let resource = self.get_resource(subject)?;
+        let mut resource = self.get_resource(subject)?;
         if let Some(agent) = for_agent {
             hierarchy::check_read(self, &resource, agent)?;
+            hierarchy::remove_hidden_properties(self, &mut resource, agent)?;
             return Ok(resource);
         }
         Ok(resource)
     }
 
+    /// Runs `apply` with a store that persists all of its writes at once if `apply` succeeds, and nothing if it fails.
+    /// [crate::Commit::apply_opts] uses this, so a resource is never persisted without its entry in the commit log.
+    /// Stores that can't do this run `apply` on themselves.
+    fn all_or_nothing<T>(&self, apply: impl FnOnce(&Self) -> AtomicResult<T>) -> AtomicResult<T>
+    where
+        Self: Sized,
+    {
+        apply(self)
+    }
+
+    /// Adds the subject of an applied Commit to the end of the commit log, if the Store keeps one.
+    /// See [crate::Db::commit_log].
+    fn append_to_commit_log(&self, _commit_subject: &str) -> AtomicResult<()> {
+        Ok(())
+    }
+
+    /// Returns the sequence number of an applied Commit in the commit log, if the Store keeps one and the Commit is in it.
+    /// This is the order in which Commits were applied, which `createdAt` does not tell.
+    fn get_commit_seq(&self, _commit_subject: &str) -> AtomicResult<Option<u64>> {
+        Ok(None)
+    }
+
+    /// Called with the outcome of the rights check of a request, see [crate::audit].
+    /// Only the Db stores these, if its audit log is enabled.
+    fn record_audit_event(
+        &self,
+        _for_agent: &str,
+        _subject: &str,
+        _right: &hierarchy::Right,
+        _result: &AtomicResult<String>,
+    ) {
+    }
+
+    /// Throws if changing `resource_old` (`None` if it is created) into `resource_new` would exceed the quota of the Drive of `resource_new`, see [crate::quotas].
+    /// Only the Db enforces quotas, if these are enabled.
+    fn check_drive_quota(
+        &self,
+        _resource_old: Option<&Resource>,
+        _resource_new: &Resource,
+    ) -> AtomicResult<()> {
+        Ok(())
+    }
+
+    /// Moves the usage of `resource_old` (`None` if it is created) to that of `resource_new` (`None` if it is destroyed), in the Drives that they are in, see [crate::quotas].
+    fn update_drive_usage(
+        &self,
+        _resource_old: Option<&Resource>,
+        _resource_new: Option<&Resource>,
+    ) -> AtomicResult<()> {
+        Ok(())
+    }
+
     /// This function is called whenever a Commit is applied.
     /// Implement this if you want to have custom handlers for Commits.
     fn handle_commit(&self, _commit_response: &CommitResponse) {}
@@ -218,6 +353,17 @@ pub trait Storelike: Sized {
         Ok(len)
     }
 
+    /// Parses NDJSON-AD (newline-delimited JSON-AD) from the `reader` one line at a time, and adds the Resources to the store.
+    /// Use this instead of [Storelike::import] for large imports.
+    /// Returns the amount of imported Resources.
+    fn import_ndjson(
+        &self,
+        reader: &mut dyn std::io::BufRead,
+        parse_opts: &crate::parse::ParseOpts,
+    ) -> AtomicResult<usize> {
+        crate::parse::parse_ndjson_ad(reader, self, parse_opts)
+    }
+
     /// Removes a resource from the store. Errors if not present.
     fn remove_resource(&self, subject: &str) -> AtomicResult<()>;
 
@@ -330,6 +476,13 @@ pub trait Storelike: Sized {
         crate::populate::populate_default_store(self)
     }
 
+    /// Returns the Resource as it was at some point in time (a timestamp in milliseconds), by replaying its Commits.
+    /// Fails if the Resource did not exist at that time. Does not check rights.
+    #[cfg(feature = "db")]
+    fn get_resource_at(&self, subject: &str, timestamp: i64) -> AtomicResult<Resource> {
+        crate::plugins::versioning::construct_version_at(subject, timestamp, self, None)
+    }
+
     /// Search the Store, returns the matching subjects.
     fn query(&self, q: &Query) -> AtomicResult<QueryResult>;
 
@@ -372,6 +525,9 @@ pub struct Query {
     pub include_nested: bool,
     /// For which Agent the query is executed. Pass `None` if you want to skip permission checks.
     pub for_agent: Option<String>,
+    /// A composable filter, which is combined with the `property` and `value` filters.
+    /// Queries with a filter are not cached in the query index, but planned on every request.
+    pub filter: Option<QueryExpr>,
 }
 
 impl Query {
@@ -388,6 +544,7 @@ impl Query {
             include_external: false,
             include_nested: true,
             for_agent: None,
+            filter: None,
         }
     }
 
@@ -406,6 +563,39 @@ impl Query {
         q.value = Some(Value::AtomicUrl(class.to_string()));
         q
     }
+
+    /// Search using a composable filter
+    pub fn new_expr(filter: QueryExpr) -> Self {
+        let mut q = Self::new();
+        q.filter = Some(filter);
+        q
+    }
+
+    /// Combines the `filter` with the `property` and `value` of this Query.
+    /// Returns `None` if no `filter` is set, which means that the Query can use the query index.
+    pub fn to_expr(&self) -> Option<QueryExpr> {
+        self.filter.as_ref()?;
+        self.combined_expr()
+    }
+
+    /// Combines the `filter`, `property` and `value` of this Query in a single [QueryExpr].
+    /// Returns `None` if the Query does not filter at all.
+    pub fn combined_expr(&self) -> Option<QueryExpr> {
+        let prop_val = match (&self.property, &self.value) {
+            (Some(property), Some(value)) => Some(QueryExpr::Equals {
+                property: property.clone(),
+                value: value.to_string(),
+            }),
+            (Some(property), None) => Some(QueryExpr::Exists(property.clone())),
+            (None, Some(value)) => Some(QueryExpr::References(value.to_string())),
+            (None, None) => None,
+        };
+        match (prop_val, self.filter.clone()) {
+            (Some(clause), Some(filter)) => Some(QueryExpr::And(vec![clause, filter])),
+            (Some(clause), None) => Some(clause),
+            (None, filter) => filter,
+        }
+    }
 }
 
 impl Default for Query {
@@ -414,6 +604,97 @@ impl Default for Query {
     }
 }
 
+/// A composable filter for [Query]s.
+/// Serializes to JSON, e.g. `{"and":[{"isA":"https://example.com/Class"},{"range":{"property":"https://example.com/age","min":18}}]}`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum QueryExpr {
+    /// The Property has this Value. For ResourceArrays, one of the members has to match.
+    Equals { property: String, value: String },
+    /// The Resource has some Value for this Property.
+    Exists(String),
+    /// The Integer, Float or Timestamp Value of the Property is between `min` and `max` (inclusive).
+    /// Leave one out for an open range.
+    Range {
+        property: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max: Option<f64>,
+    },
+    /// The Resource is an instance of this Class.
+    IsA(String),
+    /// Some Property of the Resource has this Value.
+    References(String),
+    /// All clauses must match.
+    And(Vec<QueryExpr>),
+    /// At least one of the clauses must match.
+    Or(Vec<QueryExpr>),
+}
+
+impl QueryExpr {
+    /// Parses a JSON serialized QueryExpr.
+    pub fn from_json(json: &str) -> AtomicResult<Self> {
+        serde_json::from_str(json).map_err(|e| format!("Invalid query filter: {}", e).into())
+    }
+
+    pub fn to_json(&self) -> AtomicResult<String> {
+        serde_json::to_string(self)
+            .map_err(|e| format!("Can't serialize query filter: {}", e).into())
+    }
+
+    /// Checks whether the Resource passes this filter.
+    pub fn matches(&self, resource: &Resource) -> bool {
+        match self {
+            QueryExpr::Equals { property, value } => resource
+                .get(property)
+                .map(|v| v.contains_value(&Value::String(value.clone())))
+                .unwrap_or(false),
+            QueryExpr::Exists(property) => resource.get(property).is_ok(),
+            QueryExpr::Range { property, min, max } => {
+                let number = match resource.get(property) {
+                    Ok(Value::Integer(i)) | Ok(Value::Timestamp(i)) => *i as f64,
+                    Ok(Value::Float(f)) => *f,
+                    _ => return false,
+                };
+                QueryExpr::in_range(number, *min, *max)
+            }
+            QueryExpr::IsA(class) => resource
+                .get(urls::IS_A)
+                .map(|v| v.contains_value(&Value::AtomicUrl(class.clone())))
+                .unwrap_or(false),
+            QueryExpr::References(value) => {
+                let value = Value::String(value.clone());
+                resource
+                    .get_propvals()
+                    .values()
+                    .any(|v| v.contains_value(&value))
+            }
+            QueryExpr::And(clauses) => clauses.iter().all(|c| c.matches(resource)),
+            QueryExpr::Or(clauses) => clauses.iter().any(|c| c.matches(resource)),
+        }
+    }
+
+    /// The Properties that this filter compares values of.
+    pub fn properties(&self) -> Vec<&str> {
+        match self {
+            QueryExpr::Equals { property, .. }
+            | QueryExpr::Exists(property)
+            | QueryExpr::Range { property, .. } => vec![property.as_str()],
+            QueryExpr::IsA(_) => vec![urls::IS_A],
+            QueryExpr::References(_) => Vec::new(),
+            QueryExpr::And(clauses) | QueryExpr::Or(clauses) => {
+                clauses.iter().flat_map(|c| c.properties()).collect()
+            }
+        }
+    }
+
+    /// Checks if a number is between the (optional) bounds of a [QueryExpr::Range].
+    pub fn in_range(number: f64, min: Option<f64>, max: Option<f64>) -> bool {
+        min.map(|min| number >= min).unwrap_or(true) && max.map(|max| number <= max).unwrap_or(true)
+    }
+}
+
 pub struct QueryResult {
     pub subjects: Vec<String>,
     pub resources: Vec<Resource>,
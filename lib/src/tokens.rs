@@ -0,0 +1,207 @@
+//! Tokens let scripts and integrations authenticate as an Agent using an `Authorization: Bearer` header, without its private key.
+//! They are issued by the server at [TOKENS_PATH], expire, can be limited to a subtree (`scope`) and to reading (`readOnly`), and are revoked by destroying them.
+//! A request that uses a Token has the subject of the Token as its `for_agent`, so [crate::hierarchy::check_rights] can apply these limits before checking the rights of the Agent.
+
+use crate::{
+    agents::encode_base64, errors::AtomicResult, hierarchy::Right, urls, utils::now, AtomicError,
+    Resource, Storelike, Value,
+};
+
+/// The path where Tokens are issued, and where they are stored.
+pub const TOKENS_PATH: &str = "/tokens";
+
+/// Separates the ID of the Token from its secret in the bearer value.
+const SEPARATOR: char = '.';
+
+/// See [urls::TOKEN]
+#[derive(Clone, Debug)]
+pub struct Token {
+    pub subject: String,
+    /// The Agent that the Token authenticates as
+    pub agent: String,
+    /// Timestamp after which the Token can no longer be used
+    pub expires_at: i64,
+    /// If set, the Token can only be used for this resource and its children
+    pub scope: Option<String>,
+    pub read_only: bool,
+}
+
+impl Token {
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Token> {
+        Ok(Token {
+            subject: resource.get_subject().into(),
+            agent: resource.get(urls::TOKEN_AGENT)?.to_string(),
+            expires_at: resource.get(urls::TOKEN_EXPIRES_AT)?.to_int()?,
+            scope: resource.get(urls::TOKEN_SCOPE).ok().map(|s| s.to_string()),
+            read_only: match resource.get(urls::TOKEN_READ_ONLY) {
+                Ok(val) => val.to_bool()?,
+                Err(_) => false,
+            },
+        })
+    }
+
+    pub fn check_expiry(&self) -> AtomicResult<()> {
+        if self.expires_at < now() {
+            return Err(AtomicError::unauthorized(format!(
+                "Token {} has expired",
+                self.subject
+            )));
+        }
+        Ok(())
+    }
+
+    /// Throws if the right can not be used with this Token, because of its `readOnly` flag or its `scope`.
+    /// Does not check the rights of the Agent itself.
+    pub fn check_access(
+        &self,
+        store: &impl Storelike,
+        resource: &Resource,
+        right: &Right,
+    ) -> AtomicResult<()> {
+        if self.read_only && !matches!(right, Right::Read) {
+            return Err(AtomicError::unauthorized(format!(
+                "Token {} is read-only, and can not be used for the {} right",
+                self.subject, right
+            )));
+        }
+        let scope = match &self.scope {
+            Some(scope) => scope,
+            None => return Ok(()),
+        };
+        // Commits are in scope when their target is
+        let mut current = match resource.get(urls::SUBJECT) {
+            Ok(target) => store.get_resource(&target.to_string())?,
+            Err(_) => resource.clone(),
+        };
+        loop {
+            if current.get_subject() == scope {
+                return Ok(());
+            }
+            match current.get_parent(store) {
+                Ok(parent) => current = parent,
+                Err(_) => {
+                    return Err(AtomicError::unauthorized(format!(
+                        "Token {} can only be used for {} and its children",
+                        self.subject, scope
+                    )))
+                }
+            }
+        }
+    }
+}
+
+/// Creates a Token for the Agent and saves it, without a Commit.
+/// Returns the Token resource and the secret bearer value, which is not stored and can not be retrieved later.
+/// The Token has no parent, only the Agent can read it and revoke it by destroying it.
+pub fn create_token(
+    store: &impl Storelike,
+    agent: &str,
+    expires_at: i64,
+    scope: Option<&str>,
+    read_only: bool,
+) -> AtomicResult<(Resource, String)> {
+    if agent == urls::PUBLIC_AGENT {
+        return Err(AtomicError::unauthorized(
+            "Sign in to create a Token".into(),
+        ));
+    }
+    if is_token(store, agent) {
+        return Err(AtomicError::unauthorized(
+            "Tokens can not be used to create other Tokens".into(),
+        ));
+    }
+    if expires_at <= now() {
+        return Err("The expiry of a Token must be in the future".into());
+    }
+    let id = crate::utils::random_string(16);
+    let secret = crate::utils::random_string(40);
+    let mut token = Resource::new_instance(urls::TOKEN, store)?;
+    token.set_subject(format!("{}{}/{}", store.get_server_url(), TOKENS_PATH, id));
+    token.set_propval(
+        urls::TOKEN_AGENT.into(),
+        Value::AtomicUrl(agent.into()),
+        store,
+    )?;
+    token.set_propval(
+        urls::TOKEN_EXPIRES_AT.into(),
+        Value::Timestamp(expires_at),
+        store,
+    )?;
+    if let Some(scope) = scope {
+        crate::utils::check_valid_url(scope)?;
+        token.set_propval(
+            urls::TOKEN_SCOPE.into(),
+            Value::AtomicUrl(scope.into()),
+            store,
+        )?;
+    }
+    token.set_propval(
+        urls::TOKEN_READ_ONLY.into(),
+        Value::Boolean(read_only),
+        store,
+    )?;
+    token.set_propval_string(urls::TOKEN_HASH.into(), &hash_secret(&secret), store)?;
+    token.set_propval(urls::READ.into(), vec![agent.to_string()].into(), store)?;
+    token.set_propval(urls::WRITE.into(), vec![agent.to_string()].into(), store)?;
+    store.add_resource(&token)?;
+    Ok((token, format!("{}{}{}", id, SEPARATOR, secret)))
+}
+
+/// Whether the subject is the URL of a Token on this server.
+pub fn is_token(store: &impl Storelike, subject: &str) -> bool {
+    subject.starts_with(&format!("{}{}/", store.get_server_url(), TOKENS_PATH))
+}
+
+/// Returns the Token, if it exists and has not expired.
+pub fn get_token(store: &impl Storelike, subject: &str) -> AtomicResult<Token> {
+    let token = Token::from_resource(&get_token_resource(store, subject)?)?;
+    token.check_expiry()?;
+    Ok(token)
+}
+
+/// Finds the Token of an `Authorization: Bearer` value, and checks its secret and expiry.
+/// Returns the subject of the Token, which is used as the `for_agent`.
+pub fn check_bearer(store: &impl Storelike, bearer: &str) -> AtomicResult<String> {
+    let (id, secret) = bearer
+        .split_once(SEPARATOR)
+        .ok_or_else(|| AtomicError::unauthorized("Malformed bearer token".into()))?;
+    let subject = format!("{}{}/{}", store.get_server_url(), TOKENS_PATH, id);
+    let resource = get_token_resource(store, &subject)?;
+    if resource.get(urls::TOKEN_HASH)?.to_string() != hash_secret(secret) {
+        return Err(AtomicError::unauthorized(
+            "Incorrect secret for bearer token".into(),
+        ));
+    }
+    Token::from_resource(&resource)?.check_expiry()?;
+    Ok(subject)
+}
+
+/// Tokens can only be created by the server, so Commits can only destroy them.
+#[cfg(feature = "db")]
+pub fn before_apply_commit(commit: &crate::Commit) -> AtomicResult<()> {
+    if commit.destroy != Some(true) {
+        return Err(AtomicError::unauthorized(
+            "Tokens can not be created or edited using Commits, only revoked by destroying them"
+                .into(),
+        ));
+    }
+    Ok(())
+}
+
+fn get_token_resource(store: &impl Storelike, subject: &str) -> AtomicResult<Resource> {
+    let not_found = || AtomicError::unauthorized(format!("Token {} does not exist", subject));
+    let resource = store.get_resource(subject).map_err(|_| not_found())?;
+    let is_token = resource
+        .get(urls::IS_A)
+        .and_then(|classes| classes.to_subjects(None))
+        .map(|classes| classes.iter().any(|c| c == urls::TOKEN))
+        .unwrap_or(false);
+    if !is_token {
+        return Err(not_found());
+    }
+    Ok(resource)
+}
+
+fn hash_secret(secret: &str) -> String {
+    encode_base64(ring::digest::digest(&ring::digest::SHA256, secret.as_bytes()).as_ref())
+}
@@ -0,0 +1,236 @@
+//! A Transaction bundles multiple [Commit]s, which are applied all-or-nothing.
+//! Useful when creating a parent and its children, or when changing multiple resources that depend on each other.
+
+#[cfg(feature = "db")]
+use crate::commit::{check_signature, check_timestamp, CommitOpts, CommitResponse};
+use crate::{
+    commit::sign_message, errors::AtomicResult, resources::PropVals, urls, values::SubResource,
+    Commit, Resource, Storelike, Value,
+};
+
+/// A signed set of Commits.
+/// When applied to a [crate::Db], either all Commits are persisted, or none of them.
+#[derive(Clone, Debug)]
+pub struct Transaction {
+    /// The URL of the Agent signing this Transaction. Must be the signer of every Commit.
+    pub signer: String,
+    /// The date it was created, as a unix timestamp
+    pub created_at: i64,
+    /// The Commits, in the order in which they are applied.
+    pub commits: Vec<Commit>,
+    /// Base64 encoded signature of the JSON serialized Transaction
+    pub signature: Option<String>,
+}
+
+impl Transaction {
+    /// Bundles signed Commits in a Transaction, and signs it using the Agent.
+    /// The Agent needs a private key, and should be the one that signed the Commits.
+    pub fn sign(
+        commits: Vec<Commit>,
+        agent: &crate::agents::Agent,
+        store: &impl Storelike,
+    ) -> AtomicResult<Transaction> {
+        let mut transaction = Transaction {
+            signer: agent.subject.clone(),
+            created_at: crate::utils::now(),
+            commits,
+            signature: None,
+        };
+        let stringified = transaction
+            .serialize_deterministically_json_ad(store)
+            .map_err(|e| format!("Failed serializing transaction: {}", e))?;
+        let private_key = agent.private_key.clone().ok_or("No private key in agent")?;
+        let signature = sign_message(&stringified, &private_key, &agent.public_key)?;
+        transaction.signature = Some(signature);
+        Ok(transaction)
+    }
+
+    /// Validates the signature of the Transaction and all its Commits, and applies the Commits in order.
+    /// Later Commits can depend on the changes of earlier ones, e.g. a child can be appended to a new parent.
+    /// The resources and all indexes are updated in a single database transaction, so if one Commit fails, none are persisted.
+    /// Stores the Transaction itself, with links to its Commits.
+    #[cfg(feature = "db")]
+    #[tracing::instrument(skip(store))]
+    pub fn apply_opts(
+        &self,
+        store: &crate::Db,
+        opts: &CommitOpts,
+    ) -> AtomicResult<Vec<CommitResponse>> {
+        if self.commits.is_empty() {
+            return Err("Transaction does not contain any Commits".into());
+        }
+        if opts.validate_signature {
+            let signature = self
+                .signature
+                .as_ref()
+                .ok_or("No signature set in Transaction")?;
+            let stringified = self.serialize_deterministically_json_ad(store)?;
+            let key = check_signature(store, &self.signer, &stringified, signature, self.created_at).map_err(|e| {
+                format!(
+                    "Incorrect signature for Transaction: {}. Compare this to the serialized transaction in the client: {}",
+                    e, stringified
+                )
+            })?;
+            if opts.validate_timestamp && !key.is_valid_at(crate::utils::now()) {
+                return Err(format!(
+                    "The key {} that signed this Transaction is no longer valid for {}",
+                    key.public_key, self.signer
+                )
+                .into());
+            }
+        }
+        if opts.validate_timestamp {
+            check_timestamp(self.created_at)?;
+        }
+        for commit in &self.commits {
+            if commit.signer != self.signer {
+                return Err(format!(
+                    "Commit for {} is signed by {}, but the Transaction is signed by {}. All Commits in a Transaction must have the same signer.",
+                    commit.subject, commit.signer, self.signer
+                )
+                .into());
+            }
+        }
+
+        let responses = store.all_or_nothing(|staged| {
+            let mut responses = Vec::new();
+            for commit in &self.commits {
+                let response = commit
+                    .apply_opts(staged, opts)
+                    .map_err(|e| format!("Transaction failed, nothing was changed. {}", e))?;
+                responses.push(response);
+            }
+            let mut resource = self.into_resource(staged)?;
+            let commit_subjects: Vec<String> = responses
+                .iter()
+                .map(|r| r.commit_resource.get_subject().to_string())
+                .collect();
+            resource.set_propval_unsafe(urls::TRANSACTION_COMMITS.into(), commit_subjects.into());
+            staged.add_resource_opts(&resource, false, opts.update_index, false)?;
+            Ok(responses)
+        })?;
+
+        for response in &responses {
+            store.handle_commit(response);
+        }
+        Ok(responses)
+    }
+
+    /// Converts a Resource of a Transaction into a Transaction.
+    /// The Commits must be nested resources.
+    pub fn from_resource(resource: Resource) -> AtomicResult<Transaction> {
+        let signer = resource.get(urls::SIGNER)?.to_string();
+        let created_at = resource.get(urls::CREATED_AT)?.to_int()?;
+        let signature = resource.get(urls::SIGNATURE).ok().map(|v| v.to_string());
+        let mut commits = Vec::new();
+        match resource.get(urls::TRANSACTION_COMMITS)? {
+            Value::ResourceArray(items) => {
+                for item in items {
+                    let commit_resource = match item {
+                        SubResource::Resource(r) => *r.clone(),
+                        SubResource::Nested(pv) => Resource::from_propvals(pv.clone(), "".into()),
+                        SubResource::Subject(s) => {
+                            return Err(format!(
+                                "Commits in a Transaction must be nested, got {}",
+                                s
+                            )
+                            .into())
+                        }
+                    };
+                    let mut commit = Commit::from_resource(commit_resource)?;
+                    commit.url = None;
+                    commits.push(commit);
+                }
+            }
+            other => {
+                return Err(format!(
+                    "Commits in a Transaction must be a ResourceArray, got {}",
+                    other
+                )
+                .into())
+            }
+        }
+        Ok(Transaction {
+            signer,
+            created_at,
+            commits,
+            signature,
+        })
+    }
+
+    /// Converts the Transaction into a Resource with Atomic Values.
+    /// The Commits are included as nested resources, without an `@id`.
+    /// Creates an identifier using the server_url.
+    pub fn into_resource(&self, store: &impl Storelike) -> AtomicResult<Resource> {
+        let subject = match self.signature.as_ref() {
+            Some(sig) => format!("{}/transactions/{}", store.get_server_url(), sig),
+            None => format!(
+                "{}/transactionsUnsigned/{}",
+                store.get_server_url(),
+                self.created_at
+            ),
+        };
+        let mut resource = Resource::new(subject);
+        resource.set_propval_unsafe(
+            urls::IS_A.into(),
+            vec![urls::TRANSACTION.to_string()].into(),
+        );
+        resource.set_propval_unsafe(urls::SIGNER.into(), Value::AtomicUrl(self.signer.clone()));
+        resource.set_propval_unsafe(urls::CREATED_AT.into(), Value::Timestamp(self.created_at));
+        let mut commits: Vec<SubResource> = Vec::new();
+        for commit in &self.commits {
+            let propvals: PropVals = commit.into_resource(store)?.into_propvals();
+            commits.push(SubResource::Nested(propvals));
+        }
+        resource.set_propval_unsafe(urls::TRANSACTION_COMMITS.into(), commits.into());
+        if let Some(signature) = &self.signature {
+            resource.set_propval_unsafe(urls::SIGNATURE.into(), signature.clone().into());
+        }
+        Ok(resource)
+    }
+
+    /// Generates a deterministic serialized JSON-AD representation of the Transaction, without its signature.
+    /// This is the message that is signed.
+    pub fn serialize_deterministically_json_ad(
+        &self,
+        store: &impl Storelike,
+    ) -> AtomicResult<String> {
+        let mut resource = self.into_resource(store)?;
+        resource.remove_propval(urls::SIGNATURE);
+        let json_obj = crate::serialize::propvals_to_json_ad_map(resource.get_propvals(), None)?;
+        serde_json::to_string(&json_obj).map_err(|_| "Could not serialize to JSON-AD".into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn transaction_resource_roundtrip() {
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let agent = store.create_agent(None).unwrap();
+        let mut commits = Vec::new();
+        for subject in ["https://localhost/first", "https://localhost/second"] {
+            let mut builder = crate::commit::CommitBuilder::new(subject.into());
+            builder.set(urls::NAME.into(), Value::String(subject.into()));
+            commits.push(
+                builder
+                    .sign(&agent, &store, &Resource::new(subject.into()))
+                    .unwrap(),
+            );
+        }
+        let transaction = Transaction::sign(commits, &agent, &store).unwrap();
+        let resource = transaction.into_resource(&store).unwrap();
+        let parsed = Transaction::from_resource(resource).unwrap();
+        assert_eq!(parsed.commits.len(), 2);
+        assert_eq!(parsed.commits[1].subject, "https://localhost/second");
+        assert_eq!(
+            parsed.serialize_deterministically_json_ad(&store).unwrap(),
+            transaction
+                .serialize_deterministically_json_ad(&store)
+                .unwrap()
+        );
+    }
+}
@@ -5,7 +5,10 @@ pub const CLASS: &str = "https://atomicdata.dev/classes/Class";
 pub const PROPERTY: &str = "https://atomicdata.dev/classes/Property";
 pub const DATATYPE_CLASS: &str = "https://atomicdata.dev/classes/Datatype";
 pub const COMMIT: &str = "https://atomicdata.dev/classes/Commit";
+pub const TRANSACTION: &str = "https://atomicdata.dev/classes/Transaction";
 pub const AGENT: &str = "https://atomicdata.dev/classes/Agent";
+pub const AGENT_KEY: &str = "https://atomicdata.dev/classes/AgentKey";
+pub const GROUP: &str = "https://atomicdata.dev/classes/Group";
 pub const COLLECTION: &str = "https://atomicdata.dev/classes/Collection";
 pub const ENDPOINT: &str = "https://atomicdata.dev/classes/Endpoint";
 pub const DRIVE: &str = "https://atomicdata.dev/classes/Drive";
@@ -13,6 +16,12 @@ pub const INVITE: &str = "https://atomicdata.dev/classes/Invite";
 pub const REDIRECT: &str = "https://atomicdata.dev/classes/Redirect";
 pub const ATOM: &str = "https://atomicdata.dev/classes/Atom";
 pub const FILE: &str = "https://atomicdata.dev/classes/File";
+pub const UPLOAD_SESSION: &str = "https://atomicdata.dev/classes/UploadSession";
+pub const TOKEN: &str = "https://atomicdata.dev/classes/Token";
+pub const AUDIT_EVENT: &str = "https://atomicdata.dev/classes/AuditEvent";
+pub const FACET_COUNT: &str = "https://atomicdata.dev/classes/FacetCount";
+pub const SEARCH_HIT: &str = "https://atomicdata.dev/classes/SearchHit";
+pub const SEARCH_SNIPPET_CLASS: &str = "https://atomicdata.dev/classes/SearchSnippet";
 pub const CHATROOM: &str = "https://atomicdata.dev/classes/ChatRoom";
 pub const PARAGRAPH: &str = "https://atomicdata.dev/classes/elements/Paragraph";
 pub const MESSAGE: &str = "https://atomicdata.dev/classes/Message";
@@ -23,6 +32,7 @@ pub const BOOKMARK: &str = "https://atomicdata.dev/class/Bookmark";
 // Properties
 pub const SHORTNAME: &str = "https://atomicdata.dev/properties/shortname";
 pub const DESCRIPTION: &str = "https://atomicdata.dev/properties/description";
+pub const LANGUAGE: &str = "https://atomicdata.dev/properties/language";
 pub const INCOMPLETE: &str = "https://atomicdata.dev/properties/incomplete";
 // ... for Properties
 pub const IS_A: &str = "https://atomicdata.dev/properties/isA";
@@ -45,10 +55,20 @@ pub const CREATED_AT: &str = "https://atomicdata.dev/properties/createdAt";
 pub const SIGNATURE: &str = "https://atomicdata.dev/properties/signature";
 pub const PREVIOUS_COMMIT: &str = "https://atomicdata.dev/properties/previousCommit";
 pub const LAST_COMMIT: &str = "https://atomicdata.dev/properties/lastCommit";
+// ... for Transactions
+pub const TRANSACTION_COMMITS: &str = "https://atomicdata.dev/properties/transaction/commits";
+// ... for Errors
+pub const CONFLICTING_PROPERTIES: &str =
+    "https://atomicdata.dev/properties/error/conflictingProperties";
 // ... for Agents
 pub const PUBLIC_KEY: &str = "https://atomicdata.dev/properties/publicKey";
 pub const NAME: &str = "https://atomicdata.dev/properties/name";
 pub const DRIVES: &str = "https://atomicdata.dev/properties/drives";
+pub const AGENT_KEYS: &str = "https://atomicdata.dev/properties/agentKeys";
+pub const VALID_FROM: &str = "https://atomicdata.dev/properties/validFrom";
+pub const VALID_UNTIL: &str = "https://atomicdata.dev/properties/validUntil";
+// ... for Groups
+pub const MEMBERS: &str = "https://atomicdata.dev/properties/members";
 // ... for Collections
 pub const COLLECTION_PROPERTY: &str = "https://atomicdata.dev/properties/collection/property";
 pub const COLLECTION_VALUE: &str = "https://atomicdata.dev/properties/collection/value";
@@ -65,13 +85,35 @@ pub const COLLECTION_INCLUDE_EXTERNAL: &str =
 pub const COLLECTION_PAGE_SIZE: &str = "https://atomicdata.dev/properties/collection/pageSize";
 pub const COLLECTION_SORT_BY: &str = "https://atomicdata.dev/properties/collection/sortBy";
 pub const COLLECTION_SORT_DESC: &str = "https://atomicdata.dev/properties/collection/sortDesc";
+pub const COLLECTION_FILTER: &str = "https://atomicdata.dev/properties/collection/filter";
 // ... for Endpoints
 pub const ENDPOINT_PARAMETERS: &str = "https://atomicdata.dev/properties/endpoint/parameters";
 pub const ENDPOINT_RESULTS: &str = "https://atomicdata.dev/properties/endpoint/results";
 pub const PATH: &str = "https://atomicdata.dev/properties/path";
+pub const CHANGES_SINCE: &str = "https://atomicdata.dev/properties/changes/since";
+pub const CHANGES_NEXT: &str = "https://atomicdata.dev/properties/changes/next";
+pub const REVERT_COMMIT: &str = "https://atomicdata.dev/properties/revert/commit";
 pub const SEARCH_QUERY: &str = "https://atomicdata.dev/properties/search/query";
 pub const SEARCH_LIMIT: &str = "https://atomicdata.dev/properties/search/limit";
 pub const SEARCH_PROPERTY: &str = "https://atomicdata.dev/properties/search/property";
+pub const SEARCH_SORT_BY: &str = "https://atomicdata.dev/properties/search/sortBy";
+pub const SEARCH_SORT_DESC: &str = "https://atomicdata.dev/properties/search/sortDesc";
+pub const SEARCH_RANGE: &str = "https://atomicdata.dev/properties/search/range";
+pub const SEARCH_CLASS: &str = "https://atomicdata.dev/properties/search/class";
+pub const SEARCH_FACETS: &str = "https://atomicdata.dev/properties/search/facets";
+pub const SEARCH_CONSISTENT: &str = "https://atomicdata.dev/properties/search/consistent";
+pub const SEARCH_LANG: &str = "https://atomicdata.dev/properties/search/lang";
+pub const SEARCH_MODE: &str = "https://atomicdata.dev/properties/search/mode";
+pub const SEARCH_FACET_COUNTS: &str = "https://atomicdata.dev/properties/search/facetCounts";
+pub const SEARCH_FACET_COUNTS_APPROXIMATE: &str =
+    "https://atomicdata.dev/properties/search/facetCountsApproximate";
+pub const SEARCH_FACET_VALUE: &str = "https://atomicdata.dev/properties/search/facetValue";
+pub const SEARCH_COUNT: &str = "https://atomicdata.dev/properties/search/count";
+pub const SEARCH_HITS: &str = "https://atomicdata.dev/properties/search/hits";
+pub const SEARCH_RESULT: &str = "https://atomicdata.dev/properties/search/result";
+pub const SEARCH_SCORE: &str = "https://atomicdata.dev/properties/search/score";
+pub const SEARCH_SNIPPETS: &str = "https://atomicdata.dev/properties/search/snippets";
+pub const SEARCH_SNIPPET: &str = "https://atomicdata.dev/properties/search/snippet";
 pub const URL: &str = "https://atomicdata.dev/property/url";
 pub const PREVIEW: &str = "https://atomicdata.dev/property/preview";
 // ... for Bookmarks
@@ -81,6 +123,10 @@ pub const PARENT: &str = "https://atomicdata.dev/properties/parent";
 pub const READ: &str = "https://atomicdata.dev/properties/read";
 pub const WRITE: &str = "https://atomicdata.dev/properties/write";
 pub const APPEND: &str = "https://atomicdata.dev/properties/append";
+pub const DENY_READ: &str = "https://atomicdata.dev/properties/denyRead";
+pub const DENY_WRITE: &str = "https://atomicdata.dev/properties/denyWrite";
+pub const DENY_APPEND: &str = "https://atomicdata.dev/properties/denyAppend";
+pub const PROPERTY_READ: &str = "https://atomicdata.dev/properties/propertyRead";
 pub const CHILDREN: &str = "https://atomicdata.dev/properties/children";
 pub const SUBRESOURCES: &str = "https://atomicdata.dev/properties/subresources";
 // ... for Inivtations
@@ -105,6 +151,32 @@ pub const MIMETYPE: &str = "https://atomicdata.dev/properties/mimetype";
 pub const INTERNAL_ID: &str = "https://atomicdata.dev/properties/internalId";
 pub const DOWNLOAD_URL: &str = "https://atomicdata.dev/properties/downloadURL";
 pub const ATTACHMENTS: &str = "https://atomicdata.dev/properties/attachments";
+pub const IMAGE_WIDTH: &str = "https://atomicdata.dev/properties/imageWidth";
+pub const IMAGE_HEIGHT: &str = "https://atomicdata.dev/properties/imageHeight";
+pub const DATE_TAKEN: &str = "https://atomicdata.dev/properties/dateTaken";
+
+// ... for UploadSessions
+pub const UPLOAD_OFFSET: &str = "https://atomicdata.dev/properties/uploadSession/offset";
+pub const UPLOAD_CHUNKS: &str = "https://atomicdata.dev/properties/uploadSession/chunks";
+pub const UPLOAD_EXPIRES_AT: &str = "https://atomicdata.dev/properties/uploadSession/expiresAt";
+pub const UPLOAD_FILE: &str = "https://atomicdata.dev/properties/uploadSession/file";
+// ... for Tokens
+pub const TOKEN_AGENT: &str = "https://atomicdata.dev/properties/token/agent";
+pub const TOKEN_EXPIRES_AT: &str = "https://atomicdata.dev/properties/token/expiresAt";
+pub const TOKEN_SCOPE: &str = "https://atomicdata.dev/properties/token/scope";
+pub const TOKEN_READ_ONLY: &str = "https://atomicdata.dev/properties/token/readOnly";
+pub const TOKEN_HASH: &str = "https://atomicdata.dev/properties/token/hash";
+pub const TOKEN_SECRET: &str = "https://atomicdata.dev/properties/token/secret";
+// ... for the audit log
+pub const AUDIT_AGENT: &str = "https://atomicdata.dev/properties/audit/agent";
+pub const AUDIT_SUBJECT: &str = "https://atomicdata.dev/properties/audit/subject";
+pub const AUDIT_RIGHT: &str = "https://atomicdata.dev/properties/audit/right";
+pub const AUDIT_ALLOWED: &str = "https://atomicdata.dev/properties/audit/allowed";
+pub const AUDIT_EXPLANATION: &str = "https://atomicdata.dev/properties/audit/explanation";
+pub const AUDIT_SINCE: &str = "https://atomicdata.dev/properties/audit/since";
+pub const AUDIT_FROM: &str = "https://atomicdata.dev/properties/audit/from";
+pub const AUDIT_UNTIL: &str = "https://atomicdata.dev/properties/audit/until";
+pub const AUDIT_NEXT: &str = "https://atomicdata.dev/properties/audit/next";
 // ... for ChatRooms and Messages
 pub const MESSAGES: &str = "https://atomicdata.dev/properties/messages";
 pub const NEXT_PAGE: &str = "https://atomicdata.dev/properties/nextPage";
@@ -114,6 +186,7 @@ pub const IMPORTER_JSON: &str = "https://atomicdata.dev/properties/importer/json
 pub const IMPORTER_PARENT: &str = "https://atomicdata.dev/properties/importer/parent";
 pub const IMPORTER_OVERWRITE_OUTSIDE: &str =
     "https://atomicdata.dev/properties/importer/overwrite-outside";
+pub const IMPORTER_FORMAT: &str = "https://atomicdata.dev/properties/importer/format";
 pub const LOCAL_ID: &str = "https://atomicdata.dev/properties/localId";
 
 // Datatypes
@@ -3,6 +3,14 @@
 use crate::errors::AtomicResult;
 use url::Url;
 
+lazy_static::lazy_static! {
+    /// The dates and UTC date-times that [parse_timestamp] accepts.
+    static ref POINT_IN_TIME: regex::Regex = regex::Regex::new(
+        r"^(\d{4})-(\d{2})-(\d{2})(?:T(\d{2}):(\d{2})(?::(\d{2})(?:\.(\d{1,3}))?)?Z)?$",
+    )
+    .unwrap();
+}
+
 /// Removes the path and query from a String, returns the base server URL
 pub fn server_url(url: &str) -> AtomicResult<String> {
     let mut parsed: Url = Url::parse(url)?;
@@ -45,3 +53,81 @@ pub fn random_string(n: usize) -> String {
         .collect();
     random_string.to_lowercase()
 }
+
+/// Parses a point in time into a timestamp in milliseconds since UNIX epoch.
+/// Accepts a timestamp (`1677628800000`), a date (`2023-03-01`) or a UTC date-time (`2023-03-01T12:30:00Z`).
+/// Dates without a time refer to the start of that day, in UTC.
+pub fn parse_timestamp(input: &str) -> AtomicResult<i64> {
+    if let Ok(timestamp) = input.parse::<i64>() {
+        return Ok(timestamp);
+    }
+    let captures = POINT_IN_TIME.captures(input).ok_or(format!(
+        "Invalid point in time: '{}'. Use a timestamp in milliseconds, a date like 2023-03-01 or a UTC date-time like 2023-03-01T12:30:00Z",
+        input
+    ))?;
+    let part = |i: usize| -> i64 {
+        captures
+            .get(i)
+            .map(|m| m.as_str().parse::<i64>().unwrap_or(0))
+            .unwrap_or(0)
+    };
+    let (year, month, day) = (part(1), part(2), part(3));
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+        return Err(format!("Invalid date: '{}'", input).into());
+    }
+    if part(4) > 23 || part(5) > 59 || part(6) > 59 {
+        return Err(format!("Invalid time: '{}'", input).into());
+    }
+    let millis = captures
+        .get(7)
+        .map(|m| format!("{:0<3}", m.as_str()).parse::<i64>().unwrap_or(0))
+        .unwrap_or(0);
+    let seconds =
+        days_from_civil(year, month, day) * 86_400 + part(4) * 3600 + part(5) * 60 + part(6);
+    Ok(seconds * 1000 + millis)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Amount of days since 1970-01-01 for a date in the proleptic Gregorian calendar.
+/// See http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_timestamps() {
+        assert_eq!(parse_timestamp("1677628800000").unwrap(), 1677628800000);
+        assert_eq!(parse_timestamp("1970-01-01").unwrap(), 0);
+        assert_eq!(parse_timestamp("2023-03-01").unwrap(), 1677628800000);
+        assert_eq!(
+            parse_timestamp("2023-03-01T12:30:05.5Z").unwrap(),
+            1677628800000 + 45_005_500
+        );
+        parse_timestamp("yesterday").unwrap_err();
+        parse_timestamp("2023-13-01").unwrap_err();
+        parse_timestamp("2023-02-31").unwrap_err();
+        parse_timestamp("2023-02-29").unwrap_err();
+        parse_timestamp("2023-03-01T24:00:00Z").unwrap_err();
+        assert_eq!(
+            parse_timestamp("2024-02-29").unwrap(),
+            parse_timestamp("2024-03-01").unwrap() - 86_400_000
+        );
+    }
+}
@@ -24,3 +24,9 @@ pub struct CommitMessage {
     /// Full resource of the Commit itself, the new resource, and the old one
     pub commit_response: atomic_lib::commit::CommitResponse,
 }
+
+/// Makes the CommitMonitor index all pending changes right away, instead of at its next tick.
+/// Responds after the search index is committed.
+#[derive(Message)]
+#[rtype(result = "crate::errors::AtomicServerResult<()>")]
+pub struct FlushSearchIndex;
@@ -1,6 +1,7 @@
 //! App state, which is accessible from handlers
 use crate::{
-    commit_monitor::CommitMonitor, config::Config, errors::AtomicServerResult, search::SearchState,
+    blob_store::BlobStore, commit_monitor::CommitMonitor, config::Config,
+    errors::AtomicServerResult, rate_limit::RateLimiter, search::SearchState,
 };
 use atomic_lib::{
     agents::{generate_public_key, Agent},
@@ -23,6 +24,10 @@ pub struct AppState {
     /// The Actix Address of the CommitMonitor, which should receive updates when a commit is applied
     pub commit_monitor: actix::Addr<CommitMonitor>,
     pub search_state: SearchState,
+    /// Stores the uploaded files
+    pub blob_store: std::sync::Arc<dyn BlobStore>,
+    /// Counts the requests of every Agent and IP address, see [crate::rate_limit].
+    pub rate_limiter: std::sync::Arc<RateLimiter>,
 }
 
 /// Creates the AppState (the server's context available in Handlers).
@@ -40,8 +45,8 @@ pub fn init(config: Config) -> AtomicServerResult<AppState> {
         }
     }
 
-    tracing::info!("Opening database at {:?}", &config.store_path);
-    let mut store = atomic_lib::Db::init(&config.store_path, config.server_url.clone())?;
+    tracing::info!("Opening database {:?}", &config.db_backend);
+    let mut store = atomic_lib::Db::init(&config.db_backend, config.server_url.clone())?;
     if config.initialize {
         tracing::info!("Initialize: creating and populating new Database");
         atomic_lib::populate::populate_default_store(&store)
@@ -52,13 +57,29 @@ pub fn init(config: Config) -> AtomicServerResult<AppState> {
         tracing::info!("Building index finished!");
     }
 
+    if config.opts.audit {
+        store.set_audit(Some(atomic_lib::audit::AuditConfig {
+            retention: config
+                .opts
+                .audit_retention_days
+                .map(|days| days as i64 * 24 * 60 * 60 * 1000),
+        }));
+    }
+
+    if config.opts.drive_max_resources.is_some() || config.opts.drive_max_upload_bytes.is_some() {
+        store.set_drive_quota(Some(atomic_lib::quotas::DriveQuota {
+            max_resources: config.opts.drive_max_resources,
+            max_upload_bytes: config.opts.drive_max_upload_bytes,
+        }))?;
+    }
+
     tracing::info!("Setting default agent");
     set_default_agent(&config, &store)?;
 
     // Initialize search constructs
     tracing::info!("Starting search service");
-    let search_state =
-        SearchState::new(&config).map_err(|e| format!("Failed to start search service: {}", e))?;
+    let search_state = SearchState::new(&config, &store)
+        .map_err(|e| format!("Failed to start search service: {}", e))?;
 
     // Initialize commit monitor, which watches commits and sends these to the commit_monitor actor
     tracing::info!("Starting commit monitor");
@@ -89,11 +110,16 @@ pub fn init(config: Config) -> AtomicServerResult<AppState> {
         tracing::info!("Setting rights to Drive {}", store.get_server_url());
     }
 
+    let blob_store = crate::blob_store::init(&config.blob_store);
+    let rate_limiter = std::sync::Arc::new(RateLimiter::new(&config.opts));
+
     Ok(AppState {
         store,
         config,
         commit_monitor,
         search_state,
+        blob_store,
+        rate_limiter,
     })
 }
 
@@ -1,9 +1,13 @@
-use atomic_lib::{urls, Storelike};
+use atomic_lib::{rdf::RdfFormat, urls, Storelike};
 use atomic_server_lib::config::Opts;
-use std::{fs::File, io::Write};
+use std::{
+    fs::File,
+    io::{BufRead, Read, Write},
+};
 
 mod actor_messages;
 mod appstate;
+mod blob_store;
 mod commit_monitor;
 pub mod config;
 mod content_types;
@@ -12,16 +16,20 @@ mod handlers;
 mod helpers;
 #[cfg(feature = "https")]
 mod https;
+mod images;
 mod jsonerrors;
 #[cfg(feature = "process-management")]
 mod process;
+mod rate_limit;
 mod routes;
 pub mod serve;
 // #[cfg(feature = "search")]
 mod search;
+mod similarity;
 #[cfg(test)]
 mod tests;
 mod trace;
+mod uploads;
 
 #[actix_web::main]
 async fn main() -> () {
@@ -40,32 +48,67 @@ async fn main_wrapped() -> errors::AtomicServerResult<()> {
 
     match &config.opts.command {
         Some(config::Command::Export(e)) => {
+            let since = match &e.since {
+                Some(since) => Some(atomic_lib::utils::parse_timestamp(since)?),
+                None => None,
+            };
             let path = match e.path.clone() {
                 Some(p) => std::path::Path::new(&p).to_path_buf(),
                 None => {
                     let date = chrono::Local::now().to_rfc3339();
-                    let pathstr = format!("backups/{}.json", date);
+                    let name = if e.audit { "audit-" } else { "" };
+                    let extension = match (e.audit, e.gzip) {
+                        (true, true) => "jsonl.gz",
+                        (true, false) => "jsonl",
+                        (false, true) => "ndjson.gz",
+                        (false, false) => "ndjson",
+                    };
+                    let pathstr = format!("backups/{}{}.{}", name, date, extension);
                     let mut pt = config.config_dir.clone();
                     pt.push(&pathstr);
                     pt
                 }
             };
             let appstate = appstate::init(config.clone())?;
-            let outstr = appstate.store.export(!e.only_internal)?;
             std::fs::create_dir_all(path.parent().unwrap())
                 .map_err(|e| format!("Failed to create directory {:?}. {}", path, e))?;
-            let mut file = File::create(&path)
+            let file = File::create(&path)
                 .map_err(|e| format!("Failed to write file to {:?}. {}", path, e))?;
-            write!(file, "{}", outstr)?;
-            println!("Succesfully exported data to {}", path.to_str().unwrap());
+            let file = std::io::BufWriter::new(file);
+            let export = |writer: &mut dyn Write| {
+                if e.audit {
+                    let filter = atomic_lib::audit::AuditFilter {
+                        from: since,
+                        ..Default::default()
+                    };
+                    appstate.store.export_audit_jsonl(writer, &filter)
+                } else {
+                    appstate
+                        .store
+                        .export_ndjson(writer, !e.only_internal, since)
+                }
+            };
+            let count = if e.gzip {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(file, flate2::Compression::default());
+                let count = export(&mut encoder)?;
+                encoder.finish()?.flush()?;
+                count
+            } else {
+                let mut file = file;
+                let count = export(&mut file)?;
+                file.flush()?;
+                count
+            };
+            println!(
+                "Succesfully exported {} {} to {}",
+                count,
+                if e.audit { "audit events" } else { "resources" },
+                path.to_str().unwrap()
+            );
             Ok(())
         }
         Some(config::Command::Import(import_opts)) => {
-            let readstring = {
-                let path = std::path::Path::new(&import_opts.file);
-                std::fs::read_to_string(path)?
-            };
-
             let appstate = appstate::init(config.clone())?;
             let importer_subject = if let Some(i) = &import_opts.parent {
                 i.into()
@@ -84,9 +127,50 @@ async fn main_wrapped() -> errors::AtomicServerResult<()> {
                 signer: Some(appstate.store.get_default_agent()?),
             };
             println!("Importing...");
-            appstate.store.import(&readstring, &parse_opts)?;
+            let rdf_format = match import_opts.format {
+                Some(config::ImportFormat::JsonAd) => None,
+                Some(config::ImportFormat::Turtle) => Some(RdfFormat::Turtle),
+                Some(config::ImportFormat::Ntriples) => Some(RdfFormat::NTriples),
+                Some(config::ImportFormat::Jsonld) => Some(RdfFormat::JsonLd),
+                None => import_opts
+                    .file
+                    .extension()
+                    .and_then(|ext| RdfFormat::from_extension(&ext.to_string_lossy())),
+            };
+            let mut reader = open_import_file(&import_opts.file)?;
+            let is_json_ad = is_json_ad(&mut reader)?;
+            let count = if let Some(rdf_format) = rdf_format {
+                let mut readstring = String::new();
+                reader.read_to_string(&mut readstring)?;
+                atomic_lib::rdf::import_rdf(&appstate.store, &readstring, rdf_format, &parse_opts)?
+            } else if is_json_ad {
+                let mut readstring = String::new();
+                reader.read_to_string(&mut readstring)?;
+                appstate.store.import(&readstring, &parse_opts)?
+            } else {
+                appstate.store.import_ndjson(&mut reader, &parse_opts)?
+            };
 
-            println!("Sucesfully imported {:?} to store.", import_opts.file);
+            println!(
+                "Sucesfully imported {} resources from {:?} to store.",
+                count, import_opts.file
+            );
+            Ok(())
+        }
+        Some(config::Command::MigrateUploads(migrate_opts)) => {
+            let appstate = appstate::init(config.clone())?;
+            let from = blob_store::FileSystemBlobStore::new(
+                migrate_opts
+                    .from
+                    .clone()
+                    .unwrap_or_else(|| config.uploads_path.clone()),
+            );
+            println!("Migrating uploads...");
+            let count = migrate_uploads(&appstate, &from)?;
+            println!(
+                "Succesfully copied {} files to the {:?} blob store.",
+                count, config.opts.blob_backend
+            );
             Ok(())
         }
         Some(config::Command::ShowConfig) => {
@@ -161,3 +245,79 @@ async fn main_wrapped() -> errors::AtomicServerResult<()> {
         None => serve::serve(config).await,
     }
 }
+
+/// Opens a file for importing. Decompresses it if it is gzipped.
+fn open_import_file(path: &std::path::Path) -> errors::AtomicServerResult<Box<dyn BufRead>> {
+    let mut reader = std::io::BufReader::new(
+        File::open(path).map_err(|e| format!("Failed to open {:?}. {}", path, e))?,
+    );
+    // All gzip files start with these two bytes
+    if reader.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        Ok(Box::new(std::io::BufReader::new(
+            flate2::read::GzDecoder::new(reader),
+        )))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// JSON-AD files contain a single array or object, NDJSON-AD files one object per line.
+/// An object is JSON-AD if its first line is not a complete JSON value, e.g. when it is pretty-printed.
+/// Looks at the start of the file, without consuming it.
+fn is_json_ad(reader: &mut impl BufRead) -> std::io::Result<bool> {
+    let buf = reader.fill_buf()?;
+    let start = buf.iter().position(|b| !b.is_ascii_whitespace());
+    match start.map(|i| &buf[i..]) {
+        Some([b'[', ..]) => Ok(true),
+        Some(object @ [b'{', ..]) => match object.iter().position(|b| *b == b'\n') {
+            Some(end) => Ok(serde_json::from_slice::<serde_json::Value>(&object[..end]).is_err()),
+            // Single line files can be parsed as NDJSON-AD
+            None => Ok(false),
+        },
+        _ => Ok(false),
+    }
+}
+
+/// Copies the files of all File resources from `from` to the blob store of the server. Skips files that are already present.
+fn migrate_uploads(
+    appstate: &appstate::AppState,
+    from: &dyn blob_store::BlobStore,
+) -> errors::AtomicServerResult<usize> {
+    let mut count = 0;
+    for id in uploads::referenced_blobs(&appstate.store)? {
+        if !from.exists(&id)? || appstate.blob_store.exists(&id)? {
+            continue;
+        }
+        // The blob store moves the files it stores, so the original is copied first
+        let temp_path = appstate
+            .config
+            .uploads_path
+            .join(format!(".migrate-{}", id));
+        match from.get(&id)? {
+            blob_store::Blob::File(path) => std::fs::copy(path, &temp_path).map(|_| ())?,
+            blob_store::Blob::Stream(mut stream) => {
+                std::io::copy(&mut stream.reader, &mut std::fs::File::create(&temp_path)?)
+                    .map(|_| ())?
+            }
+        }
+        appstate.blob_store.put(&id, &temp_path)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detects_json_ad() {
+        let detect = |input: &str| is_json_ad(&mut input.as_bytes()).unwrap();
+        assert!(detect("  [{\"@id\": \"https://example.com/a\"}]"));
+        assert!(detect("{\n  \"@id\": \"https://example.com/a\"\n}\n"));
+        assert!(!detect(
+            "{\"@id\": \"https://example.com/a\"}\n{\"@id\": \"https://example.com/b\"}\n"
+        ));
+        assert!(!detect("{\"@id\": \"https://example.com/a\"}"));
+    }
+}
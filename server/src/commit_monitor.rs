@@ -1,9 +1,10 @@
 //! The Commit Monitor checks for new commits and notifies listeners.
 //! It is used for WebSockets to notify front-end clients of changes in Resources,
 //! and to update the Search index.
+//! The search index is updated using the commit log of the Db, see [crate::search::index_pending].
 
 use crate::{
-    actor_messages::{CommitMessage, Subscribe},
+    actor_messages::{CommitMessage, FlushSearchIndex, Subscribe},
     errors::AtomicServerResult,
     handlers::web_sockets::WebSocketConnection,
     search::SearchState,
@@ -96,8 +97,7 @@ impl Handler<Subscribe> for CommitMonitor {
 
 impl CommitMonitor {
     /// When a commit comes in, send it to any listening subscribers,
-    /// and update the value index.
-    /// The search index is only updated if the last search commit is 15 seconds or older.
+    /// and make sure the search index is updated at the next tick.
     fn handle_internal(&mut self, msg: CommitMessage) -> AtomicServerResult<()> {
         let target = msg.commit_response.commit_struct.subject.clone();
 
@@ -115,18 +115,8 @@ impl CommitMonitor {
             tracing::debug!("No subscribers for {}", target);
         }
 
-        // Update the search index
-        if let Some(resource) = &msg.commit_response.resource_new {
-            // We could one day re-(allow) to keep old resources,
-            // but then we also should index the older versions when re-indexing.
-            crate::search::remove_resource(&self.search_state, &target)?;
-            // Add new resource to search index
-            crate::search::add_resource(&self.search_state, resource, &self.store)?;
-            self.run_expensive_next_tick = true;
-        } else {
-            // If there is no new resource, it must have been deleted, so let's remove it from the search index.
-            crate::search::remove_resource(&self.search_state, &target)?;
-        }
+        // The target is in the commit log, so it is indexed at the next tick
+        self.run_expensive_next_tick = true;
         Ok(())
     }
 
@@ -145,7 +135,7 @@ impl CommitMonitor {
     /// Run expensive updates that should not be run after every single Commit
     fn update_expensive(&mut self) -> AtomicServerResult<()> {
         tracing::debug!("Update expensive");
-        self.search_state.writer.write()?.commit()?;
+        crate::search::index_pending(&self.search_state, &self.store)?;
         self.last_search_commit = chrono::Local::now();
         self.run_expensive_next_tick = false;
         Ok(())
@@ -170,6 +160,14 @@ impl Handler<CommitMessage> for CommitMonitor {
     }
 }
 
+impl Handler<FlushSearchIndex> for CommitMonitor {
+    type Result = AtomicServerResult<()>;
+
+    fn handle(&mut self, _msg: FlushSearchIndex, _: &mut Context<Self>) -> Self::Result {
+        self.update_expensive()
+    }
+}
+
 /// Spawns a commit monitor actor
 pub fn create_commit_monitor(store: Db, search_state: SearchState) -> Addr<CommitMonitor> {
     crate::commit_monitor::CommitMonitor::create(|_ctx: &mut Context<CommitMonitor>| {
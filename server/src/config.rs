@@ -75,6 +75,100 @@ pub struct Opts {
     #[clap(long, env = "ATOMIC_DATA_DIR")]
     pub data_dir: Option<PathBuf>,
 
+    /// The key-value store that is used for persisting the data.
+    #[clap(value_enum, long, default_value = "sled", env = "ATOMIC_DB_BACKEND")]
+    pub db_backend: DbBackend,
+
+    /// Where uploaded files are stored.
+    #[clap(
+        value_enum,
+        long,
+        default_value = "filesystem",
+        env = "ATOMIC_BLOB_BACKEND"
+    )]
+    pub blob_backend: BlobBackend,
+
+    /// URL of the S3-compatible object storage, e.g. `https://s3.eu-west-1.amazonaws.com` or `http://localhost:9000` for a local MinIO. Required for `--blob-backend s3`.
+    #[clap(long, env = "ATOMIC_S3_ENDPOINT")]
+    pub s3_endpoint: Option<String>,
+
+    /// Name of the bucket that stores the uploaded files. Required for `--blob-backend s3`.
+    #[clap(long, env = "ATOMIC_S3_BUCKET")]
+    pub s3_bucket: Option<String>,
+
+    /// Region of the S3 bucket.
+    #[clap(long, default_value = "us-east-1", env = "ATOMIC_S3_REGION")]
+    pub s3_region: String,
+
+    /// Access key ID for the object storage. Required for `--blob-backend s3`.
+    #[clap(long, env = "ATOMIC_S3_ACCESS_KEY")]
+    pub s3_access_key: Option<String>,
+
+    /// Secret access key for the object storage. Required for `--blob-backend s3`.
+    #[clap(long, env = "ATOMIC_S3_SECRET_KEY")]
+    pub s3_secret_key: Option<Secret>,
+
+    /// Prepended to the names of stored objects, e.g. `uploads/`.
+    /// Servers that share a bucket without sharing their data should use different prefixes, because unreferenced files are removed.
+    #[clap(long, default_value = "", env = "ATOMIC_S3_PREFIX")]
+    pub s3_prefix: String,
+
+    /// Makes this server a follower of another Atomic Server: a read replica or offline backup.
+    /// Pass the URL of the primary server. Its Commits are pulled and applied in the same order.
+    /// The Agent of this server needs read rights on the primary.
+    #[clap(long, env = "ATOMIC_REPLICATE_FROM")]
+    pub replicate_from: Option<String>,
+
+    /// How many seconds a follower waits before pulling new Commits from its primary. See `replicate_from`.
+    #[clap(long, default_value = "10", env = "ATOMIC_REPLICATION_INTERVAL")]
+    pub replication_interval: u64,
+
+    /// Seconds after which unfinished resumable uploads are removed. Every received chunk extends this period.
+    #[clap(long, default_value = "86400", env = "ATOMIC_UPLOAD_SESSION_TIMEOUT")]
+    pub upload_session_timeout: u64,
+
+    /// Records the rights checks of requests in an audit log: who read or changed which resource, and which requests were refused.
+    /// The server's Agent can read it at `/audit`, and `export --audit` writes it to a JSON Lines file.
+    #[clap(long, env = "ATOMIC_AUDIT")]
+    pub audit: bool,
+
+    /// Days after which events are removed from the audit log. Keeps them forever if not set.
+    #[clap(long, env = "ATOMIC_AUDIT_RETENTION_DAYS", requires = "audit")]
+    pub audit_retention_days: Option<u64>,
+
+    /// Maximum amount of Commits (`/commit` and `/commits`) and new Tokens per minute, for every Agent and for every IP address. `0` means unlimited.
+    #[clap(long, default_value = "0", env = "ATOMIC_RATE_LIMIT_COMMIT")]
+    pub rate_limit_commit: u32,
+
+    /// Maximum amount of `/search` and `/similar` requests per minute, for every Agent and every IP address. `0` means unlimited.
+    #[clap(long, default_value = "0", env = "ATOMIC_RATE_LIMIT_SEARCH")]
+    pub rate_limit_search: u32,
+
+    /// Maximum amount of upload requests (`/upload` and `/upload-sessions`) per minute, for every Agent and every IP address. `0` means unlimited.
+    #[clap(long, default_value = "0", env = "ATOMIC_RATE_LIMIT_UPLOAD")]
+    pub rate_limit_upload: u32,
+
+    /// Maximum amount of `/fetch-bookmark` requests per minute, for every Agent and every IP address. `0` means unlimited.
+    #[clap(long, default_value = "0", env = "ATOMIC_RATE_LIMIT_BOOKMARK")]
+    pub rate_limit_bookmark: u32,
+
+    /// Maximum amount of other requests (reading and posting to resources, downloads) per minute, for every Agent and every IP address. `0` means unlimited.
+    #[clap(long, default_value = "0", env = "ATOMIC_RATE_LIMIT_READ")]
+    pub rate_limit_read: u32,
+
+    /// Maximum amount of resources in a single Drive. Unlimited if not set.
+    #[clap(long, env = "ATOMIC_DRIVE_MAX_RESOURCES")]
+    pub drive_max_resources: Option<u64>,
+
+    /// Maximum total size of the uploaded files in a single Drive, in bytes. Unlimited if not set.
+    #[clap(long, env = "ATOMIC_DRIVE_MAX_UPLOAD_BYTES")]
+    pub drive_max_upload_bytes: Option<u64>,
+
+    /// Keeps an embedding vector of every resource in memory, for finding similar resources at `/similar` and for `/search?mode=hybrid`.
+    /// The vectors are computed from the words in the resources, and the index is built every time the server starts.
+    #[clap(long, env = "ATOMIC_SIMILARITY")]
+    pub similarity: bool,
+
     /// CAUTION: Skip authentication checks, making all data publicly readable. Improves performance.
     #[clap(long, env = "ATOMIC_PUBLIC_MODE")]
     pub public_mode: bool,
@@ -103,6 +197,34 @@ pub enum Tracing {
     Opentelemetry,
 }
 
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum DbBackend {
+    /// Persist data on disk, in the `store` folder of the `data_dir`
+    Sled,
+    /// Keep all data in memory. Everything is lost when the server stops. Useful for testing.
+    Memory,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum BlobBackend {
+    /// Store files in the `uploads` folder of the `data_dir`
+    Filesystem,
+    /// Store files in an S3-compatible object storage. See the `s3_*` options.
+    S3,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// JSON-AD or NDJSON-AD, optionally gzipped
+    JsonAd,
+    /// RDF Turtle
+    Turtle,
+    /// RDF N-Triples
+    Ntriples,
+    /// JSON-LD, with an inline @context
+    Jsonld,
+}
+
 #[derive(clap::ValueEnum, Clone, Debug)]
 pub enum LogLevel {
     Warn,
@@ -125,6 +247,9 @@ pub enum Command {
     /// Returns the currently selected options, based on the passed flags and parsed environment variables.
     #[clap(name = "show-config")]
     ShowConfig,
+    /// Copies the files from the local uploads folder to the selected `--blob-backend`. Run this after switching to S3.
+    #[clap(name = "migrate-uploads")]
+    MigrateUploads(MigrateUploadsOpts),
     /// Danger! Removes all data from the store.
     #[clap(name = "reset")]
     Reset,
@@ -132,17 +257,29 @@ pub enum Command {
 
 #[derive(Parser, Clone, Debug)]
 pub struct ExportOpts {
-    /// Where the exported file should be saved  "~/.config/atomic/backups/{date}.json"
+    /// Where the exported file should be saved  "~/.config/atomic/backups/{date}.ndjson"
     #[clap(short)]
     pub path: Option<PathBuf>,
     /// Do not export resources that are externally defined, which are cached by this Server.
     #[clap(long)]
     pub only_internal: bool,
+    /// Only export resources that were changed at or after this moment, for incremental backups.
+    /// Accepts a UNIX timestamp in milliseconds or an ISO 8601 date, e.g. `2023-03-01`.
+    /// Destroyed resources are exported as the Commits that destroyed them. Import the export using `--force` to remove these resources as well.
+    #[clap(long)]
+    pub since: Option<String>,
+    /// Compress the export using gzip.
+    #[clap(long)]
+    pub gzip: bool,
+    /// Export the audit log as JSON Lines instead of the resources. Combine with `--since` to export recent events.
+    #[clap(long)]
+    pub audit: bool,
 }
 
 #[derive(Parser, Clone, Debug)]
 pub struct ImportOpts {
     /// Path of the file to be imported.
+    /// Accepts JSON-AD and NDJSON-AD (one resource per line), optionally compressed using gzip.
     #[clap(long)]
     pub file: PathBuf,
     /// The URL of the  Importer (parent) Resource to be used.
@@ -153,6 +290,16 @@ pub struct ImportOpts {
     /// Skip checks, allows for importing things like Commits.
     #[clap(long)]
     pub force: bool,
+    /// Format of the file. If not passed, it is derived from the file extension (`.ttl`, `.nt`, `.jsonld`), and defaults to JSON-AD.
+    #[clap(value_enum, long)]
+    pub format: Option<ImportFormat>,
+}
+
+#[derive(Parser, Clone, Debug)]
+pub struct MigrateUploadsOpts {
+    /// The folder that contains the uploaded files. Defaults to the `uploads` folder in the `data_dir`.
+    #[clap(long)]
+    pub from: Option<PathBuf>,
 }
 
 /// Start atomic-server, oi mate
@@ -183,14 +330,61 @@ pub struct Config {
     pub static_path: PathBuf,
     /// Path to where the store / database is located.
     pub store_path: PathBuf,
-    /// Path to where the uploaded files are stored.
+    /// Which key-value store the database uses. Points to `store_path` for persistent backends.
+    pub db_backend: atomic_lib::db::BackendConfig,
+    /// Path to where the uploaded files are stored. Also contains uploads in progress when another `blob_store` is used.
     pub uploads_path: PathBuf,
+    /// Where uploaded files are stored, see [crate::blob_store].
+    pub blob_store: BlobStoreConfig,
     /// Path to where the search index for tantivy full text search is located
     pub search_index_path: PathBuf,
     /// If true, the initialization scripts will be ran (create first Drive, Agent, indexing, etc)
     pub initialize: bool,
 }
 
+/// Selects and configures the [crate::blob_store::BlobStore] for uploaded files.
+#[derive(Clone, Debug)]
+pub enum BlobStoreConfig {
+    /// A local folder
+    FileSystem(PathBuf),
+    S3(S3Config),
+}
+
+/// Connection details for an S3-compatible object storage.
+#[derive(Clone, Debug)]
+pub struct S3Config {
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: Secret,
+    /// Prepended to the key of every object
+    pub prefix: String,
+}
+
+/// A value that should not end up in logs, such as a password or key.
+/// It is left out of the `Debug` output, which is printed by `show-config`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Secret(value)
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
 /// Parse .env and CLI options
 pub fn read_opts() -> Opts {
     // Parse .env file (do this before parsing the CLI opts)
@@ -266,7 +460,41 @@ pub fn build_config(opts: Opts) -> AtomicServerResult<Config> {
         }
     }
 
-    let initialize = !std::path::Path::exists(&store_path) || opts.initialize;
+    let db_backend = match opts.db_backend {
+        DbBackend::Sled => atomic_lib::db::BackendConfig::Sled(store_path.clone()),
+        DbBackend::Memory => atomic_lib::db::BackendConfig::Memory,
+    };
+
+    let blob_store = match opts.blob_backend {
+        BlobBackend::Filesystem => BlobStoreConfig::FileSystem(uploads_path.clone()),
+        BlobBackend::S3 => {
+            let required = |value: Option<&str>, flag: &str| {
+                value.map(String::from).ok_or_else(|| {
+                    format!(
+                        "The `--{}` flag (or ATOMIC_{} env) is required when using `--blob-backend s3`.",
+                        flag,
+                        flag.to_uppercase().replace('-', "_")
+                    )
+                })
+            };
+            BlobStoreConfig::S3(S3Config {
+                endpoint: required(opts.s3_endpoint.as_deref(), "s3-endpoint")?,
+                bucket: required(opts.s3_bucket.as_deref(), "s3-bucket")?,
+                region: opts.s3_region.clone(),
+                access_key: required(opts.s3_access_key.as_deref(), "s3-access-key")?,
+                secret_key: required(
+                    opts.s3_secret_key.as_ref().map(Secret::expose),
+                    "s3-secret-key",
+                )?
+                .into(),
+                prefix: opts.s3_prefix.clone(),
+            })
+        }
+    };
+
+    let initialize = !std::path::Path::exists(&store_path)
+        || opts.initialize
+        || opts.db_backend == DbBackend::Memory;
 
     if opts.https & opts.email.is_none() {
         return Err(
@@ -298,7 +526,9 @@ pub fn build_config(opts: Opts) -> AtomicServerResult<Config> {
         server_url,
         static_path,
         store_path,
+        db_backend,
         search_index_path,
         uploads_path,
+        blob_store,
     })
 }
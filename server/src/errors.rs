@@ -11,6 +11,10 @@ pub enum AppErrorType {
     NotFound,
     Unauthorized,
     MethodNotAllowed,
+    Conflict,
+    TooManyRequests,
+    /// The request has invalid parameters
+    BadRequest,
     Other,
 }
 
@@ -22,7 +26,15 @@ pub struct AtomicServerError {
     pub error_resource: Option<Box<Resource>>,
 }
 
-impl AtomicServerError {}
+impl AtomicServerError {
+    pub fn bad_request(message: String) -> Self {
+        AtomicServerError {
+            message,
+            error_type: AppErrorType::BadRequest,
+            error_resource: None,
+        }
+    }
+}
 
 impl std::fmt::Debug for AtomicServerError {
     // The derive impl is too verbose, as it includes the full `error_resource`.
@@ -43,6 +55,9 @@ impl ResponseError for AtomicServerError {
         match self.error_type {
             AppErrorType::NotFound => StatusCode::NOT_FOUND,
             AppErrorType::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            AppErrorType::Conflict => StatusCode::CONFLICT,
+            AppErrorType::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            AppErrorType::BadRequest => StatusCode::BAD_REQUEST,
             AppErrorType::Other => StatusCode::INTERNAL_SERVER_ERROR,
             AppErrorType::Unauthorized => StatusCode::UNAUTHORIZED,
         }
@@ -85,6 +100,8 @@ impl From<atomic_lib::errors::AtomicError> for AtomicServerError {
             atomic_lib::AtomicErrorType::NotFoundError => AppErrorType::NotFound,
             atomic_lib::AtomicErrorType::UnauthorizedError => AppErrorType::Unauthorized,
             atomic_lib::AtomicErrorType::MethodNotAllowed => AppErrorType::MethodNotAllowed,
+            atomic_lib::AtomicErrorType::ConflictError(_) => AppErrorType::Conflict,
+            atomic_lib::AtomicErrorType::LimitExceeded => AppErrorType::TooManyRequests,
             atomic_lib::AtomicErrorType::ParseError => AppErrorType::Other,
             atomic_lib::AtomicErrorType::OtherError => AppErrorType::Other,
         };
@@ -1,6 +1,10 @@
 use crate::{appstate::AppState, errors::AtomicServerResult};
 use actix_web::{web, HttpResponse};
-use atomic_lib::{commit::CommitOpts, parse::parse_json_ad_commit_resource, Commit, Storelike};
+use atomic_lib::{
+    commit::CommitOpts,
+    parse::{parse_json_ad_commit_resource, parse_json_ad_transaction},
+    Commit, Storelike,
+};
 
 /// Send and process a Commit.
 /// Currently only accepts JSON-AD
@@ -25,8 +29,9 @@ pub async fn post_commit(
         validate_signature: true,
         validate_timestamp: true,
         validate_rights: true,
-        // https://github.com/atomicdata-dev/atomic-data-rust/issues/412
-        validate_previous_commit: false,
+        // Concurrent Commits are merged, unless they change the same properties.
+        // Commits without a `previousCommit` are applied on top of the current version, like before merging existed.
+        validate_previous_commit: incoming_commit.previous_commit.is_some(),
         validate_for_agent: Some(incoming_commit.signer.to_string()),
         update_index: true,
     };
@@ -36,3 +41,38 @@ pub async fn post_commit(
 
     Ok(builder.body(message))
 }
+
+/// Send and process a Transaction, which bundles multiple Commits.
+/// Either all Commits are applied, or none of them.
+/// Currently only accepts JSON-AD
+#[tracing::instrument(skip(appstate))]
+pub async fn post_transaction(
+    appstate: web::Data<AppState>,
+    body: String,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let mut builder = HttpResponse::Ok();
+    let transaction = parse_json_ad_transaction(&body, store)?;
+    let self_url = store
+        .get_self_url()
+        .ok_or("Cannot apply commits to this store. No self_url is set.")?;
+    for commit in &transaction.commits {
+        if !commit.subject.contains(&self_url) {
+            return Err(format!("Subject of commit {} should be sent to other domain - this store can not own this resource.", commit.subject).into());
+        }
+    }
+    let opts = CommitOpts {
+        validate_schema: true,
+        validate_signature: true,
+        validate_timestamp: true,
+        validate_rights: true,
+        validate_previous_commit: true,
+        validate_for_agent: Some(transaction.signer.to_string()),
+        update_index: true,
+    };
+    transaction.apply_opts(store, &opts)?;
+
+    let message = transaction.into_resource(store)?.to_json_ad()?;
+
+    Ok(builder.body(message))
+}
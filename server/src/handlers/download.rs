@@ -1,14 +1,41 @@
+use std::io::Read;
+
 use actix_files::NamedFile;
-use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web::{
+    body::SizedStream,
+    http::{
+        header::{
+            self, ContentDisposition, DispositionParam, DispositionType, ETag, EntityTag,
+            IfNoneMatch,
+        },
+        StatusCode,
+    },
+    web, HttpMessage, HttpRequest, HttpResponse,
+};
 use atomic_lib::{urls, Resource, Storelike};
+use futures::Stream;
+use serde::Deserialize;
+
+use crate::{
+    appstate::AppState, blob_store::Blob, errors::AtomicServerResult, helpers::get_client_agent,
+    images,
+};
 
-use crate::{appstate::AppState, errors::AtomicServerResult, helpers::get_client_agent};
+/// The size of the parts in which streamed blobs are read and sent.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Deserialize, Debug)]
+pub struct DownloadQuery {
+    /// Requested width of an image. Serves a cached, resized variant. See [crate::images::select_variant].
+    w: Option<u32>,
+}
 
 /// Downloads the File of the Resource that matches the same URL minus the `/download` path.
 #[tracing::instrument(skip(appstate, req))]
 pub async fn handle_download(
     path: Option<web::Path<String>>,
     appstate: web::Data<AppState>,
+    query: web::Query<DownloadQuery>,
     req: actix_web::HttpRequest,
 ) -> AtomicServerResult<HttpResponse> {
     let headers = req.headers();
@@ -26,20 +53,136 @@ pub async fn handle_download(
 
     let for_agent = get_client_agent(headers, &appstate, subject.clone())?;
     tracing::info!("handle_download: {}", subject);
-    let resource = store.get_resource_extended(&subject, false, for_agent.as_deref())?;
-    download_file_handler_partial(&resource, &req, &appstate)
+    let result = store.get_resource_extended(&subject, false, for_agent.as_deref());
+    atomic_lib::audit::record_read(store, for_agent.as_deref(), &subject, &result);
+    let resource = result?;
+    let variant = query
+        .w
+        .and_then(|width| images::select_variant(&resource, width));
+    if let Some(width) = variant {
+        // Creates the variant if it was not requested before
+        let blobs = appstate.blob_store.clone();
+        let blob_id = resource.get(urls::INTERNAL_ID)?.to_string();
+        let temp_dir = appstate.config.uploads_path.clone();
+        web::block(move || images::create_variants(blobs.as_ref(), &blob_id, &[width], &temp_dir))
+            .await
+            .map_err(|e| format!("Could not resize image. {}", e))??;
+    }
+    download_file_handler_partial(&resource, &req, &appstate, variant).await
 }
 
-pub fn download_file_handler_partial(
+/// Serves the file of a File resource from the [crate::blob_store::BlobStore].
+/// Uses the `checksum` as `ETag`, so clients can cache files and revalidate them using `If-None-Match`.
+/// Pass a `variant` width to serve a resized image, which should already exist.
+/// Blobs that are not local files are streamed, and the `Range` header is passed on to their store.
+pub async fn download_file_handler_partial(
     resource: &Resource,
     req: &HttpRequest,
     appstate: &AppState,
+    variant: Option<u32>,
 ) -> AtomicServerResult<HttpResponse> {
-    let file_name = resource
+    let mut blob_id = resource
         .get(urls::INTERNAL_ID)
-        .map_err(|e| format!("Internal ID of file could not be resolved. {}", e))?;
-    let mut file_path = appstate.config.uploads_path.clone();
-    file_path.push(file_name.to_string());
-    let file = NamedFile::open(file_path)?;
-    Ok(file.into_response(req))
+        .map_err(|e| format!("Internal ID of file could not be resolved. {}", e))?
+        .to_string();
+    let mut etag = resource
+        .get(urls::CHECKSUM)
+        .ok()
+        .map(|checksum| EntityTag::new_strong(checksum.to_string()));
+    let mut mime = resource
+        .get(urls::MIMETYPE)
+        .ok()
+        .map(|mime| mime.to_string());
+    if let Some(width) = variant {
+        blob_id = images::variant_id(&blob_id, width);
+        etag = etag.map(|etag| EntityTag::new_strong(format!("{}-w{}", etag.tag(), width)));
+        mime = mime.map(|mime| images::variant_mime(&mime).to_string());
+    }
+    if let (Some(etag), Some(IfNoneMatch::Items(tags))) = (&etag, req.get_header::<IfNoneMatch>()) {
+        if tags.iter().any(|tag| tag.weak_eq(etag)) {
+            return Ok(HttpResponse::NotModified()
+                .insert_header(ETag(etag.clone()))
+                .finish());
+        }
+    }
+    // Blobs are named by their hash, so the type and name can't be derived from the path
+    let mime = mime.and_then(|mime| mime.parse::<mime::Mime>().ok());
+    let disposition = resource
+        .get(urls::FILENAME)
+        .ok()
+        .map(|filename| ContentDisposition {
+            disposition: DispositionType::Inline,
+            parameters: vec![DispositionParam::Filename(filename.to_string())],
+        });
+    let range = req
+        .headers()
+        .get(header::RANGE)
+        .and_then(|range| range.to_str().ok())
+        .map(String::from);
+    // Object storages are reached over the network, which blocks
+    let blobs = appstate.blob_store.clone();
+    let blob = web::block(move || match range {
+        Some(range) => blobs.get_range(&blob_id, &range),
+        None => blobs.get(&blob_id),
+    })
+    .await
+    .map_err(|e| format!("Could not read file. {}", e))??;
+    let mut response = match blob {
+        Blob::File(path) => {
+            let mut file = NamedFile::open(path)?;
+            if let Some(mime) = mime {
+                file = file.set_content_type(mime);
+            }
+            if let Some(disposition) = disposition {
+                file = file.set_content_disposition(disposition);
+            }
+            file.use_etag(etag.is_none()).into_response(req)
+        }
+        Blob::Stream(stream) => {
+            let status = StatusCode::from_u16(stream.status)
+                .map_err(|e| format!("Invalid status from blob store. {}", e))?;
+            let mut builder = HttpResponse::build(status);
+            builder.content_type(mime.unwrap_or(mime::APPLICATION_OCTET_STREAM));
+            builder.insert_header((header::ACCEPT_RANGES, "bytes"));
+            if let Some(disposition) = disposition {
+                builder.insert_header(disposition);
+            }
+            if let Some(content_range) = stream.content_range {
+                builder.insert_header((header::CONTENT_RANGE, content_range));
+            }
+            match stream.length {
+                Some(length) => builder.body(SizedStream::new(length, read_chunks(stream.reader))),
+                None => builder.streaming(read_chunks(stream.reader)),
+            }
+        }
+    };
+    if let Some(etag) = etag {
+        response.headers_mut().insert(
+            actix_web::http::header::ETAG,
+            actix_web::http::header::HeaderValue::from_str(&etag.to_string())
+                .map_err(|e| format!("Invalid ETag. {}", e))?,
+        );
+    }
+    Ok(response)
+}
+
+/// Reads a blocking reader in chunks on the thread pool, so the response is sent while the blob is still being read.
+fn read_chunks(
+    reader: Box<dyn Read + Send>,
+) -> impl Stream<Item = Result<web::Bytes, actix_web::Error>> {
+    futures::stream::try_unfold(reader, |mut reader| async move {
+        let (chunk, reader) = web::block(move || {
+            let mut chunk = vec![0; CHUNK_SIZE];
+            let read = reader.read(&mut chunk)?;
+            chunk.truncate(read);
+            Ok::<_, std::io::Error>((chunk, reader))
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)??;
+        if chunk.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some((web::Bytes::from(chunk), reader)))
+        }
+    })
 }
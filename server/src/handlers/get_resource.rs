@@ -67,7 +67,9 @@ pub async fn handle_get_resource(
         "no-store, no-cache, must-revalidate, private",
     ));
 
-    let resource = store.get_resource_extended(&subject, false, for_agent.as_deref())?;
+    let result = store.get_resource_extended(&subject, false, for_agent.as_deref());
+    atomic_lib::audit::record_read(store, for_agent.as_deref(), &subject, &result);
+    let resource = result?;
     timer.add("get_resource");
 
     let response_body = match content_type {
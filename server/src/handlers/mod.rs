@@ -10,6 +10,9 @@ pub mod download;
 pub mod get_resource;
 pub mod post_resource;
 pub mod search;
+pub mod similar;
 pub mod single_page_app;
+pub mod tokens;
 pub mod upload;
+pub mod upload_sessions;
 pub mod web_sockets;
@@ -3,20 +3,30 @@
 //! or after a commit is processed by the CommitMonitor.
 
 use crate::{
+    actor_messages::FlushSearchIndex,
     appstate::AppState,
     errors::{AtomicServerError, AtomicServerResult},
-    search::{resource_to_facet, Fields},
+    search::{resource_to_facet, tokenizer_name, Fields, LanguageFields},
 };
 use actix_web::{web, HttpResponse};
-use atomic_lib::{errors::AtomicResult, urls, Db, Resource, Storelike};
+use atomic_lib::{
+    errors::AtomicResult, resources::PropVals, urls, values::SubResource, Db, Resource, Storelike,
+    Value,
+};
 use serde::Deserialize;
 use simple_server_timing_header::Timer;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::Bound,
+};
 use tantivy::{
-    collector::TopDocs,
-    query::{BooleanQuery, BoostQuery, Occur, Query, QueryParser, TermQuery},
-    schema::IndexRecordOption,
-    tokenizer::Tokenizer,
-    Term,
+    collector::{Count, FacetCollector, TopDocs},
+    query::{
+        BooleanQuery, BoostQuery, Occur, Query, QueryParser, RangeQuery, TermQuery, TermSetQuery,
+    },
+    schema::{Facet, Field, IndexRecordOption, Schema, Type},
+    tokenizer::{TextAnalyzer, Tokenizer},
+    DocId, Score, SnippetGenerator, Term,
 };
 use tracing::instrument;
 
@@ -34,6 +44,22 @@ pub struct SearchQuery {
     pub parent: Option<String>,
     /// Filter based on props, using tantivy QueryParser syntax
     pub filter: Option<String>,
+    /// Sort by the value of this Property instead of by relevance. It needs a fast field, see [crate::search::build_schema].
+    pub sort_by: Option<String>,
+    /// Sort from high to low
+    pub sort_desc: Option<bool>,
+    /// JSON object of Property URLs and their inclusive `min` and `max`, e.g. `{"https://atomicdata.dev/properties/createdAt": {"min": 0}}`
+    pub range: Option<String>,
+    /// Only include instances of this Class
+    pub class: Option<String>,
+    /// Comma-separated Property URLs, for which the amount of results per linked value are counted
+    pub facets: Option<String>,
+    /// Wait until all applied Commits are indexed, so the results include the latest changes
+    pub consistent: Option<bool>,
+    /// Comma-separated language codes, e.g. `nl,de`. Also matches stemmed words in resources with these languages.
+    pub lang: Option<String>,
+    /// `text` (default) only matches the words of `q`. `hybrid` also finds resources with similar embedding vectors, see [crate::similarity].
+    pub mode: Option<String>,
 }
 
 const DEFAULT_RETURN_LIMIT: usize = 30;
@@ -41,6 +67,17 @@ const DEFAULT_RETURN_LIMIT: usize = 30;
 // We filter these results later.
 // https://github.com/atomicdata-dev/atomic-data-rust/issues/279.
 const UNAUTHORIZED_RESULTS_FACTOR: usize = 3;
+/// Maximum amount of values that are counted per facet
+const MAX_FACET_VALUES: usize = 100;
+/// Maximum amount of matching resources whose read rights are checked when counting facets.
+/// If more resources match, the counts only include the best matches.
+const MAX_FACET_CHECKS: usize = 1000;
+/// Maximum length of a highlighted snippet, in characters
+const MAX_SNIPPET_CHARS: usize = 150;
+/// How much the similarity of the vectors counts in `mode=hybrid`, between 0 and 1. The rest is the text score.
+const HYBRID_VECTOR_WEIGHT: f32 = 0.5;
+pub const SIMILARITY_DISABLED: &str =
+    "The similarity index is not enabled. Start the server with `--similarity`.";
 
 /// Parses a search query and responds with a list of resources
 #[tracing::instrument(skip(appstate, req))]
@@ -51,6 +88,15 @@ pub async fn search_query(
 ) -> AtomicServerResult<HttpResponse> {
     let mut timer = Timer::new();
     let store = &appstate.store;
+    if params.consistent.unwrap_or(false) {
+        appstate
+            .commit_monitor
+            .send(FlushSearchIndex)
+            .await
+            .map_err(|e| format!("Failed to index pending changes: {}", e))??;
+        appstate.search_state.reader.reload()?;
+        timer.add("index_pending");
+    }
     let searcher = appstate.search_state.reader.searcher();
     let fields = crate::search::get_schema_fields(&appstate.search_state)?;
     let limit = if let Some(l) = params.limit {
@@ -63,17 +109,54 @@ pub async fn search_query(
         DEFAULT_RETURN_LIMIT
     };
 
+    let hybrid = match params.mode.as_deref() {
+        None | Some("text") => false,
+        Some("hybrid") if params.sort_by.is_some() => {
+            return Err("`mode=hybrid` can not be combined with `sort_by`".into())
+        }
+        Some("hybrid") => true,
+        Some(other) => {
+            return Err(format!("Unknown mode '{}', use `text` or `hybrid`", other).into())
+        }
+    };
+
     let query = query_from_params(&params, &fields, &appstate)?;
     timer.add("build_query");
-    let top_docs = searcher
-        .search(
+    let top_docs = match &params.sort_by {
+        Some(property) => sorted_docs(
+            &searcher,
             &query,
-            &TopDocs::with_limit(limit * UNAUTHORIZED_RESULTS_FACTOR),
-        )
-        .map_err(|e| format!("Error with creating search results: {} ", e))?;
+            &fields,
+            &appstate.search_state.schema,
+            property,
+            params.sort_desc.unwrap_or(false),
+            limit * UNAUTHORIZED_RESULTS_FACTOR,
+        )?,
+        None => searcher
+            .search(
+                &query,
+                &TopDocs::with_limit(limit * UNAUTHORIZED_RESULTS_FACTOR),
+            )
+            .map_err(|e| format!("Error with creating search results: {} ", e))?,
+    };
 
     timer.add("execute_query");
-    let subjects = docs_to_subjects(top_docs, &fields, &searcher)?;
+    let mut scores = docs_to_scores(top_docs, &fields, &searcher)?;
+    if hybrid {
+        scores = hybrid_scores(
+            &params,
+            &fields,
+            &appstate,
+            &searcher,
+            scores,
+            limit * UNAUTHORIZED_RESULTS_FACTOR,
+        )?;
+        timer.add("hybrid_scores");
+    }
+    let subjects = scores
+        .iter()
+        .map(|(subject, _score)| subject.clone())
+        .collect();
 
     // Create a valid atomic data resource.
     // You'd think there would be a simpler way of getting the requested URL...
@@ -86,9 +169,39 @@ pub async fn search_query(
     let mut results_resource = atomic_lib::plugins::search::search_endpoint().to_resource(store)?;
     results_resource.set_subject(subject.clone());
 
+    let facet_counts: Option<(Vec<SubResource>, bool)> = match &params.facets {
+        Some(facets) => {
+            let for_agent =
+                crate::helpers::get_client_agent(req.headers(), &appstate, subject.clone())?;
+            let counts = count_facets(
+                &searcher,
+                &query,
+                &fields,
+                facets,
+                store,
+                for_agent.as_deref(),
+            )?;
+            timer.add("count_facets");
+            Some(counts)
+        }
+        None => None,
+    };
     let resources = get_resources(req, &appstate, &subject, subjects, limit)?;
     timer.add("get_resources");
+    let hits = build_hits(&searcher, &query, &fields, &resources, &scores)?;
+    timer.add("build_hits");
     results_resource.set_propval(urls::ENDPOINT_RESULTS.into(), resources.into(), store)?;
+    results_resource.set_propval_unsafe(urls::SEARCH_HITS.into(), Value::ResourceArray(hits));
+    if let Some((counts, approximate)) = facet_counts {
+        results_resource.set_propval_unsafe(
+            urls::SEARCH_FACET_COUNTS.into(),
+            Value::ResourceArray(counts),
+        );
+        results_resource.set_propval_unsafe(
+            urls::SEARCH_FACET_COUNTS_APPROXIMATE.into(),
+            Value::Boolean(approximate),
+        );
+    }
     let mut builder = HttpResponse::Ok();
     builder.append_header(("Server-Timing", timer.header_value()));
 
@@ -103,8 +216,9 @@ pub struct StringAtom {
     pub value: String,
 }
 
+/// Returns the resources that the Agent of the request can read, up to the `limit`.
 #[instrument(skip(appstate, req))]
-fn get_resources(
+pub fn get_resources(
     req: actix_web::HttpRequest,
     appstate: &web::Data<AppState>,
     subject: &str,
@@ -146,6 +260,29 @@ fn query_from_params(
     fields: &Fields,
     appstate: &web::Data<AppState>,
 ) -> AtomicServerResult<impl Query> {
+    let mut query_list = filter_queries(params, fields, appstate)?;
+
+    if let Some(q) = &params.q {
+        let languages = match &params.lang {
+            Some(lang) => get_languages(fields, lang, &appstate.search_state.index)?,
+            None => Vec::new(),
+        };
+        let text_query = build_text_query(fields, q, &languages)?;
+
+        query_list.push((Occur::Must, Box::new(text_query)));
+    }
+
+    let query = BooleanQuery::new(query_list);
+
+    Ok(query)
+}
+
+/// The queries for all params except `q`, which every result has to match.
+fn filter_queries(
+    params: &SearchQuery,
+    fields: &Fields,
+    appstate: &web::Data<AppState>,
+) -> AtomicServerResult<Queries> {
     let mut query_list: Queries = Vec::new();
 
     if let Some(parent) = &params.parent {
@@ -154,10 +291,18 @@ fn query_from_params(
         query_list.push((Occur::Must, Box::new(query)));
     }
 
-    if let Some(q) = &params.q {
-        let text_query = build_text_query(fields, q)?;
+    if let Some(class) = &params.class {
+        let term = Term::from_facet(fields.is_a, &Facet::from_path([class]));
+        query_list.push((
+            Occur::Must,
+            Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+        ));
+    }
 
-        query_list.push((Occur::Must, Box::new(text_query)));
+    if let Some(range) = &params.range {
+        for query in build_range_queries(fields, &appstate.search_state.schema, range)? {
+            query_list.push((Occur::Must, query));
+        }
     }
 
     if let Some(filter) = &params.filter {
@@ -169,17 +314,85 @@ fn query_from_params(
         query_list.push((Occur::Must, Box::new(filter_query)));
     }
 
-    let query = BooleanQuery::new(query_list);
+    Ok(query_list)
+}
 
-    Ok(query)
+/// Mixes the text scores with the similarity of the vectors of the query and the resources, for `mode=hybrid`.
+/// Text scores are divided by the highest one, so both are between 0 and 1.
+/// Resources that are only found by their vector still have to match the other params, such as `parent` and `class`.
+fn hybrid_scores(
+    params: &SearchQuery,
+    fields: &Fields,
+    appstate: &web::Data<AppState>,
+    searcher: &tantivy::Searcher,
+    text_scores: Vec<(String, f32)>,
+    limit: usize,
+) -> AtomicServerResult<Vec<(String, f32)>> {
+    let similarity = appstate
+        .search_state
+        .similarity
+        .as_ref()
+        .ok_or(SIMILARITY_DISABLED)?;
+    let q = params.q.as_deref().ok_or("`mode=hybrid` needs a `q`")?;
+    let mut vector_scores = similarity.nearest(&similarity.embed(q), limit)?;
+
+    let mut filters = filter_queries(params, fields, appstate)?;
+    if !filters.is_empty() && !vector_scores.is_empty() {
+        let subjects: Queries = vector_scores
+            .iter()
+            .map(|(subject, _score)| {
+                let term = Term::from_field_text(fields.subject, subject);
+                let query: Box<dyn Query> =
+                    Box::new(TermQuery::new(term, IndexRecordOption::Basic));
+                (Occur::Should, query)
+            })
+            .collect();
+        filters.push((Occur::Must, Box::new(BooleanQuery::new(subjects))));
+        let docs = searcher
+            .search(
+                &BooleanQuery::new(filters),
+                &TopDocs::with_limit(vector_scores.len()),
+            )
+            .map_err(|e| format!("Error with creating search results: {} ", e))?;
+        let allowed: HashSet<String> = docs_to_scores(docs, fields, searcher)?
+            .into_iter()
+            .map(|(subject, _score)| subject)
+            .collect();
+        vector_scores.retain(|(subject, _score)| allowed.contains(subject));
+    }
+
+    let max_text_score = text_scores
+        .iter()
+        .map(|(_subject, score)| *score)
+        .fold(0.0, f32::max);
+    let mut combined: HashMap<String, f32> = HashMap::new();
+    for (subject, score) in text_scores {
+        let normalized = if max_text_score > 0.0 {
+            score / max_text_score
+        } else {
+            0.0
+        };
+        *combined.entry(subject).or_default() += (1.0 - HYBRID_VECTOR_WEIGHT) * normalized;
+    }
+    for (subject, score) in vector_scores {
+        *combined.entry(subject).or_default() += HYBRID_VECTOR_WEIGHT * score.max(0.0);
+    }
+    let mut scores: Vec<(String, f32)> = combined.into_iter().collect();
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    Ok(scores)
 }
 
 /// Performs both fuzzy and exact queries on the text and description fields.
 /// Boosts titles and exact matches over descriptions and fuzzy matches.
+/// For every language, the stemmed words of the query are matched with the stemmed fields of that language.
 /// Does not yet search in JSON fields:
 /// https://github.com/atomicdata-dev/atomic-data-rust/issues/597
-#[tracing::instrument]
-fn build_text_query(fields: &Fields, q: &str) -> AtomicResult<impl Query> {
+#[tracing::instrument(skip(languages))]
+fn build_text_query(
+    fields: &Fields,
+    q: &str,
+    languages: &[(LanguageFields, TextAnalyzer)],
+) -> AtomicResult<impl Query> {
     let mut token_stream = tantivy::tokenizer::SimpleTokenizer.token_stream(q);
     let mut queries: Queries = Vec::new();
     // for every word, create a fuzzy query and an exact query
@@ -211,9 +424,61 @@ fn build_text_query(fields: &Fields, q: &str) -> AtomicResult<impl Query> {
         queries.push((Occur::Should, Box::new(description_fuzzy)));
     });
 
+    for (language, analyzer) in languages {
+        analyzer.token_stream(q).process(&mut |token| {
+            let title_term = Term::from_field_text(language.title, &token.text);
+            let description_term = Term::from_field_text(language.description, &token.text);
+            queries.push((
+                Occur::Should,
+                Box::new(BoostQuery::new(
+                    Box::new(TermQuery::new(title_term, IndexRecordOption::Basic)),
+                    10.,
+                )),
+            ));
+            queries.push((
+                Occur::Should,
+                Box::new(BoostQuery::new(
+                    Box::new(TermQuery::new(description_term, IndexRecordOption::Basic)),
+                    2.0,
+                )),
+            ));
+        });
+    }
+
     Ok(BooleanQuery::from(queries))
 }
 
+/// Parses comma-separated language codes, and returns their stemmed fields and the tokenizer to use for the query.
+fn get_languages(
+    fields: &Fields,
+    lang: &str,
+    index: &tantivy::Index,
+) -> AtomicServerResult<Vec<(LanguageFields, TextAnalyzer)>> {
+    let mut languages = Vec::new();
+    for code in lang.split(',').map(|code| code.trim().to_lowercase()) {
+        if code.is_empty() {
+            continue;
+        }
+        let language_fields = fields.languages.get(&code).ok_or_else(|| {
+            format!(
+                "Unsupported language '{}'. Supported languages are: {}",
+                code,
+                crate::search::LANGUAGES
+                    .iter()
+                    .map(|(code, _language)| *code)
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            )
+        })?;
+        let analyzer = index
+            .tokenizers()
+            .get(&tokenizer_name(&code))
+            .ok_or_else(|| format!("No tokenizer registered for language '{}'", code))?;
+        languages.push((*language_fields, analyzer));
+    }
+    Ok(languages)
+}
+
 #[tracing::instrument(skip(index))]
 fn build_filter_query(
     fields: &Fields,
@@ -229,6 +494,287 @@ fn build_filter_query(
     Ok(query)
 }
 
+/// Parses the JSON object of the `range` param into a RangeQuery for every Property.
+fn build_range_queries(
+    fields: &Fields,
+    schema: &Schema,
+    range: &str,
+) -> AtomicServerResult<Vec<Box<dyn Query>>> {
+    let ranges: serde_json::Map<String, serde_json::Value> = serde_json::from_str(range)
+        .map_err(|e| format!("The range must be a JSON object. {}", e))?;
+    let mut queries: Vec<Box<dyn Query>> = Vec::new();
+    for (property, bounds) in ranges {
+        let field = typed_field(fields, &property)?;
+        let bound = |key: &str| bounds.get(key).filter(|v| !v.is_null());
+        let query = match schema.get_field_entry(field).field_type().value_type() {
+            Type::I64 => RangeQuery::new_i64_bounds(
+                field,
+                to_bound(bound("min"), |v| v.as_i64())?,
+                to_bound(bound("max"), |v| v.as_i64())?,
+            ),
+            Type::F64 => RangeQuery::new_f64_bounds(
+                field,
+                to_bound(bound("min"), |v| v.as_f64())?,
+                to_bound(bound("max"), |v| v.as_f64())?,
+            ),
+            _ => RangeQuery::new_u64_bounds(
+                field,
+                to_bound(bound("min"), |v| v.as_bool().map(u64::from))?,
+                to_bound(bound("max"), |v| v.as_bool().map(u64::from))?,
+            ),
+        };
+        queries.push(Box::new(query));
+    }
+    Ok(queries)
+}
+
+fn to_bound<T>(
+    value: Option<&serde_json::Value>,
+    convert: impl Fn(&serde_json::Value) -> Option<T>,
+) -> AtomicServerResult<Bound<T>> {
+    match value {
+        Some(v) => Ok(Bound::Included(
+            convert(v).ok_or_else(|| format!("Invalid value in range: {}", v))?,
+        )),
+        None => Ok(Bound::Unbounded),
+    }
+}
+
+/// Returns the fast field of the Property, see [crate::search::build_schema].
+fn typed_field(fields: &Fields, property: &str) -> AtomicServerResult<Field> {
+    fields.typed.get(property).copied().ok_or_else(|| {
+        format!(
+            "Property {} can not be used for sorting or ranges. Only Properties with an Integer, Float, Timestamp or Boolean datatype can, and new ones only after a restart.",
+            property
+        )
+        .into()
+    })
+}
+
+/// Returns the best matching documents and their relevance scores, sorted by the value of the Property.
+/// Documents without a value are sorted as if their value is `0`.
+fn sorted_docs(
+    searcher: &tantivy::Searcher,
+    query: &dyn Query,
+    fields: &Fields,
+    schema: &Schema,
+    property: &str,
+    desc: bool,
+    limit: usize,
+) -> AtomicServerResult<Vec<(f32, tantivy::DocAddress)>> {
+    let field = typed_field(fields, property)?;
+    let value_type = schema.get_field_entry(field).field_type().value_type();
+    let collector =
+        TopDocs::with_limit(limit).tweak_score(move |segment_reader: &tantivy::SegmentReader| {
+            let fast_fields = segment_reader.fast_fields();
+            let column: Box<dyn Fn(DocId) -> f64> = match value_type {
+                Type::I64 => match fast_fields.i64(field) {
+                    Ok(column) => Box::new(move |doc| column.get_val(doc) as f64),
+                    Err(_) => Box::new(|_doc| 0.0),
+                },
+                Type::F64 => match fast_fields.f64(field) {
+                    Ok(column) => Box::new(move |doc| column.get_val(doc)),
+                    Err(_) => Box::new(|_doc| 0.0),
+                },
+                _ => match fast_fields.u64(field) {
+                    Ok(column) => Box::new(move |doc| column.get_val(doc) as f64),
+                    Err(_) => Box::new(|_doc| 0.0),
+                },
+            };
+            // The highest value comes first, the relevance score is kept for the hits
+            move |doc: DocId, score: Score| {
+                if desc {
+                    (column(doc), score)
+                } else {
+                    (-column(doc), score)
+                }
+            }
+        });
+    let docs = searcher
+        .search(query, &collector)
+        .map_err(|e| format!("Error with creating search results: {} ", e))?;
+    Ok(docs
+        .into_iter()
+        .map(|((_value, score), doc_address)| (score, doc_address))
+        .collect())
+}
+
+/// Explains why the resources match the query, as nested SearchHit resources in the same order.
+/// Every String, Slug and Markdown value that contains a word of the `q` param gets a highlighted snippet.
+/// Only the values that the Agent can read are highlighted, as these come from the authorized resources.
+fn build_hits(
+    searcher: &tantivy::Searcher,
+    query: &dyn Query,
+    fields: &Fields,
+    resources: &[Resource],
+    scores: &[(String, f32)],
+) -> AtomicServerResult<Vec<SubResource>> {
+    // Like SnippetGenerator::create, but with the words of both the title and the description
+    let mut terms = Vec::new();
+    query.query_terms(&mut |term, _| {
+        if term.field() == fields.title || term.field() == fields.description {
+            terms.push(term.clone());
+        }
+    });
+    let mut terms_text = BTreeMap::new();
+    for term in terms {
+        let doc_freq = searcher.doc_freq(&term)?;
+        if let (Some(text), true) = (term.as_str(), doc_freq > 0) {
+            terms_text.insert(text.to_string(), 1.0 / (1.0 + doc_freq as Score));
+        }
+    }
+    let tokenizer = searcher.index().tokenizer_for_field(fields.description)?;
+    let generator =
+        SnippetGenerator::new(terms_text, tokenizer, fields.description, MAX_SNIPPET_CHARS);
+    let scores: HashMap<&str, f32> = scores
+        .iter()
+        .map(|(s, score)| (s.as_str(), *score))
+        .collect();
+    let mut hits = Vec::new();
+    for resource in resources {
+        let mut snippets = Vec::new();
+        for (property, value) in resource.get_propvals() {
+            let text = match value {
+                Value::String(s) | Value::Slug(s) | Value::Markdown(s) => s,
+                _ => continue,
+            };
+            let snippet = generator.snippet(text);
+            if snippet.highlighted().is_empty() {
+                continue;
+            }
+            let mut propvals = PropVals::new();
+            propvals.insert(
+                urls::IS_A.into(),
+                vec![urls::SEARCH_SNIPPET_CLASS.to_string()].into(),
+            );
+            propvals.insert(
+                urls::SEARCH_PROPERTY.into(),
+                Value::AtomicUrl(property.clone()),
+            );
+            propvals.insert(
+                urls::SEARCH_SNIPPET.into(),
+                Value::String(snippet.to_html()),
+            );
+            snippets.push(SubResource::Nested(propvals));
+        }
+        let score = scores
+            .get(resource.get_subject().as_str())
+            .copied()
+            .unwrap_or(0.0);
+        let mut propvals = hit_propvals(resource.get_subject(), score);
+        propvals.insert(urls::SEARCH_SNIPPETS.into(), Value::ResourceArray(snippets));
+        hits.push(SubResource::Nested(propvals));
+    }
+    Ok(hits)
+}
+
+/// The propvals of a nested SearchHit, without snippets.
+pub fn hit_propvals(subject: &str, score: f32) -> PropVals {
+    let mut propvals = PropVals::new();
+    propvals.insert(urls::IS_A.into(), vec![urls::SEARCH_HIT.to_string()].into());
+    propvals.insert(urls::SEARCH_RESULT.into(), Value::AtomicUrl(subject.into()));
+    propvals.insert(urls::SEARCH_SCORE.into(), Value::Float(score.into()));
+    propvals
+}
+
+/// Counts the matching documents for every value of the requested Properties, and returns these as nested FacetCount resources.
+/// Classes are counted if [urls::IS_A] is requested.
+/// Only the resources that `for_agent` can read are counted, so the counts don't reveal private resources or what they link to.
+/// Also returns whether the counts are approximate, see [MAX_FACET_CHECKS].
+fn count_facets(
+    searcher: &tantivy::Searcher,
+    query: &dyn Query,
+    fields: &Fields,
+    facets: &str,
+    store: &Db,
+    for_agent: Option<&str>,
+) -> AtomicServerResult<(Vec<SubResource>, bool)> {
+    let readable = readable_query(searcher, query, fields, store, for_agent)?;
+    let (query, approximate) = match &readable {
+        Some((readable, approximate)) => (readable as &dyn Query, *approximate),
+        None => (query, false),
+    };
+    let mut counts = Vec::new();
+    for property in facets.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (field, prefix) = if property == urls::IS_A {
+            (fields.is_a, Facet::root())
+        } else {
+            (fields.links, Facet::from_path([property]))
+        };
+        let mut collector = FacetCollector::for_field(field);
+        collector.add_facet(prefix.clone());
+        let facet_counts = searcher
+            .search(query, &collector)
+            .map_err(|e| format!("Error with counting facets: {} ", e))?;
+        for (facet, count) in facet_counts.top_k(prefix, MAX_FACET_VALUES) {
+            let value = match facet.to_path().last() {
+                Some(value) => value.to_string(),
+                None => continue,
+            };
+            let mut propvals = PropVals::new();
+            propvals.insert(
+                urls::IS_A.into(),
+                vec![urls::FACET_COUNT.to_string()].into(),
+            );
+            propvals.insert(
+                urls::SEARCH_PROPERTY.into(),
+                Value::AtomicUrl(property.into()),
+            );
+            propvals.insert(urls::SEARCH_FACET_VALUE.into(), Value::AtomicUrl(value));
+            propvals.insert(urls::SEARCH_COUNT.into(), Value::Integer(count as i64));
+            counts.push(SubResource::Nested(propvals));
+        }
+    }
+    Ok((counts, approximate))
+}
+
+/// Restricts the `query` to the documents of the resources that `for_agent` can read.
+/// Only the [MAX_FACET_CHECKS] best matching documents are checked, the returned boolean is true if more documents match.
+/// Returns `None` if it can read everything: in public mode (without an Agent), or for the server's Agent.
+#[tracing::instrument(skip(searcher, query, fields, store))]
+fn readable_query(
+    searcher: &tantivy::Searcher,
+    query: &dyn Query,
+    fields: &Fields,
+    store: &Db,
+    for_agent: Option<&str>,
+) -> AtomicServerResult<Option<(BooleanQuery, bool)>> {
+    let agent = match for_agent {
+        Some(agent) => agent,
+        None => return Ok(None),
+    };
+    if store.get_default_agent()?.subject == agent {
+        return Ok(None);
+    }
+    let (total, docs) = searcher
+        .search(query, &(Count, TopDocs::with_limit(MAX_FACET_CHECKS)))
+        .map_err(|e| format!("Error with counting facets: {} ", e))?;
+    let mut checked = HashSet::new();
+    let mut readable = Vec::new();
+    for (_score, doc_address) in docs {
+        let doc = searcher.doc(doc_address)?;
+        let subject = match doc.get_first(fields.subject) {
+            Some(value) => unpack_value(value, &doc, "Subject".to_string())?,
+            None => continue,
+        };
+        if !checked.insert(subject.clone()) {
+            continue;
+        }
+        let can_read = store
+            .get_resource(&subject)
+            .and_then(|resource| atomic_lib::hierarchy::check_read(store, &resource, agent))
+            .is_ok();
+        if can_read {
+            readable.push(Term::from_field_text(fields.subject, &subject));
+        }
+    }
+    let readable_query = BooleanQuery::new(vec![
+        (Occur::Must, query.box_clone()),
+        (Occur::Must, Box::new(TermSetQuery::new(readable))),
+    ]);
+    Ok(Some((readable_query, total > MAX_FACET_CHECKS)))
+}
+
 #[tracing::instrument(skip(store))]
 fn build_parent_query(subject: &str, fields: &Fields, store: &Db) -> AtomicServerResult<TermQuery> {
     let resource = store.get_resource(subject)?;
@@ -256,24 +802,25 @@ fn unpack_value(
     }
 }
 
+/// Returns the subjects of the documents with their scores, in the same order and without duplicates.
 #[tracing::instrument(skip(searcher, docs))]
-fn docs_to_subjects(
+fn docs_to_scores(
     docs: Vec<(f32, tantivy::DocAddress)>,
     fields: &Fields,
     searcher: &tantivy::Searcher,
-) -> Result<Vec<String>, AtomicServerError> {
-    let mut subjects: Vec<String> = Vec::new();
+) -> Result<Vec<(String, f32)>, AtomicServerError> {
+    let mut scores: Vec<(String, f32)> = Vec::new();
 
     // convert found documents to resources
-    for (_score, doc_address) in docs {
+    for (score, doc_address) in docs {
         let retrieved_doc = searcher.doc(doc_address)?;
         let subject_val = retrieved_doc.get_first(fields.subject).ok_or("No 'subject' in search doc found. This is required when indexing. Run with --rebuild-index")?;
 
         let subject = unpack_value(subject_val, &retrieved_doc, "Subject".to_string())?;
-        if !subjects.contains(&subject) {
-            subjects.push(subject.clone());
+        if !scores.iter().any(|(s, _score)| s == &subject) {
+            scores.push((subject, score));
         }
     }
 
-    Ok(subjects.into_iter().collect())
+    Ok(scores)
 }
@@ -0,0 +1,82 @@
+//! Finds resources that are similar to a resource, using the embedding vectors of the [crate::similarity::SimilarityIndex].
+//!
+//! `GET /similar?subject={subject}&limit={limit}` responds with the most similar resources that the Agent can read, and a `hit` with the score of every result.
+
+use crate::{
+    appstate::AppState,
+    errors::AtomicServerResult,
+    handlers::search::{get_resources, hit_propvals, SIMILARITY_DISABLED},
+};
+use actix_web::{web, HttpResponse};
+use atomic_lib::{urls, values::SubResource, Storelike, Value};
+use serde::Deserialize;
+
+const DEFAULT_RETURN_LIMIT: usize = 10;
+// Like in search, we fetch extra results, as the Agent may not be able to read the first ones.
+const UNAUTHORIZED_RESULTS_FACTOR: usize = 3;
+
+#[derive(Deserialize, Debug)]
+pub struct SimilarQuery {
+    /// The resource to find similar resources for
+    pub subject: String,
+    /// Maximum amount of results
+    pub limit: Option<usize>,
+}
+
+#[tracing::instrument(skip(appstate, req))]
+pub async fn similar_query(
+    appstate: web::Data<AppState>,
+    params: web::Query<SimilarQuery>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let similarity = appstate
+        .search_state
+        .similarity
+        .as_ref()
+        .ok_or(SIMILARITY_DISABLED)?;
+    let limit = params
+        .limit
+        .filter(|limit| *limit > 0)
+        .unwrap_or(DEFAULT_RETURN_LIMIT);
+    let subject = format!(
+        "{}{}",
+        store.get_self_url().ok_or("No base URL set")?,
+        req.uri().path_and_query().ok_or("Add a query param")?
+    );
+
+    // The results are based on the contents of the resource, so the Agent has to be able to read it
+    let for_agent = crate::helpers::get_client_agent(req.headers(), &appstate, subject.clone())?;
+    let result = store.get_resource_extended(&params.subject, true, for_agent.as_deref());
+    atomic_lib::audit::record_read(store, for_agent.as_deref(), &params.subject, &result);
+    let resource = result?;
+    let vector = match similarity.get(&params.subject)? {
+        Some(vector) => vector,
+        None => similarity.embed(&crate::similarity::resource_text(&resource)),
+    };
+    let scores: Vec<(String, f32)> = similarity
+        .nearest(&vector, limit * UNAUTHORIZED_RESULTS_FACTOR + 1)?
+        .into_iter()
+        .filter(|(found, _score)| found != &params.subject)
+        .collect();
+    let subjects = scores
+        .iter()
+        .map(|(subject, _score)| subject.clone())
+        .collect();
+    let resources = get_resources(req, &appstate, &subject, subjects, limit)?;
+    let hits: Vec<SubResource> = resources
+        .iter()
+        .filter_map(|r| {
+            let (_subject, score) = scores.iter().find(|(s, _score)| s == r.get_subject())?;
+            Some(SubResource::Nested(hit_propvals(r.get_subject(), *score)))
+        })
+        .collect();
+
+    let mut results_resource =
+        atomic_lib::plugins::search::similar_endpoint().to_resource(store)?;
+    results_resource.set_subject(subject);
+    results_resource.set_propval(urls::ENDPOINT_RESULTS.into(), resources.into(), store)?;
+    results_resource.set_propval_unsafe(urls::SEARCH_HITS.into(), Value::ResourceArray(hits));
+
+    Ok(HttpResponse::Ok().body(results_resource.to_json_ad()?))
+}
@@ -0,0 +1,73 @@
+//! Issues [atomic_lib::tokens::Token]s, which can be used in an `Authorization: Bearer` header instead of signing every request.
+//!
+//! `POST /tokens?expires_in={seconds}&scope={subject}&read_only=true` creates a Token for the Agent that signed the request.
+//! The response contains the secret, which is not stored and can not be retrieved later.
+//! Revoke a Token by destroying it using a Commit.
+//! Use [atomic_lib::client::create_token] to create one.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use atomic_lib::{parse::JSON_AD_MIME, urls, utils::now, AtomicError, Storelike, Value};
+use serde::Deserialize;
+
+use crate::{
+    appstate::AppState,
+    errors::{AtomicServerError, AtomicServerResult},
+    helpers::get_client_agent,
+};
+
+/// Tokens expire after 30 days, unless `expires_in` is set.
+const DEFAULT_EXPIRES_IN: i64 = 60 * 60 * 24 * 30;
+
+#[derive(Deserialize, Debug)]
+pub struct CreateTokenQuery {
+    /// Seconds until the Token expires
+    expires_in: Option<i64>,
+    /// The Token can only be used for this resource and its children
+    scope: Option<String>,
+    read_only: Option<bool>,
+}
+
+/// Creates a Token for the Agent that signed the request. Tokens can not be used to create other Tokens.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn create_token(
+    appstate: web::Data<AppState>,
+    query: web::Query<CreateTokenQuery>,
+    req: HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let subject = format!(
+        "{}{}",
+        store.get_server_url(),
+        req.head()
+            .uri
+            .path_and_query()
+            .ok_or("Path must be given")?
+    );
+    let agent = get_client_agent(req.headers(), &appstate, subject)?.ok_or_else(|| {
+        AtomicError::unauthorized("Tokens can not be created in public mode".into())
+    })?;
+    let expires_in = query.expires_in.unwrap_or(DEFAULT_EXPIRES_IN);
+    if expires_in <= 0 {
+        return Err(AtomicServerError::bad_request(
+            "expires_in must be a positive amount of seconds".into(),
+        ));
+    }
+    let expires_at = expires_in
+        .checked_mul(1000)
+        .and_then(|millis| millis.checked_add(now()))
+        .ok_or_else(|| {
+            AtomicServerError::bad_request(format!("expires_in {} is too large", expires_in))
+        })?;
+    let (mut token, secret) = atomic_lib::tokens::create_token(
+        store,
+        &agent,
+        expires_at,
+        query.scope.as_deref(),
+        query.read_only.unwrap_or(false),
+    )?;
+    // Only added to the response, the Token stores a hash of the secret
+    token.set_propval_unsafe(urls::TOKEN_SECRET.into(), Value::String(secret));
+    Ok(HttpResponse::Ok()
+        .content_type(JSON_AD_MIME)
+        .body(token.to_json_ad()?))
+}
@@ -1,15 +1,24 @@
-use std::{ffi::OsStr, io::Write, path::Path};
+use std::{ffi::OsStr, path::Path};
 
 use actix_multipart::Multipart;
 use actix_web::{web, HttpResponse};
 use atomic_lib::{
-    commit::CommitResponse, hierarchy::check_write, urls, utils::now, AtomicError, Resource,
-    Storelike, Value,
+    hierarchy::{check_audited, Right},
+    quotas::DriveUsage,
+    urls,
+    utils::now,
+    AtomicError, Resource, Storelike, Value,
 };
 use futures::{StreamExt, TryStreamExt};
 use serde::Deserialize;
 
-use crate::{appstate::AppState, errors::AtomicServerResult, helpers::get_client_agent};
+use crate::{
+    appstate::AppState,
+    errors::AtomicServerResult,
+    helpers::get_client_agent,
+    images,
+    uploads::{BlobWriter, StoredBlob},
+};
 
 #[derive(Deserialize, Debug)]
 pub struct UploadQuery {
@@ -20,7 +29,9 @@ pub struct UploadQuery {
 /// A parent Query parameter is required for checking rights and for placing the file in a Hierarchy.
 /// Creates new File resources for every submitted file.
 /// Submission is done using multipart/form-data.
-/// The file is stored in the `/uploads` directory.
+/// The file is stored in the [crate::blob_store::BlobStore], named by its SHA-256 hash. Identical files share a single blob, see [crate::uploads].
+/// Images get an `imageWidth`, `imageHeight` and `dateTaken`, and resized variants, see [crate::images].
+/// The files count towards the storage quota of the Drive of the parent, see [atomic_lib::quotas].
 /// An `attachment` relationship is created from the parent
 #[tracing::instrument(skip(appstate, req, body))]
 pub async fn upload_handler(
@@ -40,7 +51,7 @@ pub async fn upload_handler(
             .ok_or("Path must be given")?
     );
     if let Some(agent) = get_client_agent(req.headers(), &appstate, subject)? {
-        check_write(store, &parent, &agent)?;
+        check_audited(store, &parent, &agent, Right::Write)?;
     } else {
         return Err(AtomicError::unauthorized(
             "No authorization headers present. These are required when uploading files.".into(),
@@ -48,72 +59,44 @@ pub async fn upload_handler(
         .into());
     }
 
+    // Refuse uploads to full Drives before receiving the files
+    let one_file = DriveUsage {
+        resources: 1,
+        upload_bytes: 0,
+    };
+    store.check_drive_quota_for(&query.parent, &one_file)?;
+
     let mut created_resources: Vec<Resource> = Vec::new();
-    let mut commit_responses: Vec<CommitResponse> = Vec::new();
 
     while let Ok(Some(mut field)) = body.try_next().await {
         let content_type = field.content_disposition().clone();
-        let filename = content_type.get_filename().ok_or("Filename is missing")?;
-
-        std::fs::create_dir_all(&appstate.config.uploads_path)?;
-
-        let file_id = format!(
-            "{}-{}",
-            now(),
-            sanitize_filename::sanitize(filename)
-                // Spacebars lead to very annoying bugs in browsers
-                .replace(' ', "-")
-        );
-
-        let mut file_path = appstate.config.uploads_path.clone();
-        file_path.push(&file_id);
-        let mut file = std::fs::File::create(file_path)?;
+        let filename = content_type
+            .get_filename()
+            .ok_or("Filename is missing")?
+            .to_string();
 
+        // Hash the file while it streams in, so it can be stored by its content
+        let mut writer = BlobWriter::new(&appstate.config.uploads_path)?;
         // Field in turn is stream of *Bytes* object
         while let Some(chunk) = field.next().await {
             let data = chunk.map_err(|e| format!("Error while reading multipart data. {}", e))?;
-            // TODO: Update a SHA256 hash here for checksum
-            file.write_all(&data)?;
+            writer.write(&data)?;
         }
-
-        let byte_count: i64 = file
-            .metadata()?
-            .len()
-            .try_into()
-            .map_err(|_e| "Too large")?;
-
-        let subject_path = format!("files/{}", urlencoding::encode(&file_id));
-        let new_subject = format!("{}/{}", store.get_server_url(), subject_path);
-        let download_url = format!("{}/download/{}", store.get_server_url(), subject_path);
-
-        let mut resource = atomic_lib::Resource::new_instance(urls::FILE, store)?;
-        resource.set_subject(new_subject);
-        resource.set_propval_string(urls::PARENT.into(), &query.parent, store)?;
-        resource.set_propval_string(urls::INTERNAL_ID.into(), &file_id, store)?;
-        resource.set_propval(urls::FILESIZE.into(), Value::Integer(byte_count), store)?;
-        resource.set_propval_string(
-            urls::MIMETYPE.into(),
-            &guess_mime_for_filename(filename),
-            store,
+        let image_info = images::read_image_info(writer.path());
+        let blob = writer.finish(appstate.blob_store.as_ref())?;
+        // Blobs that are not used by a File are removed by the garbage collection of uploads
+        store.check_drive_quota_for(
+            &query.parent,
+            &DriveUsage {
+                upload_bytes: blob.size,
+                ..one_file
+            },
         )?;
-        resource.set_propval_string(urls::FILENAME.into(), filename, store)?;
-        resource.set_propval_string(urls::DOWNLOAD_URL.into(), &download_url, store)?;
-        commit_responses.push(resource.save(store)?);
-        created_resources.push(resource);
+        created_resources
+            .push(save_file(&appstate, &query.parent, &filename, &blob, image_info).await?);
     }
 
-    let created_file_subjects = created_resources
-        .iter()
-        .map(|r| r.get_subject().to_string())
-        .collect::<Vec<String>>();
-
-    // Add the files as `attachments` to the parent
-    let mut parent = store.get_resource(&query.parent)?;
-    // parent.append_subjects(urls::ATTACHMENTS, created_file_subjects, false, store)?;
-    for created in created_file_subjects {
-        parent.push_propval(urls::ATTACHMENTS, created.into(), false)?;
-    }
-    commit_responses.push(parent.save(store)?);
+    add_attachments(store, &query.parent, &created_resources)?;
 
     let mut builder = HttpResponse::Ok();
 
@@ -122,6 +105,84 @@ pub async fn upload_handler(
     )?))
 }
 
+/// Creates a File resource for a blob in the [crate::blob_store::BlobStore], and saves it using a Commit.
+/// Creates resized variants of images, see [crate::images].
+pub async fn save_file(
+    appstate: &AppState,
+    parent: &str,
+    filename: &str,
+    blob: &StoredBlob,
+    image_info: Option<images::ImageInfo>,
+) -> AtomicServerResult<Resource> {
+    let store = &appstate.store;
+    if image_info.is_some() {
+        // Resizing is slow, so it should not block the async executor
+        let blobs = appstate.blob_store.clone();
+        let blob_id = blob.id.clone();
+        let temp_dir = appstate.config.uploads_path.clone();
+        let created = web::block(move || {
+            images::create_variants(
+                blobs.as_ref(),
+                &blob_id,
+                &images::UPLOAD_VARIANT_WIDTHS,
+                &temp_dir,
+            )
+        })
+        .await;
+        match created {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::warn!("Could not create thumbnails of {}: {}", filename, e),
+            Err(e) => tracing::warn!("Could not create thumbnails of {}: {}", filename, e),
+        }
+    }
+
+    let file_id = format!(
+        "{}-{}",
+        now(),
+        sanitize_filename::sanitize(filename)
+            // Spacebars lead to very annoying bugs in browsers
+            .replace(' ', "-")
+    );
+    let byte_count: i64 = blob.size.try_into().map_err(|_e| "Too large")?;
+
+    let subject_path = format!("files/{}", urlencoding::encode(&file_id));
+    let new_subject = format!("{}/{}", store.get_server_url(), subject_path);
+    let download_url = format!("{}/download/{}", store.get_server_url(), subject_path);
+
+    let mut resource = atomic_lib::Resource::new_instance(urls::FILE, store)?;
+    resource.set_subject(new_subject);
+    resource.set_propval_string(urls::PARENT.into(), parent, store)?;
+    resource.set_propval_string(urls::INTERNAL_ID.into(), &blob.id, store)?;
+    resource.set_propval_string(urls::CHECKSUM.into(), &blob.checksum, store)?;
+    resource.set_propval(urls::FILESIZE.into(), Value::Integer(byte_count), store)?;
+    resource.set_propval_string(
+        urls::MIMETYPE.into(),
+        &guess_mime_for_filename(filename),
+        store,
+    )?;
+    resource.set_propval_string(urls::FILENAME.into(), filename, store)?;
+    resource.set_propval_string(urls::DOWNLOAD_URL.into(), &download_url, store)?;
+    if let Some(image_info) = image_info {
+        image_info.set_propvals(&mut resource);
+    }
+    resource.save(store)?;
+    Ok(resource)
+}
+
+/// Adds the files as `attachments` to the parent
+pub fn add_attachments(
+    store: &impl Storelike,
+    parent: &str,
+    files: &[Resource],
+) -> AtomicServerResult<()> {
+    let mut parent = store.get_resource(parent)?;
+    for file in files {
+        parent.push_propval(urls::ATTACHMENTS, file.get_subject().clone().into(), false)?;
+    }
+    parent.save(store)?;
+    Ok(())
+}
+
 fn guess_mime_for_filename(filename: &str) -> String {
     if let Some(ext) = get_extension_from_filename(filename) {
         actix_files::file_extension_to_mime(ext).to_string()
@@ -0,0 +1,295 @@
+//! Resumable uploads for large files. The file is sent in numbered chunks to an UploadSession, which becomes a File when it is finished.
+//!
+//! 1. `POST /upload-sessions?parent={parent}&filename={name}&filesize={bytes}` creates the UploadSession.
+//! 2. `PUT {session}?chunk={number}&offset={bytes}` appends a chunk. The `offset` and `chunks` of the session show the progress, and where to continue after a failure.
+//! 3. `POST {session}` creates the File, once all bytes have been received.
+//!
+//! The received data is stored on the server that created the session.
+//! Unfinished sessions are removed after `--upload-session-timeout`, see [crate::uploads::remove_expired_sessions].
+//! Use [atomic_lib::client::upload_file] to upload a file this way.
+
+use std::io::{Seek, SeekFrom, Write};
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use atomic_lib::{
+    hierarchy::{check_audited, Right},
+    parse::JSON_AD_MIME,
+    quotas::DriveUsage,
+    urls,
+    utils::now,
+    AtomicError, Resource, Storelike, Value,
+};
+use futures::StreamExt;
+use serde::Deserialize;
+
+use crate::{
+    appstate::AppState,
+    errors::AtomicServerResult,
+    handlers::upload::{add_attachments, save_file},
+    helpers::get_client_agent,
+    images,
+    uploads::{session_path, store_file, SessionLock},
+};
+
+#[derive(Deserialize, Debug)]
+pub struct CreateSessionQuery {
+    parent: String,
+    filename: String,
+    /// Size of the complete file in bytes
+    filesize: i64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ChunkQuery {
+    /// Number of the chunk, starting at 0
+    chunk: i64,
+    /// Position of the first byte of the chunk in the file
+    offset: i64,
+}
+
+/// Creates an UploadSession. The agent needs write rights for the `parent` of the File.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn create_upload_session(
+    appstate: web::Data<AppState>,
+    query: web::Query<CreateSessionQuery>,
+    req: HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let agent = get_agent(&appstate, &req)?;
+    check_audited(
+        store,
+        &store.get_resource(&query.parent)?,
+        &agent,
+        Right::Write,
+    )?;
+    if query.filesize < 0 {
+        return Err("The filesize can not be negative".into());
+    }
+    store.check_drive_quota_for(&query.parent, &file_usage(query.filesize))?;
+
+    let subject = format!(
+        "{}/upload-sessions/{}",
+        store.get_server_url(),
+        atomic_lib::utils::random_string(16)
+    );
+    let mut session = Resource::new_instance(urls::UPLOAD_SESSION, store)?;
+    session.set_subject(subject);
+    session.set_propval_string(urls::PARENT.into(), &query.parent, store)?;
+    session.set_propval_string(urls::FILENAME.into(), &query.filename, store)?;
+    session.set_propval(urls::FILESIZE.into(), Value::Integer(query.filesize), store)?;
+    session.set_propval(urls::UPLOAD_OFFSET.into(), Value::Integer(0), store)?;
+    session.set_propval(urls::UPLOAD_CHUNKS.into(), Value::Integer(0), store)?;
+    session.set_propval(
+        urls::UPLOAD_EXPIRES_AT.into(),
+        Value::Timestamp(expires_at(&appstate)),
+        store,
+    )?;
+    std::fs::create_dir_all(&appstate.config.uploads_path)?;
+    std::fs::File::create(session_path(
+        &appstate.config.uploads_path,
+        session.get_subject(),
+    ))?;
+    // The progress changes with every chunk, so sessions are saved without Commits
+    session.save_locally(store)?;
+    json_ad_response(&session)
+}
+
+/// Appends a chunk to an UploadSession. Chunks that have already been received are ignored, so failed requests can be retried.
+/// Returns the UploadSession, which shows the progress.
+#[tracing::instrument(skip(appstate, req, payload))]
+pub async fn put_chunk(
+    path: web::Path<String>,
+    appstate: web::Data<AppState>,
+    query: web::Query<ChunkQuery>,
+    req: HttpRequest,
+    mut payload: web::Payload,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let subject = session_subject(&appstate, &path);
+    let agent = get_agent(&appstate, &req)?;
+    let _lock = SessionLock::acquire(&subject)?;
+    let mut session = get_session(&appstate, &subject, &agent)?;
+    if session.get(urls::UPLOAD_FILE).is_ok() {
+        return Err(AtomicError::conflict(
+            "This UploadSession is already finished".into(),
+            Vec::new(),
+        )
+        .into());
+    }
+    let offset = session.get(urls::UPLOAD_OFFSET)?.to_int()?;
+    let chunks = session.get(urls::UPLOAD_CHUNKS)?.to_int()?;
+    let filesize = session.get(urls::FILESIZE)?.to_int()?;
+    if query.chunk < chunks {
+        return json_ad_response(&session);
+    }
+    if query.chunk != chunks || query.offset != offset {
+        return Err(AtomicError::conflict(
+            format!(
+                "Expected chunk {} at offset {}, got chunk {} at offset {}",
+                chunks, offset, query.chunk, query.offset
+            ),
+            vec![urls::UPLOAD_OFFSET.into(), urls::UPLOAD_CHUNKS.into()],
+        )
+        .into());
+    }
+
+    let mut file = std::fs::File::options()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(session_path(&appstate.config.uploads_path, &subject))?;
+    // Removes the data of earlier chunks that were interrupted
+    file.set_len(offset as u64)?;
+    file.seek(SeekFrom::End(0))?;
+    let mut received: i64 = 0;
+    let mut result: AtomicServerResult<()> = Ok(());
+    while let Some(bytes) = payload.next().await {
+        let bytes = match bytes {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                result = Err(format!("Error while receiving chunk. {}", e).into());
+                break;
+            }
+        };
+        received += bytes.len() as i64;
+        if offset + received > filesize {
+            result = Err(format!("The chunk exceeds the filesize of {} bytes", filesize).into());
+            break;
+        }
+        if let Err(e) = file.write_all(&bytes) {
+            result = Err(e.into());
+            break;
+        }
+    }
+    if result.is_ok() && received == 0 && filesize > 0 {
+        result = Err("The chunk is empty".into());
+    }
+    if let Err(e) = result.and_then(|_| Ok(file.sync_data()?)) {
+        file.set_len(offset as u64)?;
+        return Err(e);
+    }
+
+    session.set_propval(
+        urls::UPLOAD_OFFSET.into(),
+        Value::Integer(offset + received),
+        store,
+    )?;
+    session.set_propval(
+        urls::UPLOAD_CHUNKS.into(),
+        Value::Integer(chunks + 1),
+        store,
+    )?;
+    session.set_propval(
+        urls::UPLOAD_EXPIRES_AT.into(),
+        Value::Timestamp(expires_at(&appstate)),
+        store,
+    )?;
+    session.save_locally(store)?;
+    json_ad_response(&session)
+}
+
+/// Turns a complete UploadSession into a File, and returns the File.
+/// Finishing a session again returns the same File.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn finish_upload_session(
+    path: web::Path<String>,
+    appstate: web::Data<AppState>,
+    req: HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let subject = session_subject(&appstate, &path);
+    let agent = get_agent(&appstate, &req)?;
+    let _lock = SessionLock::acquire(&subject)?;
+    let mut session = get_session(&appstate, &subject, &agent)?;
+    if let Ok(file) = session.get(urls::UPLOAD_FILE) {
+        return json_ad_response(&store.get_resource(&file.to_string())?);
+    }
+    let offset = session.get(urls::UPLOAD_OFFSET)?.to_int()?;
+    let filesize = session.get(urls::FILESIZE)?.to_int()?;
+    if offset != filesize {
+        return Err(AtomicError::conflict(
+            format!("Received {} of {} bytes", offset, filesize),
+            vec![urls::UPLOAD_OFFSET.into()],
+        )
+        .into());
+    }
+    let parent = session.get(urls::PARENT)?.to_string();
+    let filename = session.get(urls::FILENAME)?.to_string();
+    // Other files may have been added to the Drive since the session was created
+    store.check_drive_quota_for(&parent, &file_usage(filesize))?;
+
+    let temp_path = session_path(&appstate.config.uploads_path, &subject);
+    let image_info = images::read_image_info(&temp_path);
+    // Hashing large files takes a while, so it should not block the async executor
+    let blobs = appstate.blob_store.clone();
+    let blob = web::block(move || store_file(&temp_path, blobs.as_ref()))
+        .await
+        .map_err(|e| format!("Could not store upload. {}", e))??;
+    let file = save_file(&appstate, &parent, &filename, &blob, image_info).await?;
+    add_attachments(store, &parent, std::slice::from_ref(&file))?;
+
+    session.set_propval(
+        urls::UPLOAD_FILE.into(),
+        Value::AtomicUrl(file.get_subject().into()),
+        store,
+    )?;
+    session.save_locally(store)?;
+    json_ad_response(&file)
+}
+
+fn session_subject(appstate: &AppState, id: &str) -> String {
+    format!("{}/upload-sessions/{}", appstate.store.get_server_url(), id)
+}
+
+fn expires_at(appstate: &AppState) -> i64 {
+    now() + appstate.config.opts.upload_session_timeout as i64 * 1000
+}
+
+/// Returns the Agent that signed the request. Uploading requires authentication.
+fn get_agent(appstate: &AppState, req: &HttpRequest) -> AtomicServerResult<String> {
+    let subject = format!(
+        "{}{}",
+        appstate.store.get_server_url(),
+        req.head()
+            .uri
+            .path_and_query()
+            .ok_or("Path must be given")?
+    );
+    get_client_agent(req.headers(), appstate, subject)?.ok_or_else(|| {
+        AtomicError::unauthorized(
+            "No authorization headers present. These are required when uploading files.".into(),
+        )
+        .into()
+    })
+}
+
+/// How much a File of this size adds to the storage quota of its Drive, see [atomic_lib::quotas].
+fn file_usage(filesize: i64) -> DriveUsage {
+    DriveUsage {
+        resources: 1,
+        upload_bytes: filesize.max(0) as u64,
+    }
+}
+
+/// Returns the UploadSession, if the agent has write rights for the parent of its File.
+fn get_session(appstate: &AppState, subject: &str, agent: &str) -> AtomicServerResult<Resource> {
+    let store = &appstate.store;
+    let session = store.get_resource(subject)?;
+    let is_session = session
+        .get(urls::IS_A)
+        .and_then(|classes| classes.to_subjects(None))
+        .map(|classes| classes.iter().any(|c| c == urls::UPLOAD_SESSION))
+        .unwrap_or(false);
+    if !is_session {
+        return Err(AtomicError::not_found(format!("{} is not an UploadSession", subject)).into());
+    }
+    let parent = store.get_resource(&session.get(urls::PARENT)?.to_string())?;
+    check_audited(store, &parent, agent, Right::Write)?;
+    Ok(session)
+}
+
+fn json_ad_response(resource: &Resource) -> AtomicServerResult<HttpResponse> {
+    Ok(HttpResponse::Ok()
+        .content_type(JSON_AD_MIME)
+        .body(resource.to_json_ad()?))
+}
@@ -131,10 +131,16 @@ fn handle_ws_message(
                 s if s.starts_with("GET ") => {
                     let mut parts = s.split("GET ");
                     if let Some(subject) = parts.nth(1) {
-                        match conn
-                            .store
-                            .get_resource_extended(subject, false, Some(&conn.agent))
-                        {
+                        let result =
+                            conn.store
+                                .get_resource_extended(subject, false, Some(&conn.agent));
+                        atomic_lib::audit::record_read(
+                            &conn.store,
+                            Some(&conn.agent),
+                            subject,
+                            &result,
+                        );
+                        match result {
                             Ok(r) => {
                                 let serialized =
                                     r.to_json_ad().expect("Can't serialize Resource to JSON-AD");
@@ -243,11 +249,17 @@ impl Handler<CommitMessage> for WebSocketConnection {
     type Result = ();
 
     fn handle(&mut self, msg: CommitMessage, ctx: &mut ws::WebsocketContext<Self>) {
-        let resource = msg.commit_response.commit_resource;
+        let mut resource = msg.commit_response.commit_resource;
         tracing::debug!(
             "handle commit in web socket connection for resource {}",
             resource.get_subject()
         );
+        if let Err(e) =
+            atomic_lib::hierarchy::remove_hidden_properties(&self.store, &mut resource, &self.agent)
+        {
+            tracing::error!("Could not remove hidden properties from Commit: {}", e);
+            return;
+        }
         let formatted_commit = format!("COMMIT {}", resource.to_json_ad().unwrap());
         ctx.text(formatted_commit);
     }
@@ -3,7 +3,7 @@
 use actix_web::cookie::Cookie;
 use actix_web::http::header::{HeaderMap, HeaderValue};
 use actix_web::http::Uri;
-use atomic_lib::authentication::AuthValues;
+use atomic_lib::authentication::{AuthValues, Credentials};
 use atomic_lib::AtomicError;
 use percent_encoding::percent_decode_str;
 use std::str::FromStr;
@@ -121,19 +121,35 @@ pub fn get_auth_from_cookie(
     Err(err)
 }
 
+/// Returns the secret from an `Authorization: Bearer` header, see [atomic_lib::tokens].
+/// Other schemes are ignored, as they may be used by a reverse proxy (e.g. `Basic`).
+pub fn get_bearer_token(map: &HeaderMap) -> AtomicServerResult<Option<String>> {
+    let header = match map.get("Authorization") {
+        Some(header) => header
+            .to_str()
+            .map_err(|_e| "Only string headers allowed")?,
+        None => return Ok(None),
+    };
+    match header.split_once(' ') {
+        Some((scheme, token)) if scheme.eq_ignore_ascii_case("Bearer") => {
+            Ok(Some(token.trim().to_string()))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Returns the credentials from the signed headers, the bearer token or the session cookie, in that order.
 pub fn get_auth(
     map: &HeaderMap,
     requested_subject: String,
-) -> AtomicServerResult<Option<AuthValues>> {
-    let from_header = match get_auth_headers(map, requested_subject.clone()) {
-        Ok(res) => res,
-        Err(err) => return Err(err),
-    };
-
-    match from_header {
-        Some(v) => Ok(Some(v)),
-        None => get_auth_from_cookie(map, &requested_subject),
+) -> AtomicServerResult<Option<Credentials>> {
+    if let Some(v) = get_auth_headers(map, requested_subject.clone())? {
+        return Ok(Some(Credentials::Signature(v)));
     }
+    if let Some(token) = get_bearer_token(map)? {
+        return Ok(Some(Credentials::Bearer(token)));
+    }
+    Ok(get_auth_from_cookie(map, &requested_subject)?.map(Credentials::Signature))
 }
 
 /// Checks for authentication headers and returns Some agent's subject if everything is well.
@@ -148,12 +164,12 @@ pub fn get_client_agent(
         return Ok(None);
     }
     // Authentication check. If the user has no headers, continue with the Public Agent.
-    let auth_header_values = get_auth(headers, requested_subject)?;
-    let for_agent = atomic_lib::authentication::get_agent_from_auth_values_and_check(
-        auth_header_values,
-        &appstate.store,
-    )
-    .map_err(|e| format!("Authentication failed: {}", e))?;
+    let credentials = get_auth(headers, requested_subject)?;
+    let for_agent =
+        atomic_lib::authentication::get_agent_from_credentials(credentials, &appstate.store)
+            .map_err(|e| {
+                AtomicError::unauthorized(format!("Authentication failed: {}", e.message))
+            })?;
     Ok(Some(for_agent))
 }
 
@@ -203,6 +219,26 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn bearer_token() {
+        let mut headermap = HeaderMap::new();
+        assert_eq!(get_bearer_token(&headermap).unwrap(), None);
+        headermap.insert(
+            "Authorization".try_into().unwrap(),
+            HeaderValue::from_static("Bearer secret"),
+        );
+        assert_eq!(
+            get_bearer_token(&headermap).unwrap(),
+            Some("secret".to_string())
+        );
+        // Other schemes may be used by a reverse proxy, and should not be refused
+        headermap.insert(
+            "Authorization".try_into().unwrap(),
+            HeaderValue::from_static("Basic dXNlcjpwYXNz"),
+        );
+        assert_eq!(get_bearer_token(&headermap).unwrap(), None);
+    }
+
     #[test]
     fn parse_cookie() {
         let cookie = "atomic_session=eyJodHRwczovL2F0b21pY2RhdGEuZGV2L3Byb3BlcnRpZXMvYXV0aC9hZ2VudCI6Imh0dHA6Ly9sb2NhbGhvc3Q6OTg4My9hZ2VudHMvaGVua2llcGVuayIsImh0dHBzOi8vYXRvbWljZGF0YS5kZXYvcHJvcGVydGllcy9hdXRoL3JlcXVlc3RlZFN1YmplY3QiOiJodHRwOi8vbG9jYWxob3N0Ojk4ODMiLCJodHRwczovL2F0b21pY2RhdGEuZGV2L3Byb3BlcnRpZXMvYXV0aC9wdWJsaWNLZXkiOiJLM3hsa0UxQmFIVXNnRzlYT0h4MVZaVUQ1TGs3ODJua09UcDVHNFN0SDdBPSIsImh0dHBzOi8vYXRvbWljZGF0YS5kZXYvcHJvcGVydGllcy9hdXRoL3RpbWVzdGFtcCI6MTY3NjI4MTU1NjEyNCwiaHR0cHM6Ly9hdG9taWNkYXRhLmRldi9wcm9wZXJ0aWVzL2F1dGgvc2lnbmF0dXJlIjoiMlprdFFWNTNkMVhNUWp4YklSN1pYRkhCMExGT2hHcVlpVlEyRENWc3BkZHVuL3ZHRkhJN3lqdU5jRitIMmpLa0Y0L0R4amEraHdTeUJlZ2ZvTWlxQ1E9PSJ9";
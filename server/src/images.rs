@@ -0,0 +1,328 @@
+//! Reads the dimensions and EXIF metadata of uploaded images, and creates resized variants (thumbnails).
+//! Variants are stored in the [BlobStore] next to the original image, as `{id}-w{width}`.
+
+use std::{
+    io::{BufRead, BufReader, Cursor, Seek},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use atomic_lib::{urls, Resource};
+use image::{imageops::FilterType, io::Reader, DynamicImage, ImageFormat, ImageOutputFormat};
+
+use crate::{
+    blob_store::{Blob, BlobStore},
+    errors::AtomicServerResult,
+};
+
+/// The widths of variants that can be requested. Other widths are rounded up, so the amount of cached variants stays limited.
+pub const VARIANT_WIDTHS: [u32; 6] = [64, 128, 256, 512, 1024, 2048];
+
+/// Variants that are created while uploading, so they are ready when a grid of images is shown.
+pub const UPLOAD_VARIANT_WIDTHS: [u32; 1] = [256];
+
+/// Makes the names of temporary files unique.
+static VARIANT_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Metadata of an image
+#[derive(Debug)]
+pub struct ImageInfo {
+    /// Width as it is displayed, so after applying the EXIF orientation
+    pub width: u32,
+    pub height: u32,
+    /// The `DateTimeOriginal` in milliseconds since the UNIX epoch.
+    /// EXIF dates without an offset are in the local time of the camera, these are interpreted as UTC.
+    pub taken_at: Option<i64>,
+}
+
+impl ImageInfo {
+    /// Sets the `imageWidth`, `imageHeight` and `dateTaken` of a File resource.
+    pub fn set_propvals(&self, resource: &mut Resource) {
+        resource.set_propval_unsafe(
+            urls::IMAGE_WIDTH.into(),
+            atomic_lib::Value::Integer(self.width.into()),
+        );
+        resource.set_propval_unsafe(
+            urls::IMAGE_HEIGHT.into(),
+            atomic_lib::Value::Integer(self.height.into()),
+        );
+        if let Some(taken_at) = self.taken_at {
+            resource.set_propval_unsafe(
+                urls::DATE_TAKEN.into(),
+                atomic_lib::Value::Timestamp(taken_at),
+            );
+        }
+    }
+}
+
+/// Reads the dimensions and EXIF metadata of an image file, without decoding the whole image.
+/// Returns `None` for files that are not supported images.
+pub fn read_image_info(path: &Path) -> Option<ImageInfo> {
+    let reader = Reader::open(path).ok()?.with_guessed_format().ok()?;
+    reader.format()?;
+    let (width, height) = reader.into_dimensions().ok()?;
+    let exif = std::fs::File::open(path)
+        .ok()
+        .and_then(|file| read_exif(&mut BufReader::new(file)));
+    let orientation = exif.as_ref().map(orientation).unwrap_or(1);
+    let (width, height) = if orientation >= 5 {
+        (height, width)
+    } else {
+        (width, height)
+    };
+    Some(ImageInfo {
+        width,
+        height,
+        taken_at: exif.as_ref().and_then(taken_at),
+    })
+}
+
+fn read_exif<R: BufRead + Seek>(reader: &mut R) -> Option<exif::Exif> {
+    exif::Reader::new().read_from_container(reader).ok()
+}
+
+/// The EXIF orientation, from 1 (normal) to 8. 5 to 8 are rotated by 90 degrees.
+fn orientation(exif: &exif::Exif) -> u32 {
+    exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+        .and_then(|field| field.value.get_uint(0))
+        .filter(|o| (1..=8).contains(o))
+        .unwrap_or(1)
+}
+
+fn taken_at(exif: &exif::Exif) -> Option<i64> {
+    let ascii = |tag| match exif.get_field(tag, exif::In::PRIMARY) {
+        Some(exif::Field {
+            value: exif::Value::Ascii(values),
+            ..
+        }) => values.first().cloned(),
+        _ => None,
+    };
+    let mut date = ascii(exif::Tag::DateTimeOriginal)
+        .or_else(|| ascii(exif::Tag::DateTime))
+        .and_then(|data| exif::DateTime::from_ascii(&data).ok())?;
+    if let Some(offset) = ascii(exif::Tag::OffsetTimeOriginal) {
+        let _ = date.parse_offset(&offset);
+    }
+    let naive =
+        chrono::NaiveDate::from_ymd_opt(date.year.into(), date.month.into(), date.day.into())?
+            .and_hms_opt(date.hour.into(), date.minute.into(), date.second.into())?;
+    let offset_millis = i64::from(date.offset.unwrap_or(0)) * 60 * 1000;
+    Some(naive.timestamp_millis() - offset_millis)
+}
+
+/// The ID of a resized variant of a blob.
+pub fn variant_id(id: &str, width: u32) -> String {
+    format!("{}-w{}", id, width)
+}
+
+/// Returns the ID of the original blob, if `id` is a variant.
+/// Only blobs that are named by their SHA-256 hash have variants, older blobs can have names that merely look like a variant.
+pub fn original_id(id: &str) -> Option<&str> {
+    let (original, width) = id.rsplit_once("-w")?;
+    width.parse::<u32>().ok()?;
+    let is_hash = original.len() == 64 && original.chars().all(|c| c.is_ascii_hexdigit());
+    is_hash.then_some(original)
+}
+
+/// Returns the variant width that should be served for a requested width, or `None` if the original image should be served.
+/// Only works for Files that have an `imageWidth`, which is set when supported images are uploaded.
+pub fn select_variant(resource: &Resource, requested: u32) -> Option<u32> {
+    let original_width = resource.get(urls::IMAGE_WIDTH).ok()?.to_int().ok()?;
+    let width = VARIANT_WIDTHS
+        .iter()
+        .find(|w| **w >= requested)
+        .copied()
+        .unwrap_or(VARIANT_WIDTHS[VARIANT_WIDTHS.len() - 1]);
+    if i64::from(width) >= original_width {
+        return None;
+    }
+    Some(width)
+}
+
+/// Variants of JPEG images are JPEGs, other images become PNGs, which keeps their transparency.
+pub fn variant_mime(original_mime: &str) -> &'static str {
+    if original_mime == "image/jpeg" {
+        "image/jpeg"
+    } else {
+        "image/png"
+    }
+}
+
+/// Creates the variants that are missing. Stops at the first width that is larger than the image.
+pub fn create_variants(
+    blobs: &dyn BlobStore,
+    id: &str,
+    widths: &[u32],
+    temp_dir: &Path,
+) -> AtomicServerResult<()> {
+    let mut image: Option<(DynamicImage, ImageFormat)> = None;
+    for width in widths {
+        if blobs.exists(&variant_id(id, *width))? {
+            continue;
+        }
+        if image.is_none() {
+            image = Some(decode(blobs.get(id)?)?);
+        }
+        if let Some((original, format)) = &image {
+            if *width >= original.width() {
+                break;
+            }
+            let path = write_variant(original, *format, *width, temp_dir)?;
+            blobs.put(&variant_id(id, *width), &path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Decodes an image and applies its EXIF orientation.
+fn decode(blob: Blob) -> AtomicServerResult<(DynamicImage, ImageFormat)> {
+    match blob {
+        Blob::File(path) => decode_from(BufReader::new(std::fs::File::open(path)?)),
+        // Decoding needs to seek, so streams are read into memory
+        stream => decode_from(Cursor::new(stream.into_bytes()?)),
+    }
+}
+
+fn decode_from<R: BufRead + Seek>(
+    mut reader: R,
+) -> AtomicServerResult<(DynamicImage, ImageFormat)> {
+    let orientation = read_exif(&mut reader)
+        .as_ref()
+        .map(orientation)
+        .unwrap_or(1);
+    reader.rewind()?;
+    let reader = Reader::new(reader).with_guessed_format()?;
+    let format = reader.format().ok_or("Unsupported image format")?;
+    let image = reader
+        .decode()
+        .map_err(|e| format!("Could not decode image. {}", e))?;
+    let oriented = match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    };
+    Ok((oriented, format))
+}
+
+fn write_variant(
+    image: &DynamicImage,
+    format: ImageFormat,
+    width: u32,
+    temp_dir: &Path,
+) -> AtomicServerResult<PathBuf> {
+    let resized = image.resize(width, u32::MAX, FilterType::Triangle);
+    let output_format = if format == ImageFormat::Jpeg {
+        ImageOutputFormat::Jpeg(85)
+    } else {
+        ImageOutputFormat::Png
+    };
+    let mut bytes = Cursor::new(Vec::new());
+    resized
+        .write_to(&mut bytes, output_format)
+        .map_err(|e| format!("Could not encode image. {}", e))?;
+    std::fs::create_dir_all(temp_dir)?;
+    let path = temp_dir.join(format!(
+        ".variant-{}-{}",
+        atomic_lib::utils::now(),
+        VARIANT_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::write(&path, bytes.into_inner())?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blob_store::FileSystemBlobStore;
+
+    /// A minimal EXIF segment with orientation 6 (rotated 90 degrees) and a `DateTimeOriginal`.
+    fn exif_segment() -> Vec<u8> {
+        let mut tiff: Vec<u8> = b"II*\0".to_vec();
+        tiff.extend(8u32.to_le_bytes());
+        // IFD0 with Orientation and a pointer to the Exif IFD
+        tiff.extend(2u16.to_le_bytes());
+        tiff.extend([0x12, 0x01, 3, 0, 1, 0, 0, 0, 6, 0, 0, 0]);
+        tiff.extend([0x69, 0x87, 4, 0, 1, 0, 0, 0, 38, 0, 0, 0]);
+        tiff.extend(0u32.to_le_bytes());
+        // Exif IFD with DateTimeOriginal
+        tiff.extend(1u16.to_le_bytes());
+        tiff.extend([0x03, 0x90, 2, 0, 20, 0, 0, 0, 56, 0, 0, 0]);
+        tiff.extend(0u32.to_le_bytes());
+        tiff.extend(b"2023:03:01 12:30:00\0");
+        let mut segment = vec![0xFF, 0xE1];
+        segment.extend(((tiff.len() + 8) as u16).to_be_bytes());
+        segment.extend(b"Exif\0\0");
+        segment.extend(tiff);
+        segment
+    }
+
+    fn write_image(path: &Path, format: ImageOutputFormat, exif: bool) {
+        let image = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+            600,
+            400,
+            image::Rgb([200, 100, 50]),
+        ));
+        let mut bytes = Cursor::new(Vec::new());
+        image.write_to(&mut bytes, format).unwrap();
+        let mut bytes = bytes.into_inner();
+        if exif {
+            // Insert the segment right after the JPEG start of image marker
+            bytes.splice(2..2, exif_segment());
+        }
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn reads_info_and_creates_variants() {
+        let dir = std::env::temp_dir().join(format!(
+            "atomic-images-test-{}-{}",
+            std::process::id(),
+            atomic_lib::utils::now()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let blobs = FileSystemBlobStore::new(dir.clone());
+
+        let png = dir.join(".png");
+        write_image(&png, ImageOutputFormat::Png, false);
+        let info = read_image_info(&png).unwrap();
+        assert_eq!((info.width, info.height, info.taken_at), (600, 400, None));
+        blobs.put("png", &png).unwrap();
+        create_variants(&blobs, "png", &[256, 1024], &dir).unwrap();
+        assert!(!blobs.exists("png-w1024").unwrap(), "no upscaling");
+        let variant = Reader::open(dir.join("png-w256"))
+            .unwrap()
+            .with_guessed_format()
+            .unwrap();
+        assert_eq!(variant.format(), Some(ImageFormat::Png));
+        assert_eq!(variant.into_dimensions().unwrap(), (256, 171));
+
+        let jpeg = dir.join(".jpeg");
+        write_image(&jpeg, ImageOutputFormat::Jpeg(90), true);
+        let info = read_image_info(&jpeg).unwrap();
+        assert_eq!((info.width, info.height), (400, 600));
+        assert_eq!(info.taken_at, Some(1677673800000));
+        blobs.put("jpeg", &jpeg).unwrap();
+        create_variants(&blobs, "jpeg", &[256], &dir).unwrap();
+        let variant = Reader::open(dir.join("jpeg-w256"))
+            .unwrap()
+            .with_guessed_format()
+            .unwrap()
+            .decode()
+            .unwrap();
+        assert_eq!((variant.width(), variant.height()), (256, 384));
+
+        assert!(read_image_info(&dir.join("png-w256-missing")).is_none());
+        std::fs::write(dir.join(".text"), b"hello").unwrap();
+        assert!(read_image_info(&dir.join(".text")).is_none());
+        let hash = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        assert_eq!(original_id(&variant_id(hash, 256)), Some(hash));
+        assert_eq!(original_id("1680000000-my-world.jpg"), None);
+        assert_eq!(original_id("1680000000000-scan-w2"), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -5,6 +5,7 @@ See https://github.com/atomicdata-dev/atomic-data-rust/tree/master/src-tauri
 */
 mod actor_messages;
 mod appstate;
+mod blob_store;
 mod commit_monitor;
 pub mod config;
 mod content_types;
@@ -13,13 +14,17 @@ mod handlers;
 mod helpers;
 #[cfg(feature = "https")]
 mod https;
+mod images;
 mod jsonerrors;
 #[cfg(feature = "process-management")]
 mod process;
+mod rate_limit;
 mod routes;
 pub mod serve;
 // #[cfg(feature = "search")]
 mod search;
+mod similarity;
 #[cfg(test)]
 mod tests;
 mod trace;
+mod uploads;
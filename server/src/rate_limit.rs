@@ -0,0 +1,306 @@
+//! Limits the amount of requests that a single Agent and a single IP address can make.
+//! Signed requests count for both their Agent and their IP address, so new Agents can not be used to get around the limit of an IP address.
+//! Every [RouteClass] has its own limit and its own token buckets, so heavy searching does not prevent someone from saving their work.
+//! The limits are set in the [crate::config::Opts], and applied to the routes in [crate::routes::config_routes] using the [RateLimit] middleware.
+//! Going over a limit returns a 429 with an Atomic Error resource.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    web, Error, ResponseError,
+};
+use atomic_lib::{urls, AtomicError, Storelike};
+use futures::future::{ready, LocalBoxFuture, Ready};
+
+use crate::{appstate::AppState, config::Opts, errors::AtomicServerResult};
+
+/// If there are more buckets than this, the ones that have been refilled completely are removed.
+/// If that is not enough, the least recently used ones are removed as well.
+const MAX_BUCKETS: usize = 10_000;
+
+/// Routes that share a rate limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RouteClass {
+    /// Applying Commits, and creating Tokens
+    Commit,
+    Search,
+    Upload,
+    /// Fetching external web pages for Bookmarks, see [urls::PATH_FETCH_BOOKMARK]
+    Bookmark,
+    /// Everything else: reading resources, posting to Endpoints and downloading files
+    Read,
+}
+
+impl std::fmt::Display for RouteClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            RouteClass::Commit => "commit",
+            RouteClass::Search => "search",
+            RouteClass::Upload => "upload",
+            RouteClass::Bookmark => "bookmark",
+            RouteClass::Read => "read",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+struct Bucket {
+    /// The amount of requests that can be made right now
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Keeps a token bucket for every [RouteClass] and client.
+/// A bucket holds as many tokens as the limit per minute, and is refilled gradually.
+pub struct RateLimiter {
+    /// Requests per minute. Classes without a limit are missing.
+    limits: HashMap<RouteClass, u32>,
+    buckets: Mutex<HashMap<(RouteClass, String), Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(opts: &Opts) -> RateLimiter {
+        let limits = [
+            (RouteClass::Commit, opts.rate_limit_commit),
+            (RouteClass::Search, opts.rate_limit_search),
+            (RouteClass::Upload, opts.rate_limit_upload),
+            (RouteClass::Bookmark, opts.rate_limit_bookmark),
+            (RouteClass::Read, opts.rate_limit_read),
+        ]
+        .into_iter()
+        .filter(|(_class, limit)| *limit > 0)
+        .collect();
+        RateLimiter {
+            limits,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_limited(&self, class: RouteClass) -> bool {
+        self.limits.contains_key(&class)
+    }
+
+    /// Takes a token from the bucket of every client, or throws a [atomic_lib::AtomicErrorType::LimitExceeded] if one of them is empty.
+    /// `clients` are the IP address, and the subject of the Agent if the request is signed.
+    pub fn check(&self, class: RouteClass, clients: &[String]) -> AtomicServerResult<()> {
+        self.check_at(class, clients, Instant::now())
+    }
+
+    fn check_at(
+        &self,
+        class: RouteClass,
+        clients: &[String],
+        now: Instant,
+    ) -> AtomicServerResult<()> {
+        let limit = match self.limits.get(&class) {
+            Some(limit) => *limit as f64,
+            None => return Ok(()),
+        };
+        let per_second = limit / 60.0;
+        let mut buckets = self.buckets.lock()?;
+        if buckets.len() > MAX_BUCKETS {
+            let full_after = Duration::from_secs(60);
+            buckets
+                .retain(|_key, bucket| now.saturating_duration_since(bucket.updated) < full_after);
+        }
+        if buckets.len() > MAX_BUCKETS {
+            // Removes a quarter at once, so this does not happen at every request
+            let mut by_age: Vec<(Instant, (RouteClass, String))> = buckets
+                .iter()
+                .map(|(key, bucket)| (bucket.updated, key.clone()))
+                .collect();
+            by_age.sort_unstable_by_key(|(updated, _key)| *updated);
+            let remove = buckets.len() - MAX_BUCKETS * 3 / 4;
+            for (_updated, key) in by_age.into_iter().take(remove) {
+                buckets.remove(&key);
+            }
+        }
+        let mut wait: f64 = 0.0;
+        for client in clients {
+            let bucket = buckets
+                .entry((class, client.to_string()))
+                .or_insert(Bucket {
+                    tokens: limit,
+                    updated: now,
+                });
+            let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * per_second).min(limit);
+            bucket.updated = now;
+            if bucket.tokens < 1.0 {
+                wait = wait.max(((1.0 - bucket.tokens) / per_second).ceil());
+            }
+        }
+        if wait > 0.0 {
+            return Err(AtomicError::limit_exceeded(format!(
+                "Too many {} requests, the limit is {} per minute. Try again in {} seconds.",
+                class, limit, wait
+            ))
+            .into());
+        }
+        // Only when all buckets have a token, so refused requests don't count
+        for client in clients {
+            if let Some(bucket) = buckets.get_mut(&(class, client.to_string())) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Middleware that applies the rate limit of a [RouteClass] to a route.
+/// Requests to [urls::PATH_FETCH_BOOKMARK] always use [RouteClass::Bookmark].
+pub struct RateLimit {
+    class: RouteClass,
+}
+
+impl RateLimit {
+    pub fn new(class: RouteClass) -> RateLimit {
+        RateLimit { class }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service,
+            class: self.class,
+        }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: S,
+    class: RouteClass,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let class = if req.path() == urls::PATH_FETCH_BOOKMARK {
+            RouteClass::Bookmark
+        } else {
+            self.class
+        };
+        if let Some(appstate) = req.app_data::<web::Data<AppState>>() {
+            if appstate.rate_limiter.is_limited(class) {
+                let clients = get_clients(&req, appstate);
+                if let Err(e) = appstate.rate_limiter.check(class, &clients) {
+                    let response = req.into_response(e.error_response());
+                    return Box::pin(ready(Ok(response.map_into_right_body())));
+                }
+            }
+        }
+        let response = self.service.call(req);
+        Box::pin(async move { Ok(response.await?.map_into_left_body()) })
+    }
+}
+
+/// Returns the IP address of the client, and the authenticated Agent if it is signed in.
+/// Requests with invalid credentials are only limited by their IP address, the handler will refuse them.
+fn get_clients(req: &ServiceRequest, appstate: &AppState) -> Vec<String> {
+    let ip = req
+        .peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_else(|| "unknown".into());
+    let subject = format!(
+        "{}{}",
+        appstate.store.get_server_url(),
+        req.uri()
+            .path_and_query()
+            .map(|p| p.as_str())
+            .unwrap_or_default()
+    );
+    match crate::helpers::get_client_agent(req.headers(), appstate, subject) {
+        Ok(Some(agent)) if agent != urls::PUBLIC_AGENT => vec![ip, agent],
+        _ => vec![ip],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn clients(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn token_bucket() {
+        let limiter = RateLimiter {
+            limits: [(RouteClass::Search, 2)].into_iter().collect(),
+            buckets: Mutex::new(HashMap::new()),
+        };
+        let check = |class, names: &[&str], at| limiter.check_at(class, &clients(names), at);
+        let start = Instant::now();
+        check(RouteClass::Search, &["a"], start).unwrap();
+        check(RouteClass::Search, &["a"], start).unwrap();
+        let err = check(RouteClass::Search, &["a"], start).unwrap_err();
+        assert!(matches!(
+            err.error_type,
+            crate::errors::AppErrorType::TooManyRequests
+        ));
+        // Other clients and classes have their own buckets
+        check(RouteClass::Search, &["b"], start).unwrap();
+        check(RouteClass::Commit, &["a"], start).unwrap();
+        // Two per minute, so one token is added every 30 seconds
+        let later = start + Duration::from_secs(30);
+        check(RouteClass::Search, &["a"], later).unwrap();
+        check(RouteClass::Search, &["a"], later).unwrap_err();
+    }
+
+    #[test]
+    fn agents_share_the_limit_of_their_ip() {
+        let limiter = RateLimiter {
+            limits: [(RouteClass::Search, 2)].into_iter().collect(),
+            buckets: Mutex::new(HashMap::new()),
+        };
+        let check =
+            |names: &[&str]| limiter.check_at(RouteClass::Search, &clients(names), Instant::now());
+        check(&["ip", "agent-1"]).unwrap();
+        check(&["ip", "agent-2"]).unwrap();
+        check(&["ip", "agent-3"]).unwrap_err();
+        // The refused request did not use a token of the other IP address
+        check(&["other-ip", "agent-3"]).unwrap();
+        check(&["other-ip", "agent-3"]).unwrap();
+    }
+
+    #[test]
+    fn caps_buckets() {
+        let limiter = RateLimiter {
+            limits: [(RouteClass::Read, 10)].into_iter().collect(),
+            buckets: Mutex::new(HashMap::new()),
+        };
+        let now = Instant::now();
+        for i in 0..MAX_BUCKETS + 10 {
+            limiter
+                .check_at(RouteClass::Read, &clients(&[&i.to_string()]), now)
+                .unwrap();
+        }
+        assert!(limiter.buckets.lock().unwrap().len() <= MAX_BUCKETS + 1);
+    }
+}
@@ -1,7 +1,11 @@
 //! Contains routing logic, sends the client to the correct handler.
 //! We should try to minimize what happens in here, since most logic should be defined in Atomic Data - not in the server itself.
+//! Routes that use the Store are wrapped in the rate limit of their [RouteClass].
 
-use crate::{content_types, handlers};
+use crate::{
+    content_types, handlers,
+    rate_limit::{RateLimit, RouteClass},
+};
 use actix_web::{guard, http::Method, web};
 use actix_web_static_files::ResourceFiles;
 
@@ -17,7 +21,11 @@ include!(concat!(env!("OUT_DIR"), "/generated.rs"));
 // precedence over a later route.
 pub fn config_routes(app: &mut actix_web::web::ServiceConfig) {
     app.service(web::resource("/ws").to(handlers::web_sockets::web_socket_handler))
-        .service(web::resource("/download/{path:[^{}]+}").to(handlers::download::handle_download))
+        .service(
+            web::resource("/download/{path:[^{}]+}")
+                .wrap(RateLimit::new(RouteClass::Read))
+                .to(handlers::download::handle_download),
+        )
         // This `generate` imports the static files from the `app_assets` folder
         .service(
             ResourceFiles::new("/", generate())
@@ -37,28 +45,73 @@ pub fn config_routes(app: &mut actix_web::web::ServiceConfig) {
         .service(
             web::resource("/upload")
                 .guard(guard::Method(Method::POST))
+                .wrap(RateLimit::new(RouteClass::Upload))
                 .to(handlers::upload::upload_handler),
         )
+        .service(
+            web::resource("/upload-sessions")
+                .guard(guard::Method(Method::POST))
+                .wrap(RateLimit::new(RouteClass::Upload))
+                .to(handlers::upload_sessions::create_upload_session),
+        )
+        .service(
+            web::resource("/upload-sessions/{id}")
+                .guard(guard::Method(Method::PUT))
+                .wrap(RateLimit::new(RouteClass::Upload))
+                .to(handlers::upload_sessions::put_chunk),
+        )
+        .service(
+            web::resource("/upload-sessions/{id}")
+                .guard(guard::Method(Method::POST))
+                .wrap(RateLimit::new(RouteClass::Upload))
+                .to(handlers::upload_sessions::finish_upload_session),
+        )
+        .service(
+            web::resource("/tokens")
+                .guard(guard::Method(Method::POST))
+                .wrap(RateLimit::new(RouteClass::Commit))
+                .to(handlers::tokens::create_token),
+        )
         .service(
             web::resource("/commit")
                 .guard(guard::Method(Method::POST))
+                .wrap(RateLimit::new(RouteClass::Commit))
                 .to(handlers::commit::post_commit),
         )
+        .service(
+            web::resource("/commits")
+                .guard(guard::Method(Method::POST))
+                .wrap(RateLimit::new(RouteClass::Commit))
+                .to(handlers::commit::post_transaction),
+        )
         .service(
             web::resource("/search")
                 .guard(guard::Method(Method::GET))
+                .wrap(RateLimit::new(RouteClass::Search))
                 .to(handlers::search::search_query),
         )
+        .service(
+            web::resource("/similar")
+                .guard(guard::Method(Method::GET))
+                .wrap(RateLimit::new(RouteClass::Search))
+                .to(handlers::similar::similar_query),
+        )
         .service(
             web::resource(ANY)
                 .guard(guard::Method(Method::GET))
+                .wrap(RateLimit::new(RouteClass::Read))
                 .to(handlers::get_resource::handle_get_resource),
         )
         .service(
             web::resource(ANY)
                 .guard(guard::Method(Method::POST))
+                .wrap(RateLimit::new(RouteClass::Read))
                 .to(handlers::post_resource::handle_post_resource),
         )
         // Also allow the home resource (not matched by the previous one)
-        .service(web::resource("/").to(handlers::get_resource::handle_get_resource));
+        .service(
+            web::resource("/")
+                .wrap(RateLimit::new(RouteClass::Read))
+                .to(handlers::get_resource::handle_get_resource),
+        );
 }
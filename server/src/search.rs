@@ -1,16 +1,56 @@
 //! Full-text search, powered by Tantivy.
 //! A folder for the index is stored in the config.
 //! You can see the Endpoint on `http://localhost/search`
+//! The schema of the index is built from the Properties in the Db, see [build_schema].
+//! Changes are indexed by [index_pending], which uses the commit log of the Db as a durable queue, so no changes are lost if the server stops.
+//! [verify_index] compares the index with the Db and repairs any differences.
+//! Titles and descriptions of resources with a [atomic_lib::urls::LANGUAGE] are also indexed with a stemming tokenizer for that language, see [LANGUAGES].
+use std::collections::{HashMap, HashSet};
+
+use atomic_lib::datatype::DataType;
+use atomic_lib::values::SubResource;
 use atomic_lib::Db;
 use atomic_lib::Resource;
 use atomic_lib::Storelike;
+use atomic_lib::Value;
 use tantivy::schema::*;
+use tantivy::tokenizer::{
+    Language, LowerCaser, RemoveLongFilter, SimpleTokenizer, Stemmer, StopWordFilter, TextAnalyzer,
+};
 use tantivy::Index;
 use tantivy::IndexWriter;
 use tantivy::ReloadPolicy;
 
 use crate::config::Config;
 use crate::errors::AtomicServerResult;
+use crate::similarity::{HashingEmbedder, SimilarityIndex};
+
+/// The name of the cursor in the commit log that tracks which Commits have been indexed, see [atomic_lib::Db::get_index_cursor].
+pub const INDEX_CURSOR: &str = "search";
+/// The amount of commit log entries that are read at once by [index_pending].
+const QUEUE_BATCH_SIZE: usize = 1000;
+/// The languages that get their own stemmed fields, by their ISO 639-1 code.
+/// Stopwords are removed for the languages that Tantivy has a list for.
+pub const LANGUAGES: &[(&str, Language)] = &[
+    ("ar", Language::Arabic),
+    ("da", Language::Danish),
+    ("de", Language::German),
+    ("el", Language::Greek),
+    ("en", Language::English),
+    ("es", Language::Spanish),
+    ("fi", Language::Finnish),
+    ("fr", Language::French),
+    ("hu", Language::Hungarian),
+    ("it", Language::Italian),
+    ("nl", Language::Dutch),
+    ("no", Language::Norwegian),
+    ("pt", Language::Portuguese),
+    ("ro", Language::Romanian),
+    ("ru", Language::Russian),
+    ("sv", Language::Swedish),
+    ("ta", Language::Tamil),
+    ("tr", Language::Turkish),
+];
 
 /// The actual Schema used for search.
 /// It mimics a single Atom (or Triple).
@@ -21,6 +61,21 @@ pub struct Fields {
     pub description: Field,
     pub propvals: Field,
     pub hierarchy: Field,
+    /// The Classes of the resource, as facets with a single segment
+    pub is_a: Field,
+    /// Links to other resources, as facets of the Property and the linked subject
+    pub links: Field,
+    /// Fast fields for sorting and range queries, by the URL of their Property. See [build_schema].
+    pub typed: HashMap<String, Field>,
+    /// Stemmed fields, by their language code. See [LANGUAGES].
+    pub languages: HashMap<String, LanguageFields>,
+}
+
+/// The title and description, tokenized using the stemmer of a language.
+#[derive(Debug, Clone, Copy)]
+pub struct LanguageFields {
+    pub title: Field,
+    pub description: Field,
 }
 
 /// Contains the index and the schema. for search
@@ -36,56 +91,173 @@ pub struct SearchState {
     pub writer: std::sync::Arc<std::sync::RwLock<tantivy::IndexWriter>>,
     /// The shape of data stored in the index
     pub schema: tantivy::schema::Schema,
+    /// Embedding vectors of the indexed resources, if `--similarity` is enabled.
+    pub similarity: Option<std::sync::Arc<SimilarityIndex>>,
+    /// Held by [reindex_subject] while it removes and adds the documents of a subject.
+    /// Otherwise, two threads that index the same subject could both remove the old documents before adding a new one, which leaves a duplicate.
+    reindex_lock: std::sync::Arc<std::sync::Mutex<()>>,
 }
 
 impl SearchState {
     /// Create a new SearchState for the Server, which includes building the schema and index.
-    pub fn new(config: &Config) -> AtomicServerResult<SearchState> {
-        let schema = crate::search::build_schema()?;
-        let (writer, index) = crate::search::get_index(config)?;
+    /// If the Properties in the Db have changed the schema, the index is built again.
+    /// Otherwise, the Commits that were not indexed before the server stopped are indexed.
+    pub fn new(config: &Config, store: &Db) -> AtomicServerResult<SearchState> {
+        let schema = crate::search::build_schema(store)?;
+        let (writer, index, schema_changed) = crate::search::get_index(config, schema.clone())?;
         let reader = crate::search::get_reader(&index)?;
         let locked = std::sync::RwLock::from(writer);
         let arced = std::sync::Arc::from(locked);
-        Ok(SearchState {
+        let search_state = SearchState {
             schema,
             reader,
             index,
             writer: arced,
-        })
+            similarity: config.opts.similarity.then(|| {
+                std::sync::Arc::new(SimilarityIndex::new(Box::<HashingEmbedder>::default()))
+            }),
+            reindex_lock: Default::default(),
+        };
+        if schema_changed {
+            tracing::warn!("The search schema has changed, building the search index again...");
+            add_all_resources(&search_state, store)?;
+        } else {
+            let replayed = index_pending(&search_state, store)?;
+            if replayed > 0 {
+                tracing::info!(
+                    "Indexed {} resources that changed after the last search commit",
+                    replayed
+                );
+            }
+            // The vectors are only kept in memory, so they are computed again at every start
+            if let Some(similarity) = &search_state.similarity {
+                tracing::info!("Building similarity index...");
+                for resource in store.all_resources(true).filter(is_searchable) {
+                    add_to_similarity(similarity, &resource, store)?;
+                }
+                tracing::info!("Similarity index contains {} resources", similarity.len()?);
+            }
+        }
+        Ok(search_state)
     }
 }
 
 /// Returns the schema for the search index.
-pub fn build_schema() -> AtomicServerResult<tantivy::schema::Schema> {
+/// Every Property with an Integer, Float, Timestamp or Boolean datatype gets a fast field, named by its URL, which can be used for sorting and range queries.
+/// Booleans are stored as `0` and `1`.
+/// Properties that are created later only get a fast field after a restart.
+pub fn build_schema(store: &Db) -> AtomicServerResult<tantivy::schema::Schema> {
     let mut schema_builder = Schema::builder();
     // The STORED flag makes the index store the full values. Can be useful.
-    schema_builder.add_text_field("subject", TEXT | STORED);
+    // Not tokenized, so documents can be removed by their subject
+    schema_builder.add_text_field("subject", STRING | STORED);
     schema_builder.add_text_field("title", TEXT | STORED);
     schema_builder.add_text_field("description", TEXT | STORED);
     schema_builder.add_json_field("propvals", STORED | TEXT);
     schema_builder.add_facet_field("hierarchy", STORED);
+    schema_builder.add_facet_field("is_a", INDEXED);
+    schema_builder.add_facet_field("links", INDEXED);
+    for (code, _language) in LANGUAGES {
+        let options = TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer(&tokenizer_name(code))
+                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+        );
+        schema_builder.add_text_field(&format!("title_{}", code), options.clone());
+        schema_builder.add_text_field(&format!("description_{}", code), options);
+    }
+    for (subject, datatype) in typed_properties(store)? {
+        let options = NumericOptions::default().set_fast(Cardinality::SingleValue);
+        match datatype {
+            DataType::Integer | DataType::Timestamp => {
+                schema_builder.add_i64_field(&subject, options.set_indexed())
+            }
+            DataType::Float => schema_builder.add_f64_field(&subject, options.set_indexed()),
+            _ => schema_builder.add_u64_field(&subject, options.set_indexed()),
+        };
+    }
     let schema = schema_builder.build();
     Ok(schema)
 }
 
+/// The name of the stemming tokenizer of a language, see [register_tokenizers].
+pub fn tokenizer_name(code: &str) -> String {
+    format!("stem_{}", code)
+}
+
+/// Adds a tokenizer for every language in [LANGUAGES], which lowercases, removes stopwords and stems.
+/// Tokenizers are not stored in the index, so this has to be called every time it is opened.
+fn register_tokenizers(index: &Index) {
+    for (code, language) in LANGUAGES {
+        let analyzer = TextAnalyzer::from(SimpleTokenizer)
+            .filter(RemoveLongFilter::limit(40))
+            .filter(LowerCaser);
+        let analyzer = match StopWordFilter::new(*language) {
+            Some(stopwords) => analyzer.filter(stopwords),
+            None => analyzer,
+        };
+        index.tokenizers().register(
+            &tokenizer_name(code),
+            analyzer.filter(Stemmer::new(*language)),
+        );
+    }
+}
+
+/// The Properties that get a fast field, sorted by subject so the schema stays the same if they do not change.
+fn typed_properties(store: &Db) -> AtomicServerResult<Vec<(String, DataType)>> {
+    let mut query = atomic_lib::storelike::Query::new_class(atomic_lib::urls::PROPERTY);
+    query.include_external = true;
+    let mut properties: Vec<(String, DataType)> = store
+        .query(&query)?
+        .resources
+        .into_iter()
+        .filter_map(|resource| atomic_lib::schema::Property::from_resource(resource).ok())
+        .filter(|property| {
+            matches!(
+                property.data_type,
+                DataType::Integer | DataType::Float | DataType::Timestamp | DataType::Boolean
+            )
+        })
+        .map(|property| (property.subject, property.data_type))
+        .collect();
+    properties.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(properties)
+}
+
 /// Creates or reads the index from the `search_index_path` and allocates some heap size.
-pub fn get_index(config: &Config) -> AtomicServerResult<(IndexWriter, Index)> {
-    let schema = build_schema()?;
+/// If the existing index has a different schema, it is removed. The returned boolean is true in that case, as all resources have to be indexed again.
+pub fn get_index(
+    config: &Config,
+    schema: Schema,
+) -> AtomicServerResult<(IndexWriter, Index, bool)> {
     std::fs::create_dir_all(&config.search_index_path)?;
     if config.opts.rebuild_indexes {
         std::fs::remove_dir_all(&config.search_index_path)?;
         std::fs::create_dir_all(&config.search_index_path)?;
     }
-    let mmap_directory = tantivy::directory::MmapDirectory::open(&config.search_index_path)?;
-    let index = Index::open_or_create(mmap_directory, schema).map_err(|e| {
-        format!(
-            "Failed to create or open search index. Try starting again with --rebuild-index. Error: {}",
-            e
-        )
-    })?;
+    let open = || -> AtomicServerResult<Index> {
+        let mmap_directory = tantivy::directory::MmapDirectory::open(&config.search_index_path)?;
+        Ok(Index::open_or_create(mmap_directory, schema.clone())?)
+    };
+    let (index, schema_changed) = match open() {
+        Ok(index) => (index, false),
+        Err(e) => {
+            tracing::info!("Removing the search index, as it can not be opened: {}", e);
+            std::fs::remove_dir_all(&config.search_index_path)?;
+            std::fs::create_dir_all(&config.search_index_path)?;
+            let index = open().map_err(|e| {
+                format!(
+                    "Failed to create or open search index. Try starting again with --rebuild-index. Error: {}",
+                    e
+                )
+            })?;
+            (index, true)
+        }
+    };
+    register_tokenizers(&index);
     let heap_size_bytes = 50_000_000;
     let index_writer = index.writer(heap_size_bytes)?;
-    Ok((index_writer, index))
+    Ok((index_writer, index, schema_changed))
 }
 
 /// Returns the schema for the search index.
@@ -110,6 +282,31 @@ pub fn get_schema_fields(appstate: &SearchState) -> AtomicServerResult<Fields> {
         .schema
         .get_field("hierarchy")
         .ok_or("No 'hierarchy' in the schema")?;
+    let is_a = appstate
+        .schema
+        .get_field("is_a")
+        .ok_or("No 'is_a' in the schema")?;
+    let links = appstate
+        .schema
+        .get_field("links")
+        .ok_or("No 'links' in the schema")?;
+    // The typed fields are named by the URL of their Property
+    let typed = appstate
+        .schema
+        .fields()
+        .filter(|(_field, entry)| entry.name().contains("://"))
+        .map(|(field, entry)| (entry.name().to_string(), field))
+        .collect();
+    let languages = LANGUAGES
+        .iter()
+        .filter_map(|(code, _language)| {
+            let title = appstate.schema.get_field(&format!("title_{}", code))?;
+            let description = appstate
+                .schema
+                .get_field(&format!("description_{}", code))?;
+            Some((code.to_string(), LanguageFields { title, description }))
+        })
+        .collect();
 
     Ok(Fields {
         subject,
@@ -117,33 +314,154 @@ pub fn get_schema_fields(appstate: &SearchState) -> AtomicServerResult<Fields> {
         description,
         propvals,
         hierarchy,
+        is_a,
+        links,
+        typed,
+        languages,
     })
 }
 
-/// Indexes all resources from the store to search.
+/// Indexes all resources from the store to search, and marks all Commits as indexed.
 /// At this moment does not remove existing index.
 pub fn add_all_resources(search_state: &SearchState, store: &Db) -> AtomicServerResult<()> {
     tracing::info!("Building search index...");
+    // Commits that are applied while building are indexed again later
+    let last_seq = store.last_commit_seq()?;
 
-    let resources = store
-        .all_resources(true)
-        .filter(|resource| !resource.get_subject().contains("/commits/"));
+    let resources = store.all_resources(true).filter(is_searchable);
 
     for resource in resources {
-        add_resource(search_state, &resource, store).map_err(|e| {
-            format!(
+        // A resource that can not be indexed should not prevent the others from being indexed
+        if let Err(e) = add_resource(search_state, &resource, store) {
+            tracing::warn!(
                 "Failed to add resource to search index: {}. Error: {}",
                 resource.get_subject(),
                 e
-            )
-        })?
+            );
+        }
     }
 
     search_state.writer.write()?.commit()?;
+    store.set_index_cursor(INDEX_CURSOR, last_seq)?;
     tracing::info!("Search index finished!");
     Ok(())
 }
 
+/// Whether the resource should be in the search index. Commits are not.
+fn is_searchable(resource: &Resource) -> bool {
+    !resource
+        .get(atomic_lib::urls::IS_A)
+        .and_then(|classes| classes.to_subjects(None))
+        .map(|classes| classes.iter().any(|c| c == atomic_lib::urls::COMMIT))
+        .unwrap_or(false)
+}
+
+/// Replaces the documents of the subject with its current version in the Db, or removes them if it no longer exists.
+/// Returns whether the subject is in the index afterwards. Does _not_ commit!
+pub fn reindex_subject(
+    search_state: &SearchState,
+    store: &Db,
+    subject: &str,
+) -> AtomicServerResult<bool> {
+    let _lock = search_state.reindex_lock.lock()?;
+    remove_resource(search_state, subject)?;
+    match store.get_resource(subject) {
+        Ok(resource) if is_searchable(&resource) => {
+            add_resource(search_state, &resource, store)?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Indexes the targets of the Commits in the commit log that have not been indexed yet.
+/// Commits the index, and then moves the [INDEX_CURSOR], so the Commits are indexed again if the server stops before the index is committed.
+/// Returns the amount of subjects that were indexed.
+pub fn index_pending(search_state: &SearchState, store: &Db) -> AtomicServerResult<usize> {
+    let mut cursor = store.get_index_cursor(INDEX_CURSOR)?;
+    let mut subjects: HashSet<String> = HashSet::new();
+    loop {
+        let entries = store.commit_log(cursor, QUEUE_BATCH_SIZE)?;
+        let last = match entries.last() {
+            Some((seq, _commit)) => *seq,
+            None => break,
+        };
+        for (_seq, commit) in entries {
+            match store
+                .get_resource(&commit)
+                .and_then(|commit| commit.get(atomic_lib::urls::SUBJECT).map(|s| s.to_string()))
+            {
+                Ok(target) => {
+                    subjects.insert(target);
+                }
+                Err(e) => tracing::warn!("Can not index the target of Commit {}: {}", commit, e),
+            }
+        }
+        cursor = last;
+    }
+    for subject in &subjects {
+        // A resource that can not be indexed should not block the queue
+        if let Err(e) = reindex_subject(search_state, store, subject) {
+            tracing::warn!("Failed to index {}: {}", subject, e);
+        }
+    }
+    search_state.writer.write()?.commit()?;
+    store.set_index_cursor(INDEX_CURSOR, cursor)?;
+    Ok(subjects.len())
+}
+
+/// The differences between the search index and the Db that were repaired by [verify_index].
+#[derive(Debug, Default)]
+pub struct IndexRepairs {
+    /// Resources that were missing from the index, or were indexed more than once
+    pub added: usize,
+    /// Documents of resources that are not in the Db
+    pub removed: usize,
+}
+
+/// Compares the subjects in the search index with the resources in the Db, and repairs any differences.
+/// Does not detect outdated documents, as changes are indexed by [index_pending].
+pub fn verify_index(search_state: &SearchState, store: &Db) -> AtomicServerResult<IndexRepairs> {
+    let fields = get_schema_fields(search_state)?;
+    search_state.reader.reload()?;
+    let searcher = search_state.reader.searcher();
+    let mut indexed: HashMap<String, usize> = HashMap::new();
+    for (segment_ord, segment_reader) in searcher.segment_readers().iter().enumerate() {
+        for doc_id in segment_reader.doc_ids_alive() {
+            let doc = searcher.doc(tantivy::DocAddress::new(segment_ord as u32, doc_id))?;
+            if let Some(tantivy::schema::Value::Str(subject)) = doc.get_first(fields.subject) {
+                *indexed.entry(subject.clone()).or_default() += 1;
+            }
+        }
+    }
+
+    let mut repairs = IndexRepairs::default();
+    let mut in_db = HashSet::new();
+    for resource in store.all_resources(true).filter(is_searchable) {
+        let subject = resource.get_subject();
+        if indexed.get(subject) != Some(&1) {
+            // Indexes the current version, which may have changed since it was read
+            match reindex_subject(search_state, store, subject) {
+                Ok(true) => repairs.added += 1,
+                Ok(false) => {}
+                Err(e) => tracing::warn!("Failed to index {}: {}", subject, e),
+            }
+        }
+        in_db.insert(subject.clone());
+    }
+    for subject in indexed.keys().filter(|subject| !in_db.contains(*subject)) {
+        // It may have been created after the Db was read
+        match reindex_subject(search_state, store, subject) {
+            Ok(false) => repairs.removed += 1,
+            Ok(true) => {}
+            Err(e) => tracing::warn!("Failed to index {}: {}", subject, e),
+        }
+    }
+    search_state.writer.write()?.commit()?;
+    search_state.reader.reload()?;
+    Ok(repairs)
+}
+
 /// Adds a single resource to the search index, but does _not_ commit!
 /// Does not index outgoing links, or resourcesArrays
 /// `appstate.search_index_writer.write()?.commit()?;`
@@ -156,6 +474,14 @@ pub fn add_resource(
     let fields = get_schema_fields(appstate)?;
     let subject = resource.get_subject();
     let writer = appstate.writer.read()?;
+    // Hidden values should not be found by searching for them
+    let mut resource = resource.clone();
+    atomic_lib::hierarchy::remove_hidden_properties(
+        store,
+        &mut resource,
+        atomic_lib::urls::PUBLIC_AGENT,
+    )?;
+    let resource = &resource;
 
     let mut doc = Document::default();
     doc.add_json_object(
@@ -169,22 +495,76 @@ pub fn add_resource(
     );
 
     doc.add_text(fields.subject, subject);
-    doc.add_text(fields.title, get_resource_title(resource));
+    let title = get_resource_title(resource);
+    doc.add_text(fields.title, &title);
 
-    if let Ok(atomic_lib::Value::Markdown(description)) =
-        resource.get(atomic_lib::urls::DESCRIPTION)
-    {
+    let description = match resource.get(atomic_lib::urls::DESCRIPTION) {
+        Ok(atomic_lib::Value::Markdown(description)) => Some(description),
+        _ => None,
+    };
+    if let Some(description) = description {
         doc.add_text(fields.description, description);
     };
 
-    let hierarchy = resource_to_facet(resource, store)?;
+    let parents = resource.get_parent_tree(store)?;
+    if let Some(language) =
+        get_language(resource, &parents).and_then(|code| fields.languages.get(&code))
+    {
+        doc.add_text(language.title, &title);
+        if let Some(description) = description {
+            doc.add_text(language.description, description);
+        }
+    }
+
+    let hierarchy = parents_to_facet(resource, parents)?;
     doc.add_facet(fields.hierarchy, hierarchy);
 
+    for (property, value) in resource.get_propvals() {
+        if property == atomic_lib::urls::IS_A {
+            for class in linked_subjects(value) {
+                doc.add_facet(fields.is_a, Facet::from_path([class]));
+            }
+        } else {
+            for link in linked_subjects(value) {
+                doc.add_facet(fields.links, Facet::from_path([property, link]));
+            }
+        }
+        if let Some(field) = fields.typed.get(property) {
+            match (appstate.schema.get_field_entry(*field).field_type(), value) {
+                (FieldType::I64(_), Value::Integer(i) | Value::Timestamp(i)) => {
+                    doc.add_i64(*field, *i)
+                }
+                (FieldType::F64(_), Value::Float(f)) => doc.add_f64(*field, *f),
+                (FieldType::U64(_), Value::Boolean(b)) => doc.add_u64(*field, *b as u64),
+                _ => {}
+            }
+        }
+    }
+
     writer.add_document(doc)?;
+    if let Some(similarity) = &appstate.similarity {
+        add_to_similarity(similarity, resource, store)?;
+    }
 
     Ok(())
 }
 
+/// Adds the values of the resource that the public can read to the [SimilarityIndex].
+/// Hidden values are removed first, so that the similarity scores do not reveal them.
+fn add_to_similarity(
+    similarity: &SimilarityIndex,
+    resource: &Resource,
+    store: &Db,
+) -> AtomicServerResult<()> {
+    let mut resource = resource.clone();
+    atomic_lib::hierarchy::remove_hidden_properties(
+        store,
+        &mut resource,
+        atomic_lib::urls::PUBLIC_AGENT,
+    )?;
+    similarity.add_resource(&resource)
+}
+
 /// Removes a single resource from the search index, but does _not_ commit!
 /// Does not index outgoing links, or resourcesArrays
 /// `appstate.search_index_writer.write()?.commit()?;`
@@ -194,6 +574,9 @@ pub fn remove_resource(search_state: &SearchState, subject: &str) -> AtomicServe
     let writer = search_state.writer.read()?;
     let term = tantivy::Term::from_field_text(fields.subject, subject);
     writer.delete_term(term);
+    if let Some(similarity) = &search_state.similarity {
+        similarity.remove(subject)?;
+    }
     Ok(())
 }
 
@@ -211,7 +594,14 @@ pub fn subject_to_facet(subject: String) -> AtomicServerResult<Facet> {
 }
 
 pub fn resource_to_facet(resource: &Resource, store: &Db) -> AtomicServerResult<Facet> {
-    let mut parent_tree = resource.get_parent_tree(store)?;
+    parents_to_facet(resource, resource.get_parent_tree(store)?)
+}
+
+/// Builds the hierarchy facet from the parents of the resource, ordered from the nearest parent to the root.
+fn parents_to_facet(
+    resource: &Resource,
+    mut parent_tree: Vec<Resource>,
+) -> AtomicServerResult<Facet> {
     parent_tree.reverse();
 
     let mut hierarchy_bytes: Vec<u8> = Vec::new();
@@ -240,7 +630,37 @@ pub fn resource_to_facet(resource: &Resource, store: &Db) -> AtomicServerResult<
     Ok(result)
 }
 
-fn get_resource_title(resource: &Resource) -> String {
+/// Returns the language code of the resource, or of its nearest parent that has a [atomic_lib::urls::LANGUAGE].
+/// Region subtags are ignored, so `nl-BE` becomes `nl`.
+fn get_language(resource: &Resource, parents: &[Resource]) -> Option<String> {
+    std::iter::once(resource)
+        .chain(parents)
+        .find_map(|r| r.get(atomic_lib::urls::LANGUAGE).ok())
+        .and_then(|language| {
+            language
+                .to_string()
+                .split(['-', '_'])
+                .next()
+                .map(|code| code.trim().to_lowercase())
+        })
+}
+
+/// The subjects that an AtomicUrl or ResourceArray refers to. Nested resources are skipped.
+fn linked_subjects(value: &Value) -> Vec<&str> {
+    match value {
+        Value::AtomicUrl(subject) => vec![subject.as_str()],
+        Value::ResourceArray(items) => items
+            .iter()
+            .filter_map(|item| match item {
+                SubResource::Subject(subject) => Some(subject.as_str()),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+pub fn get_resource_title(resource: &Resource) -> String {
     let title = if let Ok(name) = resource.get(atomic_lib::urls::NAME) {
         name.clone()
     } else if let Ok(shortname) = resource.get(atomic_lib::urls::SHORTNAME) {
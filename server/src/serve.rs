@@ -30,6 +30,49 @@ fn rebuild_indexes(appstate: &crate::appstate::AppState) -> AtomicServerResult<(
     Ok(())
 }
 
+/// Periodically pulls the Commits of the `primary` server, see [atomic_lib::replication].
+/// Continues where it left off after a restart, because the replication cursor is stored in the Db.
+fn start_replication(appstate: &crate::appstate::AppState, primary: String, interval_secs: u64) {
+    let store = appstate.store.clone();
+    std::thread::spawn(move || loop {
+        match atomic_lib::replication::pull(&store, &primary) {
+            Ok(0) => {}
+            Ok(applied) => tracing::info!("Replicated {} Commits from {}", applied, primary),
+            Err(e) => tracing::error!("Replication from {} failed: {}", primary, e),
+        }
+        std::thread::sleep(std::time::Duration::from_secs(interval_secs));
+    });
+}
+
+/// Removes old events from the audit log every hour, see [atomic_lib::Db::prune_audit_log].
+fn start_audit_log_pruning(appstate: &crate::appstate::AppState) {
+    let store = appstate.store.clone();
+    std::thread::spawn(move || loop {
+        match store.prune_audit_log() {
+            Ok(0) => {}
+            Ok(removed) => tracing::info!("Removed {} events from the audit log", removed),
+            Err(e) => tracing::error!("Pruning the audit log failed: {}", e),
+        }
+        std::thread::sleep(std::time::Duration::from_secs(60 * 60));
+    });
+}
+
+/// Compares the search index with the Db once, and repairs any differences, see [crate::search::verify_index].
+fn start_search_index_verification(appstate: &crate::appstate::AppState) {
+    let appstate = appstate.clone();
+    std::thread::spawn(move || {
+        match crate::search::verify_index(&appstate.search_state, &appstate.store) {
+            Ok(repairs) if repairs.added + repairs.removed > 0 => tracing::warn!(
+                "Repaired the search index: added {} and removed {} resources",
+                repairs.added,
+                repairs.removed
+            ),
+            Ok(_) => tracing::info!("The search index is consistent with the database"),
+            Err(e) => tracing::error!("Verifying the search index failed: {}", e),
+        }
+    });
+}
+
 // Increase the maximum payload size (for POSTing a body, for example) to 50MB
 const PAYLOAD_MAX: usize = 50_242_880;
 
@@ -44,6 +87,15 @@ pub async fn serve(config: crate::config::Config) -> AtomicServerResult<()> {
     // Start async processes
     if config.opts.rebuild_indexes {
         rebuild_indexes(&appstate)?;
+    } else {
+        start_search_index_verification(&appstate);
+    }
+    if let Some(primary) = &config.opts.replicate_from {
+        start_replication(&appstate, primary.clone(), config.opts.replication_interval);
+    }
+    crate::uploads::start_garbage_collection(&appstate);
+    if config.opts.audit_retention_days.is_some() {
+        start_audit_log_pruning(&appstate);
     }
 
     let server = HttpServer::new(move || {
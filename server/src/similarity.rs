@@ -0,0 +1,310 @@
+//! Similarity search, using embedding vectors that are computed from the text of resources.
+//! The vectors are computed by an [Embedder], and kept in memory by the [SimilarityIndex].
+//! The index is built when the server starts, and is updated together with the search index, see [crate::search::add_resource].
+//! Nearest neighbours are found using random hyperplane hashing: only vectors that end up in the same (or an adjacent) bucket as the query are compared.
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+
+use atomic_lib::{Resource, Value};
+
+use crate::errors::AtomicServerResult;
+
+/// The amount of hash tables. More tables find more neighbours, but compare more vectors.
+const TABLES: usize = 8;
+/// The amount of hyperplanes per table, which is the amount of bits of a bucket.
+const BITS: usize = 8;
+/// Seed for the hyperplanes, so buckets are the same every time the server starts.
+const SEED: u64 = 0x5EED_A70C;
+
+/// Computes an embedding vector from a text.
+/// Implement this to use a local model instead of the [HashingEmbedder].
+pub trait Embedder: Send + Sync {
+    /// The length of the vectors.
+    fn dimensions(&self) -> usize;
+    /// Returns a vector of [Embedder::dimensions] length, with a length (L2 norm) of 1, or only zeroes for empty texts.
+    fn embed(&self, text: &str) -> Vec<f32>;
+}
+
+/// Hashes the words of a text, and their character trigrams, to positions in the vector.
+/// It needs no model and is deterministic, but it only finds texts that share words or parts of words, not texts with a similar meaning.
+pub struct HashingEmbedder {
+    dimensions: usize,
+}
+
+impl HashingEmbedder {
+    pub fn new(dimensions: usize) -> Self {
+        HashingEmbedder { dimensions }
+    }
+}
+
+impl Default for HashingEmbedder {
+    fn default() -> Self {
+        HashingEmbedder::new(256)
+    }
+}
+
+impl Embedder for HashingEmbedder {
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn embed(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0; self.dimensions];
+        let mut add = |feature: &str, weight: f32| {
+            let hash = fnv1a(feature.as_bytes());
+            // The highest bit decides the sign, so collisions cancel out instead of adding up
+            let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+            vector[(hash % self.dimensions as u64) as usize] += sign * weight;
+        };
+        for word in text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+        {
+            let word = word.to_lowercase();
+            add(&word, 1.0);
+            let chars: Vec<char> = format!("#{}#", word).chars().collect();
+            for trigram in chars.windows(3) {
+                add(&trigram.iter().collect::<String>(), 0.5);
+            }
+        }
+        normalize(&mut vector);
+        vector
+    }
+}
+
+/// Keeps the embedding vectors of resources in memory, and finds the nearest ones.
+pub struct SimilarityIndex {
+    embedder: Box<dyn Embedder>,
+    /// For every table, [BITS] random hyperplanes.
+    hyperplanes: Vec<Vec<Vec<f32>>>,
+    vectors: RwLock<Vectors>,
+}
+
+struct Vectors {
+    by_subject: HashMap<String, Vec<f32>>,
+    /// For every table, the subjects per bucket.
+    buckets: Vec<HashMap<u64, HashSet<String>>>,
+}
+
+impl SimilarityIndex {
+    pub fn new(embedder: Box<dyn Embedder>) -> Self {
+        let mut random = SplitMix64(SEED);
+        let hyperplanes = (0..TABLES)
+            .map(|_| {
+                (0..BITS)
+                    .map(|_| {
+                        (0..embedder.dimensions())
+                            .map(|_| random.next_f32())
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+        SimilarityIndex {
+            embedder,
+            hyperplanes,
+            vectors: RwLock::new(Vectors {
+                by_subject: HashMap::new(),
+                buckets: vec![HashMap::new(); TABLES],
+            }),
+        }
+    }
+
+    /// Computes the vector of a text, using the [Embedder] of this index.
+    pub fn embed(&self, text: &str) -> Vec<f32> {
+        self.embedder.embed(text)
+    }
+
+    /// Computes the vector of a resource, and replaces its previous vector.
+    pub fn add_resource(&self, resource: &Resource) -> AtomicServerResult<()> {
+        let vector = self.embed(&resource_text(resource));
+        self.insert(resource.get_subject(), vector)
+    }
+
+    /// Adds or replaces the vector of a subject.
+    pub fn insert(&self, subject: &str, vector: Vec<f32>) -> AtomicServerResult<()> {
+        let mut vectors = self.vectors.write()?;
+        self.remove_locked(&mut vectors, subject);
+        for (table, buckets) in vectors.buckets.iter_mut().enumerate() {
+            buckets
+                .entry(self.bucket(table, &vector))
+                .or_default()
+                .insert(subject.to_string());
+        }
+        vectors.by_subject.insert(subject.to_string(), vector);
+        Ok(())
+    }
+
+    pub fn remove(&self, subject: &str) -> AtomicServerResult<()> {
+        let mut vectors = self.vectors.write()?;
+        self.remove_locked(&mut vectors, subject);
+        Ok(())
+    }
+
+    fn remove_locked(&self, vectors: &mut Vectors, subject: &str) {
+        if let Some(vector) = vectors.by_subject.remove(subject) {
+            for (table, buckets) in vectors.buckets.iter_mut().enumerate() {
+                let bucket = self.bucket(table, &vector);
+                if let Some(subjects) = buckets.get_mut(&bucket) {
+                    subjects.remove(subject);
+                    if subjects.is_empty() {
+                        buckets.remove(&bucket);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The vector of a subject, if it is in the index.
+    pub fn get(&self, subject: &str) -> AtomicServerResult<Option<Vec<f32>>> {
+        Ok(self.vectors.read()?.by_subject.get(subject).cloned())
+    }
+
+    /// The amount of subjects in the index.
+    pub fn len(&self) -> AtomicServerResult<usize> {
+        Ok(self.vectors.read()?.by_subject.len())
+    }
+
+    /// Returns up to `limit` subjects with the highest cosine similarity to the vector, from high to low.
+    /// This is approximate: only subjects that share a bucket with the vector in at least one table, or differ by a single bit, are compared.
+    pub fn nearest(&self, vector: &[f32], limit: usize) -> AtomicServerResult<Vec<(String, f32)>> {
+        let vectors = self.vectors.read()?;
+        let mut candidates: HashSet<&String> = HashSet::new();
+        for (table, buckets) in vectors.buckets.iter().enumerate() {
+            let bucket = self.bucket(table, vector);
+            let adjacent = (0..BITS).map(|bit| bucket ^ (1 << bit));
+            for probe in std::iter::once(bucket).chain(adjacent) {
+                if let Some(subjects) = buckets.get(&probe) {
+                    candidates.extend(subjects);
+                }
+            }
+        }
+        let mut scores: Vec<(String, f32)> = candidates
+            .into_iter()
+            .filter_map(|subject| {
+                let score = dot(vector, vectors.by_subject.get(subject)?);
+                Some((subject.clone(), score))
+            })
+            .collect();
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        scores.truncate(limit);
+        Ok(scores)
+    }
+
+    /// The bucket of a vector in a table: one bit for every hyperplane, set if the vector is on its positive side.
+    fn bucket(&self, table: usize, vector: &[f32]) -> u64 {
+        self.hyperplanes[table]
+            .iter()
+            .enumerate()
+            .fold(0, |bucket, (bit, hyperplane)| {
+                if dot(hyperplane, vector) >= 0.0 {
+                    bucket | (1 << bit)
+                } else {
+                    bucket
+                }
+            })
+    }
+}
+
+/// The text that is embedded: the title, followed by the other string and markdown values.
+pub fn resource_text(resource: &Resource) -> String {
+    let title = crate::search::get_resource_title(resource);
+    let mut text = title.clone();
+    for value in resource.get_propvals().values() {
+        if let Value::String(s) | Value::Markdown(s) = value {
+            if s != &title {
+                text.push('\n');
+                text.push_str(s);
+            }
+        }
+    }
+    text
+}
+
+/// Cosine similarity of normalized vectors.
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+fn normalize(vector: &mut [f32]) {
+    let length = dot(vector, vector).sqrt();
+    if length > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= length);
+    }
+}
+
+/// 64-bit FNV-1a, which is stable across Rust versions and platforms, unlike the `Hash` of the standard library.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// A small deterministic random number generator for the hyperplanes.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number between -1 and 1.
+    fn next_f32(&mut self) -> f32 {
+        (self.next() >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hashing_embedder_is_deterministic() {
+        let embedder = HashingEmbedder::default();
+        let a = embedder.embed("The quick brown fox");
+        assert_eq!(a, embedder.embed("the QUICK brown fox"));
+        assert!((dot(&a, &a) - 1.0).abs() < 1e-5);
+        assert!(embedder.embed("").iter().all(|v| *v == 0.0));
+        // Shared words give a higher similarity than unrelated words
+        let related = embedder.embed("A quick brown dog");
+        let unrelated = embedder.embed("Invoices of last year");
+        assert!(dot(&a, &related) > dot(&a, &unrelated));
+    }
+
+    #[test]
+    fn nearest_neighbours() {
+        let index = SimilarityIndex::new(Box::new(HashingEmbedder::default()));
+        let texts = [
+            ("a", "Recipe for apple pie with cinnamon"),
+            ("b", "Apple pie recipe"),
+            ("c", "Quarterly financial report"),
+            ("d", "Financial report of the first quarter"),
+        ];
+        for (subject, text) in texts {
+            index.insert(subject, index.embed(text)).unwrap();
+        }
+        // Replacing a vector does not add the subject twice
+        index.insert("c", index.embed(texts[2].1)).unwrap();
+        assert_eq!(index.len().unwrap(), 4);
+
+        let found = index.nearest(&index.embed("apple pie"), 2).unwrap();
+        let subjects: Vec<&str> = found.iter().map(|(s, _score)| s.as_str()).collect();
+        assert_eq!(subjects.len(), 2);
+        assert!(subjects.contains(&"a") && subjects.contains(&"b"));
+        assert!(found[0].1 >= found[1].1);
+
+        let vector = index.get("c").unwrap().unwrap();
+        assert_eq!(index.nearest(&vector, 1).unwrap()[0].0, "c");
+        index.remove("c").unwrap();
+        assert!(index.get("c").unwrap().is_none());
+        assert!(index
+            .nearest(&vector, 10)
+            .unwrap()
+            .iter()
+            .all(|(s, _score)| s != "c"));
+    }
+}
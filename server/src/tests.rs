@@ -41,6 +41,10 @@ async fn server_tests() {
         &format!("./.temp/{}/db", unique_string),
         "--config-dir",
         &format!("./.temp/{}/config", unique_string),
+        "--audit",
+        "--similarity",
+        "--rate-limit-bookmark",
+        "1",
     ]);
 
     let mut config = config::build_config(opts)
@@ -151,6 +155,725 @@ async fn server_tests() {
         body.as_str().contains("/results"),
         "response should be a search resource"
     );
+
+    // Sort, filter on ranges and count facets, using the typed fields of the search index
+    for size in [20, 10, 30] {
+        let mut file =
+            atomic_lib::Resource::new(format!("{}/sortable-{}", store.get_server_url(), size));
+        file.set_propval_unsafe(urls::IS_A.into(), vec![urls::FILE.to_string()].into());
+        file.set_propval_unsafe(
+            urls::NAME.into(),
+            atomic_lib::Value::String(format!("sortable file {}", size)),
+        );
+        file.set_propval_unsafe(urls::FILESIZE.into(), atomic_lib::Value::Integer(size));
+        file.set_propval_unsafe(
+            urls::PARENT.into(),
+            atomic_lib::Value::AtomicUrl(store.get_server_url().into()),
+        );
+        if size != 30 {
+            file.set_propval_unsafe(
+                urls::READ.into(),
+                vec![urls::PUBLIC_AGENT.to_string()].into(),
+            );
+        }
+        store.add_resource_opts(&file, false, true, true).unwrap();
+        crate::search::add_resource(&appstate.search_state, &file, store).unwrap();
+    }
+    appstate
+        .search_state
+        .writer
+        .write()
+        .unwrap()
+        .commit()
+        .unwrap();
+    appstate.search_state.reader.reload().unwrap();
+    let search = |params: String| {
+        build_request_authenticated(&format!("/search?q=sortable&{}", params), &appstate)
+    };
+    let resp = test::call_service(
+        &app,
+        search(format!(
+            "sort_by={}&sort_desc=true&facets={}",
+            urlencoding::encode(urls::FILESIZE),
+            urlencoding::encode(urls::IS_A)
+        ))
+        .to_request(),
+    )
+    .await;
+    assert!(resp.status().is_success());
+    let json: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    let sizes: Vec<i64> = json[urls::ENDPOINT_RESULTS]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r[urls::FILESIZE].as_i64().unwrap())
+        .collect();
+    assert_eq!(sizes, vec![30, 20, 10]);
+    let facets = json[urls::SEARCH_FACET_COUNTS].as_array().unwrap();
+    assert_eq!(facets[0][urls::SEARCH_FACET_VALUE], urls::FILE);
+    assert_eq!(facets[0][urls::SEARCH_COUNT], 3);
+    // Facets only count the resources that the Agent can read
+    let req = test::TestRequest::with_uri(&format!(
+        "/search?q=sortable&facets={}",
+        urlencoding::encode(urls::IS_A)
+    ))
+    .insert_header(("Accept", "application/ad+json"));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success());
+    let public_json: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    let public_facets = public_json[urls::SEARCH_FACET_COUNTS].as_array().unwrap();
+    assert_eq!(public_facets[0][urls::SEARCH_FACET_VALUE], urls::FILE);
+    assert_eq!(public_facets[0][urls::SEARCH_COUNT], 2);
+    assert_eq!(public_json[urls::SEARCH_FACET_COUNTS_APPROXIMATE], false);
+    assert_eq!(
+        public_json[urls::ENDPOINT_RESULTS]
+            .as_array()
+            .unwrap()
+            .len(),
+        2
+    );
+    // Every result has a hit with its score and the highlighted words of the query
+    let hits = json[urls::SEARCH_HITS].as_array().unwrap();
+    assert_eq!(hits.len(), 3);
+    assert_eq!(
+        hits[0][urls::SEARCH_RESULT],
+        json[urls::ENDPOINT_RESULTS][0]["@id"]
+    );
+    assert!(hits[0][urls::SEARCH_SCORE].as_f64().unwrap() > 0.0);
+    let snippets = hits[0][urls::SEARCH_SNIPPETS].as_array().unwrap();
+    assert_eq!(snippets.len(), 1);
+    assert_eq!(snippets[0][urls::SEARCH_PROPERTY], urls::NAME);
+    assert_eq!(snippets[0][urls::SEARCH_SNIPPET], "<b>sortable</b> file 30");
+    let range = format!("{{\"{}\": {{\"min\": 15, \"max\": 25}}}}", urls::FILESIZE);
+    let resp = test::call_service(
+        &app,
+        search(format!("range={}", urlencoding::encode(&range))).to_request(),
+    )
+    .await;
+    assert!(resp.status().is_success());
+    let json: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    let results = json[urls::ENDPOINT_RESULTS].as_array().unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0][urls::FILESIZE], 20);
+    // Properties without a fast field can't be used for sorting
+    let resp = test::call_service(
+        &app,
+        search(format!("sort_by={}", urlencoding::encode(urls::NAME))).to_request(),
+    )
+    .await;
+    assert!(resp.status().is_server_error());
+
+    // Similar resources are found using the vectors of their text, excluding the resource itself
+    let sortable_10 = format!("{}/sortable-10", store.get_server_url());
+    let path = format!(
+        "/similar?subject={}&limit=2",
+        urlencoding::encode(&sortable_10)
+    );
+    let resp = test::call_service(
+        &app,
+        build_request_authenticated(&path, &appstate).to_request(),
+    )
+    .await;
+    assert!(resp.status().is_success());
+    let json: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    let results = json[urls::ENDPOINT_RESULTS].as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    for result in results {
+        assert!(result["@id"].as_str().unwrap().contains("/sortable-"));
+        assert_ne!(result["@id"], sortable_10);
+    }
+    assert!(
+        json[urls::SEARCH_HITS][0][urls::SEARCH_SCORE]
+            .as_f64()
+            .unwrap()
+            > 0.5
+    );
+    // Hybrid search also finds resources with similar words, if they match the other params
+    let search_count = |path: String| {
+        let app = &app;
+        let appstate = &appstate;
+        async move {
+            let resp = test::call_service(
+                app,
+                build_request_authenticated(&path, appstate).to_request(),
+            )
+            .await;
+            assert!(resp.status().is_success(), "{} failed", path);
+            let json: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+            json[urls::ENDPOINT_RESULTS].as_array().unwrap().len()
+        }
+    };
+    assert_eq!(search_count("/search?q=sortablefile".into()).await, 0);
+    assert_eq!(
+        search_count(format!(
+            "/search?q=sortablefile&mode=hybrid&class={}",
+            urlencoding::encode(urls::FILE)
+        ))
+        .await,
+        3
+    );
+    assert_eq!(
+        search_count(format!(
+            "/search?q=sortablefile&mode=hybrid&class={}",
+            urlencoding::encode(urls::AGENT)
+        ))
+        .await,
+        0
+    );
+    for params in ["mode=vector", "mode=hybrid&sort_by=x"] {
+        let path = format!("/search?q=sortablefile&{}", params);
+        let resp = test::call_service(
+            &app,
+            build_request_authenticated(&path, &appstate).to_request(),
+        )
+        .await;
+        assert!(!resp.status().is_success(), "{} should fail", params);
+    }
+
+    // Resources are stemmed using their own language, or the language of their parent
+    let server_url = store.get_server_url().to_string();
+    let mut folder = atomic_lib::Resource::new(format!("{}/deutsch", server_url));
+    folder.set_propval_unsafe(
+        urls::LANGUAGE.into(),
+        atomic_lib::Value::String("de".into()),
+    );
+    folder.set_propval_unsafe(
+        urls::PARENT.into(),
+        atomic_lib::Value::AtomicUrl(server_url.clone()),
+    );
+    let mut haus = atomic_lib::Resource::new(format!("{}/haus", server_url));
+    haus.set_propval_unsafe(urls::NAME.into(), atomic_lib::Value::String("Haus".into()));
+    haus.set_propval_unsafe(
+        urls::PARENT.into(),
+        atomic_lib::Value::AtomicUrl(folder.get_subject().into()),
+    );
+    let mut boek = atomic_lib::Resource::new(format!("{}/boek", server_url));
+    boek.set_propval_unsafe(urls::NAME.into(), atomic_lib::Value::String("Boek".into()));
+    boek.set_propval_unsafe(
+        urls::LANGUAGE.into(),
+        atomic_lib::Value::String("nl-BE".into()),
+    );
+    boek.set_propval_unsafe(
+        urls::PARENT.into(),
+        atomic_lib::Value::AtomicUrl(server_url.clone()),
+    );
+    for resource in [&folder, &haus, &boek] {
+        store
+            .add_resource_opts(resource, false, true, true)
+            .unwrap();
+        crate::search::add_resource(&appstate.search_state, resource, store).unwrap();
+    }
+    appstate
+        .search_state
+        .writer
+        .write()
+        .unwrap()
+        .commit()
+        .unwrap();
+    appstate.search_state.reader.reload().unwrap();
+    let search_subjects = |json: serde_json::Value| -> Vec<String> {
+        json[urls::ENDPOINT_RESULTS]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["@id"].as_str().unwrap().to_string())
+            .collect()
+    };
+    for (q, lang, subject, found) in [
+        ("häuser", "nl,de", haus.get_subject(), true),
+        ("boeken", "nl,de", boek.get_subject(), true),
+        ("häuser", "", haus.get_subject(), false),
+        ("häuser", "nl", haus.get_subject(), false),
+    ] {
+        let path = format!("/search?q={}&lang={}", urlencoding::encode(q), lang);
+        let resp = test::call_service(
+            &app,
+            build_request_authenticated(&path, &appstate).to_request(),
+        )
+        .await;
+        assert!(resp.status().is_success());
+        let json: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+        assert_eq!(
+            search_subjects(json).contains(subject),
+            found,
+            "{} with lang={}",
+            q,
+            lang
+        );
+    }
+    // Unknown languages are refused
+    let resp = test::call_service(
+        &app,
+        build_request_authenticated("/search?q=haus&lang=xx", &appstate).to_request(),
+    )
+    .await;
+    assert!(!resp.status().is_success());
+
+    // Post a Transaction with two Commits
+    let agent = store.get_default_agent().unwrap();
+    let mut commits = Vec::new();
+    for name in ["first", "second"] {
+        let subject = format!("{}/transaction-{}", store.get_server_url(), name);
+        let mut builder = atomic_lib::commit::CommitBuilder::new(subject.clone());
+        builder.set(
+            urls::PARENT.into(),
+            atomic_lib::Value::AtomicUrl(store.get_server_url().into()),
+        );
+        builder.set(
+            urls::NAME.into(),
+            atomic_lib::Value::String(format!("transaction {}", name)),
+        );
+        let commit = builder
+            .sign(&agent, store, &atomic_lib::Resource::new(subject))
+            .unwrap();
+        commits.push(commit);
+    }
+    let transaction = atomic_lib::Transaction::sign(commits, &agent, store).unwrap();
+    let req = test::TestRequest::post().uri("/commits").set_payload(
+        transaction
+            .into_resource(store)
+            .unwrap()
+            .to_json_ad()
+            .unwrap(),
+    );
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success(), "transaction not applied");
+    let created = store
+        .get_resource(&format!("{}/transaction-second", store.get_server_url()))
+        .unwrap();
+
+    // A Commit without a `previousCommit` is applied on top of the current version
+    let first = format!("{}/transaction-first", store.get_server_url());
+    let mut builder = atomic_lib::commit::CommitBuilder::new(first.clone());
+    builder.set(
+        urls::DESCRIPTION.into(),
+        atomic_lib::Value::Markdown("without previous commit".into()),
+    );
+    let commit = builder
+        .sign(&agent, store, &atomic_lib::Resource::new(first.clone()))
+        .unwrap();
+    assert!(commit.previous_commit.is_none());
+    let req = test::TestRequest::post()
+        .uri("/commit")
+        .set_payload(commit.into_resource(store).unwrap().to_json_ad().unwrap());
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success(), "commit without previousCommit");
+    assert_eq!(
+        store
+            .get_resource(&first)
+            .unwrap()
+            .get(urls::DESCRIPTION)
+            .unwrap()
+            .to_string(),
+        "without previous commit"
+    );
+
+    // Revert the Commit that created the resource, which destroys it
+    let last_commit = created.get(urls::LAST_COMMIT).unwrap().to_string();
+    let path = format!("/revert?commit={}", urlencoding::encode(&last_commit));
+    let resp = test::call_service(
+        &app,
+        build_request_authenticated(&path, &appstate).to_request(),
+    )
+    .await;
+    assert!(
+        resp.status().is_success(),
+        "resource before commit not returned"
+    );
+    let resource_old = atomic_lib::parse::parse_json_ad_resource(
+        &get_body(resp),
+        store,
+        &atomic_lib::parse::ParseOpts {
+            save: atomic_lib::parse::SaveOpts::DontSave,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let reverted =
+        atomic_lib::Commit::from_resource(store.get_resource(&last_commit).unwrap()).unwrap();
+    let inverse = reverted
+        .inverse(&resource_old)
+        .unwrap()
+        .sign(
+            &agent,
+            store,
+            &atomic_lib::Resource::new(created.get_subject().into()),
+        )
+        .unwrap();
+    let req = test::TestRequest::post()
+        .uri("/commit")
+        .set_payload(inverse.into_resource(store).unwrap().to_json_ad().unwrap());
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success(), "commit not reverted");
+    store.get_resource(created.get_subject()).unwrap_err();
+
+    // A consistent search waits until the Commits are indexed, including the destroyed resource
+    let req = build_request_authenticated("/search?q=transaction&consistent=true", &appstate);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success());
+    let json: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    let found: Vec<&str> = json[urls::ENDPOINT_RESULTS]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["@id"].as_str().unwrap())
+        .collect();
+    assert!(found.contains(&format!("{}/transaction-first", store.get_server_url()).as_str()));
+    assert!(!found.contains(&created.get_subject().as_str()));
+    // The CommitMonitor updates the vectors as well
+    let similarity = appstate.search_state.similarity.as_ref().unwrap();
+    assert!(similarity
+        .get(&format!("{}/transaction-first", store.get_server_url()))
+        .unwrap()
+        .is_some());
+    assert!(similarity.get(created.get_subject()).unwrap().is_none());
+
+    // The verifier removes documents of resources that are not in the Db, and indexes missing ones once
+    let ghost = atomic_lib::Resource::new(format!("{}/ghost", store.get_server_url()));
+    crate::search::add_resource(&appstate.search_state, &ghost, store).unwrap();
+    let duplicate = store
+        .get_resource(&format!("{}/sortable-10", store.get_server_url()))
+        .unwrap();
+    crate::search::add_resource(&appstate.search_state, &duplicate, store).unwrap();
+    appstate
+        .search_state
+        .writer
+        .write()
+        .unwrap()
+        .commit()
+        .unwrap();
+    let repairs = crate::search::verify_index(&appstate.search_state, store).unwrap();
+    assert_eq!(repairs.removed, 1);
+    // Includes the default resources, which were added without Commits
+    assert!(repairs.added > 1);
+    let repairs = crate::search::verify_index(&appstate.search_state, store).unwrap();
+    assert_eq!((repairs.added, repairs.removed), (0, 0));
+    // Indexing the same subject from several threads at once leaves a single document
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| {
+                for _ in 0..10 {
+                    crate::search::reindex_subject(
+                        &appstate.search_state,
+                        store,
+                        duplicate.get_subject(),
+                    )
+                    .unwrap();
+                }
+            });
+        }
+    });
+    let repairs = crate::search::verify_index(&appstate.search_state, store).unwrap();
+    assert_eq!((repairs.added, repairs.removed), (0, 0));
+
+    // Import Turtle
+    let imported = format!("{}/imported-turtle", store.get_server_url());
+    let turtle = format!("<{}> <{}> \"From Turtle\" .", imported, urls::NAME);
+    let path = format!(
+        "/import?format=turtle&parent={}",
+        urlencoding::encode(store.get_server_url())
+    );
+    let req = build_request_authenticated(&path, &appstate)
+        .method(actix_web::http::Method::POST)
+        .set_payload(turtle);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success(), "turtle not imported");
+    let resource = store.get_resource(&imported).unwrap();
+    assert_eq!(resource.get(urls::NAME).unwrap().to_string(), "From Turtle");
+
+    // Identical uploads share a blob, which is served with its checksum as ETag
+    let upload_path = format!(
+        "/upload?parent={}",
+        urlencoding::encode(store.get_server_url())
+    );
+    let mut uploaded = Vec::new();
+    for _ in 0..2 {
+        let body = "--boundary\r\nContent-Disposition: form-data; name=\"file\"; filename=\"hello.txt\"\r\nContent-Type: text/plain\r\n\r\nhello\r\n--boundary--\r\n";
+        let req = build_request_authenticated(&upload_path, &appstate)
+            .method(actix_web::http::Method::POST)
+            .insert_header(("Content-Type", "multipart/form-data; boundary=boundary"))
+            .set_payload(body);
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert!(resp.status().is_success(), "upload failed");
+        let files = atomic_lib::parse::parse_json_ad_string(
+            &get_body(resp),
+            store,
+            &atomic_lib::parse::ParseOpts {
+                save: atomic_lib::parse::SaveOpts::DontSave,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        uploaded.push(files[0].clone());
+    }
+    assert_ne!(uploaded[0].get_subject(), uploaded[1].get_subject());
+    assert_eq!(
+        uploaded[0].get(urls::INTERNAL_ID).unwrap().to_string(),
+        uploaded[1].get(urls::INTERNAL_ID).unwrap().to_string()
+    );
+    let checksum = uploaded[0].get(urls::CHECKSUM).unwrap().to_string();
+    assert_eq!(checksum, "LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=");
+    let download_path = uploaded[0]
+        .get(urls::DOWNLOAD_URL)
+        .unwrap()
+        .to_string()
+        .replace(store.get_server_url(), "");
+    // Downloads are authenticated using the subject of the File, without `/download`
+    let download_request = || {
+        let headers = atomic_lib::client::get_authentication_headers(
+            uploaded[0].get_subject(),
+            &store.get_default_agent().unwrap(),
+        )
+        .unwrap();
+        headers
+            .into_iter()
+            .fold(test::TestRequest::with_uri(&download_path), |req, h| {
+                req.insert_header(h)
+            })
+    };
+    let resp = test::call_service(&app, download_request().to_request()).await;
+    assert!(resp.status().is_success(), "download failed");
+    let etag = format!("\"{}\"", checksum);
+    assert_eq!(resp.headers().get("ETag").unwrap(), etag.as_str());
+    assert_eq!(test::read_body(resp).await.as_ref(), b"hello");
+    let req = download_request().insert_header(("If-None-Match", etag.as_str()));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_MODIFIED);
+
+    // Uploaded images get their dimensions, and can be downloaded in a smaller size
+    let mut png = std::io::Cursor::new(Vec::new());
+    image::DynamicImage::ImageRgb8(image::RgbImage::new(300, 200))
+        .write_to(&mut png, image::ImageOutputFormat::Png)
+        .unwrap();
+    let mut body = b"--boundary\r\nContent-Disposition: form-data; name=\"file\"; filename=\"image.png\"\r\nContent-Type: image/png\r\n\r\n".to_vec();
+    body.extend(png.into_inner());
+    body.extend(b"\r\n--boundary--\r\n");
+    let req = build_request_authenticated(&upload_path, &appstate)
+        .method(actix_web::http::Method::POST)
+        .insert_header(("Content-Type", "multipart/form-data; boundary=boundary"))
+        .set_payload(body);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success(), "image upload failed");
+    let image_subject = atomic_lib::parse::parse_json_ad_string(
+        &get_body(resp),
+        store,
+        &atomic_lib::parse::ParseOpts {
+            save: atomic_lib::parse::SaveOpts::DontSave,
+            ..Default::default()
+        },
+    )
+    .unwrap()[0]
+        .get_subject()
+        .clone();
+    let image_file = store.get_resource(&image_subject).unwrap();
+    assert_eq!(
+        image_file.get(urls::IMAGE_WIDTH).unwrap().to_int().unwrap(),
+        300
+    );
+    assert_eq!(
+        image_file
+            .get(urls::IMAGE_HEIGHT)
+            .unwrap()
+            .to_int()
+            .unwrap(),
+        200
+    );
+    let thumbnail_path = format!(
+        "{}?w=100",
+        image_subject.replace(store.get_server_url(), "/download")
+    );
+    let req = atomic_lib::client::get_authentication_headers(
+        &image_subject,
+        &store.get_default_agent().unwrap(),
+    )
+    .unwrap()
+    .into_iter()
+    .fold(test::TestRequest::with_uri(&thumbnail_path), |req, h| {
+        req.insert_header(h)
+    });
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success(), "thumbnail download failed");
+    assert_eq!(resp.headers().get("Content-Type").unwrap(), "image/png");
+    let thumbnail = image::load_from_memory(&test::read_body(resp).await).unwrap();
+    assert_eq!((thumbnail.width(), thumbnail.height()), (128, 85));
+
+    // Resumable uploads send numbered chunks to an UploadSession
+    let create_path = format!(
+        "/upload-sessions?parent={}&filename=resumed.txt&filesize=11",
+        urlencoding::encode(store.get_server_url())
+    );
+    let req =
+        build_request_authenticated(&create_path, &appstate).method(actix_web::http::Method::POST);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success(), "upload session not created");
+    let session_json: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    let session_path = session_json["@id"]
+        .as_str()
+        .unwrap()
+        .replace(store.get_server_url(), "");
+    let send_chunk = |chunk: usize, offset: usize, data: &'static str| {
+        build_request_authenticated(
+            &format!("{}?chunk={}&offset={}", session_path, chunk, offset),
+            &appstate,
+        )
+        .method(actix_web::http::Method::PUT)
+        .set_payload(data)
+        .to_request()
+    };
+    let resp = test::call_service(&app, send_chunk(0, 0, "hello ")).await;
+    assert!(resp.status().is_success(), "first chunk failed");
+    // Retrying a received chunk is ignored
+    let resp = test::call_service(&app, send_chunk(0, 0, "hello ")).await;
+    assert!(resp.status().is_success(), "retried chunk failed");
+    let resp = test::call_service(&app, send_chunk(2, 6, "world")).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::CONFLICT);
+    let finish = || {
+        build_request_authenticated(&session_path, &appstate)
+            .method(actix_web::http::Method::POST)
+            .to_request()
+    };
+    let resp = test::call_service(&app, finish()).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::CONFLICT);
+    let resp = test::call_service(&app, send_chunk(1, 6, "world")).await;
+    assert!(resp.status().is_success(), "second chunk failed");
+    // The session shows the progress
+    let resp = test::call_service(
+        &app,
+        build_request_authenticated(&session_path, &appstate).to_request(),
+    )
+    .await;
+    let session_json: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    assert_eq!(session_json[urls::UPLOAD_OFFSET], 11);
+    assert_eq!(session_json[urls::UPLOAD_CHUNKS], 2);
+    let resp = test::call_service(&app, finish()).await;
+    assert!(resp.status().is_success(), "finishing upload failed");
+    let file_json: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    assert_eq!(file_json[urls::FILESIZE], 11);
+    assert_eq!(
+        file_json[urls::CHECKSUM],
+        "uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek="
+    );
+    // Finishing again returns the same File
+    let resp = test::call_service(&app, finish()).await;
+    let again: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    assert_eq!(again["@id"], file_json["@id"]);
+
+    // Expired sessions are removed
+    let session_subject = format!("{}{}", store.get_server_url(), session_path);
+    let mut session = store.get_resource(&session_subject).unwrap();
+    session.set_propval_unsafe(
+        urls::UPLOAD_EXPIRES_AT.into(),
+        atomic_lib::Value::Timestamp(0),
+    );
+    session.save_locally(store).unwrap();
+    assert_eq!(
+        crate::uploads::remove_expired_sessions(store, &appstate.config.uploads_path).unwrap(),
+        1
+    );
+    assert!(store.get_resource(&session_subject).is_err());
+
+    // Tokens can be used as `Authorization: Bearer` instead of signing requests
+    let req = build_request_authenticated("/tokens?read_only=true&expires_in=60", &appstate)
+        .method(actix_web::http::Method::POST);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success(), "token not created");
+    let token_json: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    let secret = token_json[urls::TOKEN_SECRET].as_str().unwrap().to_string();
+    for expires_in in ["0", "-5", &i64::MAX.to_string()] {
+        let path = format!("/tokens?expires_in={}", expires_in);
+        let req =
+            build_request_authenticated(&path, &appstate).method(actix_web::http::Method::POST);
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status().as_u16(), 400, "expires_in={}", expires_in);
+    }
+    let with_bearer = |path: &str, bearer: &str| {
+        test::TestRequest::with_uri(path)
+            .insert_header(("Accept", "application/ad+json"))
+            .insert_header(("Authorization", format!("Bearer {}", bearer)))
+    };
+    let resp = test::call_service(&app, with_bearer("/properties", &secret).to_request()).await;
+    assert!(resp.status().is_success(), "bearer token not accepted");
+    let resp = test::call_service(
+        &app,
+        with_bearer("/properties", &format!("{}x", secret)).to_request(),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 401);
+    // Read-only Tokens can't upload, and Tokens can't create Tokens
+    let upload_path = format!(
+        "/upload-sessions?parent={}&filename=denied.txt&filesize=1",
+        urlencoding::encode(store.get_server_url())
+    );
+    let req = with_bearer(&upload_path, &secret).method(actix_web::http::Method::POST);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 401);
+    let req = with_bearer("/tokens", &secret).method(actix_web::http::Method::POST);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 401);
+
+    // The audit log shows refused requests, and is only available to the server's Agent
+    let resp = test::call_service(
+        &app,
+        build_request_authenticated("/audit?allowed=false", &appstate).to_request(),
+    )
+    .await;
+    assert!(resp.status().is_success(), "audit log not returned");
+    let audit_json: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    let events = audit_json[urls::ENDPOINT_RESULTS].as_array().unwrap();
+    assert!(events
+        .iter()
+        .any(|e| e[urls::AUDIT_AGENT] == token_json["@id"] && e[urls::AUDIT_ALLOWED] == false));
+    let resp = test::call_service(&app, with_bearer("/audit", &secret).to_request()).await;
+    assert_eq!(resp.status().as_u16(), 401);
+    // Reading a Collection records the Collection, not its members
+    let server_agent = store.get_default_agent().unwrap().subject;
+    let resp = test::call_service(
+        &app,
+        build_request_authenticated("/agents", &appstate).to_request(),
+    )
+    .await;
+    assert!(resp.status().is_success());
+    let audit_count = |subject: String| {
+        let path = format!(
+            "/audit?right=read&subject={}",
+            urlencoding::encode(&subject)
+        );
+        let req = build_request_authenticated(&path, &appstate).to_request();
+        let app = &app;
+        async move {
+            let resp = test::call_service(app, req).await;
+            let audit_json: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+            audit_json[urls::ENDPOINT_RESULTS].as_array().unwrap().len()
+        }
+    };
+    assert_eq!(
+        audit_count(format!("{}/agents", store.get_server_url())).await,
+        1
+    );
+    assert_eq!(audit_count(server_agent).await, 0);
+
+    // Going over the rate limit returns a 429 with an Error resource
+    let resp = test::call_service(
+        &app,
+        build_request_authenticated("/fetch-bookmark", &appstate).to_request(),
+    )
+    .await;
+    assert_ne!(resp.status().as_u16(), 429);
+    let resp = test::call_service(
+        &app,
+        build_request_authenticated("/fetch-bookmark", &appstate).to_request(),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 429);
+    let body = get_body(resp);
+    assert!(body.contains(urls::ERROR), "no error resource: {}", body);
+    // Other routes have their own limits
+    let resp = test::call_service(
+        &app,
+        build_request_authenticated("/properties", &appstate).to_request(),
+    )
+    .await;
+    assert!(resp.status().is_success());
 }
 
 /// Gets the body from the response as a String. Why doen't actix provide this?
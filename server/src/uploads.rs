@@ -0,0 +1,343 @@
+//! Content-addressed storage of uploaded files.
+//! Uploads are stored in the [BlobStore] by the hex encoded SHA-256 hash of their contents, so identical files share a single blob.
+//! The hash is the `internalId` of the File resource, and its base64 encoded form is stored as the `checksum`.
+//! Blobs that are no longer referenced by any File resource are removed by [collect_garbage], including their resized variants (see [crate::images]).
+
+use std::{
+    collections::HashSet,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use atomic_lib::{storelike::Query, urls, Db, Storelike};
+
+use crate::{blob_store::BlobStore, errors::AtomicServerResult};
+
+/// Blobs that are younger than this are never removed, because their File resource might not be saved yet.
+const GC_GRACE_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+/// How often the garbage collector runs.
+pub const GC_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Makes the names of temporary files unique, even for uploads that start at the same moment.
+static UPLOAD_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Writes an upload to a temporary file while computing its SHA-256 hash.
+/// Call [BlobWriter::finish] to move it to the [BlobStore].
+/// If it is dropped before that, e.g. because the upload was aborted, the temporary file is removed.
+pub struct BlobWriter {
+    file: std::fs::File,
+    temp_path: PathBuf,
+    hasher: ring::digest::Context,
+    size: u64,
+}
+
+/// A blob that has been stored in the [BlobStore].
+pub struct StoredBlob {
+    /// Hex encoded SHA-256 hash, which is also the file name of the blob.
+    pub id: String,
+    /// Base64 encoded SHA-256 hash, as stored in the `checksum` property.
+    pub checksum: String,
+    pub size: u64,
+}
+
+impl BlobWriter {
+    /// Creates a temporary file in `temp_dir`.
+    pub fn new(temp_dir: &Path) -> AtomicServerResult<BlobWriter> {
+        std::fs::create_dir_all(temp_dir)?;
+        // Temporary files start with a dot, so the garbage collector skips them.
+        let temp_path = temp_dir.join(format!(
+            ".upload-{}-{}",
+            atomic_lib::utils::now(),
+            UPLOAD_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        Ok(BlobWriter {
+            file: std::fs::File::create(&temp_path)?,
+            temp_path,
+            hasher: ring::digest::Context::new(&ring::digest::SHA256),
+            size: 0,
+        })
+    }
+
+    /// The temporary file, which contains the data that has been written so far.
+    pub fn path(&self) -> &Path {
+        &self.temp_path
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> AtomicServerResult<()> {
+        self.hasher.update(data);
+        self.file.write_all(data)?;
+        self.size += data.len() as u64;
+        Ok(())
+    }
+
+    /// Stores the file by its hash. If an identical blob exists, the temporary file is removed instead.
+    pub fn finish(mut self, blobs: &dyn BlobStore) -> AtomicServerResult<StoredBlob> {
+        self.file.flush()?;
+        put_blob(
+            blobs,
+            self.hasher.clone().finish(),
+            self.size,
+            &self.temp_path,
+        )
+    }
+}
+
+impl Drop for BlobWriter {
+    fn drop(&mut self) {
+        // The BlobStore has moved the file if the upload was finished
+        if self.temp_path.exists() {
+            if let Err(e) = std::fs::remove_file(&self.temp_path) {
+                tracing::warn!(
+                    "Failed to remove temporary upload {}: {}",
+                    self.temp_path.display(),
+                    e
+                );
+            }
+        }
+    }
+}
+
+/// Hashes an existing file, and moves it to the [BlobStore].
+pub fn store_file(path: &Path, blobs: &dyn BlobStore) -> AtomicServerResult<StoredBlob> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = ring::digest::Context::new(&ring::digest::SHA256);
+    let mut buffer = vec![0; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        size += read as u64;
+    }
+    put_blob(blobs, hasher.finish(), size, path)
+}
+
+fn put_blob(
+    blobs: &dyn BlobStore,
+    digest: ring::digest::Digest,
+    size: u64,
+    path: &Path,
+) -> AtomicServerResult<StoredBlob> {
+    let id: String = digest
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    blobs.put(&id, path)?;
+    Ok(StoredBlob {
+        id,
+        checksum: base64::encode(digest.as_ref()),
+        size,
+    })
+}
+
+/// Removes the blobs that are not the `internalId` of any File resource.
+/// Skips recently written blobs. Returns the amount of removed blobs.
+pub fn collect_garbage(store: &Db, blobs: &dyn BlobStore) -> AtomicServerResult<usize> {
+    collect_garbage_older_than(store, blobs, GC_GRACE_PERIOD)
+}
+
+fn collect_garbage_older_than(
+    store: &Db,
+    blobs: &dyn BlobStore,
+    grace_period: Duration,
+) -> AtomicServerResult<usize> {
+    let stored = blobs.list()?;
+    if stored.is_empty() {
+        return Ok(0);
+    }
+    let referenced = referenced_blobs(store)?;
+    let mut removed = 0;
+    for blob in stored {
+        let original = crate::images::original_id(&blob.id).unwrap_or(&blob.id);
+        if referenced.contains(&blob.id) || referenced.contains(original) {
+            continue;
+        }
+        let age = blob.modified.elapsed().unwrap_or(Duration::from_secs(0));
+        if age < grace_period {
+            continue;
+        }
+        blobs.delete(&blob.id)?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+/// The `internalId`s of all File resources
+pub fn referenced_blobs(store: &Db) -> AtomicServerResult<HashSet<String>> {
+    Ok(store
+        .query(&Query::new_class(urls::FILE))?
+        .resources
+        .iter()
+        .filter_map(|file| file.get(urls::INTERNAL_ID).ok().map(|id| id.to_string()))
+        .collect())
+}
+
+/// The file that contains the data that has been received for an UploadSession.
+/// Starts with a dot, so the garbage collector skips it.
+pub fn session_path(uploads_path: &Path, session_subject: &str) -> PathBuf {
+    let id = session_subject.rsplit('/').next().unwrap_or_default();
+    uploads_path.join(format!(".session-{}", id))
+}
+
+/// UploadSessions that are currently receiving a chunk
+static ACTIVE_SESSIONS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Prevents concurrent writes to an UploadSession. The session is released when this is dropped.
+pub struct SessionLock {
+    subject: String,
+}
+
+impl SessionLock {
+    /// Returns a `Conflict` error if the session is already locked.
+    pub fn acquire(subject: &str) -> AtomicServerResult<SessionLock> {
+        let mut active = ACTIVE_SESSIONS.lock()?;
+        if active.iter().any(|s| s == subject) {
+            return Err(atomic_lib::AtomicError::conflict(
+                format!("UploadSession {} is already receiving a chunk", subject),
+                Vec::new(),
+            )
+            .into());
+        }
+        active.push(subject.to_string());
+        Ok(SessionLock {
+            subject: subject.to_string(),
+        })
+    }
+}
+
+impl Drop for SessionLock {
+    fn drop(&mut self) {
+        if let Ok(mut active) = ACTIVE_SESSIONS.lock() {
+            active.retain(|s| s != &self.subject);
+        }
+    }
+}
+
+/// Removes the UploadSessions that have expired, including their received data. Returns the amount of removed sessions.
+pub fn remove_expired_sessions(store: &Db, uploads_path: &Path) -> AtomicServerResult<usize> {
+    let now = atomic_lib::utils::now();
+    let sessions = store
+        .query(&Query::new_class(urls::UPLOAD_SESSION))?
+        .resources;
+    let mut removed = 0;
+    for session in sessions {
+        let expires_at = match session.get(urls::UPLOAD_EXPIRES_AT) {
+            Ok(expires_at) => expires_at.to_int()?,
+            Err(_) => 0,
+        };
+        if expires_at > now {
+            continue;
+        }
+        let path = session_path(uploads_path, session.get_subject());
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        store.remove_resource(session.get_subject())?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+/// Periodically removes unreferenced blobs and expired UploadSessions, see [collect_garbage] and [remove_expired_sessions].
+pub fn start_garbage_collection(appstate: &crate::appstate::AppState) {
+    let store = appstate.store.clone();
+    let blobs = appstate.blob_store.clone();
+    let uploads_path = appstate.config.uploads_path.clone();
+    std::thread::spawn(move || loop {
+        match remove_expired_sessions(&store, &uploads_path) {
+            Ok(0) => {}
+            Ok(removed) => tracing::info!("Removed {} expired upload sessions", removed),
+            Err(e) => tracing::error!("Removing expired upload sessions failed: {}", e),
+        }
+        match collect_garbage(&store, blobs.as_ref()) {
+            Ok(0) => {}
+            Ok(removed) => tracing::info!("Removed {} unreferenced uploads", removed),
+            Err(e) => tracing::error!("Garbage collection of uploads failed: {}", e),
+        }
+        std::thread::sleep(GC_INTERVAL);
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blob_store::FileSystemBlobStore;
+
+    #[test]
+    fn dedupes_and_collects_garbage() {
+        let store = Db::init_temp("uploads_gc").unwrap();
+        let uploads_path = std::env::temp_dir().join(format!(
+            "atomic-uploads-test-{}-{}",
+            std::process::id(),
+            atomic_lib::utils::now()
+        ));
+        let blobs = FileSystemBlobStore::new(uploads_path.clone());
+        let upload = |data: &[u8]| {
+            let mut writer = BlobWriter::new(&uploads_path).unwrap();
+            writer.write(data).unwrap();
+            writer.finish(&blobs).unwrap()
+        };
+        let first = upload(b"hello");
+        let second = upload(b"hello");
+        let other = upload(b"other");
+        assert_eq!(first.id, second.id);
+        assert_ne!(first.id, other.id);
+        assert_eq!(first.size, 5);
+        assert_eq!(
+            first.id,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert_eq!(std::fs::read_dir(&uploads_path).unwrap().count(), 2);
+        // Aborted uploads don't leave their temporary file behind
+        let mut aborted = BlobWriter::new(&uploads_path).unwrap();
+        aborted.write(b"partial").unwrap();
+        let aborted_path = aborted.path().to_path_buf();
+        drop(aborted);
+        assert!(!aborted_path.exists());
+
+        let mut file = atomic_lib::Resource::new_instance(urls::FILE, &store).unwrap();
+        file.set_propval_string(urls::INTERNAL_ID.into(), &first.id, &store)
+            .unwrap();
+        file.set_propval_string(
+            urls::DOWNLOAD_URL.into(),
+            "http://localhost/download",
+            &store,
+        )
+        .unwrap();
+        file.save_locally(&store).unwrap();
+
+        // Blobs of older versions are named after their upload time and filename, which can look like a variant
+        let legacy_id = "1680000000000-scan-w2";
+        std::fs::write(uploads_path.join(legacy_id), b"legacy").unwrap();
+        let mut legacy = atomic_lib::Resource::new_instance(urls::FILE, &store).unwrap();
+        legacy
+            .set_propval_string(urls::INTERNAL_ID.into(), legacy_id, &store)
+            .unwrap();
+        legacy
+            .set_propval_string(
+                urls::DOWNLOAD_URL.into(),
+                "http://localhost/download/legacy",
+                &store,
+            )
+            .unwrap();
+        legacy.save_locally(&store).unwrap();
+
+        // Recent blobs are kept
+        assert_eq!(collect_garbage(&store, &blobs).unwrap(), 0);
+        let removed = collect_garbage_older_than(&store, &blobs, Duration::from_secs(0)).unwrap();
+        assert_eq!(removed, 1);
+        assert!(uploads_path.join(&first.id).exists());
+        assert!(uploads_path.join(legacy_id).exists());
+        assert!(!uploads_path.join(&other.id).exists());
+        std::fs::remove_dir_all(&uploads_path).unwrap();
+    }
+}